@@ -0,0 +1,639 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+use elsa::game::difficulty::Difficulty;
+use elsa::game::jsonl::Io;
+use elsa::game::permissions::{self, Capability};
+use elsa::game::simulate::Traveller;
+use elsa::llm::sampler::{SamplerConfig, SamplerStage};
+
+pub const USAGE: &str = "\
+Usage: cargo run <path-to-model.gguf> <path-to-scenario.json> [options]
+       cargo run <path-to-model.gguf> --resume <save.json> [options]
+       cargo run replay <session.jsonl> [path-to-model.gguf] [options]
+       cargo run simulate <path-to-model.gguf> <path-to-scenario.json> [options]
+       cargo run eval <tests.yaml> <path-to-model.gguf> [options]
+       cargo run graph <path-to-scenario.json> [-o scenario.dot|scenario.mmd]
+       cargo run lint <path-to-scenario.json>
+       cargo run serve <path-to-model.gguf> <path-to-scenario.json> [--port 8080]
+       cargo run --features discord discord <path-to-model.gguf> <path-to-scenario.json>
+       cargo run --features telegram telegram <path-to-model.gguf> <path-to-scenario.json>
+       cargo run --features loadtest loadtest [http://host:port] [--sessions 20 --turns 10]
+
+Commands:
+  replay                  Re-play a recorded session. With a model, the judge is
+                          re-run on every recorded answer and changed decisions
+                          are flagged.
+  simulate                The model plays the player against the judge for
+                          a number of games and prints how they ended.
+  eval                    Judge a file of labeled cases and report accuracy, a
+                          confusion matrix and the failing cases.
+  graph                   Export the scenario as a Graphviz DOT graph or a
+                          Mermaid flowchart.
+  lint                    Flag judge criteria worded in ways that confuse small
+                          models, with suggested rewrites.
+  serve                   Play the scenario over an HTTP JSON API on localhost.
+  discord                 Run the scenario as a Discord bot: /start opens a game
+                          in a new thread. The bot token is read from DISCORD_TOKEN.
+  telegram                Run the scenario as a Telegram bot, one game per chat.
+                          The bot token is read from TELEGRAM_TOKEN.
+  loadtest                Play many sessions at once against a running `serve` and
+                          report queue wait, per-turn latency and how evenly the
+                          sessions were served.
+
+A scenario can also be a built-in one: builtin:airport, builtin:interview,
+builtin:traffic_stop or builtin:locked_out.
+
+Options:
+  --temp <f32>            Judge sampling temperature (default 0.2)
+  --top-k <i32>           Judge top-k
+  --top-p <f32>           Judge top-p
+  --repeat-penalty <f32>  Judge repetition penalty (1.0 = off)
+  --ensemble <n>          Sample the judge n times per answer and take the majority
+                          decision; the prompt is decoded once for all of them
+  --chat-temp <f32>       Free chat / narration temperature
+  --chat-top-k <i32>      Free chat / narration top-k
+  --chat-top-p <f32>      Free chat / narration top-p
+  --chat-mirostat <tau>   Use mirostat v2 for narration with the given target surprise
+  --chat-mirostat-eta <f32>
+                          Mirostat learning rate (default 0.1)
+  --chat-dynatemp <f32>   Dynamic temperature range for narration (0 = off)
+  --seed <u64>            Run seed; the same seed and inputs replay the same game
+  --config <path>         Config file (default: ./elsa.toml if present)
+  --hw <name>             Hardware profile from the config's [hw.<name>] (default:
+                          the one listing this machine's hostname)
+  --resume <save.json>    Continue a game written by the in-game `/save` command
+  --migrate               Migrate a save made with an older version of the scenario
+                          without asking (required to do so with --io jsonl)
+  --log-dir <dir>         Write a JSONL transcript of every turn to <dir>
+  --scenario <path>       Scenario file (replay, eval: overrides the one recorded in the file)
+  --cps <f32>             Print guard lines at this many characters per second
+                          (0 = instant). Press Enter (F4 in the TUI) to skip ahead.
+  --difficulty <level>    easy, normal (default) or strict judging; scenarios can
+                          change a node's criteria per level
+  --lang <code>           Play in another language, e.g. de: scenario text from the
+                          nodes' \"locales\", and the game's own words where it has them
+  --language-hints        Tell the judge when the player answers in another language
+                          than the game's, so it translates before judging
+  --adaptive              Judge a level stricter after a run of passes and a level
+                          more leniently after each failure
+  --narrate               Have the model rewrite the guard's lines in the voice of the
+                          scenario's persona (name, temperament, quirks)
+  --speak                 Read the guard's lines out loud with the text-to-speech
+                          command in the config's [voice] section
+  --listen                Answer out loud: /talk (F3 in the full-screen UI) records an
+                          answer with the config's [voice] listen command, and shows
+                          what was heard to send or redo
+  --fast                  Speedrun: no narration, typing out, ambient queue, small talk
+                          or follow-up questions, and the judge sees only the last
+                          exchange and answers with a single number where it can
+  --strict                End the session with a diagnostic on judge failures and
+                          fallback transitions instead of recovering
+  -o, --output <path>     graph: write to <path> instead of stdout
+                          simulate: write the games to <path> as an eval dataset
+                          (YAML, or JSON for .json), labeled by the referee:
+                          --supervisor-model, or the judge model with the
+                          supervisor sampler
+  --report <path>         eval: also write the results to <path>, as an HTML page
+                          (.html) or a CSV file (.csv); repeatable
+  --lang-matrix           eval: judge the cases against the scenario as written and in
+                          each language of its \"locales\", and report the accuracy
+                          per language and the nodes whose translation does worse
+  --format <dot|mermaid>  graph: output format (default: from the -o extension,
+                          .mmd/.md = mermaid, otherwise dot)
+  --ambient               Show other travellers in the queue while the judge thinks
+                          (uses a second, low-priority model context)
+  --power-save            Use fewer threads and pause between decode chunks to keep
+                          the machine cool and quiet, at the cost of latency
+  --plain                 Line-by-line terminal output instead of the full-screen UI
+                          (always used when stdout is not a terminal)
+  --no-color              Plain terminal output and log lines without colors (also
+                          when the NO_COLOR environment variable is set)
+  --io <terminal|jsonl>   jsonl: play over line-delimited JSON on stdin/stdout, for
+                          driving the game from another program (logs go to stderr)
+  --games <n>             simulate: number of games (default 20)
+  --traveller <kind>      simulate: cooperative, evasive or hostile
+  --stats <path>          Keep your per-scenario record (plays, wins, best score,
+                          endings discovered) in <path> (default: elsa-stats.json)
+  --no-stats              Don't read or write the stats file
+  --no-clock              Ignore the local time of day, which otherwise picks the
+                          guard's shift in scenarios that have them
+  --players <a,b>         Couch co-op: players take turns answering, e.g. a couple
+                          travelling together. The judge is told who is speaking
+  --allow <list>          Let the scenario use features it asks for in its
+                          \"permissions\": dynamic-dialogue, tool-use,
+                          web-catalog-assets, scripting, or all
+  --max-turns <n>         simulate: cut a game off after this many turns (default 30)
+  --port <u16>            serve: port to listen on (default 8080)
+  --sessions <n>          loadtest: concurrent sessions (default 20)
+  --turns <n>             loadtest: messages each session sends (default 10)
+  --corrections <path>    Library of misjudged cases (JSONL); the most similar ones are
+                          shown to the judge as examples. eval adds its failing cases
+  --supervisor-model <path>
+                          Model that rules on appeals at failure endings
+                          (default: the judge model with the supervisor sampler)
+  --model-alias <name>=<path>
+                          Load another model under a name that scenario nodes can ask
+                          for in their \"model\" field (repeatable)
+
+Example:
+  cargo run ./SmolLM3-Q4_K_M.gguf ./scenarios/airport.json --temp 0.2
+";
+
+/// What the binary was asked to do.
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    /// Play a scenario interactively.
+    #[default]
+    Play,
+    /// Re-play a recorded session transcript.
+    Replay { log_path: String },
+    /// Let the model play the traveller for a number of games.
+    Simulate,
+    /// Measure the judge against labeled test cases.
+    Eval { suite_path: String },
+    /// Export the scenario structure.
+    Graph,
+    /// Check the scenario's judge criteria.
+    Lint,
+    /// Serve the scenario over HTTP.
+    Serve,
+    /// Run the scenario as a Discord bot.
+    Discord,
+    /// Run the scenario as a Telegram bot.
+    Telegram,
+    /// Load test a running server.
+    Loadtest { url: Option<String> },
+}
+
+/// Parsed command line.
+#[derive(Debug, Default)]
+pub struct Cli {
+    pub command: Command,
+    /// Required for play; optional for replay (enables re-judging).
+    pub model_path: Option<String>,
+    /// Scenario to play. May be omitted when resuming a save that recorded it.
+    pub scenario_path: Option<String>,
+    pub resume_path: Option<String>,
+    /// Migrate a save from an older scenario version without asking.
+    pub migrate: bool,
+    pub log_dir: Option<String>,
+    pub config_path: Option<String>,
+    /// Hardware profile from the config.
+    pub hw: Option<String>,
+    /// Run seed from which the sampler and game RNG seeds are derived.
+    pub seed: Option<u64>,
+    /// Guard line typing speed; overrides `[pacing] cps`.
+    pub cps: Option<f32>,
+    /// Treat engine-level anomalies as fatal.
+    pub strict: bool,
+    /// Ambient queue of other travellers during play.
+    pub ambient: bool,
+    /// Plain stdin/stdout play instead of the TUI.
+    pub plain: bool,
+    /// No colors in terminal output.
+    pub no_color: bool,
+    pub difficulty: Difficulty,
+    /// Language to play in, e.g. "de".
+    pub lang: Option<String>,
+    /// Tell the judge when an answer is in another language.
+    pub language_hints: bool,
+    /// Speedrun bundle: no narration, pacing, ambient queue or small talk,
+    /// and a lean, routed judge.
+    pub fast: bool,
+    /// Adapt the difficulty to the player's streak.
+    pub adaptive: bool,
+    /// Rewrite guard lines in the persona's voice.
+    pub narrate: bool,
+    /// Read guard lines out loud.
+    pub speak: bool,
+    /// Let the player answer out loud.
+    pub listen: bool,
+    /// Keep the clock out of play, for deterministic runs.
+    pub no_clock: bool,
+    /// Stats file; overrides the default path.
+    pub stats_path: Option<String>,
+    /// Keep no stats.
+    pub no_stats: bool,
+    /// Terminal play or the JSONL protocol.
+    pub io: Io,
+    /// Throttle generation (fewer threads, pauses between decode chunks).
+    pub power_save: bool,
+    pub games: Option<usize>,
+    pub traveller: Option<Traveller>,
+    /// Couch co-op player names, in turn order.
+    pub players: Vec<String>,
+    /// Scenario permissions the player allows.
+    pub allow: Vec<Capability>,
+    pub max_turns: Option<usize>,
+    pub output_path: Option<String>,
+    pub graph_format: Option<String>,
+    pub port: Option<u16>,
+    pub sessions: Option<usize>,
+    pub turns: Option<usize>,
+    /// Failure library for corrective few-shot examples.
+    pub corrections_path: Option<String>,
+    /// Separate model for the appeal supervisor.
+    pub supervisor_model: Option<String>,
+    /// Extra models by alias, for nodes that route to one.
+    pub model_aliases: Vec<(String, String)>,
+    /// Files to write the eval results to.
+    pub report_paths: Vec<String>,
+    /// Run the eval in every language of the scenario.
+    pub lang_matrix: bool,
+    pub judge_sampler: SamplerOverrides,
+    pub chat_sampler: SamplerOverrides,
+    /// Judge samplings per answer, majority-voted.
+    pub ensemble: Option<usize>,
+}
+
+/// Sampler parameters given on the command line. Unset values keep the
+/// profile's defaults. When the profile has an explicit chain, matching
+/// stages in it are overridden instead.
+#[derive(Debug, Default)]
+pub struct SamplerOverrides {
+    pub temperature: Option<f32>,
+    pub top_k: Option<i32>,
+    pub top_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+    pub mirostat_tau: Option<f32>,
+    pub mirostat_eta: Option<f32>,
+    pub dynatemp_range: Option<f32>,
+}
+
+impl SamplerOverrides {
+    pub fn apply(&self, config: &mut SamplerConfig) {
+        if let Some(t) = self.temperature {
+            config.temperature = t;
+        }
+        if let Some(k) = self.top_k {
+            config.top_k = k;
+        }
+        if let Some(p) = self.top_p {
+            config.top_p = p;
+        }
+        if let Some(r) = self.repeat_penalty {
+            config.repeat_penalty = r;
+        }
+        if self.mirostat_tau.is_some() || self.mirostat_eta.is_some() {
+            let mut mirostat = config.mirostat.unwrap_or_default();
+            mirostat.tau = self.mirostat_tau.unwrap_or(mirostat.tau);
+            mirostat.eta = self.mirostat_eta.unwrap_or(mirostat.eta);
+            config.mirostat = Some(mirostat);
+        }
+        if let Some(range) = self.dynatemp_range {
+            config.dynatemp_range = range;
+        }
+
+        for stage in config.chain.iter_mut().flatten() {
+            match stage {
+                SamplerStage::Temp { t } | SamplerStage::TempExt { t, .. } => {
+                    *t = self.temperature.unwrap_or(*t)
+                }
+                SamplerStage::TopK { k } => *k = self.top_k.unwrap_or(*k),
+                SamplerStage::TopP { p } => *p = self.top_p.unwrap_or(*p),
+                SamplerStage::Penalties { repeat, .. } => {
+                    *repeat = self.repeat_penalty.unwrap_or(*repeat)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Cli {
+    /// Parse arguments, excluding the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut cli = Cli::default();
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let arg = if arg == "-o" { "--output".into() } else { arg };
+            let Some(flag) = arg.strip_prefix("--") else {
+                positional.push(arg);
+                continue;
+            };
+
+            // Accept both `--flag value` and `--flag=value`.
+            let (name, inline) = match flag.split_once('=') {
+                Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                None => (flag.to_string(), None),
+            };
+            let mut value = || -> Result<String> {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .with_context(|| format!("missing value for --{name}"))
+            };
+
+            match name.as_str() {
+                "temp" => cli.judge_sampler.temperature = Some(parse(&name, &value()?)?),
+                "top-k" => cli.judge_sampler.top_k = Some(parse(&name, &value()?)?),
+                "top-p" => cli.judge_sampler.top_p = Some(parse(&name, &value()?)?),
+                "repeat-penalty" => {
+                    cli.judge_sampler.repeat_penalty = Some(parse(&name, &value()?)?)
+                }
+                "chat-temp" => cli.chat_sampler.temperature = Some(parse(&name, &value()?)?),
+                "chat-top-k" => cli.chat_sampler.top_k = Some(parse(&name, &value()?)?),
+                "chat-top-p" => cli.chat_sampler.top_p = Some(parse(&name, &value()?)?),
+                "chat-mirostat" => cli.chat_sampler.mirostat_tau = Some(parse(&name, &value()?)?),
+                "chat-mirostat-eta" => {
+                    cli.chat_sampler.mirostat_eta = Some(parse(&name, &value()?)?)
+                }
+                "chat-dynatemp" => cli.chat_sampler.dynatemp_range = Some(parse(&name, &value()?)?),
+                "ensemble" => cli.ensemble = Some(parse(&name, &value()?)?),
+                "seed" => cli.seed = Some(parse(&name, &value()?)?),
+                "strict" if inline.is_none() => cli.strict = true,
+                "ambient" if inline.is_none() => cli.ambient = true,
+                "adaptive" if inline.is_none() => cli.adaptive = true,
+                "narrate" if inline.is_none() => cli.narrate = true,
+                "speak" if inline.is_none() => cli.speak = true,
+                "listen" if inline.is_none() => cli.listen = true,
+                "language-hints" if inline.is_none() => cli.language_hints = true,
+                "fast" if inline.is_none() => cli.fast = true,
+                "lang-matrix" if inline.is_none() => cli.lang_matrix = true,
+                "plain" if inline.is_none() => cli.plain = true,
+                "no-color" if inline.is_none() => cli.no_color = true,
+                "no-clock" if inline.is_none() => cli.no_clock = true,
+                "migrate" if inline.is_none() => cli.migrate = true,
+                "no-stats" if inline.is_none() => cli.no_stats = true,
+                "stats" => cli.stats_path = Some(value()?),
+                "power-save" if inline.is_none() => cli.power_save = true,
+                "io" => cli.io = parse(&name, &value()?)?,
+                "difficulty" => cli.difficulty = parse(&name, &value()?)?,
+                "lang" => cli.lang = Some(value()?),
+                "cps" => cli.cps = Some(parse(&name, &value()?)?),
+                "games" => cli.games = Some(parse(&name, &value()?)?),
+                "traveller" => cli.traveller = Some(parse(&name, &value()?)?),
+                "max-turns" => cli.max_turns = Some(parse(&name, &value()?)?),
+                "allow" => cli.allow.extend(permissions::parse_allowed(&value()?)?),
+                "players" => {
+                    cli.players = value()?
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .collect()
+                }
+                "output" => cli.output_path = Some(value()?),
+                "format" => cli.graph_format = Some(value()?),
+                "port" => cli.port = Some(parse(&name, &value()?)?),
+                "sessions" => cli.sessions = Some(parse(&name, &value()?)?),
+                "turns" => cli.turns = Some(parse(&name, &value()?)?),
+                "supervisor-model" => cli.supervisor_model = Some(value()?),
+                "model-alias" => {
+                    let value = value()?;
+                    let Some((alias, path)) = value.split_once('=') else {
+                        bail!("invalid value '{value}' for --{name} (expected <name>=<path>)");
+                    };
+                    cli.model_aliases
+                        .push((alias.to_string(), path.to_string()));
+                }
+                "report" => cli.report_paths.push(value()?),
+                "corrections" => cli.corrections_path = Some(value()?),
+                "config" => cli.config_path = Some(value()?),
+                "hw" => cli.hw = Some(value()?),
+                "resume" => cli.resume_path = Some(value()?),
+                "log-dir" => cli.log_dir = Some(value()?),
+                "scenario" => cli.scenario_path = Some(value()?),
+                _ => bail!("unknown option --{name}\n\n{USAGE}"),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        match positional.next().as_deref() {
+            Some("replay") => {
+                let log_path = positional.next().context(USAGE)?;
+                cli.command = Command::Replay { log_path };
+                cli.model_path = positional.next();
+            }
+            Some("eval") => {
+                let suite_path = positional.next().context(USAGE)?;
+                cli.command = Command::Eval { suite_path };
+                cli.model_path = Some(positional.next().context(USAGE)?);
+            }
+            Some("loadtest") => {
+                cli.command = Command::Loadtest {
+                    url: positional.next(),
+                };
+            }
+            Some(command @ ("graph" | "lint")) => {
+                cli.command = match command {
+                    "lint" => Command::Lint,
+                    _ => Command::Graph,
+                };
+                if cli.scenario_path.is_none() {
+                    cli.scenario_path = Some(positional.next().context(USAGE)?);
+                }
+            }
+            Some(command @ ("simulate" | "serve" | "discord" | "telegram")) => {
+                cli.command = match command {
+                    "serve" => Command::Serve,
+                    "discord" => Command::Discord,
+                    "telegram" => Command::Telegram,
+                    _ => Command::Simulate,
+                };
+                cli.model_path = Some(positional.next().context(USAGE)?);
+                if cli.scenario_path.is_none() {
+                    cli.scenario_path = Some(positional.next().context(USAGE)?);
+                }
+            }
+            Some(model_path) => {
+                cli.model_path = Some(model_path.to_string());
+                if cli.scenario_path.is_none() {
+                    cli.scenario_path = positional.next();
+                }
+                if cli.scenario_path.is_none() && cli.resume_path.is_none() {
+                    bail!(USAGE);
+                }
+            }
+            None => bail!(USAGE),
+        }
+        if let Some(extra) = positional.next() {
+            bail!("unexpected argument '{extra}'\n\n{USAGE}");
+        }
+
+        Ok(cli)
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("invalid value '{value}' for --{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_positional_and_flags() {
+        let cli = Cli::parse(args("m.gguf s.json --temp 0.2 --top-k=5 --seed 7")).unwrap();
+        assert_eq!(cli.command, Command::Play);
+        assert_eq!(cli.model_path.as_deref(), Some("m.gguf"));
+        assert_eq!(cli.scenario_path.as_deref(), Some("s.json"));
+        assert_eq!(cli.judge_sampler.temperature, Some(0.2));
+        assert_eq!(cli.judge_sampler.top_k, Some(5));
+        assert_eq!(cli.seed, Some(7));
+        assert_eq!(cli.chat_sampler.temperature, None);
+        assert_eq!(
+            Cli::parse(args("m.gguf s.json --ensemble 3"))
+                .unwrap()
+                .ensemble,
+            Some(3)
+        );
+        assert!(!cli.plain);
+        assert_eq!(
+            Cli::parse(args("m.gguf s.json --lang de"))
+                .unwrap()
+                .lang
+                .as_deref(),
+            Some("de")
+        );
+        assert!(Cli::parse(args("m.gguf s.json --plain")).unwrap().plain);
+        assert_eq!(cli.io, Io::Terminal);
+        assert_eq!(
+            Cli::parse(args("m.gguf s.json --io jsonl")).unwrap().io,
+            Io::Jsonl
+        );
+        assert!(Cli::parse(args("m.gguf s.json --io xml")).is_err());
+        assert!(
+            Cli::parse(args("m.gguf s.json --power-save"))
+                .unwrap()
+                .power_save
+        );
+        assert_eq!(
+            Cli::parse(args("m.gguf s.json --hw laptop"))
+                .unwrap()
+                .hw
+                .as_deref(),
+            Some("laptop")
+        );
+    }
+
+    #[test]
+    fn test_parse_replay() {
+        let cli = Cli::parse(args("replay session.jsonl")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Replay {
+                log_path: "session.jsonl".into()
+            }
+        );
+        assert_eq!(cli.model_path, None);
+        let cli = Cli::parse(args("replay session.jsonl m.gguf")).unwrap();
+        assert_eq!(cli.model_path.as_deref(), Some("m.gguf"));
+    }
+
+    #[test]
+    fn test_parse_simulate_and_eval() {
+        let cli = Cli::parse(args("simulate m.gguf s.json --games 5 --traveller hostile")).unwrap();
+        assert_eq!(cli.command, Command::Simulate);
+        assert_eq!(cli.scenario_path.as_deref(), Some("s.json"));
+        assert_eq!(cli.games, Some(5));
+        assert_eq!(cli.traveller, Some(Traveller::Hostile));
+        assert!(Cli::parse(args("simulate m.gguf s.json --traveller grumpy")).is_err());
+
+        let cli = Cli::parse(args("eval tests.yaml m.gguf")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Eval {
+                suite_path: "tests.yaml".into()
+            }
+        );
+        assert_eq!(cli.model_path.as_deref(), Some("m.gguf"));
+        assert!(!cli.strict);
+        assert!(
+            Cli::parse(args("eval tests.yaml m.gguf --strict"))
+                .unwrap()
+                .strict
+        );
+        let cli = Cli::parse(args("eval t.yaml m.gguf --report r.html --report r.csv")).unwrap();
+        assert_eq!(cli.report_paths, ["r.html", "r.csv"]);
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        let cli = Cli::parse(args("serve m.gguf s.json --port 9000")).unwrap();
+        assert_eq!(cli.command, Command::Serve);
+        assert_eq!(cli.model_path.as_deref(), Some("m.gguf"));
+        assert_eq!(cli.scenario_path.as_deref(), Some("s.json"));
+        assert_eq!(cli.port, Some(9000));
+        assert!(Cli::parse(args("serve m.gguf s.json --port 99999")).is_err());
+        let cli = Cli::parse(args("discord m.gguf builtin:interview")).unwrap();
+        assert_eq!(cli.command, Command::Discord);
+        assert_eq!(cli.scenario_path.as_deref(), Some("builtin:interview"));
+        let cli = Cli::parse(args("telegram m.gguf s.json")).unwrap();
+        assert_eq!(cli.command, Command::Telegram);
+    }
+
+    #[test]
+    fn test_parse_supervisor_model() {
+        let cli = Cli::parse(args("m.gguf s.json --supervisor-model big.gguf")).unwrap();
+        assert_eq!(cli.supervisor_model.as_deref(), Some("big.gguf"));
+        let cli = Cli::parse(args(
+            "m.gguf s.json --model-alias big=big.gguf --model-alias=tiny=tiny.gguf",
+        ))
+        .unwrap();
+        assert_eq!(
+            cli.model_aliases,
+            [
+                ("big".to_string(), "big.gguf".to_string()),
+                ("tiny".to_string(), "tiny.gguf".to_string())
+            ]
+        );
+        assert!(Cli::parse(args("m.gguf s.json --model-alias big.gguf")).is_err());
+        let cli = Cli::parse(args("m.gguf s.json --players Ann,Ben")).unwrap();
+        assert_eq!(cli.players, ["Ann", "Ben"]);
+        let cli = Cli::parse(args("m.gguf s.json --allow tool-use --allow scripting")).unwrap();
+        assert_eq!(cli.allow, [Capability::ToolUse, Capability::Scripting]);
+        let cli = Cli::parse(args("eval t.yaml m.gguf --corrections fix.jsonl")).unwrap();
+        assert_eq!(cli.corrections_path.as_deref(), Some("fix.jsonl"));
+        assert_eq!(cli.model_path.as_deref(), Some("m.gguf"));
+    }
+
+    #[test]
+    fn test_parse_graph() {
+        let cli = Cli::parse(args("graph s.json -o s.dot")).unwrap();
+        assert_eq!(cli.command, Command::Graph);
+        assert_eq!(cli.scenario_path.as_deref(), Some("s.json"));
+        assert_eq!(cli.output_path.as_deref(), Some("s.dot"));
+        assert_eq!(cli.model_path, None);
+        let cli = Cli::parse(args("lint s.json")).unwrap();
+        assert_eq!(cli.command, Command::Lint);
+        assert_eq!(cli.scenario_path.as_deref(), Some("s.json"));
+    }
+
+    #[test]
+    fn test_parse_loadtest() {
+        let cli = Cli::parse(args("loadtest --sessions 4 --turns 3")).unwrap();
+        assert_eq!(cli.command, Command::Loadtest { url: None });
+        assert_eq!((cli.sessions, cli.turns), (Some(4), Some(3)));
+        assert_eq!(cli.model_path, None);
+        let cli = Cli::parse(args("loadtest http://box:9000")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Loadtest {
+                url: Some("http://box:9000".into())
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_flag() {
+        assert!(Cli::parse(args("m.gguf s.json --bogus 1")).is_err());
+        assert!(Cli::parse(args("m.gguf")).is_err());
+        assert!(Cli::parse(args("m.gguf --resume save.json")).is_ok());
+        assert!(
+            Cli::parse(args("m.gguf --resume save.json --migrate"))
+                .unwrap()
+                .migrate
+        );
+    }
+}
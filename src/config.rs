@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::Deserialize;
+
+use crate::game::keymap::Keymap;
+use crate::game::pacing::PacingConfig;
+use crate::llm::sampler::{validate_chain, SamplerStage};
+use crate::llm::{CacheType, ModelConfig};
+use crate::persistence::RetentionPolicy;
+#[cfg(feature = "voice")]
+use crate::voice::VoiceConfig;
+
+/// Config file looked up in the working directory when `--config` is not given.
+pub const DEFAULT_CONFIG_PATH: &str = "elsa.toml";
+
+/// Contents of `elsa.toml`. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Size limits and retention for journals and transcripts.
+    pub persistence: RetentionPolicy,
+    /// Explicit sampler chains for the judge, free chat and supervisor profiles.
+    pub sampler: SamplerChains,
+    /// Typing pace for guard lines.
+    pub pacing: PacingConfig,
+    /// Choice keys and TUI shortcuts.
+    pub keys: Keymap,
+    /// Text-to-speech for guard lines (`--speak`).
+    #[cfg(feature = "voice")]
+    pub voice: VoiceConfig,
+    /// Model settings per machine, by name (`--hw <name>`).
+    pub hw: BTreeMap<String, HardwareProfile>,
+}
+
+/// How to run the model on one machine, so a config synced between
+/// machines doesn't need re-tuning on each:
+///
+/// ```toml
+/// [hw.laptop]
+/// hosts = ["work-laptop"]
+/// threads = 4
+/// ctx = 4096
+/// cache_type = "q8_0"
+///
+/// [hw.desktop]
+/// hosts = ["gaming-pc"]
+/// gpu_layers = 99
+/// ```
+///
+/// Unset fields keep the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HardwareProfile {
+    /// Hostnames this profile is used on when `--hw` is not given.
+    pub hosts: Vec<String>,
+    pub threads: Option<i32>,
+    /// Layers to offload to the GPU.
+    pub gpu_layers: Option<u32>,
+    /// Context window in tokens.
+    pub ctx: Option<u32>,
+    /// KV cache precision, e.g. `"q8_0"` to halve its memory.
+    pub cache_type: Option<CacheType>,
+}
+
+impl HardwareProfile {
+    pub fn apply(&self, config: &mut ModelConfig) {
+        if let Some(threads) = self.threads {
+            config.n_threads = Some(threads);
+        }
+        if let Some(gpu_layers) = self.gpu_layers {
+            config.n_gpu_layers = gpu_layers;
+        }
+        if let Some(ctx) = self.ctx {
+            config.n_ctx = ctx;
+        }
+        if let Some(cache_type) = self.cache_type {
+            config.cache_type = Some(cache_type);
+        }
+    }
+
+    /// Whether the profile lists `host`. Names match regardless of case,
+    /// and with or without a domain (`gaming-pc.local`).
+    fn matches(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        let short = host.split('.').next().unwrap_or_default();
+        self.hosts.iter().any(|listed| {
+            let listed = listed.to_lowercase();
+            listed == host || listed == short
+        })
+    }
+}
+
+/// ```toml
+/// [sampler]
+/// judge = [{ type = "top_k", k = 40 }, { type = "temp", t = 0.2 }, { type = "dist" }]
+/// chat = [{ type = "temp_ext", t = 0.8, delta = 0.3 }, { type = "mirostat_v2", tau = 5.0, eta = 0.1 }]
+/// supervisor = [{ type = "top_k", k = 1 }, { type = "dist" }]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplerChains {
+    pub judge: Option<Vec<SamplerStage>>,
+    pub chat: Option<Vec<SamplerStage>>,
+    /// Supervisor judging appeals.
+    pub supervisor: Option<Vec<SamplerStage>>,
+}
+
+impl Config {
+    /// Load the config from an explicit path, or from `elsa.toml` if it
+    /// exists. A missing default file yields the built-in defaults.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(p) => p,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH,
+            None => return Ok(Self::default()),
+        };
+
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        let config: Config =
+            toml::from_str(&text).with_context(|| format!("failed to parse {path}"))?;
+        for (name, chain) in [
+            ("judge", &config.sampler.judge),
+            ("chat", &config.sampler.chat),
+            ("supervisor", &config.sampler.supervisor),
+        ] {
+            if let Some(chain) = chain {
+                validate_chain(chain)
+                    .with_context(|| format!("invalid [sampler] {name} chain in {path}"))?;
+            }
+        }
+        config
+            .keys
+            .validate()
+            .with_context(|| format!("invalid [keys] in {path}"))?;
+
+        for (name, profile) in &config.hw {
+            if profile.ctx == Some(0) {
+                bail!("invalid [hw.{name}] in {path}: ctx must be above 0");
+            }
+        }
+
+        info!("Loaded config from {path}");
+        Ok(config)
+    }
+
+    /// The hardware profile to run with: the one named by `--hw`, or else
+    /// the first (by name) that lists this machine's hostname.
+    pub fn hardware(&self, name: Option<&str>) -> Result<Option<(&str, &HardwareProfile)>> {
+        pick_hardware(&self.hw, name, hostname().as_deref())
+    }
+}
+
+fn pick_hardware<'a>(
+    profiles: &'a BTreeMap<String, HardwareProfile>,
+    name: Option<&str>,
+    host: Option<&str>,
+) -> Result<Option<(&'a str, &'a HardwareProfile)>> {
+    if let Some(name) = name {
+        return match profiles.get_key_value(name) {
+            Some((name, profile)) => Ok(Some((name, profile))),
+            None if profiles.is_empty() => {
+                bail!("no hardware profile '{name}': the config has no [hw] profiles")
+            }
+            None => {
+                let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                bail!(
+                    "no hardware profile '{name}' (expected one of {})",
+                    names.join(", ")
+                )
+            }
+        };
+    }
+    let Some(host) = host else {
+        return Ok(None);
+    };
+    Ok(profiles
+        .iter()
+        .find(|(_, profile)| profile.matches(host))
+        .map(|(name, profile)| (name.as_str(), profile)))
+}
+
+/// This machine's name, from the environment or the `hostname` command.
+fn hostname() -> Option<String> {
+    let from_env = ["COMPUTERNAME", "HOSTNAME"]
+        .into_iter()
+        .find_map(|var| std::env::var(var).ok());
+    let name = from_env.or_else(|| {
+        let output = Command::new("hostname").output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    })?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_hardware() {
+        let profiles = BTreeMap::from([
+            (
+                "desktop".to_string(),
+                HardwareProfile {
+                    hosts: vec!["Gaming-PC".into()],
+                    gpu_layers: Some(99),
+                    ..Default::default()
+                },
+            ),
+            (
+                "laptop".to_string(),
+                HardwareProfile {
+                    hosts: vec!["work-laptop".into()],
+                    threads: Some(4),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let picked = |name, host| {
+            pick_hardware(&profiles, name, host)
+                .unwrap()
+                .map(|(name, _)| name)
+        };
+        assert_eq!(picked(None, Some("gaming-pc.local")), Some("desktop"));
+        assert_eq!(picked(None, Some("work-laptop")), Some("laptop"));
+        assert_eq!(picked(None, Some("work")), None);
+        assert_eq!(picked(None, None), None);
+        // --hw wins over the hostname.
+        assert_eq!(picked(Some("laptop"), Some("gaming-pc")), Some("laptop"));
+        let err = pick_hardware(&profiles, Some("server"), None).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("(expected one of desktop, laptop)"));
+
+        let mut config = ModelConfig::default();
+        profiles["laptop"].apply(&mut config);
+        assert_eq!((config.n_threads, config.n_gpu_layers), (Some(4), 0));
+    }
+}
@@ -1,17 +1,99 @@
-use std::io::{self, Write};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::Result;
-use log::{debug, info, warn};
+use anyhow::{bail, Result};
+use log::{info, warn};
 
+#[cfg(feature = "voice")]
+use crate::voice::{self, SentenceChunker};
 use crate::{
     game::node::NodeType,
-    llm::{ChatMessage, LLM},
+    llm::{strip_think, ChatMessage, LlmDecision, Usage, LLM},
+    persistence::RetentionPolicy,
+    rng::Rng,
 };
-use node::GameNode;
+use ambient::Ambient;
+use beats::Beats;
+use budget::{PromptBudget, Section};
+use command::Command;
+use corrections::Corrections;
+use difficulty::Difficulty;
+use instruction::{estimate_tokens, JudgeInstruction, Slot};
+use keymap::Keymap;
+use locale::{fill, Messages};
+use memory::Memory;
+use node::{Appeal, GameNode};
+use party::Party;
+use report_card::ReportCard;
+use save::{Compatibility, SaveFile, SavedState};
+use session::{Judge, JudgeRequest, Reply, Session};
+use setting::Setting;
+use transcript::TranscriptLog;
 use tree::GameTree;
+use ui::Ui;
+use vars::Vars;
 
+pub mod ambient;
+pub mod beats;
+#[cfg(any(feature = "discord", feature = "telegram"))]
+pub mod bot;
+pub mod budget;
+pub mod classifier;
+pub mod command;
+pub mod corrections;
+pub mod difficulty;
+#[cfg(feature = "discord")]
+pub mod discord;
+pub mod engine;
+pub mod eval;
+pub mod graph;
+pub mod history;
+pub mod instruction;
+pub mod jsonl;
+pub mod keymap;
+pub mod language;
+#[cfg(feature = "readline")]
+pub mod line_editor;
+pub mod lint;
+#[cfg(feature = "loadtest")]
+pub mod loadtest;
+pub mod locale;
+pub mod memory;
 pub mod node;
+pub mod pacing;
+pub mod party;
+pub mod permissions;
+pub mod persona;
+pub mod plan;
+pub mod replay;
+pub mod report;
+pub mod report_card;
+#[cfg(test)]
+mod roundtrip;
+pub mod save;
+pub mod scenarios;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+pub mod setting;
+pub mod shift;
+pub mod simulate;
+pub mod stats;
+#[cfg(feature = "server")]
+pub mod stream;
+pub mod suspicion;
+#[cfg(feature = "telegram")]
+pub mod telegram;
+pub mod template;
+pub mod transcript;
 pub mod tree;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod ui;
+pub mod vars;
+#[cfg(any(feature = "server", feature = "discord", feature = "telegram"))]
+pub mod worker;
 
 // ---------------------------------------------------------------------------
 // Game state
@@ -26,22 +108,154 @@ struct GameState {
     conversation: Vec<ChatMessage>,
     /// Number of non-terminal steps the player has completed.
     steps_completed: usize,
+    /// Picks among a node's transcript variants.
+    rng: Rng,
+    /// `rng` as the current node's line was picked, for snapshots.
+    line_rng: Rng,
+    /// Variables set by the nodes entered so far.
+    vars: Vars,
+    /// Reading of the scenario's suspicion meter, if it has one.
+    suspicion: i32,
+    /// Times each node was entered this round.
+    visits: BTreeMap<String, usize>,
+    /// Points collected this round.
+    score: i32,
+    /// Passes (positive) or failures (negative) in a row.
+    streak: i32,
+    /// Solid answers in a row toward the suspicion meter's decay.
+    calm: usize,
 }
 
 impl GameState {
-    fn new(tree: GameTree) -> Self {
+    fn new(tree: GameTree, seed: u64) -> Self {
         let start_id = tree.start_node_id.clone();
         Self {
             tree,
             current_node_id: start_id,
             conversation: Vec::new(),
             steps_completed: 0,
+            rng: Rng::new(seed),
+            line_rng: Rng::new(seed),
+            vars: Vars::new(),
+            suspicion: 0,
+            visits: BTreeMap::new(),
+            score: 0,
+            streak: 0,
+            calm: 0,
         }
     }
 
+    fn resumed(tree: GameTree, saved: SavedState, seed: u64) -> Self {
+        Self {
+            current_node_id: saved.current_node_id,
+            conversation: saved.conversation,
+            steps_completed: saved.steps_completed,
+            vars: saved.vars,
+            suspicion: saved.suspicion,
+            visits: saved.visits,
+            score: saved.score,
+            streak: saved.streak,
+            calm: saved.calm,
+            ..Self::new(tree, saved.rng.unwrap_or(seed))
+        }
+    }
+
+    /// `vars` as edge conditions see them: with the game's own readings
+    /// `suspicion` and `score` added, unless the scenario sets variables of
+    /// those names itself.
+    fn condition_vars(&self) -> Vars {
+        let mut vars = self.vars.clone();
+        vars.entry("suspicion".to_string())
+            .or_insert(self.suspicion.into());
+        vars.entry("score".to_string()).or_insert(self.score.into());
+        vars
+    }
+
     fn current_node(&self) -> &GameNode {
         self.tree.get(&self.current_node_id).unwrap()
     }
+
+    /// Snapshot taken while waiting for the player's answer. The current
+    /// node's line is left out because it is re-emitted when the node is
+    /// entered again on resume.
+    fn snapshot(&self) -> SavedState {
+        let mut conversation = self.conversation.clone();
+        conversation.pop();
+        // Likewise, the visit is counted again, and so are the node's
+        // points if it was the first.
+        let mut visits = self.visits.clone();
+        let mut score = self.score;
+        if let Some(count) = visits.get_mut(&self.current_node_id) {
+            *count -= 1;
+            if *count == 0 {
+                score -= self.current_node().points.unwrap_or(0);
+            }
+        }
+        visits.retain(|_, count| *count > 0);
+        SavedState {
+            current_node_id: self.current_node_id.clone(),
+            conversation,
+            steps_completed: self.steps_completed,
+            vars: self.vars.clone(),
+            suspicion: self.suspicion,
+            visits,
+            score,
+            streak: self.streak,
+            calm: self.calm,
+            rng: Some(self.line_rng.state()),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Run options
+// ---------------------------------------------------------------------------
+
+/// Default file written by the in-game `/save` command.
+const DEFAULT_SAVE_PATH: &str = "elsa-save.json";
+
+/// Options for a play session.
+pub struct RunOptions {
+    /// Run seed; every round's RNG derives from it.
+    pub seed: u64,
+    /// Where the scenario was loaded from, recorded in save files so that
+    /// `--resume` can find it again.
+    pub scenario_path: Option<String>,
+    /// Save to resume the first round from.
+    pub resume: Option<SaveFile>,
+    /// Whether to migrate a save made with an older version of the
+    /// scenario (`--migrate`); `None` asks the player.
+    pub migrate: Option<bool>,
+    /// Directory for per-session JSONL transcripts. `None` disables logging.
+    pub log_dir: Option<PathBuf>,
+    /// Rotation policy for transcript files.
+    pub retention: RetentionPolicy,
+    /// End the session on judge failures and fallback transitions instead
+    /// of recovering.
+    pub strict: bool,
+    /// Show other travellers being processed while the judge thinks.
+    pub ambient: bool,
+    /// Failure library to draw corrective judge examples from.
+    pub corrections: Option<Corrections>,
+    /// Couch co-op: players taking turns answering.
+    pub party: Option<Party>,
+    /// Let the local time of day pick the guard's shift (see
+    /// [`shift::Shift`]). Off for deterministic runs.
+    pub clock: bool,
+    /// File keeping the player's per-scenario stats. `None` disables them.
+    pub stats: Option<PathBuf>,
+    pub difficulty: Difficulty,
+    /// Adapt the difficulty to the player's streak (see
+    /// [`Difficulty::adapted`]).
+    pub adaptive: bool,
+    /// Have the chat model rewrite guard lines in the persona's voice.
+    pub narrate: bool,
+    /// Tell the judge about answers in another language than this
+    /// `--lang` code.
+    pub language_hints: Option<String>,
+    /// Speedrun play: no small talk, follow-ups or waiting beats, and a
+    /// lean judge that picks by number where it can.
+    pub fast: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -50,57 +264,346 @@ impl GameState {
 
 /// Short, direct system prompt. Small models do better with brief instructions
 /// that don't contain meta-commentary about rules or JSON schemas.
-const SYSTEM_PROMPT: &str = "\
-You are a border security guard at an airport. You are having a conversation with a traveller. Your job is to categorize the Traveller's last response based on the following rules:";
+fn system_prompt(setting: &Setting) -> String {
+    if let Some(prompt) = &setting.judge_system_prompt {
+        return prompt.clone();
+    }
+    format!(
+        "{} Your job is to categorize the {}'s last response based on the following rules:",
+        setting.role, setting.player
+    )
+}
+
+/// Decode the judge's role, the start of every judge prompt, into the
+/// model's KV cache once up front. Failing only loses the saving.
+fn prime_judge(model: &mut LLM, tree: &GameTree) {
+    if let Err(e) = model.prime(&system_prompt(&tree.setting())) {
+        warn!("Could not prime the judge prompt: {e:#}");
+    }
+}
+
+/// Build the complete message list for an LLM judge call. `instruction`
+/// carries the lines other subsystems contribute for this turn. The
+/// conversation and the instruction lines share the default
+/// [`PromptBudget`]. Fails unless `historical_node` is a decision node.
+fn build_judge_messages(
+    conversation: &[ChatMessage],
+    historical_node: &GameNode,
+    setting: &Setting,
+    mut instruction: JudgeInstruction,
+) -> Result<Vec<ChatMessage>> {
+    // Fast play judges the last exchange alone.
+    let conversation = if instruction.is_fast() {
+        &conversation[conversation.len().saturating_sub(2)..]
+    } else {
+        conversation
+    };
+    let budget = PromptBudget::default();
+    let system_tokens = estimate_tokens(&system_prompt(setting));
+    let criteria_tokens = estimate_tokens(&build_judge_instruction(
+        historical_node,
+        JudgeInstruction::new(),
+    )?);
+    let conversation_tokens: usize = conversation
+        .iter()
+        .map(|m| estimate_tokens(&m.content))
+        .sum();
+    let allocate = |instruction: &JudgeInstruction| {
+        budget.allocate(|section| match section {
+            Section::System => system_tokens,
+            Section::Criteria => criteria_tokens,
+            Section::History => conversation_tokens,
+            Section::Facts | Section::Examples => instruction.demand(section),
+        })
+    };
+
+    // The history is cut exactly when it is over its share; the note about
+    // the cut is then charged to the facts before sharing out again.
+    let mut allocation = allocate(&instruction);
+    if conversation.len() > 2 && conversation_tokens > allocation.get(Section::History) {
+        instruction.add(
+            Slot::State,
+            "history",
+            usize::MAX,
+            [history::JUDGE_NOTE.to_string()],
+        );
+        allocation = allocate(&instruction);
+    }
+    let history = history::window(conversation, allocation.get(Section::History));
+    instruction.limit(allocation);
 
-/// Build the complete message list for an LLM judge call.
-fn build_judge_messages(state: &GameState, historical_node: &GameNode) -> Vec<ChatMessage> {
     let mut messages = Vec::new();
 
     // 1. General system prompt + Judge instructions
     messages.push(ChatMessage::system(format!(
-        "{SYSTEM_PROMPT} \n {}",
-        build_judge_instruction(historical_node)
+        "{} \n {}",
+        system_prompt(setting),
+        build_judge_instruction(historical_node, instruction)?
     )));
 
-    // 2. Conversation so far (assistant + user turns only)
-    messages.extend(state.conversation.clone());
+    // 2. Conversation so far (assistant + user turns only), with older
+    //    turns replaced by an explicit marker if it is over budget
+    messages.extend(history.messages);
 
-    messages
+    Ok(messages)
 }
 
 /// Build the judge instruction. Kept short and direct:
 /// - States what the guard just asked
 /// - Lists the PASS option first, then the FAIL option
 /// - Asks for a one-line JSON
-fn build_judge_instruction(node: &GameNode) -> String {
+///
+/// Only decision nodes are judged; any other node is an error.
+fn build_judge_instruction(node: &GameNode, mut instruction: JudgeInstruction) -> Result<String> {
     match node.node_type.clone() {
-        NodeType::Terminal(_) => unimplemented!(),
+        NodeType::Random(_) => {
+            bail!(
+                "node '{}' picks a branch at random and is never judged",
+                node.id
+            )
+        }
+        NodeType::Interlude(_) => {
+            bail!(
+                "node '{}' is an interlude, routed by the game's state and never judged",
+                node.id
+            )
+        }
+        NodeType::Terminal(_) => {
+            bail!("node '{}' ends the game and is never judged", node.id)
+        }
         NodeType::Decision(next_nodes) => {
             let mut s = String::new();
 
             if let Some(ctx) = &node.system_context {
-                s.push_str(ctx);
+                instruction.add(Slot::Scene, "node", usize::MAX, [ctx.clone()]);
+            }
+            let context = instruction.render();
+            if !context.is_empty() {
+                s.push_str(&context);
                 s.push_str("\n\n");
             }
 
+            if routes(node, &instruction) {
+                let options: Vec<String> = next_nodes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| format!("{}. {}", i + 1, n.description))
+                    .collect();
+                s.push_str(&format!(
+                    "Pick one:\n{}\nReply with the number of your pick only.",
+                    options.join("\n")
+                ));
+                return Ok(s);
+            }
+
             let options: Vec<String> = next_nodes
                 .iter()
                 .map(|n| format!("id: {}, description: {}", n.id, n.description))
                 .collect();
             let options_str = options.join(", ");
+            // Facts to extract ride along as extra keys of the same flat
+            // object.
+            let mut facts: String = node
+                .extract
+                .iter()
+                .map(|(name, what)| format!(", \"{name}\": \"<{what}, or null>\""))
+                .collect();
+            if node.contradiction.is_some() {
+                facts.push_str(&format!(
+                    ", \"{CONTRADICTS}\": <true if the last response contradicts the facts above, else false>"
+                ));
+            }
+            for (key, what) in instruction.reply_keys() {
+                facts.push_str(&format!(", \"{key}\": <{what}>"));
+            }
 
             s.push_str(&format!("
                 Pick one: {options_str}\n
-                Reply with JSON only: {{\"decision\": \"<PICK>\", \"reason\": \"<why>\"}}. JSON must be valid.
+                Reply with JSON only: {{\"decision\": \"<PICK>\", \"reason\": \"<why>\"{facts}}}. JSON must be valid.
             "));
 
-            if let Some(sc) = node.system_context.clone() {
-                s.push_str(sc.as_str());
-            }
+            Ok(s)
+        }
+    }
+}
+
+/// Whether the judge picks by number alone at `node` (see
+/// [`LLM::route`]): in fast play, where its reply needs no facts or other
+/// keys.
+fn routes(node: &GameNode, instruction: &JudgeInstruction) -> bool {
+    instruction.is_fast()
+        && node.extract.is_empty()
+        && node.contradiction.is_none()
+        && instruction.reply_keys().is_empty()
+}
+
+/// The supervisor is stricter than the guard: an appeal overturns a
+/// refusal only when the plea clearly meets the stated criteria.
+fn supervisor_prompt(setting: &Setting) -> String {
+    format!(
+        "{} Grant the appeal only if the {}'s plea clearly meets the criteria below; when in doubt, deny.",
+        setting.supervisor, setting.player
+    )
+}
+
+/// Build the message list for a `/hint` nudge at `question`. Unlike the
+/// judge, the model writes to the player: it is told what the answer
+/// should be like (`hints`) but asked to only point towards it.
+fn build_hint_messages(question: &str, hints: &[&str], setting: &Setting) -> Vec<ChatMessage> {
+    let npc = &setting.npc;
+    let prompt = format!(
+        "You coach a player in a conversation game where they play the {}. The {npc} just said: \"{question}\"\n\
+         The {npc} hopes for an answer like: {}\n\
+         In one short sentence, nudge the player towards that kind of answer. Do not quote or list what the {npc} hopes for, and do not write the answer for them. Plain text only, no quotes.",
+        setting.player,
+        hints.join("; ")
+    );
+    vec![ChatMessage::system(prompt), ChatMessage::user("Any tips?")]
+}
+
+/// Build the message list for rewriting the `line` of `npc` in the voice
+/// of the persona (`persona` is its [`persona::Persona::describe`]).
+fn build_narration_messages(line: &str, persona: &str, npc: &str) -> Vec<ChatMessage> {
+    let prompt = format!(
+        "You play the {npc} in a conversation game. {persona}\n\
+         Rewrite the line you are given as you would say it, in character. Keep its meaning and every question in it, and ask nothing new. Reply with the line only, plain text, no quotes."
+    );
+    vec![ChatMessage::system(prompt), ChatMessage::user(line)]
+}
 
-            s
+/// The conversation as a script, one "Speaker: line" per message. In
+/// scenarios with several speakers, their lines already start that way.
+fn script(conversation: &[ChatMessage], tree: &GameTree) -> Vec<String> {
+    let setting = tree.setting();
+    let npc = setting.npc_title();
+    conversation
+        .iter()
+        .map(|m| match m.role.as_str() {
+            "assistant" if !tree.speakers.is_empty() => m.content.clone(),
+            "assistant" => format!("{npc}: {}", m.content),
+            _ => format!("{}: {}", setting.player, m.content),
+        })
+        .collect()
+}
+
+/// Build the message list for the coach's feedback on a failed round: the
+/// whole conversation as a script, and what the question the player failed
+/// at was looking for.
+fn build_feedback_messages(
+    conversation: &[ChatMessage],
+    failed_at: &GameNode,
+    tree: &GameTree,
+) -> Vec<ChatMessage> {
+    let setting = tree.setting();
+    let script = script(conversation, tree);
+    let criteria: Vec<String> = match &failed_at.node_type {
+        NodeType::Decision(next) => next
+            .iter()
+            .map(|edge| match tree.get(&edge.id).map(|n| &n.node_type) {
+                Some(NodeType::Terminal(false)) => format!("- fails: {}", edge.description),
+                _ => format!("- passes: {}", edge.description),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let prompt = format!(
+        "You coach a player of a conversation game where they play the {} and talk to a {}. \
+         They just lost. The answer that lost was judged against these criteria:\n{}\n\
+         Read the conversation and, in at most three short sentences, say what went wrong and how to answer next time. \
+         Speak to the player directly. Plain text only.",
+        setting.player,
+        setting.npc,
+        criteria.join("\n")
+    );
+    vec![
+        ChatMessage::system(prompt),
+        ChatMessage::user(script.join("\n")),
+    ]
+}
+
+/// Extra key of the judge's reply at nodes with a `contradiction` edge.
+const CONTRADICTS: &str = "contradicts";
+
+/// Decisions the supervisor picks from.
+const APPEAL_GRANTED: &str = "GRANTED";
+const APPEAL_DENIED: &str = "DENIED";
+
+/// Build the message list for the supervisor's ruling on the player's plea,
+/// which is the last message of `conversation`.
+fn build_appeal_messages(
+    conversation: &[ChatMessage],
+    appeal: &Appeal,
+    setting: &Setting,
+) -> Vec<ChatMessage> {
+    let history = history::window(conversation, PromptBudget::default().history);
+    let mut instruction = format!(
+        "{}\n\nCriteria: {}\n",
+        supervisor_prompt(setting),
+        appeal.criteria
+    );
+    if history.truncated() {
+        instruction.push_str(&format!("{}\n", history::JUDGE_NOTE));
+    }
+    instruction.push_str(&format!(
+        "\nPick one: id: {APPEAL_GRANTED}, description: the plea meets the criteria, \
+         id: {APPEAL_DENIED}, description: it does not\n\
+         Reply with JSON only: {{\"decision\": \"<PICK>\", \"reason\": \"<why>\"}}. JSON must be valid."
+    ));
+
+    let mut messages = vec![ChatMessage::system(instruction)];
+    messages.extend(history.messages);
+    messages
+}
+
+// ---------------------------------------------------------------------------
+// Anomalies
+// ---------------------------------------------------------------------------
+
+/// Something the engine can recover from but that makes the judged result
+/// untrustworthy: the judge call failed, or its decision was replaced by a
+/// fallback. Strict mode turns these into fatal errors.
+#[derive(Debug)]
+struct Anomaly {
+    kind: &'static str,
+    node_id: String,
+    player_input: String,
+    valid_choices: Vec<String>,
+    detail: String,
+}
+
+impl Anomaly {
+    fn new(
+        kind: &'static str,
+        node: &GameNode,
+        input: &str,
+        choices: &[&str],
+        detail: String,
+    ) -> Self {
+        Self {
+            kind,
+            node_id: node.id.clone(),
+            player_input: input.to_string(),
+            valid_choices: choices.iter().map(|c| c.to_string()).collect(),
+            detail,
+        }
+    }
+
+    /// Fail with the diagnostic in strict mode, otherwise log it.
+    fn report(self, strict: bool) -> Result<()> {
+        if strict {
+            bail!("strict mode: {self}");
         }
+        warn!("{self}");
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} at node {}", self.kind, self.node_id)?;
+        writeln!(f, "  player input : {:?}", self.player_input)?;
+        writeln!(f, "  valid choices: {}", self.valid_choices.join(", "))?;
+        write!(f, "  detail       : {}", self.detail)
     }
 }
 
@@ -109,178 +612,729 @@ fn build_judge_instruction(node: &GameNode) -> String {
 // ---------------------------------------------------------------------------
 
 /// Outcome of a single game round.
-enum GameOutcome {
+#[derive(Debug, Clone)]
+pub enum GameOutcome {
     /// Player reached a terminal node.
     Finished {
         success: bool,
         steps_completed: usize,
         total_steps: usize,
         terminal_node_id: String,
+        /// What players are shown for the terminal node (see
+        /// [`GameTree::display_name`]).
+        ending: String,
+        /// The supervisor granted an appeal on the way.
+        appealed: bool,
+        /// After a failure: the checkpoint node the player can retry from.
+        checkpoint: Option<String>,
+        /// Final suspicion meter reading, if the scenario has a meter.
+        suspicion: Option<i32>,
+        /// Points collected, if the scenario keeps score.
+        score: Option<i32>,
+        /// The terminal node's grade for the score.
+        grade: Option<String>,
+        /// The scenario's line for this result ([`Setting::cleared`] or
+        /// [`Setting::denied`]).
+        headline: String,
+        /// Times the player asked for a hint.
+        hints: usize,
+        /// The player's record on the scenario, from [`stats`].
+        stats: Vec<String>,
     },
     /// Player typed quit mid-game.
-    Quit,
+    Quit {
+        steps_completed: usize,
+        /// [`Setting::walked_away`].
+        headline: String,
+    },
 }
 
-fn show_game_over(outcome: &GameOutcome) {
-    println!("\n========================================");
-    println!("             GAME OVER");
-    println!("========================================");
+impl GameOutcome {
+    /// Lines of the game over screen.
+    pub fn summary(&self, m: &Messages) -> Vec<String> {
+        match self {
+            GameOutcome::Finished {
+                success,
+                steps_completed,
+                total_steps,
+                ending,
+                appealed,
+                suspicion,
+                score,
+                grade,
+                headline,
+                hints,
+                stats,
+                ..
+            } => {
+                let result = if *success { m.cleared } else { m.denied };
+                let mut lines = vec![
+                    format!("{}: {result} - {headline}", m.result),
+                    format!(
+                        "{}:  {steps_completed} / {total_steps} {}",
+                        m.score, m.steps_completed
+                    ),
+                    format!("{}: {ending}", m.ended_at),
+                ];
+                if let Some(score) = score {
+                    lines.push(format!("{}: {score}", m.points));
+                }
+                if let Some(grade) = grade {
+                    lines.push(format!("{}:  {grade}", m.grade));
+                }
+                if let Some(suspicion) = suspicion {
+                    lines.push(format!("{}: {suspicion}", m.suspicion));
+                }
+                if *hints > 0 {
+                    lines.push(format!("{}:  {hints}", m.hints));
+                }
+                if *appealed {
+                    lines.push(m.appeal_granted.to_string());
+                }
+                lines.extend(stats.iter().cloned());
+                lines
+            }
+            GameOutcome::Quit { headline, .. } => vec![headline.clone()],
+        }
+    }
 
-    match outcome {
-        GameOutcome::Finished {
+    /// The post-game options, for the game over screen.
+    pub fn options(&self, keys: &Keymap, m: &Messages) -> String {
+        let mut options = vec![format!("[{}] {}", keys.restart, m.restart)];
+        if let GameOutcome::Finished {
+            checkpoint,
             success,
-            steps_completed,
-            total_steps,
-            terminal_node_id,
-        } => {
-            if *success {
-                println!("  Result: CLEARED - You passed border control!");
-            } else {
-                println!("  Result: DENIED - You were stopped at the border.");
+            ..
+        } = self
+        {
+            if checkpoint.is_some() {
+                options.push(format!("[{}] {}", keys.retry, m.retry));
+            }
+            if checkpoint.is_some() || !success {
+                options.push(format!("[{}] {}", keys.feedback, m.feedback));
             }
-            println!(
-                "  Score:  {} / {} steps completed",
-                steps_completed, total_steps
-            );
-            println!("  Ended at: {}", terminal_node_id);
-        }
-        GameOutcome::Quit => {
-            println!("  You walked away from the border control booth.");
         }
+        options.push(format!("[{}] {}", keys.quit, m.quit));
+        options.join("    ")
     }
+}
 
-    println!("========================================\n");
-    println!("  [r] Restart    [q] Quit\n");
+/// Check a save against the loaded scenario and restore it. A save made
+/// with an older version of the scenario is migrated as best it can be if
+/// `migrate` says so; when it is `None`, the player is asked through `ui`.
+pub fn resume_from(
+    save: SaveFile,
+    tree: &GameTree,
+    ui: &mut impl Ui,
+    migrate: Option<bool>,
+) -> Result<SavedState> {
+    let migrate = match save.compatibility(tree) {
+        Compatibility::Compatible => false,
+        Compatibility::Incompatible(why) => bail!("cannot resume: {why}"),
+        Compatibility::ScenarioChanged { saved, current } => match migrate {
+            Some(true) => true,
+            Some(false) => bail!(
+                "cannot resume: the scenario changed since this game was saved ({saved} -> {current}); pass --migrate to migrate the save"
+            ),
+            None => {
+                let m = ui.messages();
+                let changed = fill(
+                    m.scenario_changed,
+                    &[
+                        ("saved", &saved.to_string()),
+                        ("current", &current.to_string()),
+                    ],
+                );
+                let options = format!("  [m] {}    [q] {}", m.migrate, m.quit);
+                let hint = format!(
+                    "  {} [m] {} {} [q] {}.",
+                    m.press, m.to_migrate, m.or, m.to_quit
+                );
+                ui.notice(&changed)?;
+                ui.notice(&options)?;
+                if !ui.choose('m', 'q', &hint)? {
+                    bail!("resume cancelled");
+                }
+                true
+            }
+        },
+    };
+    save.restore(tree, migrate)
 }
 
-/// Read the player's post-game choice. Returns `true` to restart, `false` to quit.
-fn prompt_restart() -> Result<bool> {
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        match input.trim().to_lowercase().as_str() {
-            "r" => return Ok(true),
-            "q" => return Ok(false),
-            _ => println!("  Press [r] to restart or [q] to quit."),
+/// What the player does after a round.
+enum NextRound {
+    Restart,
+    /// Play on from the failed round's checkpoint.
+    Retry,
+    /// Hear the coach on what went wrong, then choose again.
+    Feedback,
+    Quit,
+}
+
+/// How a round ended, with what the post-game choices need from it.
+struct RoundEnd {
+    outcome: GameOutcome,
+    /// State to retry from after a failure.
+    checkpoint: Option<SavedState>,
+    /// Prompt for the coach's feedback after a failure.
+    feedback: Option<Vec<ChatMessage>>,
+    /// The player's streak, carried into the next round.
+    streak: i32,
+    card: ReportCard,
+}
+
+impl RoundEnd {
+    fn new(session: &Session, outcome: GameOutcome, card: ReportCard) -> Self {
+        Self {
+            outcome,
+            checkpoint: session.checkpoint().cloned(),
+            feedback: session.feedback_messages(),
+            streak: session.streak(),
+            card,
+        }
+    }
+}
+
+/// Read the player's post-game choice. Feedback is offered while `round`
+/// still has it to give.
+fn prompt_restart(ui: &mut impl Ui, round: &RoundEnd) -> Result<NextRound> {
+    let map = ui.keys().clone();
+    let m = ui.messages();
+    let mut keys = vec![map.restart];
+    let mut hint = vec![format!("[{}] {}", map.restart, m.to_restart)];
+    if matches!(
+        round.outcome,
+        GameOutcome::Finished {
+            checkpoint: Some(_),
+            ..
         }
+    ) {
+        keys.push(map.retry);
+        hint.push(format!("[{}] {}", map.retry, m.to_retry));
+    }
+    if round.feedback.is_some() {
+        keys.push(map.feedback);
+        hint.push(format!("[{}] {}", map.feedback, m.for_feedback));
     }
+    keys.push(map.quit);
+    let hint = format!(
+        "  {} {} {} [{}] {}.",
+        m.press,
+        hint.join(", "),
+        m.or,
+        map.quit,
+        m.to_quit
+    );
+    Ok(match ui.pick(&keys, &hint)? {
+        key if key == map.restart => NextRound::Restart,
+        key if key == map.retry => NextRound::Retry,
+        key if key == map.feedback => NextRound::Feedback,
+        _ => NextRound::Quit,
+    })
 }
 
 // ---------------------------------------------------------------------------
 // Single game round
 // ---------------------------------------------------------------------------
 
-fn play_round(model: &mut LLM, tree: &GameTree) -> Result<GameOutcome> {
-    let mut state = GameState::new(tree.clone());
-    let total_steps = tree.total_steps();
-
-    info!("Game started. Initial node: {}", state.current_node_id);
+#[allow(clippy::too_many_arguments)]
+fn play_round(
+    ui: &mut impl Ui,
+    model: &mut LLM,
+    tree: &GameTree,
+    options: &RunOptions,
+    seed: u64,
+    resume: Option<SavedState>,
+    streak: Option<i32>,
+    mut log: Option<&mut TranscriptLog>,
+    ambient: Option<&Ambient>,
+    mut corrections: Option<&mut Corrections>,
+) -> Result<RoundEnd> {
+    // Transcript variants draw from a stream of their own, so pacing jitter
+    // stays the same for a given seed.
+    let mut session = Session::new(tree.clone(), resume, options.strict, seed.rotate_left(32));
+    session.set_party(options.party.clone());
+    session.set_difficulty(options.difficulty);
+    if let Some(lang) = &options.language_hints {
+        session.set_language_hints(lang);
+    }
+    if options.fast {
+        session.set_fast();
+    }
+    if options.adaptive {
+        session.set_adaptive(streak);
+    }
+    if options.clock {
+        session.clock_in(shift::local_minute());
+    }
+    let mut memory = tree
+        .persona
+        .as_ref()
+        .is_some_and(|p| p.callbacks)
+        .then(Memory::default);
+    // Presentation-side randomness (pacing jitter), derived from the run seed.
+    let mut rng = Rng::new(seed);
+    let beats = Beats::new(if options.fast {
+        Vec::new()
+    } else {
+        tree.setting().beats
+    });
+    prime_judge(model, tree);
+    // Judged turns of the round, for the report card.
+    let mut path = Vec::new();
 
     loop {
-        let node = state.current_node().clone();
-        info!("Current node: {}. node_type {:?}", node.id, node.node_type);
-
-        println!("\n{}", node.transcript);
-
-        state
-            .conversation
-            .push(ChatMessage::assistant(&node.transcript));
-
-        match &node.node_type {
-            NodeType::Terminal(success) => {
-                info!("Game over at node: {}. success {}", node.id, success);
-                return Ok(GameOutcome::Finished {
-                    success: *success,
-                    steps_completed: state.steps_completed,
-                    total_steps,
-                    terminal_node_id: node.id.clone(),
+        ui.progress(session.steps_completed(), session.total_steps());
+        let node_id = session.node().id.clone();
+        if options.narrate {
+            narrate(ui, model, &mut session);
+        }
+        let line = &session.guard_line().display;
+        match session.npc_name() {
+            Some(name) => ui.guard_line(&node_id, &format!("{name}: {line}"), &mut rng)?,
+            None => ui.guard_line(&node_id, line, &mut rng)?,
+        }
+
+        if let Some(line) = session.appeal_line() {
+            let (appeal, accept) = (ui.keys().appeal, ui.keys().quit);
+            let m = ui.messages();
+            let hint = format!(
+                "  {} [{appeal}] {} {} [{accept}] {}.",
+                m.press, m.to_appeal, m.or, m.to_accept
+            );
+            if !ui.choose(appeal, accept, &hint)? {
+                show_latency(ui, &session, model)?;
+                let outcome = session.outcome().expect("appeals are offered at terminals");
+                return Ok(RoundEnd::new(
+                    &session,
+                    outcome,
+                    ReportCard::new(tree, &path),
+                ));
+            }
+            ui.guard_line(&node_id, &line.display, &mut rng)?;
+            let plea = loop {
+                let plea = ui.read_input()?.trim().to_string();
+                if !plea.is_empty() {
+                    break plea;
+                }
+                ui.notice(ui.messages().say_something)?;
+            };
+            let request = session.ask(&plea)?;
+            let mut judge = Waiting {
+                ui,
+                model,
+                ambient,
+                beats: &beats,
+            };
+            match session.settle(request, &mut judge, &mut |_| {})? {
+                Some(Reply::Appealed { granted, record }) => {
+                    ui.decision(&record)?;
+                    path.push(record.clone());
+                    if let Some(log) = log.as_deref_mut() {
+                        log.turn(record)?;
+                    }
+                    if granted {
+                        ui.notice(ui.messages().appeal_upheld)?;
+                    } else {
+                        ui.notice(ui.messages().appeal_rejected)?;
+                    }
+                }
+                _ => ui.notice(ui.messages().appeal_unclear)?,
+            }
+            continue;
+        }
+
+        if let Some(outcome) = session.outcome() {
+            show_latency(ui, &session, model)?;
+            return Ok(RoundEnd::new(
+                &session,
+                outcome,
+                ReportCard::new(tree, &path),
+            ));
+        }
+
+        if let Some(speaker) = session.speaker() {
+            ui.notice(&fill(ui.messages().turn, &[("speaker", speaker)]))?;
+        }
+        let input = ui.read_input()?;
+        let input = input.trim().to_string();
+
+        if input.is_empty() {
+            ui.notice(ui.messages().say_something)?;
+            continue;
+        }
+
+        let input = match Command::parse(&input) {
+            None => input,
+            Some(Command::Talk) => match ui.listen()? {
+                Some(said) => said,
+                None => continue,
+            },
+            Some(Command::Quit) => {
+                show_latency(ui, &session, model)?;
+                let outcome = GameOutcome::Quit {
+                    steps_completed: session.steps_completed(),
+                    headline: tree.setting().walked_away,
+                };
+                return Ok(RoundEnd {
+                    outcome,
+                    checkpoint: None,
+                    feedback: None,
+                    streak: session.streak(),
+                    card: ReportCard::new(tree, &path),
                 });
             }
+            Some(command) => {
+                run_command(ui, model, &mut session, options, command)?;
+                continue;
+            }
+        };
 
-            NodeType::Decision(next_nodes) => {
-                print!("\n[You]: ");
-                io::stdout().flush()?;
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-                let input = input.trim().to_string();
+        let reading = session.lie_detector().map(|detector| {
+            model
+                .score(&session.guard_line().neutral, &input)
+                .map(|surprise| detector.reading(&surprise))
+        });
+        match reading {
+            Some(Ok(reading)) => {
+                let notice = format!("({}: {reading}%)", ui.messages().stress_reading);
+                ui.notice(&notice)?;
+                session.read_stress(reading);
+            }
+            Some(Err(e)) => warn!("Lie detector failed: {e:#}"),
+            None => {}
+        }
 
-                if input.is_empty() {
-                    println!("(Please say something.)");
-                    state.conversation.pop();
-                    continue;
+        let mut instruction = match corrections.as_deref_mut() {
+            Some(corrections) => corrections.instruction(model, &node_id, &input),
+            None => JudgeInstruction::new(),
+        };
+        if let Some(memory) = memory.as_ref().filter(|_| session.speaks_next()) {
+            memory.recall(model, &input, &mut instruction);
+        }
+        let request = session.ask_with(&input, instruction)?;
+        let mut judge = Waiting {
+            ui,
+            model,
+            ambient,
+            beats: &beats,
+        };
+        let reply = session
+            .settle(request, &mut judge, &mut |_| {})?
+            .expect("the judge on this thread takes every request");
+        match reply {
+            Reply::Retry => {
+                let notice = fill(ui.messages().say_again, &[("npc", &tree.setting().npc)]);
+                ui.notice(&notice)?;
+            }
+            Reply::Judged(record) | Reply::Appealed { record, .. } => {
+                // Turns only pass on judged answers, so a retry is the same
+                // player's again.
+                session.pass_turn();
+                if let Some(memory) = memory.as_mut() {
+                    memory.remember(model, &record.player_input);
                 }
-
-                if input.eq_ignore_ascii_case("quit") || input.eq_ignore_ascii_case("exit") {
-                    return Ok(GameOutcome::Quit);
+                ui.turn_stats(model.last_usage(), Duration::from_millis(record.latency_ms));
+                ui.decision(&record)?;
+                path.push(record.clone());
+                if let Some(log) = log.as_deref_mut() {
+                    log.turn(record)?;
                 }
+            }
+            Reply::Reask(_) => unreachable!("settled above"),
+        }
+    }
+}
 
-                info!("User input: \"{input}\"");
-
-                // Add user response to conversation
-                state.conversation.push(ChatMessage::user(&input));
-
-                // Build messages and judge
-                let messages = build_judge_messages(&state, &node);
-                debug!(
-                    "Judge messages ({} total):\n{}",
-                    messages.len(),
-                    messages
-                        .iter()
-                        .enumerate()
-                        .map(|(i, m)| format!("  msg[{i}] {m}"))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                );
+/// The judge on the game's thread, behind the UI's waiting indicator.
+struct Waiting<'a, U> {
+    ui: &'a mut U,
+    model: &'a mut LLM,
+    ambient: Option<&'a Ambient>,
+    beats: &'a Beats,
+}
+
+impl<U: Ui> Judge for Waiting<'_, U> {
+    fn decide(
+        &mut self,
+        request: &JudgeRequest,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        let model = &mut *self.model;
+        let judged = self
+            .ui
+            .waiting(self.ambient, self.beats, || request.run(model, on_piece));
+        Some((judged, self.model.last_usage()))
+    }
+
+    fn classify(&mut self, request: &JudgeRequest) -> Result<LlmDecision> {
+        let model = &mut *self.model;
+        self.ui.waiting(self.ambient, self.beats, || {
+            classifier::classify(model, request.input(), request.criteria())
+        })
+    }
+}
 
-                // Valid choices for the grammar-constrained judge
-                let valid_choices: Vec<&str> = next_nodes.iter().map(|n| n.id.as_str()).collect();
-
-                println!("\n(Thinking...)");
-                let decision = model.judge(&messages, &valid_choices)?;
-
-                // Grammar ensures decision is valid, but keep a safety check
-                if !valid_choices.contains(&decision.decision.as_str()) {
-                    warn!(
-                        "LLM chose '{}' which is not in {:?}. Falling back to first option.",
-                        decision.decision, valid_choices
-                    );
-                    let fallback = next_nodes.first().unwrap().id.clone();
-                    state.current_node_id = fallback.clone();
-                    info!("Fallback transition: {} -> {}", node.id, fallback);
-                } else {
-                    info!(
-                        "Transition: {} -> {} (reason: {})",
-                        node.id, decision.decision, decision.reason
-                    );
-                    state.current_node_id = decision.decision.clone();
+/// Carry out a meta command that keeps the round going.
+fn run_command(
+    ui: &mut impl Ui,
+    model: &mut LLM,
+    session: &mut Session,
+    options: &RunOptions,
+    command: Command,
+) -> Result<()> {
+    match command {
+        Command::Help => {
+            for line in command::HELP {
+                ui.notice(line)?;
+            }
+        }
+        Command::History => {
+            let history = session.history();
+            if history.is_empty() {
+                ui.notice(ui.messages().nothing_said)?;
+            }
+            for line in history {
+                ui.notice(&line)?;
+            }
+        }
+        Command::Hint => match session.hint_messages() {
+            Some(messages) => match chat_aloud(ui, model, &messages, None) {
+                Ok(nudge) => {
+                    let m = ui.messages();
+                    let cost = match session.charge_hint() {
+                        0 => String::new(),
+                        points => {
+                            format!(" {}", fill(m.hint_cost, &[("points", &points.to_string())]))
+                        }
+                    };
+                    let notice = format!("({}: {}{cost})", m.hint, strip_think(&nudge).trim());
+                    ui.notice(&notice)?;
+                }
+                Err(e) => {
+                    warn!("Hint failed: {e:#}");
+                    ui.notice(ui.messages().no_hint)?;
                 }
+            },
+            None => ui.notice(ui.messages().nothing_to_hint)?,
+        },
+        Command::Save(path) => {
+            let path = path.as_deref().unwrap_or(DEFAULT_SAVE_PATH);
+            let save = session.save_file(options.scenario_path.clone());
+            let notice = match save.write(Path::new(path)) {
+                Ok(()) => fill(ui.messages().saved, &[("path", path)]),
+                Err(e) => fill(ui.messages().save_failed, &[("error", &format!("{e:#}"))]),
+            };
+            ui.notice(&notice)?;
+        }
+        Command::Unknown(name) => {
+            let notice = fill(ui.messages().unknown_command, &[("name", &name)]);
+            ui.notice(&notice)?
+        }
+        Command::Talk | Command::Quit => unreachable!("handled by the round"),
+    }
+    Ok(())
+}
+
+/// Have the model say the guard line just entered in the persona's voice.
+/// Failing keeps the line as written.
+fn narrate(ui: &mut impl Ui, model: &mut LLM, session: &mut Session) {
+    let Some(messages) = session.narration_messages() else {
+        return;
+    };
+    let npc = session.npc_name();
+    let line = match chat_aloud(ui, model, &messages, npc.as_deref()) {
+        Ok(line) => Some(strip_think(&line).trim().trim_matches('"').to_string()),
+        Err(e) => {
+            warn!("Narration failed: {e:#}");
+            None
+        }
+    };
+    session.narrate(line.as_deref());
+    // Spoken while it was generated; showing it must not say it again.
+    #[cfg(feature = "voice")]
+    if line.is_some_and(|line| !line.is_empty()) {
+        if let Some(speech) = ui.speech() {
+            speech.said_ahead();
+        }
+    }
+}
+
+/// [`LLM::chat`], read out sentence by sentence as it is generated when
+/// the front end speaks (`--speak`), in `speaker`'s voice.
+#[cfg(feature = "voice")]
+fn chat_aloud(
+    ui: &mut impl Ui,
+    model: &mut LLM,
+    messages: &[ChatMessage],
+    speaker: Option<&str>,
+) -> Result<String> {
+    let Some(speech) = ui.speech() else {
+        return model.chat(messages);
+    };
+    let mut sink = speech.sink(speaker);
+    let mut chunker = SentenceChunker::new();
+    let text = model.chat_stream(messages, voice::tee_to_speech(&mut chunker, &mut sink))?;
+    if let Some(rest) = chunker.finish() {
+        voice::speak_line(&rest, &mut sink);
+    }
+    Ok(text)
+}
 
-                // Advance step counter
-                state.steps_completed += 1;
+#[cfg(not(feature = "voice"))]
+fn chat_aloud(
+    _ui: &mut impl Ui,
+    model: &mut LLM,
+    messages: &[ChatMessage],
+    _speaker: Option<&str>,
+) -> Result<String> {
+    model.chat(messages)
+}
 
-                info!("(Judge reasoning: {})", decision.reason);
+/// Count the round in the stats file and add the player's record to a
+/// finished round's summary. Failing only loses the stats.
+fn record_stats(path: &Path, scenario: Option<&str>, tree: &GameTree, outcome: &mut GameOutcome) {
+    let scenario = scenario.unwrap_or("(unknown)");
+    match stats::record(path, scenario, outcome) {
+        Ok(record) => {
+            if let GameOutcome::Finished { stats, .. } = outcome {
+                *stats = record.summary(tree);
             }
         }
+        Err(e) => warn!("Could not record stats: {e:#}"),
     }
 }
 
+/// Ask the model to coach the player on a failed round, and show its
+/// answer. Failing only loses the feedback.
+fn show_feedback(ui: &mut impl Ui, model: &mut LLM, messages: &[ChatMessage]) -> Result<()> {
+    ui.notice(ui.messages().coach_reading)?;
+    match chat_aloud(ui, model, messages, None) {
+        Ok(feedback) => {
+            for line in strip_think(&feedback).trim().lines() {
+                ui.notice(line)?;
+            }
+        }
+        Err(e) => {
+            warn!("Feedback failed: {e:#}");
+            ui.notice(ui.messages().coach_silent)?;
+        }
+    }
+    Ok(())
+}
+
+fn show_latency(ui: &mut impl Ui, session: &Session, model: &LLM) -> Result<()> {
+    for line in session.latency().lines(model.power_save(), ui.messages()) {
+        ui.notice(&line)?;
+    }
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Public entry point — runs games in a loop until the player quits
 // ---------------------------------------------------------------------------
 
-pub fn run(model: &mut LLM, tree: GameTree) -> Result<()> {
-    loop {
-        let outcome = play_round(model, &tree)?;
-        show_game_over(&outcome);
+pub fn run(
+    ui: &mut impl Ui,
+    model: &mut LLM,
+    tree: GameTree,
+    mut options: RunOptions,
+) -> Result<()> {
+    // Each round gets its own seed so restarts differ but the whole session
+    // stays reproducible from the run seed.
+    let mut rounds = Rng::new(options.seed);
+    let mut resume = options
+        .resume
+        .take()
+        .map(|save| resume_from(save, &tree, ui, options.migrate))
+        .transpose()?;
+    // Taken from the save, if any, in the first round.
+    let mut streak = None;
+    let mut corrections = options.corrections.take();
+    let mut log = match &options.log_dir {
+        Some(dir) => {
+            let log = TranscriptLog::create(
+                dir,
+                options.retention.clone(),
+                options.scenario_path.as_deref(),
+                &tree,
+                options.seed,
+            )?;
+            info!("Writing session transcript to {}", log.path().display());
+            Some(log)
+        }
+        None => None,
+    };
+    let ambient = options
+        .ambient
+        .then(|| Ambient::start(model.background(), options.seed, tree.setting().ambient));
 
-        if !prompt_restart()? {
-            println!("Thanks for playing!");
-            break;
+    'rounds: loop {
+        if let Some(log) = log.as_mut() {
+            log.start_round();
         }
+        let mut round = play_round(
+            ui,
+            model,
+            &tree,
+            &options,
+            rounds.next_u64(),
+            resume.take(),
+            streak,
+            log.as_mut(),
+            ambient.as_ref(),
+            corrections.as_mut(),
+        )?;
+        if let Some(log) = log.as_mut() {
+            match &round.outcome {
+                GameOutcome::Finished {
+                    success,
+                    steps_completed,
+                    terminal_node_id,
+                    ..
+                } => log.game_over(
+                    Some(terminal_node_id.clone()),
+                    Some(*success),
+                    *steps_completed,
+                )?,
+                GameOutcome::Quit {
+                    steps_completed, ..
+                } => log.game_over(None, None, *steps_completed)?,
+            }
+            if !round.card.is_empty() {
+                log.report_card(&round.card.rows)?;
+            }
+        }
+        if let Some(path) = &options.stats {
+            let scenario = options.scenario_path.as_deref();
+            record_stats(path, scenario, &tree, &mut round.outcome);
+        }
+        ui.game_over(&round.outcome)?;
+        for line in round.card.lines(ui.messages()) {
+            ui.notice(&line)?;
+        }
+        streak = Some(round.streak);
 
-        info!("Player chose to restart");
+        loop {
+            match prompt_restart(ui, &round)? {
+                NextRound::Restart => info!("Player chose to restart"),
+                NextRound::Retry => {
+                    info!("Player chose to retry from the checkpoint");
+                    resume = round.checkpoint.take();
+                }
+                NextRound::Feedback => {
+                    let messages = round.feedback.take().expect("offered only with feedback");
+                    show_feedback(ui, model, &messages)?;
+                    continue;
+                }
+                NextRound::Quit => {
+                    ui.notice(ui.messages().thanks)?;
+                    break 'rounds;
+                }
+            }
+            break;
+        }
     }
 
     Ok(())
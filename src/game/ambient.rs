@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::llm::{strip_think, BackgroundModel, ChatMessage};
+use crate::rng::Rng;
+
+/// Lines generated ahead of time; the worker idles once this many are queued.
+const BUFFER_LINES: usize = 3;
+/// Pause between ambient lines shown while the judge runs.
+pub const SHOW_EVERY: Duration = Duration::from_millis(2500);
+
+/// The background slot is kept small so it barely competes with the judge.
+const SLOT_CTX: u32 = 512;
+const SLOT_THREADS: i32 = 1;
+const SLOT_MAX_TOKENS: usize = 48;
+
+/// Other travellers being processed around the player. Lines are generated
+/// on a low-priority background slot and shown while the judge is thinking,
+/// so the wait reads as part of the scene.
+pub struct Ambient {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Ambient {
+    /// Start generating lines with `prompt` (the scenario's
+    /// [`Setting::ambient`](crate::game::setting::Setting::ambient)).
+    pub fn start(model: BackgroundModel, seed: u64, prompt: String) -> Self {
+        let lines = Arc::new(Mutex::new(VecDeque::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let lines = Arc::clone(&lines);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || fill(model, seed, &prompt, &lines, &stop))
+        };
+        Self {
+            lines,
+            stop,
+            worker: Some(worker),
+        }
+    }
+
+    /// Take the oldest queued line, if any.
+    pub fn next_line(&self) -> Option<String> {
+        self.lines.lock().unwrap().pop_front()
+    }
+}
+
+impl Drop for Ambient {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Worker loop: keep the buffer topped up until asked to stop.
+fn fill(
+    model: BackgroundModel,
+    seed: u64,
+    prompt: &str,
+    lines: &Mutex<VecDeque<String>>,
+    stop: &AtomicBool,
+) {
+    let mut slot = match model.open_slot(SLOT_CTX, SLOT_THREADS, SLOT_MAX_TOKENS) {
+        Ok(slot) => slot,
+        Err(e) => {
+            warn!("Ambient queue disabled: {e:#}");
+            return;
+        }
+    };
+    let mut rng = Rng::new(seed);
+    let messages = [ChatMessage::system(prompt), ChatMessage::user("Next.")];
+
+    while !stop.load(Ordering::Relaxed) {
+        if lines.lock().unwrap().len() >= BUFFER_LINES {
+            thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        match slot.chat(&messages, rng.next_u32()) {
+            Ok(raw) => {
+                let line = clean_line(&raw);
+                if !line.is_empty() {
+                    debug!("Ambient line: {line}");
+                    lines.lock().unwrap().push_back(line);
+                }
+            }
+            Err(e) => {
+                warn!("Ambient generation failed, stopping: {e:#}");
+                return;
+            }
+        }
+    }
+}
+
+/// First line of the output, without think blocks or surrounding quotes.
+fn clean_line(raw: &str) -> String {
+    strip_think(raw)
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .unwrap_or("")
+        .trim_matches('"')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_line() {
+        assert_eq!(
+            clean_line("<think>hmm</think>\n\"A man argues about his visa.\"\nMore."),
+            "A man argues about his visa."
+        );
+        assert_eq!(clean_line("  \n"), "");
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+/// Judge calls quicker than this show no beat.
+const FIRST_AFTER: Duration = Duration::from_secs(2);
+/// Later beats follow at this interval.
+const EVERY: Duration = Duration::from_secs(4);
+
+/// In-fiction lines covering a slow judge, e.g. "The guard types something
+/// into the terminal...". Which one shows depends on how long the decision
+/// has taken so far; the last one holds until it arrives.
+#[derive(Debug, Clone, Default)]
+pub struct Beats {
+    lines: Vec<String>,
+}
+
+impl Beats {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self { lines }
+    }
+
+    /// Index of the line for a wait of `elapsed`, if any is due yet.
+    fn due(&self, elapsed: Duration) -> Option<usize> {
+        if self.lines.is_empty() || elapsed < FIRST_AFTER {
+            return None;
+        }
+        let i = ((elapsed - FIRST_AFTER).as_millis() / EVERY.as_millis()) as usize;
+        Some(i.min(self.lines.len() - 1))
+    }
+
+    /// The line to show after `elapsed`, if it is not the one in `shown`
+    /// already. Renderers call this from their waiting loop.
+    pub fn next(&self, elapsed: Duration, shown: &mut Option<usize>) -> Option<&str> {
+        let due = self.due(elapsed)?;
+        if *shown == Some(due) {
+            return None;
+        }
+        *shown = Some(due);
+        Some(&self.lines[due])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beats_follow_elapsed_time() {
+        let beats = Beats::new(vec!["typing".into(), "frowning".into()]);
+        let mut shown = None;
+        let at = |secs: f32| Duration::from_secs_f32(secs);
+
+        assert_eq!(beats.next(at(1.0), &mut shown), None);
+        assert_eq!(beats.next(at(2.5), &mut shown), Some("typing"));
+        assert_eq!(beats.next(at(3.0), &mut shown), None);
+        assert_eq!(beats.next(at(6.5), &mut shown), Some("frowning"));
+        // The last line holds.
+        assert_eq!(beats.next(at(60.0), &mut shown), None);
+
+        assert_eq!(Beats::default().next(at(60.0), &mut None), None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::game::locale::{fill, Messages};
+use crate::game::session::{Reply, Session};
+use crate::game::setting::Setting;
+
+/// A game played in a chat. Its answers are judged one at a time, in the
+/// order they were sent, on a thread of its own, so the thread reading
+/// the chat never waits for the judge. Answers still queued when the game
+/// is dropped are skipped.
+pub struct Game {
+    session: Arc<Mutex<Session>>,
+    answers: Sender<String>,
+    ended: Arc<AtomicBool>,
+}
+
+impl Game {
+    /// Start `session`, handing each answer to `answer` with the session
+    /// locked.
+    pub fn new(
+        session: Session,
+        mut answer: impl FnMut(&mut Session, &str) + Send + 'static,
+    ) -> Self {
+        let session = Arc::new(Mutex::new(session));
+        let ended = Arc::new(AtomicBool::new(false));
+        let (answers, queued) = mpsc::channel::<String>();
+        let (judged, over) = (Arc::clone(&session), Arc::clone(&ended));
+        thread::spawn(move || {
+            for text in queued {
+                let mut session = judged.lock().unwrap();
+                if over.load(Ordering::SeqCst) {
+                    break;
+                }
+                answer(&mut session, &text);
+            }
+        });
+        Self {
+            session,
+            answers,
+            ended,
+        }
+    }
+
+    /// The session, for commands that must not wait for the judge to
+    /// `try_lock`.
+    pub fn session(&self) -> Arc<Mutex<Session>> {
+        Arc::clone(&self.session)
+    }
+
+    /// Queue the player's answer.
+    pub fn answer(&self, text: &str) {
+        // The thread only stops once the game is dropped.
+        let _ = self.answers.send(text.to_string());
+    }
+
+    /// Skip the answers still queued. Call it with the session locked so
+    /// none is being judged.
+    pub fn end(&self) {
+        self.ended.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Game {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// What a chat bot posts once an answer is settled: the guard's next line,
+/// or what became of an appeal, in `m`'s words. `narrate` marks up the
+/// lines nobody says, e.g. in italics.
+pub fn reply_lines(
+    reply: &Reply,
+    m: &Messages,
+    setting: &Setting,
+    guard_line: &str,
+    narrate: impl Fn(&str) -> String,
+) -> Vec<String> {
+    match reply {
+        Reply::Retry => vec![narrate(&fill(m.say_again, &[("npc", &setting.npc)]))],
+        Reply::Judged(_) => vec![guard_line.to_string()],
+        Reply::Appealed { granted: true, .. } => {
+            vec![narrate(m.appeal_upheld), guard_line.to_string()]
+        }
+        Reply::Appealed { granted: false, .. } => vec![narrate(m.appeal_rejected)],
+        Reply::Reask(_) => unreachable!("a settled answer is not asked again"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::locale::{ENGLISH, GERMAN};
+
+    #[test]
+    fn test_game_judges_answers_in_order() {
+        use crate::game::tree::GameTree;
+        use std::time::Duration;
+
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        let (seen, heard) = mpsc::channel();
+        let game = Game::new(Session::new(tree, None, false, 0), move |_, text| {
+            // The first answer takes longest; the others still wait for it.
+            if text == "1" {
+                thread::sleep(Duration::from_millis(50));
+            }
+            seen.send(text.to_string()).unwrap();
+        });
+        for text in ["1", "2", "3"] {
+            game.answer(text);
+        }
+        let order: Vec<String> = (0..3).map(|_| heard.recv().unwrap()).collect();
+        assert_eq!(order, ["1", "2", "3"]);
+
+        // Once the game ends, queued answers are dropped.
+        let session = game.session();
+        let locked = session.lock().unwrap();
+        game.answer("4");
+        game.end();
+        drop(locked);
+        drop(game);
+        assert!(heard.recv().is_err());
+    }
+
+    #[test]
+    fn test_reply_lines() {
+        let setting = Setting::default();
+        let italics = |text: &str| format!("*{text}*");
+        assert_eq!(
+            reply_lines(&Reply::Retry, &ENGLISH, &setting, "Next.", italics),
+            ["*(The guard frowns. \"Sorry, could you say that again?\")*"]
+        );
+        assert_eq!(
+            reply_lines(&Reply::Retry, &GERMAN, &setting, "Next.", italics),
+            ["*(Stirnrunzeln beim guard. „Wie bitte? Noch einmal, bitte.“)*"]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use log::debug;
+
+/// Parts of a judge prompt, from the highest priority to the lowest. When
+/// a prompt is over budget, sections are trimmed from the lowest up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    /// The judge's role. Never trimmed.
+    System,
+    /// The node's options, roleplay context and reply format. Never trimmed.
+    Criteria,
+    /// Running game state and facts to check answers against.
+    Facts,
+    /// The conversation so far. The exchange being judged is always kept,
+    /// whatever its share.
+    History,
+    /// Corrective examples of earlier misjudged answers.
+    Examples,
+}
+
+const SECTIONS: [Section; 5] = [
+    Section::System,
+    Section::Criteria,
+    Section::Facts,
+    Section::History,
+    Section::Examples,
+];
+
+impl Section {
+    pub fn trimmable(self) -> bool {
+        !matches!(self, Section::System | Section::Criteria)
+    }
+}
+
+/// Token caps for a judge prompt: one per section and one for the whole
+/// prompt. Small models judge better on short prompts, so every subsystem
+/// that feeds the judge takes its share from here.
+#[derive(Debug, Clone)]
+pub struct PromptBudget {
+    pub total: usize,
+    pub system: usize,
+    pub criteria: usize,
+    pub facts: usize,
+    pub history: usize,
+    pub examples: usize,
+}
+
+impl Default for PromptBudget {
+    fn default() -> Self {
+        Self {
+            total: 2304,
+            system: 128,
+            criteria: 512,
+            facts: 256,
+            history: 1536,
+            examples: 240,
+        }
+    }
+}
+
+/// Tokens granted to each section of one prompt by [`PromptBudget::allocate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Allocation([usize; SECTIONS.len()]);
+
+impl Allocation {
+    pub fn get(&self, section: Section) -> usize {
+        self.0[section as usize]
+    }
+}
+
+impl PromptBudget {
+    pub fn cap(&self, section: Section) -> usize {
+        match section {
+            Section::System => self.system,
+            Section::Criteria => self.criteria,
+            Section::Facts => self.facts,
+            Section::History => self.history,
+            Section::Examples => self.examples,
+        }
+    }
+
+    /// Share out the budget given what each section would like (`demand`,
+    /// in tokens). Every section gets at most its cap; if the total is
+    /// still over, the lowest-priority sections give up tokens first.
+    /// Sections that are never trimmed get all they ask for.
+    pub fn allocate(&self, demand: impl Fn(Section) -> usize) -> Allocation {
+        let mut granted = SECTIONS.map(|section| {
+            let wanted = demand(section);
+            let cap = self.cap(section);
+            if wanted > cap && !section.trimmable() {
+                debug!("Prompt budget: {section:?} is over its cap ({wanted} > {cap} tokens)");
+                return wanted;
+            }
+            wanted.min(cap)
+        });
+
+        let mut over = granted.iter().sum::<usize>().saturating_sub(self.total);
+        for section in SECTIONS.into_iter().rev() {
+            if over == 0 || !section.trimmable() {
+                break;
+            }
+            let cut = over.min(granted[section as usize]);
+            if cut > 0 {
+                debug!("Prompt budget: trimmed {section:?} by {cut} tokens");
+            }
+            granted[section as usize] -= cut;
+            over -= cut;
+        }
+        Allocation(granted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lowest_priority_is_trimmed_first() {
+        let budget = PromptBudget {
+            total: 1000,
+            system: 100,
+            criteria: 200,
+            facts: 100,
+            history: 600,
+            examples: 200,
+        };
+        let demand = |history: usize| {
+            move |section| match section {
+                Section::System => 50,
+                Section::Criteria => 300,
+                Section::Facts => 80,
+                Section::History => history,
+                Section::Examples => 150,
+            }
+        };
+
+        // Fits: everyone gets what they asked for, criteria past its cap.
+        let fits = budget.allocate(demand(100));
+        assert_eq!(fits.get(Section::Criteria), 300);
+        assert_eq!(fits.get(Section::History), 100);
+        assert_eq!(fits.get(Section::Examples), 150);
+
+        // Over by 80: examples give way.
+        let over = budget.allocate(demand(500));
+        assert_eq!(over.get(Section::Examples), 70);
+        assert_eq!(over.get(Section::History), 500);
+
+        // Far over: examples are gone, then history shrinks.
+        let far = budget.allocate(demand(5000));
+        assert_eq!(far.get(Section::Examples), 0);
+        assert_eq!(far.get(Section::History), 570);
+        assert_eq!(far.get(Section::Facts), 80);
+    }
+}
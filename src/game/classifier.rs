@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+use crate::llm::{LlmBackend, LlmDecision};
+
+/// Judge failures in a row after which answers are classified by embedding
+/// instead of asking the player again.
+pub const FALLBACK_AFTER: usize = 2;
+
+/// Last resort for a judge that keeps failing: pick the criterion, given
+/// as id and description, whose description is nearest `input` by
+/// embedding. It needs no well-formed reply from the model and, for a
+/// given model, always picks the same.
+pub fn classify(
+    backend: &mut (impl LlmBackend + ?Sized),
+    input: &str,
+    criteria: &[(String, String)],
+) -> Result<LlmDecision> {
+    let answer = backend.embed(input)?;
+    let embedded = criteria
+        .iter()
+        .map(|(_, description)| backend.embed(description))
+        .collect::<Result<Vec<_>>>()?;
+    let (i, similarity) = nearest(&answer, &embedded).context("no criteria to classify by")?;
+    let (id, description) = &criteria[i];
+    info!("Classified by embedding: {id} (similarity {similarity:.2})");
+    Ok(LlmDecision {
+        decision: id.clone(),
+        reason: format!(
+            "Nearest criterion by embedding (similarity {similarity:.2}): {description}"
+        ),
+        ..Default::default()
+    })
+}
+
+/// Judge an answer where the player cannot be asked again (simulations,
+/// evals): run `judge` up to [`FALLBACK_AFTER`] times and, when it keeps
+/// failing, [`classify`] the answer instead. The judge's last error when
+/// classifying fails too.
+pub fn judge_or_classify<B: LlmBackend + ?Sized>(
+    backend: &mut B,
+    input: &str,
+    criteria: &[(String, String)],
+    mut judge: impl FnMut(&mut B) -> Result<LlmDecision>,
+) -> Result<LlmDecision> {
+    let mut failures = 0;
+    loop {
+        match judge(backend) {
+            Ok(decision) => return Ok(decision),
+            Err(e) if failures + 1 < FALLBACK_AFTER => {
+                warn!("Judge failed ({e:#}); asking again");
+                failures += 1;
+            }
+            Err(e) => {
+                warn!("Judge failed again ({e:#}); classifying the answer by embedding");
+                return classify(backend, input, criteria)
+                    .inspect_err(|why| warn!("Classifying failed too: {why:#}"))
+                    .or(Err(e));
+            }
+        }
+    }
+}
+
+/// Index and cosine similarity of the criterion closest to `answer`, the
+/// first one on a tie. Embeddings are unit length, so this is the dot
+/// product.
+fn nearest(answer: &[f32], criteria: &[Vec<f32>]) -> Option<(usize, f32)> {
+    criteria
+        .iter()
+        .map(|v| v.iter().zip(answer).map(|(a, b)| a * b).sum::<f32>())
+        .enumerate()
+        .fold(None, |best, (i, similarity)| match best {
+            Some((_, top)) if top >= similarity => best,
+            _ => Some((i, similarity)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::session::Session;
+    use crate::game::tree::GameTree;
+
+    #[test]
+    fn test_nearest_criterion() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?", "clarify": {"limit": 1},
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"},
+                                                 {"id": "C", "description": "refuses"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let request = Session::new(tree, None, false, 0).ask("Here.").unwrap();
+        // The follow-up question is no criterion to classify by.
+        assert_eq!(request.valid_choices().len(), 3);
+        let ids: Vec<&str> = request
+            .criteria()
+            .iter()
+            .map(|(id, _)| id.as_str())
+            .collect();
+        assert_eq!(ids, ["B", "C"]);
+
+        let criteria = vec![vec![1.0, 0.0], vec![0.6, 0.8], vec![0.6, 0.8]];
+        assert_eq!(nearest(&[0.0, 1.0], &criteria), Some((1, 0.8)));
+        assert_eq!(nearest(&[1.0, 0.0], &criteria), Some((0, 1.0)));
+        assert_eq!(nearest(&[1.0, 0.0], &[]), None);
+    }
+}
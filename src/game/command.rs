@@ -0,0 +1,97 @@
+/// Meta commands start with this. Input starting with it is never sent
+/// to the judge.
+pub const PREFIX: char = '/';
+
+/// What `/help` shows.
+pub const HELP: &[&str] = &[
+    "Commands:",
+    "  /help            this list",
+    "  /history         the conversation so far",
+    "  /hint            a nudge for the current question",
+    "  /talk            answer out loud (with --listen)",
+    "  /save [path]     save the game to resume later",
+    "  /quit            walk away",
+];
+
+/// Command names, for completion.
+#[cfg(any(test, feature = "readline"))]
+const NAMES: &[&str] = &[
+    "/help", "/history", "/hint", "/talk", "/save", "/quit", "/exit",
+];
+
+/// Commands that `input` (all typed so far) could be the start of.
+#[cfg(any(test, feature = "readline"))]
+pub fn complete(input: &str) -> Vec<&'static str> {
+    if !input.starts_with(PREFIX) || input.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let typed = input.to_ascii_lowercase();
+    NAMES
+        .iter()
+        .copied()
+        .filter(|name| name.starts_with(&typed))
+        .collect()
+}
+
+/// A meta command typed instead of an answer.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    History,
+    Hint,
+    /// Answer out loud ([`crate::game::ui::Ui::listen`]).
+    Talk,
+    /// Save to the given path, or to the default one.
+    Save(Option<String>),
+    Quit,
+    /// Any other `/word`, reported back rather than judged.
+    Unknown(String),
+}
+
+impl Command {
+    /// The command in `input`, or `None` for an answer. Command names are
+    /// case-insensitive; `/exit` is taken for `/quit`.
+    pub fn parse(input: &str) -> Option<Self> {
+        let rest = input.trim().strip_prefix(PREFIX)?;
+        let (name, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let arg = arg.trim();
+        Some(match name.to_ascii_lowercase().as_str() {
+            "help" | "?" => Self::Help,
+            "history" => Self::History,
+            "hint" => Self::Hint,
+            "talk" => Self::Talk,
+            "save" => Self::Save((!arg.is_empty()).then(|| arg.to_string())),
+            "quit" | "exit" => Self::Quit,
+            _ => Self::Unknown(format!("{PREFIX}{name}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("/HELP"), Some(Command::Help));
+        assert_eq!(Command::parse(" /exit "), Some(Command::Quit));
+        assert_eq!(Command::parse("/talk"), Some(Command::Talk));
+        assert_eq!(Command::parse("/save"), Some(Command::Save(None)));
+        assert_eq!(
+            Command::parse("/save  games/mine.json"),
+            Some(Command::Save(Some("games/mine.json".into())))
+        );
+        assert_eq!(
+            Command::parse("/dance now"),
+            Some(Command::Unknown("/dance".into()))
+        );
+        // Plain words are answers, even "quit".
+        assert_eq!(Command::parse("quit"), None);
+        assert_eq!(Command::parse("I have nothing to declare"), None);
+
+        assert_eq!(complete("/h"), ["/help", "/history", "/hint"]);
+        assert_eq!(complete("/Q"), ["/quit"]);
+        assert!(complete("/save x").is_empty());
+        assert!(complete("hello").is_empty());
+    }
+}
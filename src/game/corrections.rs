@@ -0,0 +1,214 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::game::instruction::{JudgeInstruction, Slot};
+use crate::llm::LLM;
+
+/// Corrective examples shown to the judge for each answer.
+pub const FEW_SHOT_K: usize = 3;
+
+/// A past answer the judge got wrong, with the decision it should have made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Correction {
+    pub node: String,
+    pub input: String,
+    /// What the judge decided.
+    pub judged: String,
+    /// What it should have decided.
+    pub expected: String,
+}
+
+/// Library of misjudged cases, one JSON object per line. Relevant entries
+/// are picked by embedding similarity to the answer being judged and shown
+/// to the judge as corrective examples.
+pub struct Corrections {
+    path: PathBuf,
+    entries: Vec<Correction>,
+    /// Embedding of each entry's input, computed on first use since they
+    /// depend on the loaded model.
+    embeddings: Vec<Option<Vec<f32>>>,
+}
+
+impl Corrections {
+    /// Load the library at `path`. A missing file is an empty library.
+    pub fn open(path: &Path) -> Result<Self> {
+        let entries: Vec<Correction> = match fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| {
+                    serde_json::from_str(line).with_context(|| {
+                        format!("{}:{}: malformed correction", path.display(), i + 1)
+                    })
+                })
+                .collect::<Result<_>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        info!(
+            "Loaded {} corrections from {}",
+            entries.len(),
+            path.display()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            embeddings: vec![None; entries.len()],
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add a misjudged case and append it to the file. Returns `false` if
+    /// the same answer at the same node is already in the library.
+    pub fn record(&mut self, correction: Correction) -> Result<bool> {
+        if self
+            .entries
+            .iter()
+            .any(|c| c.node == correction.node && c.input == correction.input)
+        {
+            return Ok(false);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&correction)?)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        self.entries.push(correction);
+        self.embeddings.push(None);
+        Ok(true)
+    }
+
+    /// The `k` corrections at `node` whose answers are most similar to
+    /// `answer`, most similar first.
+    pub fn relevant(
+        &mut self,
+        model: &mut LLM,
+        node: &str,
+        answer: &str,
+        k: usize,
+    ) -> Result<Vec<&Correction>> {
+        let at_node: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].node == node)
+            .collect();
+        if at_node.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        for &i in &at_node {
+            if self.embeddings[i].is_none() {
+                self.embeddings[i] = Some(model.embed(&self.entries[i].input)?);
+            }
+        }
+        let query = model.embed(answer)?;
+        let candidates = at_node
+            .iter()
+            .map(|&i| (i, self.embeddings[i].as_deref().unwrap_or_default()));
+        Ok(rank(&query, candidates, k)
+            .into_iter()
+            .map(|i| &self.entries[i])
+            .collect())
+    }
+
+    /// Judge instruction carrying the corrective examples for `answer` at
+    /// `node`. Empty if there are none or the lookup failed; a broken
+    /// library never stops a game.
+    pub fn instruction(&mut self, model: &mut LLM, node: &str, answer: &str) -> JudgeInstruction {
+        let mut instruction = JudgeInstruction::new();
+        match self.relevant(model, node, answer, FEW_SHOT_K) {
+            Ok(examples) if !examples.is_empty() => {
+                debug!("Adding {} corrective examples", examples.len());
+                let mut lines = vec![
+                    "Answers like these were misjudged before; decide them as corrected:"
+                        .to_string(),
+                ];
+                lines.extend(examples.iter().map(|c| example_line(c)));
+                // Capped by the examples' share of the prompt budget.
+                instruction.add(Slot::Examples, "corrections", usize::MAX, lines);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Skipping corrective examples: {e:#}"),
+        }
+        instruction
+    }
+}
+
+fn example_line(correction: &Correction) -> String {
+    format!(
+        "- \"{}\" is {}, not {}",
+        correction.input, correction.expected, correction.judged
+    )
+}
+
+/// Indices of the `k` candidates closest to `query` by cosine similarity,
+/// best first. Embeddings are unit length, so this is the dot product.
+fn rank<'a>(
+    query: &[f32],
+    candidates: impl Iterator<Item = (usize, &'a [f32])>,
+    k: usize,
+) -> Vec<usize> {
+    let mut scored: Vec<(usize, f32)> = candidates
+        .map(|(i, v)| (i, v.iter().zip(query).map(|(a, b)| a * b).sum()))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().take(k).map(|(i, _)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_picks_nearest() {
+        let candidates: Vec<(usize, Vec<f32>)> = vec![
+            (0, vec![1.0, 0.0]),
+            (4, vec![0.0, 1.0]),
+            (7, vec![0.6, 0.8]),
+        ];
+        let ranked = rank(
+            &[0.0, 1.0],
+            candidates.iter().map(|(i, v)| (*i, v.as_slice())),
+            2,
+        );
+        assert_eq!(ranked, [4, 7]);
+    }
+
+    #[test]
+    fn test_record_skips_duplicates_and_persists() {
+        let path =
+            std::env::temp_dir().join(format!("elsa-corrections-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let correction = Correction {
+            node: "START".into(),
+            input: "Not telling".into(),
+            judged: "QUESTION_1".into(),
+            expected: "SUSPICIOUS".into(),
+        };
+
+        let mut library = Corrections::open(&path).unwrap();
+        assert_eq!(library.len(), 0);
+        assert!(library.record(correction.clone()).unwrap());
+        assert!(!library.record(correction.clone()).unwrap());
+
+        let library = Corrections::open(&path).unwrap();
+        assert_eq!(library.entries, vec![correction.clone()]);
+        assert_eq!(
+            example_line(&correction),
+            "- \"Not telling\" is SUSPICIOUS, not QUESTION_1"
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Judge temperature ceiling on [`Difficulty::Strict`], so a lucky sample
+/// doesn't let a weak answer through.
+const STRICT_TEMPERATURE: f32 = 0.1;
+
+/// Passes in a row that make adaptive difficulty a level stricter.
+const TIGHTEN_AFTER: i32 = 3;
+
+const LEVELS: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Strict];
+
+/// How hard the judge is on the player, picked with `--difficulty`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Strict,
+}
+
+impl FromStr for Difficulty {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "easy" => Ok(Self::Easy),
+            "normal" => Ok(Self::Normal),
+            "strict" => Ok(Self::Strict),
+            _ => bail!("unknown difficulty '{s}' (expected easy, normal or strict)"),
+        }
+    }
+}
+
+impl Difficulty {
+    /// The judge's note on how to rule at this difficulty, if any.
+    pub fn instruction(self, player: &str) -> Option<String> {
+        match self {
+            Difficulty::Easy => Some(format!(
+                "Be generous: if the {player}'s response roughly fits an option, pick it, and give them the benefit of the doubt on anything vague."
+            )),
+            Difficulty::Normal => None,
+            Difficulty::Strict => Some(format!(
+                "Be strict: the {player}'s response only fits an option if it meets it clearly and completely. Vague, evasive or partial responses do not."
+            )),
+        }
+    }
+
+    /// This level adapted to the player's `streak`: passes in a row if
+    /// positive, failures in a row if negative. Every [`TIGHTEN_AFTER`]
+    /// passes make it a level stricter and every failure a level more
+    /// lenient, within easy to strict.
+    pub fn adapted(self, streak: i32) -> Self {
+        let shift = if streak > 0 {
+            streak / TIGHTEN_AFTER
+        } else {
+            streak
+        };
+        let at = LEVELS.iter().position(|level| *level == self).unwrap_or(1) as i32;
+        LEVELS[(at + shift).clamp(0, LEVELS.len() as i32 - 1) as usize]
+    }
+
+    /// The judge's sampling temperature at this difficulty.
+    pub fn judge_temperature(self, temperature: f32) -> f32 {
+        match self {
+            Difficulty::Strict => temperature.min(STRICT_TEMPERATURE),
+            _ => temperature,
+        }
+    }
+}
+
+/// A decision node's criteria at one difficulty, e.g. for `strict`:
+///
+/// ```json
+/// {"suffix": "Only a return ticket counts as proof.",
+///  "edges": {"CLEARED": "shows a return ticket"}}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Criteria {
+    /// Added to the node's `system_context`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    /// Edge id -> description used instead of the edge's own.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub edges: BTreeMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapts_to_streaks() {
+        assert_eq!(Difficulty::Normal.adapted(0), Difficulty::Normal);
+        assert_eq!(Difficulty::Normal.adapted(2), Difficulty::Normal);
+        assert_eq!(Difficulty::Normal.adapted(3), Difficulty::Strict);
+        assert_eq!(Difficulty::Normal.adapted(9), Difficulty::Strict);
+        assert_eq!(Difficulty::Normal.adapted(-1), Difficulty::Easy);
+        assert_eq!(Difficulty::Strict.adapted(-1), Difficulty::Normal);
+        assert_eq!(Difficulty::Strict.adapted(-5), Difficulty::Easy);
+        assert_eq!(Difficulty::Easy.adapted(6), Difficulty::Strict);
+    }
+}
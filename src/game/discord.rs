@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tungstenite::protocol::CloseFrame;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::game::bot;
+use crate::game::locale::{fill, Messages};
+use crate::game::session::Session;
+use crate::game::setting::Setting;
+use crate::game::tree::GameTree;
+use crate::game::worker::{self, JudgeQueue};
+use crate::game::{prime_judge, GameOutcome};
+use crate::llm::LLM;
+use crate::rng::Rng;
+
+/// Environment variable holding the bot token.
+pub const TOKEN_VAR: &str = "DISCORD_TOKEN";
+
+const API: &str = "https://discord.com/api/v10";
+const GATEWAY_HOST: &str = "gateway.discord.gg";
+const GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+/// GUILDS, GUILD_MESSAGES and MESSAGE_CONTENT. The last one is privileged
+/// and has to be enabled for the bot in the developer portal.
+const INTENTS: u64 = 1 | (1 << 9) | (1 << 15);
+/// Games running at once; `/start` is refused beyond this.
+const MAX_GAMES: usize = 64;
+/// Judge calls that may wait for the model; more are asked to resend.
+const QUEUE_DEPTH: usize = 16;
+/// How long a gateway read blocks before heartbeats are checked.
+const TICK: Duration = Duration::from_millis(250);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Discord's message length limit, in characters.
+const MAX_MESSAGE: usize = 2000;
+const PUBLIC_THREAD: u8 = 11;
+/// Interaction response flag: only the user who ran the command sees it.
+const EPHEMERAL: u64 = 1 << 6;
+
+pub struct DiscordOptions {
+    /// Seed for the games' transcript variants.
+    pub seed: u64,
+    pub strict: bool,
+    /// The bot's words, in the `--lang` language.
+    pub messages: &'static Messages,
+}
+
+/// Run the scenario as a Discord bot until the gateway refuses the bot.
+/// `/start` in a channel opens a thread with a game in it, and every
+/// message in that thread is the player's answer. `/hint` and `/quit` work
+/// in the thread. Games share the model through the judge queue, which
+/// runs on this thread; the gateway is read on another.
+pub fn run(model: &mut LLM, tree: &GameTree, options: DiscordOptions) -> Result<()> {
+    let token =
+        std::env::var(TOKEN_VAR).with_context(|| format!("set {TOKEN_VAR} to the bot token"))?;
+    prime_judge(model, tree);
+    let (queue, jobs) = JudgeQueue::new(QUEUE_DEPTH);
+    let bot = Arc::new(Bot::new(tree.clone(), Rest::new(&token), &options, queue));
+    let gateway = thread::spawn(move || listen(&bot, &token));
+    worker::run(jobs, |job, on_piece| job.run(model, on_piece));
+    gateway
+        .join()
+        .map_err(|_| anyhow!("the gateway thread panicked"))?
+}
+
+/// Stay connected to the gateway, reconnecting when the connection drops.
+/// Returns only when Discord refuses the bot for good.
+fn listen(bot: &Arc<Bot>, token: &str) -> Result<()> {
+    loop {
+        match Gateway::connect().and_then(|mut gateway| gateway.run(bot, token)) {
+            Ok(Closed::Fatal(why)) => bail!("Discord closed the connection: {why}"),
+            Ok(Closed::Reconnect) => info!("Gateway asked to reconnect"),
+            Err(e) => warn!("Gateway connection lost: {e:#}"),
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Gateway
+// ---------------------------------------------------------------------------
+
+/// A gateway payload.
+#[derive(Deserialize)]
+struct Payload {
+    op: u8,
+    #[serde(default)]
+    d: Value,
+    s: Option<u64>,
+    t: Option<String>,
+}
+
+enum Closed {
+    /// Connect again and identify afresh.
+    Reconnect,
+    /// Bad token or intents; reconnecting would fail the same way.
+    Fatal(String),
+}
+
+struct Gateway {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    /// Last sequence number, sent back with heartbeats.
+    seq: Option<u64>,
+}
+
+impl Gateway {
+    fn connect() -> Result<Self> {
+        let tcp = TcpStream::connect((GATEWAY_HOST, 443)).context("cannot reach the gateway")?;
+        // Kept to set the timeout once the handshake is done; socket options
+        // are shared with the stream handed to tungstenite.
+        let handle = tcp.try_clone()?;
+        let (socket, _) = tungstenite::client_tls(GATEWAY_URL, tcp)
+            .map_err(|e| anyhow!("gateway handshake failed: {e}"))?;
+        handle.set_read_timeout(Some(TICK))?;
+        Ok(Self { socket, seq: None })
+    }
+
+    fn send(&mut self, payload: Value) -> Result<()> {
+        self.socket.send(Message::text(payload.to_string()))?;
+        Ok(())
+    }
+
+    fn heartbeat(&mut self) -> Result<()> {
+        let seq = self.seq;
+        self.send(json!({ "op": 1, "d": seq }))
+    }
+
+    /// Identify, then hand events to `bot` and keep the heartbeat going
+    /// until the connection closes.
+    fn run(&mut self, bot: &Arc<Bot>, token: &str) -> Result<Closed> {
+        let mut heartbeat: Option<(Duration, Instant)> = None;
+        loop {
+            if let Some((interval, due)) = heartbeat {
+                if Instant::now() >= due {
+                    self.heartbeat()?;
+                    heartbeat = Some((interval, due + interval));
+                }
+            }
+            let payload: Payload = match self.socket.read() {
+                Ok(Message::Text(text)) => serde_json::from_str(&text)?,
+                Ok(Message::Close(frame)) => return Ok(closed(frame)),
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if payload.s.is_some() {
+                self.seq = payload.s;
+            }
+            match payload.op {
+                0 => bot.dispatch(payload.t.as_deref().unwrap_or(""), &payload.d),
+                1 => self.heartbeat()?,
+                7 | 9 => return Ok(Closed::Reconnect),
+                10 => {
+                    let interval = payload.d["heartbeat_interval"]
+                        .as_u64()
+                        .context("hello without a heartbeat interval")?;
+                    let interval = Duration::from_millis(interval);
+                    heartbeat = Some((interval, Instant::now() + interval));
+                    self.send(json!({
+                        "op": 2,
+                        "d": {
+                            "token": token,
+                            "intents": INTENTS,
+                            "properties": { "os": std::env::consts::OS, "browser": "elsa", "device": "elsa" },
+                        },
+                    }))?;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Close codes 4004 (authentication failed), 4013 and 4014 (intents) are
+/// not worth retrying.
+fn closed(frame: Option<CloseFrame>) -> Closed {
+    match frame {
+        Some(frame) if matches!(u16::from(frame.code), 4004 | 4013 | 4014) => {
+            Closed::Fatal(format!("{} ({})", frame.reason, u16::from(frame.code)))
+        }
+        _ => Closed::Reconnect,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// REST
+// ---------------------------------------------------------------------------
+
+struct Rest {
+    auth: String,
+}
+
+impl Rest {
+    fn new(token: &str) -> Self {
+        Self {
+            auth: format!("Bot {token}"),
+        }
+    }
+
+    fn send(&self, method: &str, path: &str, body: Value) -> Result<Value> {
+        let request =
+            ureq::request(method, &format!("{API}{path}")).set("Authorization", &self.auth);
+        match request.send_json(body) {
+            // Some endpoints answer 204 with no body.
+            Ok(response) => Ok(response.into_json().unwrap_or(Value::Null)),
+            Err(ureq::Error::Status(status, response)) => bail!(
+                "{method} {path} returned {status}: {}",
+                response.into_string().unwrap_or_default()
+            ),
+            Err(e) => Err(e).with_context(|| format!("{method} {path} failed")),
+        }
+    }
+
+    fn say(&self, channel: &str, text: &str) -> Result<()> {
+        let path = format!("/channels/{channel}/messages");
+        self.send("POST", &path, json!({ "content": clip(text) }))?;
+        Ok(())
+    }
+
+    fn typing(&self, channel: &str) -> Result<()> {
+        self.send("POST", &format!("/channels/{channel}/typing"), json!({}))?;
+        Ok(())
+    }
+
+    fn respond(&self, id: &str, token: &str, reply: &CommandReply) -> Result<()> {
+        let flags = if reply.private { EPHEMERAL } else { 0 };
+        self.send(
+            "POST",
+            &format!("/interactions/{id}/{token}/callback"),
+            json!({ "type": 4, "data": { "content": clip(&reply.text), "flags": flags } }),
+        )?;
+        Ok(())
+    }
+}
+
+/// `text` cut to Discord's message length limit.
+fn clip(text: &str) -> String {
+    if text.chars().count() <= MAX_MESSAGE {
+        return text.to_string();
+    }
+    let end = text
+        .char_indices()
+        .nth(MAX_MESSAGE - 1)
+        .map_or(text.len(), |(i, _)| i);
+    format!("{}…", &text[..end])
+}
+
+// ---------------------------------------------------------------------------
+// Bot
+// ---------------------------------------------------------------------------
+
+/// Answer to a slash command.
+struct CommandReply {
+    text: String,
+    /// Shown only to the user who ran the command.
+    private: bool,
+}
+
+impl CommandReply {
+    fn public(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            private: false,
+        }
+    }
+
+    fn private(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            private: true,
+        }
+    }
+}
+
+/// Shared by the gateway thread and the games' threads. Each game judges
+/// its messages in order on its own thread while other games proceed.
+struct Bot {
+    tree: GameTree,
+    setting: Setting,
+    messages: &'static Messages,
+    rest: Rest,
+    strict: bool,
+    seeds: Mutex<Rng>,
+    queue: JudgeQueue,
+    /// Games by the id of the thread they are played in.
+    games: Mutex<HashMap<String, bot::Game>>,
+}
+
+impl Bot {
+    fn new(tree: GameTree, rest: Rest, options: &DiscordOptions, queue: JudgeQueue) -> Self {
+        Self {
+            setting: tree.setting(),
+            messages: options.messages,
+            tree,
+            rest,
+            strict: options.strict,
+            seeds: Mutex::new(Rng::new(options.seed)),
+            queue,
+            games: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn game(&self, thread: &str) -> Option<Arc<Mutex<Session>>> {
+        self.games
+            .lock()
+            .unwrap()
+            .get(thread)
+            .map(bot::Game::session)
+    }
+
+    fn end(&self, thread: &str) {
+        if self.games.lock().unwrap().remove(thread).is_some() {
+            info!("Game in thread {thread} ended");
+        }
+    }
+
+    /// Handle a gateway event. Failures are logged; they never drop the
+    /// connection.
+    fn dispatch(self: &Arc<Self>, event: &str, d: &Value) {
+        let result = match event {
+            "READY" => self.ready(d),
+            "INTERACTION_CREATE" => self.interaction(d),
+            "MESSAGE_CREATE" => {
+                self.message(d);
+                Ok(())
+            }
+            "THREAD_DELETE" => {
+                self.end(d["id"].as_str().unwrap_or(""));
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to handle {event}: {e:#}");
+        }
+    }
+
+    fn ready(&self, d: &Value) -> Result<()> {
+        info!(
+            "Connected to Discord as {}",
+            d["user"]["username"].as_str().unwrap_or("?")
+        );
+        let application = d["application"]["id"]
+            .as_str()
+            .context("READY without an application id")?;
+        self.rest.send(
+            "PUT",
+            &format!("/applications/{application}/commands"),
+            json!([
+                { "name": "start", "description": "Start a game in a new thread" },
+                { "name": "hint", "description": "What the current question is looking for" },
+                { "name": "quit", "description": "End the game in this thread" },
+            ]),
+        )?;
+        Ok(())
+    }
+
+    fn interaction(self: &Arc<Self>, d: &Value) -> Result<()> {
+        let id = d["id"].as_str().context("interaction without an id")?;
+        let token = d["token"].as_str().context("interaction without a token")?;
+        let channel = d["channel_id"].as_str().unwrap_or("");
+        let user = d["member"]["user"]["username"]
+            .as_str()
+            .or_else(|| d["user"]["username"].as_str())
+            .unwrap_or("someone");
+        let reply = match d["data"]["name"].as_str() {
+            Some("start") => self.start(channel, user),
+            Some("hint") => self.hint(channel),
+            Some("quit") => self.quit(channel),
+            _ => CommandReply::private(self.messages.unknown_bot_command),
+        };
+        self.rest.respond(id, token, &reply)
+    }
+
+    fn start(self: &Arc<Self>, channel: &str, user: &str) -> CommandReply {
+        let m = self.messages;
+        if self.game(channel).is_some() {
+            return CommandReply::private(m.game_running);
+        }
+        if self.games.lock().unwrap().len() >= MAX_GAMES {
+            return CommandReply::private(m.too_many_games);
+        }
+        let thread = self.rest.send(
+            "POST",
+            &format!("/channels/{channel}/threads"),
+            json!({ "name": fill(m.thread_name, &[("user", user)]), "type": PUBLIC_THREAD, "auto_archive_duration": 60 }),
+        );
+        let thread = match thread.map(|t| t["id"].as_str().map(String::from)) {
+            Ok(Some(thread)) => thread,
+            Ok(None) => return CommandReply::private(m.no_thread),
+            Err(e) => {
+                warn!("Failed to open a thread in {channel}: {e:#}");
+                return CommandReply::private(m.no_thread);
+            }
+        };
+        let seed = self.seeds.lock().unwrap().next_u64();
+        let session = Session::new(self.tree.clone(), None, self.strict, seed);
+        if let Err(e) = self.rest.say(&thread, &self.guard_line(&session)) {
+            warn!("Failed to greet thread {thread}: {e:#}");
+        }
+        info!("Game started by {user} in thread {thread}");
+        let (bot, id) = (Arc::clone(self), thread.clone());
+        let game = bot::Game::new(session, move |session, content| {
+            if let Err(e) = bot.answer(&id, session, content) {
+                warn!("Failed to answer in thread {id}: {e:#}");
+            }
+        });
+        self.games.lock().unwrap().insert(thread.clone(), game);
+        CommandReply::public(fill(
+            m.game_started,
+            &[("user", user), ("thread", &format!("<#{thread}>"))],
+        ))
+    }
+
+    fn hint(&self, thread: &str) -> CommandReply {
+        let Some(session) = self.game(thread) else {
+            return CommandReply::private(self.messages.no_game_start);
+        };
+        // The gateway thread must not wait for the judge.
+        let Ok(session) = session.try_lock() else {
+            return CommandReply::private(self.wait_for_answer());
+        };
+        if session.pending_appeal().is_some() {
+            return CommandReply::private(self.messages.tell_supervisor);
+        }
+        CommandReply::private(hint_text(self.messages, &self.setting, &session.hint()))
+    }
+
+    fn quit(&self, thread: &str) -> CommandReply {
+        let Some(session) = self.game(thread) else {
+            return CommandReply::private(self.messages.no_game);
+        };
+        let Ok(session) = session.try_lock() else {
+            return CommandReply::private(self.wait_for_answer());
+        };
+        // At an appeal, quitting accepts the decision.
+        let outcome = session.outcome().unwrap_or(GameOutcome::Quit {
+            steps_completed: session.steps_completed(),
+            headline: self.setting.walked_away.clone(),
+        });
+        drop(session);
+        self.end(thread);
+        CommandReply::public(game_over_text(self.messages, &outcome))
+    }
+
+    /// Queue a message posted in a game thread for its game.
+    fn message(&self, d: &Value) {
+        if d["author"]["bot"].as_bool().unwrap_or(false) {
+            return;
+        }
+        let (Some(thread), Some(content)) = (d["channel_id"].as_str(), d["content"].as_str())
+        else {
+            return;
+        };
+        if let Some(game) = self.games.lock().unwrap().get(thread) {
+            game.answer(content);
+        }
+    }
+
+    fn answer(&self, thread: &str, session: &mut Session, content: &str) -> Result<()> {
+        let content = content.trim();
+        if content.is_empty() || (session.outcome().is_some() && session.pending_appeal().is_none())
+        {
+            return Ok(());
+        }
+
+        let request = session.ask(content)?;
+        if let Err(e) = self.rest.typing(thread) {
+            debug!("Typing indicator failed: {e:#}");
+        }
+        let reply = match session.settle(request, &mut self.queue.clone(), &mut |_| {}) {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return self.rest.say(thread, &format!("*{}*", self.messages.busy)),
+            Err(e) => {
+                // Strict mode: the game cannot continue reliably.
+                self.end(thread);
+                let text = fill(self.messages.game_broke, &[("error", &format!("{e:#}"))]);
+                return self.rest.say(thread, &format!("*{text}*"));
+            }
+        };
+        let guard_line = self.guard_line(session);
+        let m = self.messages;
+        let mut lines = bot::reply_lines(&reply, m, &self.setting, &guard_line, |text| {
+            format!("*{text}*")
+        });
+
+        if let Some(line) = session.appeal_line() {
+            lines.push(format!("**{}:** {}", m.supervisor, line.display));
+            lines.push(format!("*{}*", m.plead_or_quit));
+        } else if let Some(outcome) = session.outcome() {
+            lines.push(game_over_text(m, &outcome));
+            self.end(thread);
+        }
+        self.rest.say(thread, &lines.join("\n"))
+    }
+
+    fn wait_for_answer(&self) -> String {
+        fill(self.messages.wait_for_answer, &[("npc", &self.setting.npc)])
+    }
+
+    fn guard_line(&self, session: &Session) -> String {
+        format!(
+            "**{}:** {}",
+            session
+                .npc_name()
+                .unwrap_or_else(|| self.setting.npc_title()),
+            session.guard_line().display
+        )
+    }
+}
+
+fn hint_text(m: &Messages, setting: &Setting, hints: &[&str]) -> String {
+    if hints.is_empty() {
+        return m.nothing_left_to_hint.to_string();
+    }
+    let mut text = fill(m.hoping_for, &[("npc", &setting.npc)]);
+    for hint in hints {
+        text.push_str(&format!("\n- {hint}"));
+    }
+    text
+}
+
+fn game_over_text(m: &Messages, outcome: &GameOutcome) -> String {
+    let mut text = format!("**{}**", m.game_over);
+    for line in outcome.summary(m) {
+        text.push_str(&format!("\n> {line}"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::locale::{ENGLISH, GERMAN};
+
+    #[test]
+    fn test_clip_respects_the_message_limit() {
+        assert_eq!(clip("short"), "short");
+        let long = "é".repeat(MAX_MESSAGE + 10);
+        assert_eq!(clip(&long).chars().count(), MAX_MESSAGE);
+        assert_eq!(clip(&"a".repeat(MAX_MESSAGE)), "a".repeat(MAX_MESSAGE));
+    }
+
+    #[test]
+    fn test_texts() {
+        let setting = Setting::default();
+        assert_eq!(
+            hint_text(&ENGLISH, &setting, &["Shows the passport."]),
+            "The guard is hoping for something like:\n- Shows the passport."
+        );
+        assert!(hint_text(&ENGLISH, &setting, &[]).contains("game is over"));
+        let outcome = GameOutcome::Quit {
+            steps_completed: 1,
+            headline: setting.walked_away,
+        };
+        assert_eq!(
+            game_over_text(&ENGLISH, &outcome),
+            "**GAME OVER**\n> You walked away from the border control booth."
+        );
+        assert!(game_over_text(&GERMAN, &outcome).starts_with("**SPIEL VORBEI**"));
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+
+use crate::game::session::{Reply, Session};
+use crate::game::transcript::TurnRecord;
+use crate::game::tree::GameTree;
+use crate::game::GameOutcome;
+use crate::llm::LlmBackend;
+
+/// What became of one answer.
+#[derive(Debug, Clone)]
+pub struct JudgeResult {
+    /// The judge's decision and reason. `None` when its reply could not be
+    /// used; the guard asks again and the answer can simply be given anew.
+    pub record: Option<TurnRecord>,
+    /// Appeals only: whether the supervisor granted it.
+    pub granted: Option<bool>,
+    /// What the guard says next.
+    pub guard_line: String,
+    /// Set once the game is over.
+    pub outcome: Option<GameOutcome>,
+    /// At a failure that can be appealed: the supervisor's opening line.
+    /// The next answer is then the player's plea.
+    pub appeal_line: Option<String>,
+}
+
+/// One game of a scenario, with answers judged by `B`. The embedding
+/// application shows [`Engine::guard_line`], passes the player's answers
+/// to [`Engine::respond`], and stops once a result has an outcome and no
+/// appeal line.
+pub struct Engine<B> {
+    session: Session,
+    backend: B,
+}
+
+impl<B: LlmBackend> Engine<B> {
+    /// A game of `tree` from its start. `seed` picks among the transcript
+    /// variants.
+    pub fn new(tree: GameTree, backend: B, seed: u64) -> Self {
+        Self {
+            session: Session::new(tree, None, false, seed),
+            backend,
+        }
+    }
+
+    /// What the guard is saying now.
+    pub fn guard_line(&self) -> &str {
+        &self.session.guard_line().display
+    }
+
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        self.session.outcome()
+    }
+
+    /// The game state, for anything the engine doesn't surface itself.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Judge the player's answer and move the game on.
+    pub fn respond(&mut self, answer: &str) -> Result<JudgeResult> {
+        let request = self.session.ask(answer)?;
+        let reply = self
+            .session
+            .settle(request, &mut self.backend, &mut |_| {})?
+            .expect("a backend takes every request");
+        let (record, granted) = match reply {
+            Reply::Retry => (None, None),
+            Reply::Judged(record) => (Some(record), None),
+            Reply::Appealed { granted, record } => (Some(record), Some(granted)),
+            Reply::Reask(_) => unreachable!("settled above"),
+        };
+        Ok(JudgeResult {
+            record,
+            granted,
+            guard_line: self.guard_line().to_string(),
+            outcome: self.session.outcome(),
+            appeal_line: self.session.appeal_line().map(|line| line.display),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, LlmDecision};
+
+    /// Picks the first choice that isn't a reserved one.
+    struct FirstChoice;
+
+    impl LlmBackend for FirstChoice {
+        fn judge(
+            &mut self,
+            _alias: Option<&str>,
+            _messages: &[ChatMessage],
+            valid_choices: &[&str],
+        ) -> Result<LlmDecision> {
+            Ok(LlmDecision {
+                decision: valid_choices[0].to_string(),
+                reason: "fine".into(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_engine_plays_a_scenario_with_any_backend() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        let mut engine = Engine::new(tree, FirstChoice, 0);
+        assert_eq!(engine.guard_line(), "Passport?");
+
+        let result = engine.respond("Here you go.").unwrap();
+        assert_eq!(result.record.unwrap().decision, "B");
+        assert_eq!(result.guard_line, "Welcome.");
+        assert!(matches!(
+            result.outcome,
+            Some(GameOutcome::Finished { success: true, .. })
+        ));
+        assert!(result.appeal_line.is_none());
+    }
+}
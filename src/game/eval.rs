@@ -0,0 +1,789 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::classifier;
+use crate::game::corrections::{Correction, Corrections};
+use crate::game::instruction::{JudgeInstruction, Slot};
+use crate::game::node::{NodeType, CLARIFY, CONTINUE, LOOKUP, UNDECIDABLE};
+use crate::game::persona::Persona;
+use crate::game::save::SavedState;
+use crate::game::session::{contradiction, JudgeRequest, Reply, Session};
+use crate::game::template;
+use crate::game::tree::GameTree;
+use crate::game::vars::Vars;
+use crate::game::{build_judge_messages, prime_judge, Anomaly, CONTRADICTS};
+use crate::llm::{ChatMessage, LlmDecision, Usage, LLM};
+
+/// Label used in the confusion matrix when the judge call failed.
+const ERROR_LABEL: &str = "(error)";
+
+/// Accuracy a node may lose in translation, against the scenario as
+/// written, before its wording is flagged.
+const DEGRADED: f32 = 0.2;
+
+// ---------------------------------------------------------------------------
+// Test suite
+// ---------------------------------------------------------------------------
+
+/// A file of labeled judge cases:
+///
+/// ```yaml
+/// scenario: scenarios/airport.json
+/// cases:
+///   - node: START
+///     input: "Here is my passport."
+///     expected: QUESTION_1
+///   - turns:
+///       - node: START
+///         input: "Here you go."
+///         expected: QUESTION_1
+///       - input: "From Lisbon."
+///         expected: QUESTION_2
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvalSuite {
+    /// Scenario the node ids refer to. Can be overridden with `--scenario`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    pub cases: Vec<EvalEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EvalEntry {
+    /// One answer, judged right after the node's guard line.
+    Case(EvalCase),
+    /// A scripted conversation, judged turn by turn with the turns before
+    /// it as history. The script follows the expected decisions whatever
+    /// the judge picks, so every turn sees the same history.
+    Conversation { turns: Vec<EvalTurn> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvalCase {
+    pub node: String,
+    pub input: String,
+    pub expected: String,
+}
+
+/// A turn of a scripted conversation. Only the first turn needs a node;
+/// later ones are at the node the previous turn expected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvalTurn {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    pub input: String,
+    pub expected: String,
+}
+
+impl EvalSuite {
+    /// Read a suite from YAML, or JSON when the file ends in `.json`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let suite = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&text).map_err(anyhow::Error::from)
+        } else {
+            serde_yaml::from_str(&text).map_err(anyhow::Error::from)
+        };
+        suite.with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Write the suite as YAML, or JSON when the file ends in `.json`,
+    /// after a `# header` comment in YAML.
+    pub fn write(&self, path: &Path, header: &str) -> Result<()> {
+        let text = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::to_string_pretty(self)?
+        } else {
+            let comment: String = header.lines().map(|line| format!("# {line}\n")).collect();
+            comment + &serde_yaml::to_string(self)?
+        };
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Check every case against the scenario before any model time is spent,
+    /// and resolve each entry into the cases it judges.
+    fn validate(&self, tree: &GameTree) -> Result<Vec<Script>> {
+        let mut scripts = Vec::with_capacity(self.cases.len());
+        for (i, entry) in self.cases.iter().enumerate() {
+            let n = i + 1;
+            let script = match entry {
+                EvalEntry::Case(case) => {
+                    check_case(tree, case).with_context(|| format!("case {n}"))?;
+                    Script::Single(case.clone())
+                }
+                EvalEntry::Conversation { turns } => {
+                    if turns.is_empty() {
+                        bail!("case {n}: a conversation needs at least one turn");
+                    }
+                    let mut cases: Vec<EvalCase> = Vec::with_capacity(turns.len());
+                    for (t, turn) in turns.iter().enumerate() {
+                        let at = cases.last().map(|c| c.expected.as_str());
+                        let node = match (&turn.node, at) {
+                            (Some(node), Some(at)) if node != at => bail!(
+                                "case {n}, turn {}: the script is at '{at}', not '{node}'",
+                                t + 1
+                            ),
+                            (Some(node), _) => node.clone(),
+                            (None, Some(at)) => at.to_string(),
+                            (None, None) => bail!("case {n}: the first turn needs a node"),
+                        };
+                        let case = EvalCase {
+                            node,
+                            input: turn.input.clone(),
+                            expected: turn.expected.clone(),
+                        };
+                        check_case(tree, &case)
+                            .with_context(|| format!("case {n}, turn {}", t + 1))?;
+                        cases.push(case);
+                    }
+                    Script::Conversation(cases)
+                }
+            };
+            scripts.push(script);
+        }
+        Ok(scripts)
+    }
+}
+
+/// An entry resolved by [`EvalSuite::validate`].
+#[derive(Debug)]
+enum Script {
+    Single(EvalCase),
+    Conversation(Vec<EvalCase>),
+}
+
+impl Script {
+    fn len(&self) -> usize {
+        match self {
+            Script::Single(_) => 1,
+            Script::Conversation(cases) => cases.len(),
+        }
+    }
+}
+
+fn check_case(tree: &GameTree, case: &EvalCase) -> Result<()> {
+    let Some(node) = tree.get(&case.node) else {
+        bail!("node '{}' is not in the scenario", case.node);
+    };
+    let NodeType::Decision(next_nodes) = &node.node_type else {
+        bail!("node '{}' is not judged", case.node);
+    };
+    let contradiction = node.contradiction.as_deref() == Some(case.expected.as_str());
+    let stays = match case.expected.as_str() {
+        CLARIFY => node.clarify.is_some(),
+        CONTINUE => node.turns().is_some(),
+        UNDECIDABLE => tree.undecidable.is_some(),
+        _ => false,
+    };
+    if !contradiction && !stays && !next_nodes.iter().any(|next| next.id == case.expected) {
+        bail!(
+            "'{}' is not a choice of node '{}'",
+            case.expected,
+            case.node
+        );
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Running
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct CaseResult {
+    pub case: EvalCase,
+    /// Turn of a scripted conversation (from 1), `None` for single cases.
+    pub turn: Option<usize>,
+    /// The judge's decision, or `None` when the call failed.
+    pub decision: Option<String>,
+    /// The judge's reason, or the error message.
+    pub reason: String,
+    /// The judge's output as generated, empty when the call failed.
+    pub raw: String,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.decision.as_deref() == Some(self.case.expected.as_str())
+    }
+
+    pub fn label(&self) -> &str {
+        self.decision.as_deref().unwrap_or(ERROR_LABEL)
+    }
+}
+
+/// Judge every case of `suite`. A single case is judged in isolation: the
+/// conversation is just the node's guard line followed by the case's input.
+/// Each turn of a conversation is judged and scored like a case of its own.
+/// In strict mode a failed judge call aborts the run instead of being
+/// scored as `(error)`. With a failure library, the judge sees its
+/// corrective examples.
+pub fn evaluate(
+    model: &mut LLM,
+    tree: &GameTree,
+    suite: &EvalSuite,
+    strict: bool,
+    mut corrections: Option<&mut Corrections>,
+) -> Result<EvalReport> {
+    let scripts = suite.validate(tree)?;
+    prime_judge(model, tree);
+    let total: usize = scripts.iter().map(Script::len).sum();
+
+    let mut results = Vec::with_capacity(total);
+    for script in scripts {
+        let judged = match script {
+            Script::Single(case) => {
+                vec![judge_case(
+                    model,
+                    tree,
+                    case,
+                    strict,
+                    corrections.as_deref_mut(),
+                )?]
+            }
+            Script::Conversation(cases) => {
+                judge_conversation(model, tree, cases, strict, corrections.as_deref_mut())?
+            }
+        };
+        for result in judged {
+            eprintln!(
+                "[{}/{total}] {} {}{}",
+                results.len() + 1,
+                if result.passed() { "ok  " } else { "FAIL" },
+                result.case.node,
+                result
+                    .turn
+                    .map_or(String::new(), |turn| format!(" (turn {turn})"))
+            );
+            results.push(result);
+        }
+    }
+    Ok(EvalReport {
+        scenario: tree.revision(),
+        results,
+    })
+}
+
+fn judge_case(
+    model: &mut LLM,
+    tree: &GameTree,
+    case: EvalCase,
+    strict: bool,
+    corrections: Option<&mut Corrections>,
+) -> Result<CaseResult> {
+    let mut node = tree.get(&case.node).unwrap().clone();
+    // Cases stand alone, so judge them as at the first answer that may be
+    // decided.
+    if let Some((min, _)) = node.turns() {
+        node.offer_continue(min);
+    }
+    node.offer_clarify();
+    let node = &node;
+    let NodeType::Decision(next_nodes) = &node.node_type else {
+        unreachable!("validated above");
+    };
+
+    let guard_line = template::render(node.transcript.first(), tree.persona.as_ref()).neutral;
+    let conversation = [
+        ChatMessage::assistant(guard_line),
+        ChatMessage::user(&case.input),
+    ];
+    let mut instruction = match corrections {
+        Some(corrections) => corrections.instruction(model, &case.node, &case.input),
+        None => JudgeInstruction::new(),
+    };
+    if let Some(persona) = tree.persona.as_ref().and_then(Persona::describe) {
+        instruction.add(Slot::Scene, "persona", usize::MAX, [persona]);
+    }
+    let messages = build_judge_messages(&conversation, node, &tree.setting(), instruction)?;
+    let valid_choices: Vec<&str> = next_nodes.iter().map(|n| n.id.as_str()).collect();
+
+    let mut raw = String::new();
+    let criteria: Vec<_> = next_nodes
+        .iter()
+        .map(|n| (n.id.clone(), n.description.clone()))
+        .collect();
+    let judged = match node.rule_for(&case.input, &valid_choices) {
+        Some(rule) => Ok(rule.decision()),
+        None => classifier::judge_or_classify(model, &case.input, &criteria, |model| {
+            model.judge_with(node.model.as_deref(), &messages, &valid_choices, |piece| {
+                raw.push_str(piece)
+            })
+        }),
+    };
+    Ok(match judged {
+        Ok(d) => CaseResult {
+            case,
+            turn: None,
+            decision: Some(d.decision),
+            reason: d.reason,
+            raw,
+        },
+        Err(e) => {
+            let detail = format!("{e:#}");
+            Anomaly::new(
+                "judge failure",
+                node,
+                &case.input,
+                &valid_choices,
+                detail.clone(),
+            )
+            .report(strict)?;
+            CaseResult {
+                case,
+                turn: None,
+                decision: None,
+                reason: detail,
+                raw: String::new(),
+            }
+        }
+    })
+}
+
+/// Judge `request` as a game would, collecting the judge's output in `raw`.
+fn judge_request(model: &mut LLM, request: &JudgeRequest, raw: &mut String) -> Result<LlmDecision> {
+    classifier::judge_or_classify(model, request.input(), request.criteria(), |model| {
+        model.judge_with(
+            request.model(),
+            &request.messages,
+            &request.valid_choices(),
+            |piece| raw.push_str(piece),
+        )
+    })
+}
+
+/// Play a scripted conversation through a [`Session`] started at its first
+/// node, so the judge sees the real history (windowed and summarized like
+/// in a game) and the game variables gathered on the way.
+fn judge_conversation(
+    model: &mut LLM,
+    tree: &GameTree,
+    cases: Vec<EvalCase>,
+    strict: bool,
+    mut corrections: Option<&mut Corrections>,
+) -> Result<Vec<CaseResult>> {
+    let start = SavedState {
+        current_node_id: cases[0].node.clone(),
+        conversation: Vec::new(),
+        steps_completed: 0,
+        vars: Vars::new(),
+        suspicion: 0,
+        visits: Default::default(),
+        score: 0,
+        streak: 0,
+        calm: 0,
+        rng: None,
+    };
+    let mut session = Session::new(tree.clone(), Some(start), strict, 0);
+
+    let mut results = Vec::with_capacity(cases.len());
+    for (t, case) in cases.into_iter().enumerate() {
+        let instruction = match corrections.as_deref_mut() {
+            Some(corrections) => corrections.instruction(model, &case.node, &case.input),
+            None => JudgeInstruction::new(),
+        };
+        let mut request = session.ask_with(&case.input, instruction)?;
+        let mut raw = String::new();
+        let mut judged = match request.ruled() {
+            Some(decision) => Ok(decision),
+            None => judge_request(model, &request, &mut raw),
+        };
+        // Read the regulation the judge looked up, as in a game.
+        while judged.as_ref().is_ok_and(|d| d.decision == LOOKUP)
+            && request.valid_choices().contains(&LOOKUP)
+        {
+            let Reply::Reask(again) = session.apply(request, judged, Usage::default())? else {
+                unreachable!("a lookup is asked again");
+            };
+            request = again;
+            judged = judge_request(model, &request, &mut raw);
+        }
+
+        // Score what the game would have done, then stay on the script.
+        let mut scripted = LlmDecision {
+            decision: case.expected.clone(),
+            reason: String::new(),
+            ..Default::default()
+        };
+        let result = match judged {
+            Ok(d) => {
+                let decision = contradiction(session.node(), &d)
+                    .unwrap_or(&d.decision)
+                    .to_string();
+                scripted.facts = d.facts;
+                scripted.facts.remove(CONTRADICTS);
+                CaseResult {
+                    case,
+                    turn: Some(t + 1),
+                    decision: Some(decision),
+                    reason: d.reason,
+                    raw,
+                }
+            }
+            Err(e) => {
+                let detail = format!("{e:#}");
+                Anomaly::new(
+                    "judge failure",
+                    session.node(),
+                    &case.input,
+                    &request.valid_choices(),
+                    detail.clone(),
+                )
+                .report(strict)?;
+                CaseResult {
+                    case,
+                    turn: Some(t + 1),
+                    decision: None,
+                    reason: detail,
+                    raw: String::new(),
+                }
+            }
+        };
+        if session.node().contradiction.as_deref() == Some(scripted.decision.as_str()) {
+            scripted.facts.insert(CONTRADICTS.to_string(), true.into());
+        }
+        // An expected UNDECIDABLE stays at the node.
+        if let Reply::Reask(request) = session.apply(request, Ok(scripted), Usage::default())? {
+            session.withdraw(request);
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+// ---------------------------------------------------------------------------
+// Report
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct EvalReport {
+    /// Revision of the scenario judged against ([`GameTree::revision`]), so
+    /// results from different scenario versions are not compared blindly.
+    pub scenario: String,
+    pub results: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn accuracy(&self) -> f32 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        passed as f32 / self.results.len() as f32
+    }
+
+    /// Cases passed and judged at each node, by node id.
+    pub fn node_accuracy(&self) -> BTreeMap<&str, (usize, usize)> {
+        let mut nodes = BTreeMap::new();
+        for r in &self.results {
+            let (passed, total) = nodes.entry(r.case.node.as_str()).or_insert((0, 0));
+            *passed += usize::from(r.passed());
+            *total += 1;
+        }
+        nodes
+    }
+
+    /// Labels (sorted) and counts indexed `[expected][predicted]`.
+    pub fn confusion_matrix(&self) -> (Vec<String>, Vec<Vec<usize>>) {
+        let labels: Vec<String> = self
+            .results
+            .iter()
+            .flat_map(|r| [r.case.expected.as_str(), r.label()])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let index = |label: &str| labels.iter().position(|l| l == label).unwrap();
+
+        let mut matrix = vec![vec![0; labels.len()]; labels.len()];
+        for r in &self.results {
+            matrix[index(&r.case.expected)][index(r.label())] += 1;
+        }
+        (labels, matrix)
+    }
+
+    /// Add every misjudged case to the failure library. Judge errors are
+    /// skipped, since there is no wrong decision to correct. Returns how
+    /// many were new.
+    pub fn record_failures(&self, corrections: &mut Corrections) -> Result<usize> {
+        let mut added = 0;
+        for r in self.results.iter().filter(|r| !r.passed()) {
+            let Some(judged) = &r.decision else {
+                continue;
+            };
+            let correction = Correction {
+                node: r.case.node.clone(),
+                input: r.case.input.clone(),
+                judged: judged.clone(),
+                expected: r.case.expected.clone(),
+            };
+            if corrections.record(correction)? {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn print(&self) {
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        println!("\nScenario: {}", self.scenario);
+        println!(
+            "Accuracy: {:.1}% ({passed}/{})",
+            100.0 * self.accuracy(),
+            self.results.len()
+        );
+
+        let (labels, matrix) = self.confusion_matrix();
+        let width = labels.iter().map(String::len).max().unwrap_or(0).max(8);
+        println!("\nConfusion matrix (rows: expected, columns: judged)");
+        print!("{:width$}", "");
+        for label in &labels {
+            print!("  {label:>width$}");
+        }
+        println!();
+        for (label, row) in labels.iter().zip(&matrix) {
+            print!("{label:width$}");
+            for count in row {
+                print!("  {count:>width$}");
+            }
+            println!();
+        }
+
+        let failures: Vec<&CaseResult> = self.results.iter().filter(|r| !r.passed()).collect();
+        if !failures.is_empty() {
+            println!("\nFailing cases:");
+        }
+        for r in failures {
+            match r.turn {
+                Some(turn) => println!("\n  [{}, turn {turn}] \"{}\"", r.case.node, r.case.input),
+                None => println!("\n  [{}] \"{}\"", r.case.node, r.case.input),
+            }
+            println!("    expected {}, judged {}", r.case.expected, r.label());
+            println!("    reason: {}", r.reason);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Language matrix
+// ---------------------------------------------------------------------------
+
+/// The same suite judged against the scenario as written and in each
+/// language of its `locales`.
+#[derive(Debug)]
+pub struct LanguageMatrix {
+    pub written: EvalReport,
+    pub languages: Vec<(String, EvalReport)>,
+}
+
+/// A node whose translated criteria the judge gets wrong more often.
+#[derive(Debug, PartialEq)]
+pub struct Degraded<'a> {
+    pub lang: &'a str,
+    pub node: &'a str,
+    pub written: f32,
+    pub translated: f32,
+}
+
+/// Judge `suite` against `tree` as written, then against each of its
+/// translations. The cases' answers stay as they are.
+pub fn evaluate_languages(
+    model: &mut LLM,
+    tree: &GameTree,
+    suite: &EvalSuite,
+    strict: bool,
+    mut corrections: Option<&mut Corrections>,
+) -> Result<LanguageMatrix> {
+    let languages = tree.languages();
+    if languages.is_empty() {
+        bail!("the scenario has no \"locales\" to compare");
+    }
+    eprintln!("As written:");
+    let written = evaluate(model, tree, suite, strict, corrections.as_deref_mut())?;
+    let mut reports = Vec::with_capacity(languages.len());
+    for lang in languages {
+        eprintln!("\n{lang}:");
+        let mut translated = tree.clone();
+        translated.localize(lang);
+        let report = evaluate(
+            model,
+            &translated,
+            suite,
+            strict,
+            corrections.as_deref_mut(),
+        )?;
+        reports.push((lang.to_string(), report));
+    }
+    Ok(LanguageMatrix {
+        written,
+        languages: reports,
+    })
+}
+
+impl LanguageMatrix {
+    /// Nodes that lost at least [`DEGRADED`] accuracy in a language, by
+    /// language and node.
+    pub fn degraded(&self) -> Vec<Degraded<'_>> {
+        let written = self.written.node_accuracy();
+        let mut flagged = Vec::new();
+        for (lang, report) in &self.languages {
+            for (node, (passed, total)) in report.node_accuracy() {
+                let Some(&(was, of)) = written.get(node) else {
+                    continue;
+                };
+                let written = was as f32 / of as f32;
+                let translated = passed as f32 / total as f32;
+                if written - translated >= DEGRADED {
+                    flagged.push(Degraded {
+                        lang,
+                        node,
+                        written,
+                        translated,
+                    });
+                }
+            }
+        }
+        flagged
+    }
+
+    pub fn print(&self) {
+        println!("\nScenario: {}", self.written.scenario);
+        let written = self.written.accuracy();
+        println!("\nLanguage    Accuracy     Delta");
+        println!("(written)  {:>8.1}%", 100.0 * written);
+        for (lang, report) in &self.languages {
+            let accuracy = report.accuracy();
+            println!(
+                "{lang:10} {:>8.1}%  {:>+7.1}",
+                100.0 * accuracy,
+                100.0 * (accuracy - written)
+            );
+        }
+
+        let degraded = self.degraded();
+        if degraded.is_empty() {
+            return;
+        }
+        println!("\nTranslations the judge does worse on:");
+        for d in degraded {
+            println!(
+                "  [{}] {}: {:.0}% -> {:.0}%",
+                d.lang,
+                d.node,
+                100.0 * d.written,
+                100.0 * d.translated
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(expected: &str, decision: Option<&str>) -> CaseResult {
+        CaseResult {
+            case: EvalCase {
+                node: "START".into(),
+                input: "hello".into(),
+                expected: expected.into(),
+            },
+            turn: None,
+            decision: decision.map(String::from),
+            reason: String::new(),
+            raw: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_accuracy_and_confusion_matrix() {
+        let report = EvalReport {
+            scenario: "0123456789abcdef".into(),
+            results: vec![
+                result("PASS", Some("PASS")),
+                result("PASS", Some("FAIL")),
+                result("FAIL", Some("FAIL")),
+                result("FAIL", None),
+            ],
+        };
+        assert_eq!(report.accuracy(), 0.5);
+
+        let (labels, matrix) = report.confusion_matrix();
+        assert_eq!(labels, vec!["(error)", "FAIL", "PASS"]);
+        // Expected FAIL: one error, one correct.
+        assert_eq!(matrix[1], vec![1, 1, 0]);
+        // Expected PASS: one judged FAIL, one correct.
+        assert_eq!(matrix[2], vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn test_language_matrix_flags_degraded_nodes() {
+        let report = |results| EvalReport {
+            scenario: "0123456789abcdef".into(),
+            results,
+        };
+        let matrix = LanguageMatrix {
+            written: report(vec![
+                result("PASS", Some("PASS")),
+                result("PASS", Some("PASS")),
+            ]),
+            languages: vec![
+                (
+                    "de".into(),
+                    report(vec![result("PASS", Some("FAIL")), result("PASS", None)]),
+                ),
+                (
+                    "fr".into(),
+                    report(vec![
+                        result("PASS", Some("PASS")),
+                        result("PASS", Some("PASS")),
+                    ]),
+                ),
+            ],
+        };
+        assert_eq!(
+            matrix.degraded(),
+            [Degraded {
+                lang: "de",
+                node: "START",
+                written: 1.0,
+                translated: 0.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_conversation_turns_follow_the_script() {
+        let tree = crate::game::tree::airport_security_scenario();
+        let suite: EvalSuite = serde_json::from_str(
+            r#"{"cases": [
+                {"node": "START", "input": "Hi.", "expected": "PASSPORT_CHECK"},
+                {"turns": [
+                    {"node": "START", "input": "Here.", "expected": "PASSPORT_CHECK"},
+                    {"input": "Lisbon.", "expected": "QUESTION_PURPOSE"}]}]}"#,
+        )
+        .unwrap();
+        let scripts = suite.validate(&tree).unwrap();
+        let Script::Conversation(cases) = &scripts[1] else {
+            panic!("expected a conversation");
+        };
+        assert_eq!(cases[1].node, "PASSPORT_CHECK");
+        assert_eq!(scripts.iter().map(Script::len).sum::<usize>(), 3);
+
+        let off_script: EvalSuite = serde_json::from_str(
+            r#"{"cases": [{"turns": [
+                {"node": "START", "input": "a", "expected": "FAILED"},
+                {"input": "b", "expected": "CLEARED"}]}]}"#,
+        )
+        .unwrap();
+        let err = off_script.validate(&tree).unwrap_err();
+        assert!(format!("{err:#}").contains("case 1, turn 2"));
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::game::node::NodeType;
+use crate::game::tree::GameTree;
+use crate::game::vars::Condition;
+
+// ---------------------------------------------------------------------------
+// Graph walk
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Decision,
+    /// The engine picks the next node by chance.
+    Random,
+    /// The game's state picks the next node.
+    Interlude,
+    Success,
+    Failure,
+    /// Referenced by an edge but not defined in the scenario.
+    Missing,
+}
+
+#[derive(Debug)]
+pub struct GraphNode<'a> {
+    pub id: &'a str,
+    pub kind: NodeKind,
+    /// The guard's line, empty for missing nodes.
+    pub transcript: &'a str,
+}
+
+#[derive(Debug)]
+pub struct GraphEdge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub description: &'a str,
+    /// Share of the branches of a random node, in `[0, 1]`.
+    pub chance: Option<f32>,
+    /// Condition the edge is only offered under.
+    pub when: Option<&'a Condition>,
+}
+
+impl GraphEdge<'_> {
+    fn label(&self) -> String {
+        let mut label = excerpt(self.description, 40);
+        if let Some(when) = self.when {
+            label = format!("[{when}] {label}");
+        }
+        match self.chance {
+            Some(chance) => format!("{:.0}%: {label}", chance * 100.0),
+            None => label,
+        }
+    }
+}
+
+/// Label of the edge from a failure terminal to its appeal continuation.
+const APPEAL_EDGE: &str = "appeal granted";
+/// Label of the edge taken when an answer contradicts earlier ones.
+const CONTRADICTION_EDGE: &str = "contradicts earlier answers";
+/// Label of the loop taken when the judge asks a follow-up.
+const CLARIFY_EDGE: &str = "unclear: follow-up question";
+/// Label of the loop taken while a multi-turn node's conversation goes on.
+const CONTINUE_EDGE: &str = "keeps talking";
+
+/// A scenario flattened for exporters. Nodes are ordered breadth-first from
+/// the start node, followed by unreachable ones sorted by id, so the output
+/// is stable across runs.
+#[derive(Debug)]
+pub struct Graph<'a> {
+    pub start: &'a str,
+    pub nodes: Vec<GraphNode<'a>>,
+    pub edges: Vec<GraphEdge<'a>>,
+}
+
+impl<'a> Graph<'a> {
+    pub fn new(tree: &'a GameTree) -> Self {
+        let mut order: Vec<&str> = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut queue = VecDeque::from([tree.start_node_id.as_str()]);
+        // Suspicion thresholds and the strikes warning can be reached from
+        // any decision node; they come after everything reachable by edges.
+        let mut anywhere: VecDeque<&str> = tree
+            .suspicion
+            .iter()
+            .flat_map(|meter| meter.thresholds.iter().map(|t| t.node.as_str()))
+            .chain(tree.strikes.iter().map(|strikes| strikes.warning.as_str()))
+            .collect();
+        while let Some(id) = queue.pop_front().or_else(|| anywhere.pop_front()) {
+            if !seen.insert(id) {
+                continue;
+            }
+            order.push(id);
+            let Some(node) = tree.get(id) else {
+                continue;
+            };
+            queue.extend(node.node_type.next_nodes().map(|n| n.id.as_str()));
+            if let Some(appeal) = &node.appeal {
+                queue.push_back(appeal.granted.as_str());
+            }
+            if let Some(contradiction) = &node.contradiction {
+                queue.push_back(contradiction.as_str());
+            }
+        }
+        let unreachable: BTreeSet<&str> = tree
+            .nodes
+            .keys()
+            .map(String::as_str)
+            .filter(|id| !seen.contains(id))
+            .collect();
+        order.extend(unreachable);
+
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for id in order {
+            let Some(node) = tree.get(id) else {
+                nodes.push(GraphNode {
+                    id,
+                    kind: NodeKind::Missing,
+                    transcript: "",
+                });
+                continue;
+            };
+            let kind = match &node.node_type {
+                NodeType::Terminal(true) => NodeKind::Success,
+                NodeType::Terminal(false) => NodeKind::Failure,
+                NodeType::Decision(next) => {
+                    edges.extend(next.iter().map(|n| GraphEdge {
+                        from: node.id.as_str(),
+                        to: n.id.as_str(),
+                        description: n.description.as_str(),
+                        chance: None,
+                        when: n.when.as_ref(),
+                    }));
+                    NodeKind::Decision
+                }
+                NodeType::Random(branches) => {
+                    let total: f32 = branches.iter().map(|(_, w)| w.max(0.0)).sum();
+                    edges.extend(branches.iter().map(|(n, w)| GraphEdge {
+                        from: node.id.as_str(),
+                        to: n.id.as_str(),
+                        description: n.description.as_str(),
+                        chance: Some(if total > 0.0 { w.max(0.0) / total } else { 0.0 }),
+                        when: n.when.as_ref(),
+                    }));
+                    NodeKind::Random
+                }
+                NodeType::Interlude(next) => {
+                    edges.extend(next.iter().map(|n| GraphEdge {
+                        from: node.id.as_str(),
+                        to: n.id.as_str(),
+                        description: n.description.as_str(),
+                        chance: None,
+                        when: n.when.as_ref(),
+                    }));
+                    NodeKind::Interlude
+                }
+            };
+            if let Some(appeal) = &node.appeal {
+                edges.push(GraphEdge {
+                    from: node.id.as_str(),
+                    to: appeal.granted.as_str(),
+                    description: APPEAL_EDGE,
+                    chance: None,
+                    when: None,
+                });
+            }
+            if let Some(contradiction) = &node.contradiction {
+                edges.push(GraphEdge {
+                    from: node.id.as_str(),
+                    to: contradiction.as_str(),
+                    description: CONTRADICTION_EDGE,
+                    chance: None,
+                    when: None,
+                });
+            }
+            if node.clarify.is_some() {
+                edges.push(GraphEdge {
+                    from: node.id.as_str(),
+                    to: node.id.as_str(),
+                    description: CLARIFY_EDGE,
+                    chance: None,
+                    when: None,
+                });
+            }
+            if node.turns().is_some() {
+                edges.push(GraphEdge {
+                    from: node.id.as_str(),
+                    to: node.id.as_str(),
+                    description: CONTINUE_EDGE,
+                    chance: None,
+                    when: None,
+                });
+            }
+            nodes.push(GraphNode {
+                id: node.id.as_str(),
+                kind,
+                transcript: node.transcript.first(),
+            });
+        }
+
+        Self {
+            start: tree.start_node_id.as_str(),
+            nodes,
+            edges,
+        }
+    }
+}
+
+/// Shorten a guard line for use in a node label.
+fn excerpt(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+// ---------------------------------------------------------------------------
+// DOT
+// ---------------------------------------------------------------------------
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the scenario as a Graphviz digraph. Terminal nodes are green
+/// (success) or red (failure); missing nodes are dashed.
+pub fn to_dot(tree: &GameTree) -> String {
+    let graph = Graph::new(tree);
+    let mut out = String::new();
+    out.push_str("digraph scenario {\n");
+    out.push_str("    rankdir=TB;\n");
+    out.push_str("    node [shape=box, style=\"rounded,filled\", fillcolor=white, fontname=\"Helvetica\"];\n");
+    out.push_str("    edge [fontname=\"Helvetica\", fontsize=10];\n\n");
+
+    for node in &graph.nodes {
+        let label = match node.kind {
+            NodeKind::Missing => format!("{}\\n(missing)", dot_escape(node.id)),
+            _ => format!(
+                "{}\\n{}",
+                dot_escape(node.id),
+                dot_escape(&excerpt(node.transcript, 40))
+            ),
+        };
+        let style = match node.kind {
+            NodeKind::Decision if node.id == graph.start => ", penwidth=2",
+            NodeKind::Decision => "",
+            NodeKind::Random => ", shape=diamond, fillcolor=\"#f7f0c5\"",
+            NodeKind::Interlude => ", shape=note, fillcolor=\"#dde8f7\"",
+            NodeKind::Success => ", shape=doubleoctagon, fillcolor=\"#c8f7c5\"",
+            NodeKind::Failure => ", shape=doubleoctagon, fillcolor=\"#f7c5c5\"",
+            NodeKind::Missing => ", style=dashed, color=red",
+        };
+        let _ = writeln!(
+            out,
+            "    \"{}\" [label=\"{label}\"{style}];",
+            dot_escape(node.id)
+        );
+    }
+    out.push('\n');
+
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "    \"{}\" -> \"{}\" [label=\"{}\"];",
+            dot_escape(edge.from),
+            dot_escape(edge.to),
+            dot_escape(&edge.label())
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Mermaid
+// ---------------------------------------------------------------------------
+
+/// Mermaid node ids may only contain letters, digits and underscores.
+fn mermaid_id(id: &str) -> String {
+    id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', " ")
+}
+
+/// Render the scenario as a Mermaid flowchart, ready to paste into a
+/// ```` ```mermaid ```` block on GitHub or in Obsidian.
+pub fn to_mermaid(tree: &GameTree) -> String {
+    let graph = Graph::new(tree);
+    let mut out = String::from("flowchart TD\n");
+
+    for node in &graph.nodes {
+        let id = mermaid_id(node.id);
+        let label = match node.kind {
+            NodeKind::Missing => format!("{}<br/>(missing)", mermaid_escape(node.id)),
+            _ => format!(
+                "{}<br/>{}",
+                mermaid_escape(node.id),
+                mermaid_escape(&excerpt(node.transcript, 40))
+            ),
+        };
+        let (open, close, class) = match node.kind {
+            NodeKind::Decision if node.id == graph.start => ("([", "])", ""),
+            NodeKind::Decision => ("[", "]", ""),
+            NodeKind::Random => ("{", "}", ":::random"),
+            NodeKind::Interlude => ("[/", "/]", ":::interlude"),
+            NodeKind::Success => ("{{", "}}", ":::success"),
+            NodeKind::Failure => ("{{", "}}", ":::failure"),
+            NodeKind::Missing => ("[", "]", ":::missing"),
+        };
+        let _ = writeln!(out, "    {id}{open}\"{label}\"{close}{class}");
+    }
+
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "    {} -->|\"{}\"| {}",
+            mermaid_id(edge.from),
+            mermaid_escape(&edge.label()),
+            mermaid_id(edge.to)
+        );
+    }
+
+    out.push_str("    classDef success fill:#c8f7c5,stroke:#2e7d32\n");
+    out.push_str("    classDef failure fill:#f7c5c5,stroke:#c62828\n");
+    out.push_str("    classDef missing stroke:#c62828,stroke-dasharray:5 5\n");
+    out.push_str("    classDef random fill:#f7f0c5,stroke:#f9a825\n");
+    out.push_str("    classDef interlude fill:#dde8f7,stroke:#1565c0\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tree::airport_security_scenario;
+
+    #[test]
+    fn test_walk_starts_at_start_node() {
+        let tree = airport_security_scenario();
+        let graph = Graph::new(&tree);
+        assert_eq!(graph.nodes[0].id, tree.start_node_id);
+        let defined = graph
+            .nodes
+            .iter()
+            .filter(|n| n.kind != NodeKind::Missing)
+            .count();
+        assert_eq!(defined, tree.nodes.len());
+        assert!(graph.nodes.iter().any(|n| n.kind == NodeKind::Success));
+    }
+
+    #[test]
+    fn test_dot_output() {
+        let dot = to_dot(&airport_security_scenario());
+        assert!(dot.starts_with("digraph scenario {"));
+        assert!(dot.contains("fillcolor=\"#c8f7c5\""));
+        assert!(dot.contains(" -> "));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_mermaid_output() {
+        let mermaid = to_mermaid(&airport_security_scenario());
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains(":::success"));
+        assert!(mermaid.contains(" -->|"));
+        assert_eq!(mermaid_id("step-2.a"), "step_2_a");
+    }
+}
@@ -0,0 +1,178 @@
+use log::debug;
+
+use crate::game::instruction::estimate_tokens;
+use crate::llm::ChatMessage;
+
+/// Guard lines quoted in the truncation marker, most recent last.
+const SUMMARY_LINES: usize = 3;
+/// Each quoted guard line is shortened to this many characters.
+const SUMMARY_LINE_CHARS: usize = 80;
+
+/// Line added to the judge instruction whenever the history was cut, so
+/// the judge knows the marker is not part of the exchange.
+pub const JUDGE_NOTE: &str = "\
+Earlier turns were replaced by a bracketed summary. Judge only the last response; do not penalize it for referring to details that are missing from the summary.";
+
+/// Conversation as sent to the judge: the newest turns that fit the
+/// budget, preceded by a marker message when older turns were dropped.
+#[derive(Debug)]
+pub struct History {
+    pub messages: Vec<ChatMessage>,
+    /// Number of messages replaced by the marker.
+    pub omitted: usize,
+}
+
+impl History {
+    pub fn truncated(&self) -> bool {
+        self.omitted > 0
+    }
+}
+
+/// Keep the newest messages within `budget` tokens. Older messages are
+/// dropped whole, never silently: a system message of the form
+/// `[earlier conversation summarized: ...]` takes their place. The last
+/// exchange is always kept, and the kept part never starts with an answer
+/// whose question was dropped.
+pub fn window(conversation: &[ChatMessage], budget: usize) -> History {
+    let min_keep = conversation.len().min(2);
+    let mut start = conversation.len() - min_keep;
+    let mut used: usize = conversation[start..]
+        .iter()
+        .map(|m| estimate_tokens(&m.content))
+        .sum();
+    while start > 0 {
+        let cost = estimate_tokens(&conversation[start - 1].content);
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        start -= 1;
+    }
+    if start > 0 && conversation[start].role == "user" && conversation.len() - start > min_keep {
+        start += 1;
+    }
+
+    if start == 0 {
+        return History {
+            messages: conversation.to_vec(),
+            omitted: 0,
+        };
+    }
+
+    debug!(
+        "History: summarized {start} of {} messages (budget {budget} tokens)",
+        conversation.len()
+    );
+    let mut messages = vec![marker(&conversation[..start])];
+    messages.extend_from_slice(&conversation[start..]);
+    History {
+        messages,
+        omitted: start,
+    }
+}
+
+/// Marker standing in for `dropped`: how much is missing and the last few
+/// things the guard asked.
+fn marker(dropped: &[ChatMessage]) -> ChatMessage {
+    let questions: Vec<&ChatMessage> = dropped.iter().filter(|m| m.role == "assistant").collect();
+    let quoted: Vec<String> = questions
+        .iter()
+        .skip(questions.len().saturating_sub(SUMMARY_LINES))
+        .map(|m| format!("\"{}\"", shorten(m.content.trim(), SUMMARY_LINE_CHARS)))
+        .collect();
+
+    let mut summary = format!("{} earlier messages omitted", dropped.len());
+    if !quoted.is_empty() {
+        summary.push_str(&format!("; the guard had asked {}", quoted.join(", ")));
+    }
+    ChatMessage::system(format!("[earlier conversation summarized: {summary}]"))
+}
+
+fn shorten(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::budget::PromptBudget;
+    use crate::game::build_judge_messages;
+    use crate::game::instruction::JudgeInstruction;
+    use crate::game::tree::GameTree;
+
+    fn exchange(turns: usize) -> Vec<ChatMessage> {
+        (0..turns)
+            .flat_map(|i| {
+                [
+                    ChatMessage::assistant(format!("Question {i}? {}", "x".repeat(40))),
+                    ChatMessage::user(format!("Answer {i}. {}", "y".repeat(40))),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_short_history_is_kept_whole() {
+        let conversation = exchange(3);
+        let history = window(&conversation, PromptBudget::default().history);
+        assert!(!history.truncated());
+        assert_eq!(history.messages, conversation);
+    }
+
+    #[test]
+    fn test_long_history_gets_marker() {
+        let conversation = exchange(10);
+        // Roughly three exchanges fit.
+        let history = window(&conversation, 80);
+        assert!(history.truncated());
+
+        let marker = &history.messages[0];
+        assert_eq!(marker.role, "system");
+        assert!(marker
+            .content
+            .starts_with("[earlier conversation summarized:"));
+        assert!(marker
+            .content
+            .contains(&format!("{} earlier messages omitted", history.omitted)));
+        // The kept part starts on a guard line and ends with the last answer.
+        assert_eq!(history.messages[1].role, "assistant");
+        assert_eq!(history.messages.last(), conversation.last());
+        assert_eq!(history.omitted + history.messages.len() - 1, 20);
+
+        // Even a zero budget keeps the exchange being judged.
+        let history = window(&conversation, 0);
+        assert_eq!(history.messages.len(), 3);
+        assert_eq!(history.omitted, 18);
+    }
+
+    #[test]
+    fn test_judge_is_told_about_the_marker() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {"A": {"id": "A", "transcript": "Hi",
+                "node_type": {"Decision": [{"id": "B", "description": "ok"}]}}}}"#,
+        )
+        .unwrap();
+        let node = tree.get("A").unwrap();
+
+        let messages =
+            build_judge_messages(&exchange(2), node, &tree.setting(), JudgeInstruction::new())
+                .unwrap();
+        assert!(!messages[0].content.contains(JUDGE_NOTE));
+
+        let messages = build_judge_messages(
+            &exchange(100),
+            node,
+            &tree.setting(),
+            JudgeInstruction::new(),
+        )
+        .unwrap();
+        assert!(messages[0].content.contains(JUDGE_NOTE));
+        assert!(messages[1]
+            .content
+            .starts_with("[earlier conversation summarized:"));
+        assert_eq!(messages.last().unwrap().content, exchange(100)[199].content);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+
+use crate::game::budget::{Allocation, Section};
+
+/// Where a contribution lands in the judge instruction. Slots render in
+/// declaration order; contributions within a slot keep the order they were
+/// added in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(dead_code)]
+pub enum Slot {
+    /// Roleplay context of the current node (`system_context`).
+    Scene,
+    /// Facts the judge should check answers against (dossier, inventory).
+    Facts,
+    /// Running game state (suspicion, ledger of earlier answers).
+    State,
+    /// Corrective examples of answers the judge got wrong before.
+    Examples,
+}
+
+impl Slot {
+    /// The prompt budget section the slot's lines are charged to.
+    fn section(self) -> Section {
+        match self {
+            Slot::Scene => Section::Criteria,
+            Slot::Facts | Slot::State => Section::Facts,
+            Slot::Examples => Section::Examples,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Contribution {
+    slot: Slot,
+    source: &'static str,
+    /// Approximate token budget; lines past it are dropped.
+    budget: usize,
+    lines: Vec<String>,
+}
+
+/// Collects lines from the subsystems that feed the judge, so each one
+/// contributes through the same place instead of editing prompt text
+/// directly.
+#[derive(Debug, Clone, Default)]
+pub struct JudgeInstruction {
+    contributions: Vec<Contribution>,
+    /// Cap on the rendered text across all contributions. `None` = unlimited.
+    total_budget: Option<usize>,
+    /// Per-section caps from the prompt budget, see [`JudgeInstruction::limit`].
+    allocation: Option<Allocation>,
+    /// Extra keys the judge is asked to add to its JSON reply, with what
+    /// goes in them.
+    reply_keys: Vec<(&'static str, String)>,
+    /// Fast play: a lean prompt, and a one-token pick where the judge owes
+    /// nothing but the decision.
+    fast: bool,
+}
+
+/// Rough token count (about four characters per token for English text).
+/// Good enough for budgeting without a round-trip through the tokenizer.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+impl JudgeInstruction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(dead_code)]
+    pub fn with_total_budget(mut self, tokens: usize) -> Self {
+        self.total_budget = Some(tokens);
+        self
+    }
+
+    /// Add lines from `source` to `slot`, keeping at most `budget` tokens.
+    pub fn add(
+        &mut self,
+        slot: Slot,
+        source: &'static str,
+        budget: usize,
+        lines: impl IntoIterator<Item = String>,
+    ) {
+        self.contributions.push(Contribution {
+            slot,
+            source,
+            budget,
+            lines: lines.into_iter().filter(|l| !l.trim().is_empty()).collect(),
+        });
+    }
+
+    /// Ask the judge to add `key` to its reply; `what` describes the value.
+    pub fn reply_key(&mut self, key: &'static str, what: impl Into<String>) {
+        self.reply_keys.push((key, what.into()));
+    }
+
+    pub fn reply_keys(&self) -> &[(&'static str, String)] {
+        &self.reply_keys
+    }
+
+    /// Judge for fast play (`--fast`).
+    pub fn fast(&mut self) {
+        self.fast = true;
+    }
+
+    pub fn is_fast(&self) -> bool {
+        self.fast
+    }
+
+    /// Tokens the contributions charged to `section` would take, each
+    /// within its own budget.
+    pub fn demand(&self, section: Section) -> usize {
+        self.contributions
+            .iter()
+            .filter(|c| c.slot.section() == section)
+            .map(|c| {
+                c.lines
+                    .iter()
+                    .map(|line| estimate_tokens(line))
+                    .scan(0, |used, cost| {
+                        *used += cost;
+                        Some(*used)
+                    })
+                    .take_while(|used| *used <= c.budget)
+                    .last()
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+
+    /// Cap each section's lines at what the prompt budget granted it.
+    pub fn limit(&mut self, allocation: Allocation) {
+        self.allocation = Some(allocation);
+    }
+
+    /// Render every contribution in slot order. Each is trimmed to its own
+    /// budget first, then to its section's allocation and the total budget;
+    /// trimming drops whole lines.
+    pub fn render(&self) -> String {
+        let mut ordered: Vec<&Contribution> = self.contributions.iter().collect();
+        ordered.sort_by_key(|c| c.slot);
+
+        let mut remaining = self.total_budget.unwrap_or(usize::MAX);
+        let mut section_used: BTreeMap<Section, usize> = BTreeMap::new();
+        let mut out = Vec::new();
+        for c in ordered {
+            let section = c.slot.section();
+            let allowed = match self.allocation {
+                Some(allocation) if section.trimmable() => allocation.get(section),
+                _ => usize::MAX,
+            };
+            let mut used = 0;
+            for (i, line) in c.lines.iter().enumerate() {
+                let cost = estimate_tokens(line);
+                let section_used = section_used.entry(section).or_default();
+                if used + cost > c.budget || cost > remaining || *section_used + cost > allowed {
+                    debug!(
+                        "Judge instruction: dropped {} of {} lines from {} (budget {} tokens)",
+                        c.lines.len() - i,
+                        c.lines.len(),
+                        c.source,
+                        c.budget
+                    );
+                    break;
+                }
+                used += cost;
+                remaining -= cost;
+                *section_used += cost;
+                out.push(line.as_str());
+            }
+        }
+        out.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::budget::PromptBudget;
+
+    #[test]
+    fn test_slots_render_in_order() {
+        let mut instr = JudgeInstruction::new();
+        instr.add(
+            Slot::State,
+            "suspicion",
+            100,
+            ["Suspicion: low".to_string()],
+        );
+        instr.add(Slot::Scene, "node", 100, ["You are tired.".to_string()]);
+        instr.add(Slot::Facts, "dossier", 100, ["Name: Ada".to_string()]);
+        assert_eq!(instr.render(), "You are tired.\nName: Ada\nSuspicion: low");
+    }
+
+    #[test]
+    fn test_budgets_drop_whole_lines() {
+        let line = "x".repeat(40); // 10 tokens
+        let mut instr = JudgeInstruction::new();
+        instr.add(Slot::Facts, "dossier", 25, vec![line.clone(); 3]);
+        assert_eq!(instr.render().lines().count(), 2);
+
+        let mut instr = JudgeInstruction::new();
+        instr.add(
+            Slot::Examples,
+            "corrections",
+            usize::MAX,
+            vec![line.clone(); 3],
+        );
+        assert_eq!(instr.demand(Section::Examples), 30);
+        let budget = PromptBudget {
+            examples: 20,
+            ..PromptBudget::default()
+        };
+        let allocation = budget.allocate(|section| instr.demand(section));
+        instr.limit(allocation);
+        assert_eq!(instr.render().lines().count(), 2);
+
+        let mut instr = JudgeInstruction::new().with_total_budget(15);
+        instr.add(Slot::Facts, "dossier", 100, vec![line.clone(); 3]);
+        instr.add(Slot::State, "ledger", 100, vec![line; 3]);
+        assert_eq!(instr.render().lines().count(), 1);
+    }
+}
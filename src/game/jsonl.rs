@@ -0,0 +1,282 @@
+use std::io::{self, BufRead, StdinLock, Stdout, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::ambient::Ambient;
+use crate::game::beats::Beats;
+use crate::game::transcript::TurnRecord;
+use crate::game::ui::Ui;
+use crate::game::GameOutcome;
+use crate::rng::Rng;
+
+/// How play talks to the player.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Io {
+    /// The TUI or the plain terminal.
+    #[default]
+    Terminal,
+    /// Line-delimited JSON on stdin/stdout, see [`JsonlUi`].
+    Jsonl,
+}
+
+impl FromStr for Io {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "terminal" => Ok(Self::Terminal),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => bail!("unknown io mode '{s}' (expected terminal or jsonl)"),
+        }
+    }
+}
+
+/// One line written to stdout in JSONL mode.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The guard (or the supervisor, on appeal) speaks at a node.
+    NodeEntered {
+        node_id: String,
+        text: String,
+        steps_completed: usize,
+        total_steps: usize,
+    },
+    /// The engine is waiting for an input line. With `choices`, the message
+    /// must be one of them.
+    NeedInput {
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        choices: Vec<String>,
+    },
+    /// Out-of-band message, e.g. a retry or a save.
+    Notice { text: String },
+    /// The judge (or the supervisor) ruled on an answer.
+    Decision {
+        node_id: String,
+        input: String,
+        decision: String,
+        reason: String,
+        latency_ms: u64,
+    },
+    /// A round ended. `success` and `terminal_node_id` are absent when the
+    /// player quit.
+    GameOver {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        success: Option<bool>,
+        steps_completed: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        terminal_node_id: Option<String>,
+        summary: Vec<String>,
+    },
+    /// An input line could not be read; the engine waits for another one.
+    Error { message: String },
+}
+
+/// One line read from stdin in JSONL mode.
+#[derive(Debug, Deserialize)]
+struct Input {
+    message: String,
+}
+
+/// Front end for other programs: events go to `out` as JSON lines and
+/// every input is a `{"message": "..."}` line on `input`. Closing the input
+/// quits the game.
+pub struct JsonlUi<R, W> {
+    input: R,
+    out: W,
+    steps_completed: usize,
+    total_steps: usize,
+}
+
+impl JsonlUi<StdinLock<'static>, Stdout> {
+    pub fn stdio() -> Self {
+        Self::new(io::stdin().lock(), io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> JsonlUi<R, W> {
+    pub fn new(input: R, out: W) -> Self {
+        Self {
+            input,
+            out,
+            steps_completed: 0,
+            total_steps: 0,
+        }
+    }
+
+    fn emit(&mut self, event: Event) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)?;
+        self.out.flush().context("failed to write event")
+    }
+
+    /// Ask for a message and read it. `None` once the input is closed.
+    fn next_message(&mut self, choices: &[char]) -> Result<Option<String>> {
+        self.emit(Event::NeedInput {
+            choices: choices.iter().map(|c| c.to_string()).collect(),
+        })?;
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Input>(&line) {
+                Ok(input) => return Ok(Some(input.message)),
+                Err(e) => self.emit(Event::Error {
+                    message: format!("expected {{\"message\": \"...\"}}: {e}"),
+                })?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Ui for JsonlUi<R, W> {
+    fn guard_line(&mut self, node_id: &str, text: &str, _rng: &mut Rng) -> Result<()> {
+        self.emit(Event::NodeEntered {
+            node_id: node_id.to_string(),
+            text: text.to_string(),
+            steps_completed: self.steps_completed,
+            total_steps: self.total_steps,
+        })
+    }
+
+    fn read_input(&mut self) -> Result<String> {
+        Ok(self.next_message(&[])?.unwrap_or_else(|| "/quit".into()))
+    }
+
+    fn notice(&mut self, text: &str) -> Result<()> {
+        self.emit(Event::Notice {
+            text: text.to_string(),
+        })
+    }
+
+    fn progress(&mut self, steps_completed: usize, total_steps: usize) {
+        self.steps_completed = steps_completed;
+        self.total_steps = total_steps;
+    }
+
+    fn waiting<T>(
+        &mut self,
+        _ambient: Option<&Ambient>,
+        _beats: &Beats,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        f()
+    }
+
+    fn decision(&mut self, record: &TurnRecord) -> Result<()> {
+        self.emit(Event::Decision {
+            node_id: record.node_id.clone(),
+            input: record.player_input.clone(),
+            decision: record.decision.clone(),
+            reason: record.reason.clone(),
+            latency_ms: record.latency_ms,
+        })
+    }
+
+    fn game_over(&mut self, outcome: &GameOutcome) -> Result<()> {
+        let event = match outcome {
+            GameOutcome::Finished {
+                success,
+                steps_completed,
+                terminal_node_id,
+                ..
+            } => Event::GameOver {
+                success: Some(*success),
+                steps_completed: *steps_completed,
+                terminal_node_id: Some(terminal_node_id.clone()),
+                summary: outcome.summary(self.messages()),
+            },
+            GameOutcome::Quit {
+                steps_completed, ..
+            } => Event::GameOver {
+                success: None,
+                steps_completed: *steps_completed,
+                terminal_node_id: None,
+                summary: outcome.summary(self.messages()),
+            },
+        };
+        self.emit(event)
+    }
+
+    fn pick(&mut self, keys: &[char], hint: &str) -> Result<char> {
+        loop {
+            let Some(message) = self.next_message(keys)? else {
+                return Ok(*keys.last().expect("a choice has keys"));
+            };
+            match message.trim().to_lowercase().chars().collect::<Vec<_>>()[..] {
+                [c] if keys.contains(&c) => return Ok(c),
+                _ => self.notice(hint.trim())?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(out: &[u8]) -> Vec<serde_json::Value> {
+        out.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let input = "{\"message\": \"Here is my passport.\"}\nnot json\n{\"message\": \"x\"}\n{\"message\": \"Q\"}\n";
+        let mut out = Vec::new();
+        let mut ui = JsonlUi::new(input.as_bytes(), &mut out);
+
+        ui.progress(1, 4);
+        ui.guard_line("START", "Passport, please.", &mut Rng::new(1))
+            .unwrap();
+        assert_eq!(ui.read_input().unwrap(), "Here is my passport.");
+        assert!(!ui.choose('r', 'q', "  Press [r] or [q].").unwrap());
+        // Closed input quits.
+        assert_eq!(ui.read_input().unwrap(), "/quit");
+        assert!(!ui.choose('r', 'q', "").unwrap());
+
+        let events = events(&out);
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "node_entered",
+                "need_input",
+                "need_input",
+                "error",
+                "notice",
+                "need_input",
+                "need_input",
+                "need_input",
+            ]
+        );
+        assert_eq!(events[0]["steps_completed"], 1);
+        assert_eq!(events[0]["text"], "Passport, please.");
+        assert!(events[1].get("choices").is_none());
+        assert_eq!(events[2]["choices"], serde_json::json!(["r", "q"]));
+        assert_eq!(events[4]["text"], "Press [r] or [q].");
+    }
+
+    #[test]
+    fn test_game_over_event() {
+        let mut out = Vec::new();
+        JsonlUi::new(&b""[..], &mut out)
+            .game_over(&GameOutcome::Quit {
+                steps_completed: 2,
+                headline: "Bye.".into(),
+            })
+            .unwrap();
+        assert_eq!(
+            events(&out)[0],
+            serde_json::json!({"type": "game_over", "steps_completed": 2, "summary": ["Bye."]})
+        );
+    }
+}
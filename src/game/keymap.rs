@@ -0,0 +1,174 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// Keys the front ends read choices and shortcuts with, from the `[keys]`
+/// section of the config:
+///
+/// ```toml
+/// [keys]
+/// restart = "n"
+/// quit = "x"
+/// hint = "Ctrl+h"
+/// sidebar = "Tab"
+/// talk = "F3"
+/// skip = "F5"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keymap {
+    /// Game over: play again from the start.
+    pub restart: char,
+    /// Game over: retry from the last checkpoint.
+    pub retry: char,
+    /// Game over: the coach's feedback on a failed round.
+    pub feedback: char,
+    /// Game over and appeals: the way out (quit, or accept the decision).
+    pub quit: char,
+    /// Appeal a decision to the supervisor.
+    pub appeal: char,
+    /// TUI: ask for a hint, as if `/hint` was typed.
+    pub hint: Key,
+    /// TUI: show or hide the progress sidebar.
+    pub sidebar: Key,
+    /// TUI: answer out loud, as if `/talk` was typed.
+    pub talk: Key,
+    /// TUI: show the rest of a guard line being typed out.
+    pub skip: Key,
+}
+
+impl Keymap {
+    pub const DEFAULT: Keymap = Keymap {
+        restart: 'r',
+        retry: 'c',
+        feedback: 'f',
+        quit: 'q',
+        appeal: 'a',
+        hint: Key::F(1),
+        sidebar: Key::F(2),
+        talk: Key::F(3),
+        skip: Key::F(4),
+    };
+
+    /// Choice keys are typed on their own line by the plain front end and
+    /// compared lowercased, so they must be distinct lowercase letters or
+    /// digits.
+    pub fn validate(&self) -> Result<()> {
+        let choices = [
+            ("restart", self.restart),
+            ("retry", self.retry),
+            ("feedback", self.feedback),
+            ("quit", self.quit),
+            ("appeal", self.appeal),
+        ];
+        for (i, (name, key)) in choices.iter().enumerate() {
+            if !key.is_ascii_lowercase() && !key.is_ascii_digit() {
+                bail!("{name} key '{key}' must be a lowercase letter or a digit");
+            }
+            if let Some((other, _)) = choices[..i].iter().find(|(_, k)| k == key) {
+                bail!("{name} and {other} are both bound to '{key}'");
+            }
+        }
+        let shortcuts = [
+            ("hint", self.hint),
+            ("sidebar", self.sidebar),
+            ("talk", self.talk),
+            ("skip", self.skip),
+        ];
+        for (i, (name, key)) in shortcuts.iter().enumerate() {
+            if let Some((other, _)) = shortcuts[..i].iter().find(|(_, k)| k == key) {
+                bail!("{name} and {other} are both bound to {key}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A TUI shortcut. Plain characters are typed into the answer, so only
+/// keys that don't type anything can be bound.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Key {
+    /// F1 to F12.
+    F(u8),
+    /// A letter with Ctrl held.
+    Ctrl(char),
+    Tab,
+}
+
+impl TryFrom<String> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        let lower = name.to_ascii_lowercase();
+        if lower == "tab" {
+            return Ok(Key::Tab);
+        }
+        if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            if (1..=12).contains(&n) {
+                return Ok(Key::F(n));
+            }
+        }
+        if let Some(c) = lower.strip_prefix("ctrl+") {
+            if let [c] = c.chars().collect::<Vec<_>>()[..] {
+                // Ctrl+C always quits.
+                if c.is_ascii_lowercase() && c != 'c' {
+                    return Ok(Key::Ctrl(c));
+                }
+            }
+        }
+        bail!("'{name}' is not a key that can be bound (use F1-F12, Ctrl+<letter> or Tab)")
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::F(n) => write!(f, "F{n}"),
+            Key::Ctrl(c) => write!(f, "Ctrl+{}", c.to_ascii_uppercase()),
+            Key::Tab => write!(f, "Tab"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_parse_and_validate() {
+        let key = |name: &str| Key::try_from(name.to_string());
+        assert_eq!(key("F5").unwrap(), Key::F(5));
+        assert_eq!(key("ctrl+h").unwrap(), Key::Ctrl('h'));
+        assert_eq!(key("Ctrl+h").unwrap().to_string(), "Ctrl+H");
+        assert_eq!(key("TAB").unwrap(), Key::Tab);
+        // Typed characters and Ctrl+C (quit) cannot be bound.
+        assert!(key("x").is_err());
+        assert!(key("F13").is_err());
+        assert!(key("Ctrl+c").is_err());
+
+        assert!(Keymap::DEFAULT.validate().is_ok());
+        let clash = Keymap {
+            retry: 'q',
+            ..Keymap::DEFAULT
+        };
+        assert!(clash.validate().is_err());
+        let upper = Keymap {
+            restart: 'R',
+            ..Keymap::DEFAULT
+        };
+        assert!(upper.validate().is_err());
+        let shortcuts = Keymap {
+            talk: Key::F(1),
+            ..Keymap::DEFAULT
+        };
+        assert!(shortcuts.validate().is_err());
+    }
+}
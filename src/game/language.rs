@@ -0,0 +1,137 @@
+use std::cmp::Reverse;
+
+use log::info;
+
+/// Languages the detector knows: `--lang` code, name and words common in
+/// everyday answers. The lists overlap; a language wins on count.
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    (
+        "en",
+        "English",
+        &[
+            "the", "and", "is", "are", "i", "i'm", "you", "my", "to", "of", "from", "with", "have",
+            "this", "for", "not", "it", "just", "here", "am", "visiting", "yes", "thanks",
+        ],
+    ),
+    (
+        "es",
+        "Spanish",
+        &[
+            "el", "la", "los", "las", "y", "es", "estoy", "soy", "mi", "yo", "que", "con", "para",
+            "por", "una", "vengo", "aquí", "tengo", "gracias", "hola", "sí", "viaje", "mis",
+        ],
+    ),
+    (
+        "de",
+        "German",
+        &[
+            "der", "die", "das", "und", "ist", "ich", "bin", "mein", "meine", "nicht", "mit",
+            "für", "aus", "ein", "eine", "habe", "hier", "nein", "danke", "bitte", "komme",
+        ],
+    ),
+    (
+        "fr",
+        "French",
+        &[
+            "le", "la", "les", "et", "est", "je", "suis", "mon", "ma", "pas", "avec", "pour", "du",
+            "une", "un", "ici", "oui", "merci", "bonjour", "viens", "j'ai", "mes",
+        ],
+    ),
+    (
+        "it",
+        "Italian",
+        &[
+            "il", "lo", "gli", "e", "è", "sono", "mio", "mia", "non", "con", "per", "di", "una",
+            "qui", "sì", "grazie", "ciao", "vengo", "ho", "miei",
+        ],
+    ),
+    (
+        "pt",
+        "Portuguese",
+        &[
+            "o", "os", "e", "é", "sou", "estou", "meu", "minha", "não", "com", "para", "do", "da",
+            "uma", "um", "aqui", "sim", "obrigado", "obrigada", "olá", "venho", "tenho",
+        ],
+    ),
+    (
+        "nl",
+        "Dutch",
+        &[
+            "de", "het", "en", "is", "ik", "ben", "mijn", "niet", "met", "voor", "van", "uit",
+            "een", "hier", "nee", "dank", "kom", "heb",
+        ],
+    ),
+];
+
+/// Common words an answer needs before its language is called.
+const MIN_WORDS: usize = 2;
+
+/// The code and name of the language `text` is in, if its common words
+/// say so clearly: at least [`MIN_WORDS`] of them, and more than for any
+/// other language.
+pub fn detect(text: &str) -> Option<(&'static str, &'static str)> {
+    let lowered = text.to_lowercase();
+    let words: Vec<&str> = lowered
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let mut counts: Vec<(usize, &str, &str)> = LANGUAGES
+        .iter()
+        .map(|(code, name, common)| {
+            let hits = words.iter().filter(|word| common.contains(word)).count();
+            (hits, *code, *name)
+        })
+        .collect();
+    counts.sort_by_key(|&(hits, ..)| Reverse(hits));
+    match counts[..] {
+        [(best, code, name), (second, ..), ..] if best >= MIN_WORDS && best > second => {
+            Some((code, name))
+        }
+        _ => None,
+    }
+}
+
+/// Note for the judge when `input` is in another language than
+/// `expected`, the `--lang` code the game is played in.
+pub fn hint(input: &str, expected: &str, player: &str) -> Option<String> {
+    let (code, name) = detect(input).filter(|(code, _)| *code != expected)?;
+    let into = LANGUAGES
+        .iter()
+        .find(|(code, ..)| *code == expected)
+        .map_or("the language of the conversation", |(_, name, _)| *name);
+    info!("Answer detected as {name} ({code})");
+    Some(format!(
+        "The {player} replied in {name}. Translate their response into {into} before judging it, and judge what it means, not the language it is in."
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_the_answer_language() {
+        assert_eq!(
+            detect("Vengo de Madrid para ver a mi hermana."),
+            Some(("es", "Spanish"))
+        );
+        assert_eq!(
+            detect("Ich komme aus Berlin, hier ist mein Pass."),
+            Some(("de", "German"))
+        );
+        assert_eq!(detect("I'm visiting my sister."), Some(("en", "English")));
+        assert_eq!(detect("Sí."), None);
+
+        let note = hint("Vengo de Madrid para ver a mi hermana.", "en", "traveller").unwrap();
+        assert!(note.starts_with(
+            "The traveller replied in Spanish. Translate their response into English"
+        ));
+        assert!(hint("I'm visiting my sister.", "en", "traveller").is_none());
+        assert!(hint(
+            "Ich komme aus Berlin, hier ist mein Pass.",
+            "de",
+            "traveller"
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::game::command;
+
+/// The plain front end's prompt on a terminal: arrow keys, Ctrl+W and the
+/// like, recall of earlier answers with Up and Tab completion of
+/// `/commands`.
+pub struct LineEditor {
+    editor: Editor<Commands, DefaultHistory>,
+}
+
+impl LineEditor {
+    pub fn new() -> Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(Commands));
+        Ok(Self { editor })
+    }
+
+    /// Read an answer, kept for recall. Ctrl+C and Ctrl+D walk away.
+    pub fn answer(&mut self, prompt: &str) -> Result<String> {
+        let Some(line) = self.read(prompt)? else {
+            return Ok("/quit".to_string());
+        };
+        if !line.trim().is_empty() {
+            self.editor.add_history_entry(line.as_str())?;
+        }
+        Ok(line)
+    }
+
+    /// Read a line that is not an answer, e.g. a key choice. `None` on
+    /// Ctrl+C or Ctrl+D.
+    pub fn read(&mut self, prompt: &str) -> Result<Option<String>> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Some(line)),
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Completes `/commands`; answers are left alone.
+struct Commands;
+
+impl Completer for Commands {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let names = command::complete(&line[..pos]);
+        Ok((0, names.into_iter().map(String::from).collect()))
+    }
+}
+
+impl Hinter for Commands {
+    type Hint = String;
+}
+
+impl Highlighter for Commands {}
+
+impl Validator for Commands {}
+
+impl Helper for Commands {}
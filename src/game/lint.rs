@@ -0,0 +1,155 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::game::node::{GameNode, NodeType};
+use crate::game::tree::GameTree;
+
+/// Node ids that are plain English verdicts. Small judges tend to echo
+/// them from the conversation ("I'm afraid you FAILED...") instead of
+/// weighing the criteria.
+const ECHOED_IDS: &[&str] = &[
+    "ACCEPTED", "APPROVED", "CLEARED", "CORRECT", "DENIED", "FAIL", "FAILED", "FALSE", "NO", "OK",
+    "PASS", "PASSED", "REJECTED", "SUCCESS", "TRUE", "WRONG", "YES",
+];
+
+/// Longest criterion, in words, that small judges still read reliably.
+const MAX_CRITERION_WORDS: usize = 25;
+
+/// Wording in a scenario that is known to trip up small judge models.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub node: String,
+    /// Target of the edge whose criterion the warning is about.
+    pub edge: String,
+    pub problem: String,
+    /// How to reword it.
+    pub suggestion: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {}: {}\n    try: {}",
+            self.node, self.edge, self.problem, self.suggestion
+        )
+    }
+}
+
+/// Check the judge criteria of every decision node, in node id order.
+pub fn lint(tree: &GameTree) -> Vec<Warning> {
+    let rules = Rules::new();
+    let mut nodes: Vec<&GameNode> = tree.nodes.values().collect();
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut warnings = Vec::new();
+    for node in nodes {
+        let NodeType::Decision(next) = &node.node_type else {
+            continue;
+        };
+        let warn = |edge: &str, problem: String, suggestion: &str| Warning {
+            node: node.id.clone(),
+            edge: edge.to_string(),
+            problem,
+            suggestion: suggestion.to_string(),
+        };
+        for edge in next {
+            let criterion = edge.description.as_str();
+            let edge_id = edge.id.as_str();
+            if let Some(negation) = rules.negation.find(criterion) {
+                warnings.push(warn(
+                    edge_id,
+                    format!("criterion is a negation (\"{}\")", negation.as_str()),
+                    "say what the answer does, e.g. \"refuses to show the passport\" \
+                     instead of \"does NOT show the passport\"",
+                ));
+            }
+            if rules.conditional.find_iter(criterion).count() >= 2 {
+                warnings.push(warn(
+                    edge_id,
+                    "criterion stacks several conditions".to_string(),
+                    "keep one condition per edge; split the rest into edges of their own",
+                ));
+            }
+            let words = criterion.split_whitespace().count();
+            if words > MAX_CRITERION_WORDS {
+                warnings.push(warn(
+                    edge_id,
+                    format!("criterion is {words} words long"),
+                    "shorten it to one direct sentence",
+                ));
+            }
+            if ECHOED_IDS.contains(&edge.id.as_str()) {
+                warnings.push(warn(
+                    edge_id,
+                    format!("the judge may echo '{}' as a word", edge.id),
+                    "give the node a descriptive id, e.g. REFUSED_PASSPORT, and map the old \
+                     one in node_aliases so saves still load",
+                ));
+            }
+        }
+        let fails = |id: &str| {
+            matches!(
+                tree.get(id).map(|n| &n.node_type),
+                Some(NodeType::Terminal(false))
+            )
+        };
+        if let [first, rest @ ..] = next.as_slice() {
+            if fails(&first.id) && rest.iter().any(|edge| !fails(&edge.id)) {
+                warnings.push(warn(
+                    &first.id,
+                    "a failure is the first option".to_string(),
+                    "list the passing option first; small judges favour the first one",
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+struct Rules {
+    negation: Regex,
+    conditional: Regex,
+}
+
+impl Rules {
+    fn new() -> Self {
+        Self {
+            negation: Regex::new(r"(?i)\b(not|never|neither|nor|without)\b|n't\b").unwrap(),
+            conditional: Regex::new(r"(?i)\b(if|unless|when|whether|except|only)\b").unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_flags_hostile_wording() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [
+                          {"id": "FAILED", "description": "User does NOT show the passport"},
+                          {"id": "CHECK", "description": "Shows it, only if asked twice and unless rude"}]}},
+                "CHECK": {"id": "CHECK", "transcript": "Thanks.",
+                          "node_type": {"Decision": [{"id": "DONE", "description": "answers the question"}]}},
+                "DONE": {"id": "DONE", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "FAILED": {"id": "FAILED", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let warnings = lint(&tree);
+        let problems: Vec<&str> = warnings.iter().map(|w| w.problem.as_str()).collect();
+        assert_eq!(
+            problems,
+            [
+                "criterion is a negation (\"NOT\")",
+                "the judge may echo 'FAILED' as a word",
+                "criterion stacks several conditions",
+                "a failure is the first option",
+            ]
+        );
+        assert!(warnings.iter().all(|w| w.node == "A"));
+    }
+}
@@ -0,0 +1,286 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use serde_json::{json, Value};
+
+/// What the simulated players answer, in turn. None of it is scenario
+/// specific; the point is to keep the judge busy, not to win.
+const ANSWERS: [&str; 6] = [
+    "Here is my passport.",
+    "I'm visiting family for two weeks.",
+    "Just a carry-on and one checked bag.",
+    "No, nothing to declare.",
+    "Sorry, could you repeat the question?",
+    "Yes, I packed everything myself.",
+];
+/// How long a client waits before resending a message the server refused.
+const BUSY_DELAY: Duration = Duration::from_millis(250);
+
+pub struct LoadtestOptions {
+    /// Server to test, e.g. `http://127.0.0.1:8080`.
+    pub url: String,
+    /// Simulated clients playing at the same time.
+    pub sessions: usize,
+    /// Messages each client sends. A finished game is replaced by a new one.
+    pub turns: usize,
+}
+
+/// One judged message as seen by a client.
+#[derive(Debug, Clone, Copy)]
+struct Turn {
+    /// Round trip of the HTTP request.
+    latency: Duration,
+    /// Time the server reports the judge call spent queued.
+    queued: Duration,
+}
+
+/// What one client saw.
+#[derive(Debug, Default)]
+struct ClientLog {
+    turns: Vec<Turn>,
+    /// Messages refused with 503 and resent.
+    busy: usize,
+    games: usize,
+    error: Option<String>,
+}
+
+impl ClientLog {
+    fn mean_latency(&self) -> Option<Duration> {
+        let total: Duration = self.turns.iter().map(|t| t.latency).sum();
+        Some(total / u32::try_from(self.turns.len()).ok().filter(|&n| n > 0)?)
+    }
+}
+
+/// Drive `options.sessions` clients against a running `elsa serve` and
+/// measure how it holds up.
+pub fn run(options: &LoadtestOptions) -> Result<LoadReport> {
+    let url = options.url.trim_end_matches('/').to_string();
+    match ureq::get(&format!("{url}/sessions/-")).call() {
+        // No such session, but the server answered.
+        Ok(_) | Err(ureq::Error::Status(404, _)) => {}
+        Err(e) => bail!("no elsa server at {url}: {e}"),
+    }
+
+    let started = Instant::now();
+    let clients: Vec<_> = (0..options.sessions)
+        .map(|client| {
+            let url = url.clone();
+            let turns = options.turns;
+            thread::spawn(move || play(&url, client, turns))
+        })
+        .collect();
+    let logs = clients
+        .into_iter()
+        .map(|client| client.join().expect("load test client panicked"))
+        .collect();
+    Ok(LoadReport {
+        logs,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Play `turns` messages as one client, starting new games as they end.
+fn play(url: &str, client: usize, turns: usize) -> ClientLog {
+    let mut log = ClientLog::default();
+    if let Err(e) = play_turns(url, client, turns, &mut log) {
+        warn!("Load test client {client} stopped: {e:#}");
+        log.error = Some(format!("{e:#}"));
+    }
+    log
+}
+
+fn play_turns(url: &str, client: usize, turns: usize, log: &mut ClientLog) -> Result<()> {
+    let mut session = None;
+    let mut sent = 0;
+    while sent < turns {
+        let id = match &session {
+            Some(id) => String::clone(id),
+            None => {
+                let created = post(&format!("{url}/sessions"), json!({}))?;
+                log.games += 1;
+                let id = created["session_id"]
+                    .as_str()
+                    .context("the server returned no session id")?
+                    .to_string();
+                session.insert(id).clone()
+            }
+        };
+
+        let answer = ANSWERS[(client + sent) % ANSWERS.len()];
+        let start = Instant::now();
+        let response = match ureq::post(&format!("{url}/sessions/{id}/messages"))
+            .send_json(json!({ "message": answer }))
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(503, _)) => {
+                log.busy += 1;
+                thread::sleep(BUSY_DELAY);
+                continue;
+            }
+            Err(ureq::Error::Status(409, _)) => {
+                // The game ended under us; start another.
+                session = None;
+                continue;
+            }
+            Err(e) => return Err(e).context("sending a message"),
+        };
+        let latency = start.elapsed();
+        let body: Value = response.into_json()?;
+        sent += 1;
+        log.turns.push(Turn {
+            latency,
+            queued: Duration::from_millis(body["judged"]["queue_ms"].as_u64().unwrap_or(0)),
+        });
+        if body["session"]["finished"].as_bool() == Some(true) {
+            debug!("Load test client {client} finished a game");
+            let _ = ureq::delete(&format!("{url}/sessions/{id}")).call();
+            session = None;
+        }
+    }
+    if let Some(id) = session {
+        let _ = ureq::delete(&format!("{url}/sessions/{id}")).call();
+    }
+    Ok(())
+}
+
+fn post(url: &str, body: Value) -> Result<Value> {
+    match ureq::post(url).send_json(body) {
+        Ok(response) => Ok(response.into_json()?),
+        Err(ureq::Error::Status(status, response)) => {
+            let body: Value = response.into_json().unwrap_or_default();
+            bail!(
+                "{url}: {status} {}",
+                body["error"].as_str().unwrap_or("no description")
+            )
+        }
+        Err(e) => Err(e).with_context(|| format!("{url} failed")),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Report
+// ----------------------------------------------------------------------------
+
+pub struct LoadReport {
+    logs: Vec<ClientLog>,
+    elapsed: Duration,
+}
+
+impl LoadReport {
+    fn turns(&self) -> impl Iterator<Item = &Turn> {
+        self.logs.iter().flat_map(|log| &log.turns)
+    }
+
+    /// Jain's fairness index over the sessions' mean latencies: 1.0 when
+    /// every session waited the same, down to 1/n when one got all the
+    /// waiting.
+    pub fn fairness(&self) -> Option<f64> {
+        let means: Vec<f64> = self
+            .logs
+            .iter()
+            .filter_map(ClientLog::mean_latency)
+            .map(|mean| mean.as_secs_f64())
+            .collect();
+        let sum: f64 = means.iter().sum();
+        let squares: f64 = means.iter().map(|x| x * x).sum();
+        (squares > 0.0).then(|| sum * sum / (means.len() as f64 * squares))
+    }
+
+    pub fn print(&self) {
+        let judged = self.turns().count();
+        let busy: usize = self.logs.iter().map(|log| log.busy).sum();
+        let games: usize = self.logs.iter().map(|log| log.games).sum();
+        println!(
+            "{} sessions, {judged} messages judged in {:.1}s ({:.2}/s), {games} games, {busy} refused as busy",
+            self.logs.len(),
+            self.elapsed.as_secs_f64(),
+            judged as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON),
+        );
+        let rows: [(&str, Vec<Duration>); 2] = [
+            ("Queue wait", self.turns().map(|t| t.queued).collect()),
+            ("Turn latency", self.turns().map(|t| t.latency).collect()),
+        ];
+        for (label, values) in rows {
+            if let Some([p50, p95, max]) = percentiles(values) {
+                println!(
+                    "{label:<13} p50 {:>6} ms   p95 {:>6} ms   max {:>6} ms",
+                    p50.as_millis(),
+                    p95.as_millis(),
+                    max.as_millis()
+                );
+            }
+        }
+        let means: Vec<Duration> = self
+            .logs
+            .iter()
+            .filter_map(ClientLog::mean_latency)
+            .collect();
+        if let (Some(min), Some(max), Some(fairness)) =
+            (means.iter().min(), means.iter().max(), self.fairness())
+        {
+            println!(
+                "Per session   mean latency {} to {} ms, fairness {fairness:.3}",
+                min.as_millis(),
+                max.as_millis()
+            );
+        }
+        for (client, log) in self.logs.iter().enumerate() {
+            if let Some(error) = &log.error {
+                println!("Session {client} stopped early: {error}");
+            }
+        }
+    }
+}
+
+/// Median, 95th percentile and maximum.
+fn percentiles(mut values: Vec<Duration>) -> Option<[Duration; 3]> {
+    values.sort();
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    (!values.is_empty()).then(|| [at(0.5), at(0.95), at(1.0)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(latencies_ms: &[u64]) -> ClientLog {
+        ClientLog {
+            turns: latencies_ms
+                .iter()
+                .map(|&ms| Turn {
+                    latency: Duration::from_millis(ms),
+                    queued: Duration::ZERO,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fairness_and_percentiles() {
+        let even = LoadReport {
+            logs: vec![client(&[100, 300]), client(&[200, 200])],
+            elapsed: Duration::from_secs(1),
+        };
+        assert!((even.fairness().unwrap() - 1.0).abs() < 1e-9);
+
+        // One session got all the waiting.
+        let starved = LoadReport {
+            logs: vec![
+                client(&[100]),
+                client(&[100]),
+                client(&[100]),
+                client(&[1000]),
+            ],
+            elapsed: Duration::from_secs(1),
+        };
+        assert!(starved.fairness().unwrap() < 0.5);
+
+        let ms = |v: u64| Duration::from_millis(v);
+        let [p50, p95, max] = percentiles((1..=100).map(ms).collect()).unwrap();
+        assert_eq!((p50, p95, max), (ms(51), ms(95), ms(100)));
+        assert!(percentiles(Vec::new()).is_none());
+    }
+}
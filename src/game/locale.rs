@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::node::{GameNode, NodeType, Transcript};
+
+// ---------------------------------------------------------------------------
+// Scenario text
+// ---------------------------------------------------------------------------
+
+/// A node's text in another language, picked with `--lang`. Anything left
+/// out stays as written.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Localized {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<Transcript>,
+    /// Edge descriptions (the judge's criteria) by target node id.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub edges: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+impl GameNode {
+    /// Switch the node's text to `lang`. Returns whether it had any.
+    pub fn localize(&mut self, lang: &str) -> bool {
+        let Some(text) = self.locales.remove(lang) else {
+            return false;
+        };
+        if let Some(transcript) = text.transcript {
+            self.transcript = transcript;
+        }
+        let edges: Box<dyn Iterator<Item = _>> = match &mut self.node_type {
+            NodeType::Terminal(_) => Box::new(std::iter::empty()),
+            NodeType::Decision(next) | NodeType::Interlude(next) => Box::new(next.iter_mut()),
+            NodeType::Random(branches) => Box::new(branches.iter_mut().map(|(next, _)| next)),
+        };
+        for edge in edges {
+            if let Some(description) = text.edges.get(&edge.id) {
+                edge.description = description.clone();
+            }
+        }
+        if text.display_name.is_some() {
+            self.display_name = text.display_name;
+        }
+        true
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Engine text
+// ---------------------------------------------------------------------------
+
+/// The engine's own words on screen (game over screen, prompts, notices),
+/// in one language. Sentences with `{name}` placeholders are completed
+/// with [`fill`].
+#[derive(Debug, PartialEq)]
+pub struct Messages {
+    pub game_over: &'static str,
+    pub you: &'static str,
+    pub thinking: &'static str,
+    pub say_something: &'static str,
+    /// `{speaker}`: the player to answer next.
+    pub turn: &'static str,
+    pub result: &'static str,
+    pub cleared: &'static str,
+    pub denied: &'static str,
+    pub score: &'static str,
+    pub steps_completed: &'static str,
+    pub ended_at: &'static str,
+    pub points: &'static str,
+    pub grade: &'static str,
+    pub suspicion: &'static str,
+    pub hints: &'static str,
+    pub appeal_granted: &'static str,
+    pub restart: &'static str,
+    pub retry: &'static str,
+    pub feedback: &'static str,
+    pub quit: &'static str,
+    pub press: &'static str,
+    pub or: &'static str,
+    pub to_restart: &'static str,
+    pub to_retry: &'static str,
+    pub for_feedback: &'static str,
+    pub to_quit: &'static str,
+    pub thanks: &'static str,
+    pub report_card: &'static str,
+    pub went_well: &'static str,
+    pub flagged: &'static str,
+    pub tip: &'static str,
+    /// `{saved}` and `{current}`: the scenario's versions.
+    pub scenario_changed: &'static str,
+    pub migrate: &'static str,
+    pub to_migrate: &'static str,
+    pub to_appeal: &'static str,
+    pub to_accept: &'static str,
+    pub appeal_upheld: &'static str,
+    pub appeal_rejected: &'static str,
+    pub appeal_unclear: &'static str,
+    pub stress_reading: &'static str,
+    /// `{npc}`: who asked.
+    pub say_again: &'static str,
+    pub nothing_said: &'static str,
+    pub hint: &'static str,
+    /// `{points}`: what the hint cost.
+    pub hint_cost: &'static str,
+    pub no_hint: &'static str,
+    pub nothing_to_hint: &'static str,
+    /// `{path}`: the save file.
+    pub saved: &'static str,
+    /// `{error}`: why not.
+    pub save_failed: &'static str,
+    /// `{name}`: what was typed.
+    pub unknown_command: &'static str,
+    pub coach_reading: &'static str,
+    pub coach_silent: &'static str,
+    /// `{seconds}` and `{turns}`.
+    pub judge_latency: &'static str,
+    /// `{threads}` and `{seconds}`.
+    pub power_save_latency: &'static str,
+    // The chat bots.
+    pub supervisor: &'static str,
+    pub appeal: &'static str,
+    pub accept: &'static str,
+    pub plead: &'static str,
+    pub plead_or_quit: &'static str,
+    pub busy: &'static str,
+    /// `{error}`: what went wrong.
+    pub game_broke: &'static str,
+    /// `{npc}`: who is answering.
+    pub wait_for_answer: &'static str,
+    pub too_many_games: &'static str,
+    pub send_start: &'static str,
+    pub game_is_over: &'static str,
+    pub no_game: &'static str,
+    pub no_game_start: &'static str,
+    pub game_running: &'static str,
+    pub no_thread: &'static str,
+    /// `{user}`: who started the game.
+    pub thread_name: &'static str,
+    /// `{user}` and `{thread}`: who started the game, and where.
+    pub game_started: &'static str,
+    pub unknown_bot_command: &'static str,
+    pub tell_supervisor: &'static str,
+    /// `{npc}`: who asked.
+    pub hoping_for: &'static str,
+    pub nothing_left_to_hint: &'static str,
+}
+
+pub const ENGLISH: Messages = Messages {
+    game_over: "GAME OVER",
+    you: "You",
+    thinking: "Thinking...",
+    say_something: "(Please say something.)",
+    turn: "({speaker}'s turn)",
+    result: "Result",
+    cleared: "CLEARED",
+    denied: "DENIED",
+    score: "Score",
+    steps_completed: "steps completed",
+    ended_at: "Ended at",
+    points: "Points",
+    grade: "Grade",
+    suspicion: "Suspicion",
+    hints: "Hints",
+    appeal_granted: "Appeal granted by the supervisor",
+    restart: "Restart",
+    retry: "Retry from checkpoint",
+    feedback: "Feedback",
+    quit: "Quit",
+    press: "Press",
+    or: "or",
+    to_restart: "to restart",
+    to_retry: "to retry from the checkpoint",
+    for_feedback: "for feedback",
+    to_quit: "to quit",
+    thanks: "Thanks for playing!",
+    report_card: "REPORT CARD",
+    went_well: "Went well",
+    flagged: "Raised a flag",
+    tip: "Tip",
+    scenario_changed: "The scenario changed since this game was saved ({saved} -> {current}).",
+    migrate: "Attempt migration",
+    to_migrate: "to migrate",
+    to_appeal: "to appeal",
+    to_accept: "to accept",
+    appeal_upheld: "(The supervisor waves you back to the desk.)",
+    appeal_rejected: "(The supervisor shakes their head. The decision stands.)",
+    appeal_unclear: "(The supervisor sighs. \"Let's try that again.\")",
+    stress_reading: "Stress reading",
+    say_again: "(The {npc} frowns. \"Sorry, could you say that again?\")",
+    nothing_said: "(Nothing has been said yet.)",
+    hint: "Hint",
+    hint_cost: "(-{points} points)",
+    no_hint: "(No hint this time.)",
+    nothing_to_hint: "(Nothing to hint at here.)",
+    saved: "(Game saved to {path}. Resume with --resume {path})",
+    save_failed: "(Could not save: {error})",
+    unknown_command: "(No command {name}. Type /help for the list.)",
+    coach_reading: "(The coach reads back through the conversation...)",
+    coach_silent: "(The coach has nothing to say this time.)",
+    judge_latency: "Judge latency: {seconds}s average over {turns} turns",
+    power_save_latency: "Power save ({threads} threads): {seconds}s of each turn spent in pauses",
+    supervisor: "Supervisor",
+    appeal: "Appeal",
+    accept: "Accept",
+    plead: "(Plead your case.)",
+    plead_or_quit: "(Plead your case in your next message, or /quit to accept the decision.)",
+    busy: "(Busy. Say that again in a moment.)",
+    game_broke: "(Something went wrong and the game has ended: {error})",
+    wait_for_answer: "Wait for the {npc} to answer first.",
+    too_many_games: "Too many games are running; try again later.",
+    send_start: "Send /start to play.",
+    game_is_over: "The game is over.",
+    no_game: "No game is running here.",
+    no_game_start: "No game is running here. Use /start in a channel.",
+    game_running: "A game is already running in this thread.",
+    no_thread: "Could not open a thread here.",
+    thread_name: "Game with {user}",
+    game_started:
+        "{user} started a game in {thread}. Answer there; /hint and /quit work in the thread.",
+    unknown_bot_command: "Unknown command.",
+    tell_supervisor: "Tell the supervisor why the decision was wrong.",
+    hoping_for: "The {npc} is hoping for something like:",
+    nothing_left_to_hint: "Nothing left to hint at: the game is over.",
+};
+
+pub const GERMAN: Messages = Messages {
+    game_over: "SPIEL VORBEI",
+    you: "Sie",
+    thinking: "Denkt nach...",
+    say_something: "(Bitte sagen Sie etwas.)",
+    turn: "({speaker} ist dran)",
+    result: "Ergebnis",
+    cleared: "DURCHGELASSEN",
+    denied: "ABGEWIESEN",
+    score: "Stand",
+    steps_completed: "Schritte geschafft",
+    ended_at: "Endete bei",
+    points: "Punkte",
+    grade: "Note",
+    suspicion: "Verdacht",
+    hints: "Tipps",
+    appeal_granted: "Einspruch vom Vorgesetzten stattgegeben",
+    restart: "Neu starten",
+    retry: "Ab Checkpoint wiederholen",
+    feedback: "Rückmeldung",
+    quit: "Beenden",
+    press: "Drücken Sie",
+    or: "oder",
+    to_restart: "für einen Neustart",
+    to_retry: "für einen neuen Versuch ab dem Checkpoint",
+    for_feedback: "für eine Rückmeldung",
+    to_quit: "zum Beenden",
+    thanks: "Danke fürs Spielen!",
+    report_card: "ZEUGNIS",
+    went_well: "Lief gut",
+    flagged: "Fiel auf",
+    tip: "Tipp",
+    scenario_changed: "Das Szenario hat sich seit dem Speichern geändert ({saved} -> {current}).",
+    migrate: "Übernahme versuchen",
+    to_migrate: "für eine Übernahme",
+    to_appeal: "für einen Einspruch",
+    to_accept: "zum Annehmen",
+    appeal_upheld: "(Der Vorgesetzte winkt Sie zurück an den Schalter.)",
+    appeal_rejected: "(Der Vorgesetzte schüttelt den Kopf. Die Entscheidung bleibt.)",
+    appeal_unclear: "(Der Vorgesetzte seufzt. „Versuchen wir das noch einmal.“)",
+    stress_reading: "Stressmessung",
+    say_again: "(Stirnrunzeln beim {npc}. „Wie bitte? Noch einmal, bitte.“)",
+    nothing_said: "(Es wurde noch nichts gesagt.)",
+    hint: "Tipp",
+    hint_cost: "(-{points} Punkte)",
+    no_hint: "(Diesmal kein Tipp.)",
+    nothing_to_hint: "(Hier gibt es nichts zu tippen.)",
+    saved: "(Spiel gespeichert in {path}. Weiter mit --resume {path})",
+    save_failed: "(Speichern fehlgeschlagen: {error})",
+    unknown_command: "(Den Befehl {name} gibt es nicht. /help zeigt die Liste.)",
+    coach_reading: "(Der Coach liest das Gespräch noch einmal durch...)",
+    coach_silent: "(Der Coach hat diesmal nichts zu sagen.)",
+    judge_latency: "Bedenkzeit: im Schnitt {seconds} s über {turns} Runden",
+    power_save_latency: "Energiesparen ({threads} Threads): {seconds} s jeder Runde in Pausen",
+    supervisor: "Vorgesetzter",
+    appeal: "Einspruch",
+    accept: "Annehmen",
+    plead: "(Tragen Sie Ihren Einspruch vor.)",
+    plead_or_quit: "(Tragen Sie Ihren Einspruch mit der nächsten Nachricht vor, oder nehmen Sie die Entscheidung mit /quit an.)",
+    busy: "(Gerade beschäftigt. Sagen Sie das gleich noch einmal.)",
+    game_broke: "(Etwas ist schiefgegangen, das Spiel ist beendet: {error})",
+    wait_for_answer: "Warten Sie zuerst die Antwort ab ({npc}).",
+    too_many_games: "Es laufen zu viele Spiele; versuchen Sie es später noch einmal.",
+    send_start: "Senden Sie /start, um zu spielen.",
+    game_is_over: "Das Spiel ist vorbei.",
+    no_game: "Hier läuft kein Spiel.",
+    no_game_start: "Hier läuft kein Spiel. Starten Sie eines mit /start in einem Kanal.",
+    game_running: "In diesem Thread läuft schon ein Spiel.",
+    no_thread: "Hier konnte kein Thread geöffnet werden.",
+    thread_name: "Spiel mit {user}",
+    game_started: "{user} hat ein Spiel in {thread} begonnen. Antworten Sie dort; /hint und /quit funktionieren im Thread.",
+    unknown_bot_command: "Unbekannter Befehl.",
+    tell_supervisor: "Sagen Sie dem Vorgesetzten, warum die Entscheidung falsch war.",
+    hoping_for: "Erhofft wird etwa ({npc}):",
+    nothing_left_to_hint: "Nichts mehr zu tippen: Das Spiel ist vorbei.",
+};
+
+impl Default for &Messages {
+    fn default() -> Self {
+        &ENGLISH
+    }
+}
+
+/// `text` with each `{name}` placeholder replaced by its value in `args`.
+pub fn fill(text: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(text.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// The engine's words for `lang`, e.g. "de". Languages without a catalog
+/// get English.
+pub fn messages(lang: Option<&str>) -> &'static Messages {
+    match lang.map(|lang| lang.split(['-', '_']).next().unwrap_or(lang)) {
+        Some("de") => &GERMAN,
+        _ => &ENGLISH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tree::GameTree;
+
+    #[test]
+    fn test_localize_switches_text() {
+        let mut tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"},
+                                                 {"id": "C", "description": "refuses"}]},
+                      "locales": {"de": {"transcript": "Reisepass?", "edges": {"B": "zeigt ihn"}}}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true},
+                      "locales": {"de": {"display_name": "Willkommen"}}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(tree.localize("de"), 2);
+        let node = tree.get("A").unwrap();
+        assert_eq!(node.transcript.first(), "Reisepass?");
+        let edges: Vec<_> = node
+            .node_type
+            .next_nodes()
+            .map(|n| &n.description)
+            .collect();
+        assert_eq!(edges, ["zeigt ihn", "refuses"]);
+        assert_eq!(tree.display_name("B"), "Willkommen");
+        assert_eq!(tree.get("C").unwrap().transcript.first(), "Step aside.");
+
+        assert_eq!(messages(Some("de-AT")), &GERMAN);
+        assert_eq!(messages(Some("fr")), &ENGLISH);
+    }
+
+    #[test]
+    fn test_notices_follow_the_locale() {
+        let saved = |m: &Messages| fill(m.saved, &[("path", "elsa-save.json")]);
+        assert_eq!(
+            saved(&ENGLISH),
+            "(Game saved to elsa-save.json. Resume with --resume elsa-save.json)"
+        );
+        assert_eq!(
+            saved(messages(Some("de"))),
+            "(Spiel gespeichert in elsa-save.json. Weiter mit --resume elsa-save.json)"
+        );
+    }
+}
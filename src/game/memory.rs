@@ -0,0 +1,108 @@
+use log::{debug, warn};
+
+use crate::game::instruction::{JudgeInstruction, Slot};
+use crate::llm::LLM;
+
+/// Earlier answers recalled for one reply.
+const RECALLED: usize = 2;
+
+/// Least cosine similarity for an earlier answer to be worth a callback.
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// Answers shorter than this, in words ("Yes.", "Two weeks."), carry no
+/// detail to call back to and are not remembered.
+const MIN_WORDS: usize = 4;
+
+/// The guard's memory of the round: the player's judged answers, indexed
+/// by embedding. When the guard talks in character, the one or two
+/// earlier answers closest to the new one are recalled so the reply can
+/// call back to them ("Still nothing to declare besides that famous
+/// Frankfurt sausage?").
+#[derive(Debug, Default)]
+pub struct Memory {
+    answers: Vec<(String, Vec<f32>)>,
+}
+
+impl Memory {
+    /// Index a judged answer. Failing only loses the callback.
+    pub fn remember(&mut self, model: &mut LLM, answer: &str) {
+        if answer.split_whitespace().count() < MIN_WORDS {
+            return;
+        }
+        match model.embed(answer) {
+            Ok(embedding) => self.answers.push((answer.to_string(), embedding)),
+            Err(e) => warn!("Not remembering an answer: {e:#}"),
+        }
+    }
+
+    /// Add the earlier answers closest to `answer` to the instruction for
+    /// the guard's in-character reply.
+    pub fn recall(&self, model: &mut LLM, answer: &str, instruction: &mut JudgeInstruction) {
+        if self.answers.is_empty() {
+            return;
+        }
+        let query = match model.embed(answer) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("Skipping callbacks: {e:#}");
+                return;
+            }
+        };
+        let recalled = closest(&query, &self.answers);
+        if recalled.is_empty() {
+            return;
+        }
+        debug!("Recalled {} earlier answers", recalled.len());
+        let mut lines = vec![
+            "Earlier answers you may call back to in what you say back (not for your decision):"
+                .to_string(),
+        ];
+        lines.extend(recalled.iter().map(|said| format!("- \"{said}\"")));
+        instruction.add(Slot::State, "memory", usize::MAX, lines);
+    }
+}
+
+/// Up to [`RECALLED`] of `answers` at least [`MIN_SIMILARITY`] from
+/// `query`, closest first. Embeddings are unit length, so similarity is
+/// the dot product.
+fn closest<'a>(query: &[f32], answers: &'a [(String, Vec<f32>)]) -> Vec<&'a str> {
+    let mut scored: Vec<(&str, f32)> = answers
+        .iter()
+        .map(|(said, v)| (said.as_str(), v.iter().zip(query).map(|(a, b)| a * b).sum()))
+        .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored
+        .into_iter()
+        .take(RECALLED)
+        .map(|(said, _)| said)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_answers() {
+        let answers = vec![
+            (
+                "I brought a sausage from Frankfurt".to_string(),
+                vec![1.0, 0.0],
+            ),
+            ("I am visiting my sister".to_string(), vec![0.0, 1.0]),
+            (
+                "Some cheese from Frankfurt as well".to_string(),
+                vec![0.8, 0.6],
+            ),
+        ];
+        assert_eq!(
+            closest(&[1.0, 0.0], &answers),
+            [
+                "I brought a sausage from Frankfurt",
+                "Some cheese from Frankfurt as well"
+            ]
+        );
+        assert!(closest(&[-1.0, 0.0], &answers).is_empty());
+    }
+}
@@ -1,31 +1,589 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 
+use log::warn;
+
+use crate::game::difficulty::{Criteria, Difficulty};
+use crate::game::locale::Localized;
+use crate::game::vars::{self, Condition, Vars};
+use crate::llm::LlmDecision;
+use crate::rng::Rng;
+
 /// A single node in the game's decision tree.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GameNode {
     /// Unique identifier for this node (e.g. "START", "QUESTION_1", "FAILED").
     pub id: String,
     /// The line the guard says when entering this node.
-    pub transcript: String,
+    pub transcript: Transcript,
     // Terminal, success, next_node, etc
     pub node_type: NodeType,
     /// Extra system-prompt context injected when the game reaches this node.
     /// Gives the LLM roleplay instructions specific to this stage.
     pub system_context: Option<String>,
+    /// Failure terminals only: the player may plead once to a supervisor.
+    /// Omitted from serialization when unset so existing scenarios keep
+    /// their content hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appeal: Option<Appeal>,
+    /// Decision nodes only: what happens when the player keeps giving the
+    /// same answer here. Omitted from serialization when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeats: Option<Repeats>,
+    /// Game variables set when the node is entered.
+    #[serde(default, skip_serializing_if = "Vars::is_empty")]
+    pub set: Vars,
+    /// Facts the judge should pull out of the player's answer into game
+    /// variables, by variable name, e.g. `"origin_city": "the city the
+    /// traveller flew in from"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extract: BTreeMap<String, String>,
+    /// Decision nodes only: node the game goes to when the answer
+    /// contradicts what the player said earlier. The judge is shown the
+    /// game variables and asked about it alongside its decision.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contradiction: Option<String>,
+    /// Decision nodes only: alias of the model that judges answers here
+    /// (see `--model-alias`). Unset or unknown aliases use the default
+    /// model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Decision nodes only: lets the judge ask a follow-up question instead
+    /// of deciding when the answer is unclear.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clarify: Option<Clarify>,
+    /// Decision nodes only: answers the guard takes here before the judge
+    /// may decide, for a short back-and-forth such as small talk. Unset
+    /// means 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_turns: Option<usize>,
+    /// Decision nodes only: answers after which the judge has to decide.
+    /// Up to then it may keep the conversation going. Unset means
+    /// `min_turns`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    /// After a failure further on, the player may retry from here instead
+    /// of from the start.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub checkpoint: bool,
+    /// Points for reaching this node, counted the first time it is entered
+    /// in a round. Can be negative.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub points: Option<i32>,
+    /// Terminal nodes only: grades by final score, shown at game over.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rank: Vec<Rank>,
+    /// Decision nodes only: criteria changes at a `--difficulty`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub difficulty: BTreeMap<Difficulty, Criteria>,
+    /// Key of the scenario's `speakers` who says this node's line; the
+    /// guard if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// What players are shown for this node, e.g. "Contraband found" for
+    /// `FAILED_CONTRABAND`; ids stay internal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The node's text in other languages, by language code (e.g. "de"),
+    /// for `--lang`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locales: BTreeMap<String, Localized>,
+    /// Decision nodes only: answers decided without the judge, tried in
+    /// order before it is asked.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl GameNode {
+    /// This node as it stands with `vars`: `{{vars.name}}` placeholders in
+    /// its `system_context` are filled in, and edges whose `when` does not
+    /// hold are left out. If none holds, every edge is kept so the game can
+    /// go on.
+    pub fn resolve(&self, vars: &Vars) -> GameNode {
+        let open = |next: &NextNode| next.is_open(vars);
+        let mut node = self.clone();
+        node.system_context = node
+            .system_context
+            .map(|context| vars::interpolate(&context, vars));
+        let kept = match &mut node.node_type {
+            NodeType::Terminal(_) => return node,
+            NodeType::Decision(next) | NodeType::Interlude(next) => {
+                next.retain(open);
+                next.len()
+            }
+            NodeType::Random(branches) => {
+                branches.retain(|(next, _)| open(next));
+                branches.len()
+            }
+        };
+        if kept == 0 {
+            warn!("No edge condition of node {} holds", self.id);
+            node.node_type = self.node_type.clone();
+        }
+        node
+    }
+
+    /// Every node id this node leads to, for renaming: edges, the appeal's
+    /// continuation and the `repeats` and `contradiction` targets.
+    pub fn targets_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        let edges: Box<dyn Iterator<Item = &mut NextNode>> = match &mut self.node_type {
+            NodeType::Terminal(_) => Box::new(std::iter::empty()),
+            NodeType::Decision(next) | NodeType::Interlude(next) => Box::new(next.iter_mut()),
+            NodeType::Random(branches) => Box::new(branches.iter_mut().map(|(next, _)| next)),
+        };
+        edges
+            .map(|next| &mut next.id)
+            .chain(self.appeal.as_mut().map(|appeal| &mut appeal.granted))
+            .chain(self.repeats.as_mut().and_then(|r| r.next.as_mut()))
+            .chain(self.contradiction.as_mut())
+            .chain(self.rules.iter_mut().map(|rule| &mut rule.next))
+    }
+
+    /// The first rule that decides `input` and leads to one of
+    /// `valid_choices`.
+    pub fn rule_for(&self, input: &str, valid_choices: &[impl AsRef<str>]) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .filter(|rule| valid_choices.iter().any(|c| c.as_ref() == rule.next))
+            .find(|rule| rule.matches(input))
+    }
+
+    /// Apply the node's criteria for `difficulty`: overridden edge
+    /// descriptions and the suffix to its context.
+    pub fn at_difficulty(&mut self, difficulty: Difficulty) {
+        let Some(criteria) = self.difficulty.get(&difficulty) else {
+            return;
+        };
+        if let NodeType::Decision(next) = &mut self.node_type {
+            for edge in next.iter_mut() {
+                if let Some(description) = criteria.edges.get(&edge.id) {
+                    edge.description = description.clone();
+                }
+            }
+        }
+        if let Some(suffix) = &criteria.suffix {
+            self.system_context = Some(match self.system_context.take() {
+                Some(context) => format!("{context} {suffix}"),
+                None => suffix.clone(),
+            });
+        }
+    }
+
+    /// Add [`CLARIFY`] to the judge's options, if the node allows
+    /// follow-ups.
+    pub fn offer_clarify(&mut self) {
+        if let (Some(_), NodeType::Decision(next)) = (&self.clarify, &mut self.node_type) {
+            next.push(NextNode {
+                id: CLARIFY.to_string(),
+                description: CLARIFY_EDGE.to_string(),
+                when: None,
+            });
+        }
+    }
+
+    /// Add [`LOOKUP`] to the judge's options.
+    pub fn offer_lookup(&mut self) {
+        if let NodeType::Decision(next) = &mut self.node_type {
+            next.push(NextNode {
+                id: LOOKUP.to_string(),
+                description: LOOKUP_EDGE.to_string(),
+                when: None,
+            });
+        }
+    }
+
+    /// Add [`UNDECIDABLE`] to the judge's options.
+    pub fn offer_undecidable(&mut self) {
+        if let NodeType::Decision(next) = &mut self.node_type {
+            next.push(NextNode {
+                id: UNDECIDABLE.to_string(),
+                description: UNDECIDABLE_EDGE.to_string(),
+                when: None,
+            });
+        }
+    }
+
+    /// The fewest and most answers taken here, if the node holds a
+    /// conversation of more than one exchange.
+    pub fn turns(&self) -> Option<(usize, usize)> {
+        let min = self.min_turns.unwrap_or(1).max(1);
+        let max = self.max_turns.unwrap_or(min).max(min);
+        (max > 1).then_some((min, max))
+    }
+
+    /// Offer [`CONTINUE`] for the `turn`th answer at a multi-turn node:
+    /// as the only option before `min_turns`, next to the edges until
+    /// `max_turns`. Returns whether the judge may decide on this answer.
+    pub fn offer_continue(&mut self, turn: usize) -> bool {
+        let (Some((min, max)), NodeType::Decision(next)) = (self.turns(), &mut self.node_type)
+        else {
+            return true;
+        };
+        if turn < min {
+            next.clear();
+        }
+        if turn < max {
+            next.push(NextNode {
+                id: CONTINUE.to_string(),
+                description: CONTINUE_EDGE.to_string(),
+                when: None,
+            });
+        }
+        turn >= min
+    }
+}
+
+/// A grade at a terminal node, e.g. "Model Citizen" from 10 points up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rank {
+    /// Lowest score that earns it.
+    pub at: i32,
+    pub title: String,
+}
+
+/// The grade `score` earns: the rank with the highest `at` it reaches.
+pub fn grade(ranks: &[Rank], score: i32) -> Option<&str> {
+    ranks
+        .iter()
+        .filter(|rank| score >= rank.at)
+        .max_by_key(|rank| rank.at)
+        .map(|rank| rank.title.as_str())
+}
+
+/// Decision the judge picks to keep talking at multi-turn nodes instead of
+/// deciding yet.
+pub const CONTINUE: &str = "CONTINUE";
+
+const CONTINUE_EDGE: &str =
+    "keep the conversation going; you are not ready to decide yet, say something back";
+
+/// Decision the judge picks to ask a follow-up at nodes with `clarify`.
+pub const CLARIFY: &str = "CLARIFY";
+
+const CLARIFY_EDGE: &str = "the answer is unclear or ambiguous; ask a follow-up question";
+
+/// Decision the judge picks, in scenarios with `undecidable`, when it
+/// cannot tell which option an answer meets instead of guessing.
+pub const UNDECIDABLE: &str = "UNDECIDABLE";
+
+const UNDECIDABLE_EDGE: &str =
+    "you cannot tell which option the answer meets; say why in the reason";
+
+/// Decision the judge picks, in scenarios with `regulations`, to read a
+/// section of them before deciding. It is then asked again with the text.
+pub const LOOKUP: &str = "LOOKUP";
+
+const LOOKUP_EDGE: &str = "you need to read a regulation before you can decide; name its section";
+
+/// Decisions the engine reserves; no node may be called one of these.
+pub const RESERVED: &[&str] = &[CONTINUE, CLARIFY, UNDECIDABLE, LOOKUP];
+
+/// Follow-up questions at a node. The game stays there and the guard asks
+/// again in other words, instead of the judge being forced to pass or fail
+/// an unclear answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Clarify {
+    /// The follow-up, or variants of it. Without one, the judge words its
+    /// own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<Transcript>,
+    /// Follow-ups in a row at most; after that the judge has to decide.
+    #[serde(default = "default_clarify_limit")]
+    pub limit: usize,
+}
+
+fn default_clarify_limit() -> usize {
+    2
+}
+
+/// A node's line: either a single string or a list of variants, one of
+/// which is picked each time the node is entered. A single line serializes
+/// as a plain string, so existing scenarios keep their content hash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Transcript {
+    Line(String),
+    Variants(Vec<String>),
+}
+
+impl Transcript {
+    /// The line, or the first variant. Used where one fixed line is needed,
+    /// e.g. graph labels and eval cases.
+    pub fn first(&self) -> &str {
+        match self {
+            Transcript::Line(line) => line,
+            Transcript::Variants(variants) => variants.first().map_or("", String::as_str),
+        }
+    }
+
+    /// A variant picked with `rng`. A single line draws nothing from it.
+    pub fn pick(&self, rng: &mut Rng) -> &str {
+        match self {
+            Transcript::Variants(variants) if variants.len() > 1 => {
+                &variants[rng.below(variants.len())]
+            }
+            _ => self.first(),
+        }
+    }
+}
+
+impl From<&str> for Transcript {
+    fn from(line: &str) -> Self {
+        Transcript::Line(line.to_string())
+    }
+}
+
+/// Appeal edge out of a failure terminal. The plea is judged by a stricter
+/// supervisor; if it is granted the game continues at `granted` with a
+/// reduced score.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Appeal {
+    /// Node the game continues from when the appeal is granted.
+    pub granted: String,
+    /// The supervisor's opening line.
+    pub transcript: String,
+    /// What makes a plea convincing, for the supervisor's prompt.
+    pub criteria: String,
+    /// Steps deducted from the score when the appeal is granted.
+    #[serde(default = "default_penalty")]
+    pub penalty: usize,
+}
+
+fn default_penalty() -> usize {
+    1
+}
+
+/// Penalty for answering a node with the same words over and over, e.g.
+/// at a node that loops back to itself until the player clarifies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repeats {
+    /// Identical answers in a row that trigger the penalty.
+    #[serde(default = "default_repeat_limit")]
+    pub limit: usize,
+    /// Node the game is routed to once the limit is reached. Without one,
+    /// the judge is told that the player keeps repeating themselves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+fn default_repeat_limit() -> usize {
+    3
+}
+
+/// A cheap check of the answer that decides it without the judge, for
+/// obvious answers such as "no comment". It matches when the answer holds
+/// one of `keywords` as whole words or `pattern` matches; both ignore case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// A regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Edge taken when the rule matches. Rules whose edge is not open at
+    /// the moment are skipped.
+    pub next: String,
+    /// Given as the decision's reason instead of a generic one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip)]
+    compiled: Compiled,
+}
+
+/// A rule's regular expression, built once: when the scenario loads, or
+/// on the first answer for trees that were not loaded from a scenario.
+/// `None` for a bad pattern. Not part of the rule's value.
+#[derive(Debug, Clone, Default)]
+struct Compiled(OnceLock<Option<Regex>>);
+
+impl PartialEq for Compiled {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Rule {
+    /// The rule's keywords and pattern as one regular expression.
+    pub fn regex(&self) -> Result<Regex> {
+        let mut alternatives = Vec::new();
+        if !self.keywords.is_empty() {
+            let words: Vec<String> = self
+                .keywords
+                .iter()
+                .map(|k| regex::escape(k.trim()))
+                .collect();
+            alternatives.push(format!(r"\b(?:{})\b", words.join("|")));
+        }
+        alternatives.extend(self.pattern.iter().map(|p| format!("(?:{p})")));
+        if alternatives.is_empty() {
+            bail!("rule to {} has neither keywords nor a pattern", self.next);
+        }
+        RegexBuilder::new(&alternatives.join("|"))
+            .case_insensitive(true)
+            .build()
+            .with_context(|| format!("bad pattern in the rule to {}", self.next))
+    }
+
+    /// Build the rule's regular expression ahead of the first answer.
+    pub fn compile(&self) -> Result<()> {
+        let regex = self.regex()?;
+        let _ = self.compiled.0.set(Some(regex));
+        Ok(())
+    }
+
+    pub fn matches(&self, input: &str) -> bool {
+        self.compiled
+            .0
+            .get_or_init(|| {
+                self.regex()
+                    .inspect_err(|e| warn!("rule never matches: {e:#}"))
+                    .ok()
+            })
+            .as_ref()
+            .is_some_and(|re| re.is_match(input))
+    }
+
+    /// The decision this rule makes, in place of the judge's.
+    pub fn decision(&self) -> LlmDecision {
+        LlmDecision {
+            decision: self.next.clone(),
+            reason: self
+                .reason
+                .clone()
+                .unwrap_or_else(|| format!("The answer matches a rule leading to {}.", self.next)),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NextNode {
     // ID of an existing node
     pub id: String,
     // Useful for the LLM's system prompt
     pub description: String,
+    /// The edge is only offered while this holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl NextNode {
+    /// Whether the edge's condition, if any, holds in `vars`.
+    pub fn is_open(&self, vars: &Vars) -> bool {
+        self.when.as_ref().is_none_or(|when| when.holds(vars))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum NodeType {
     // Terminal node (is_success)
     Terminal(bool),
     // Decision node
     Decision(Vec<NextNode>),
+    // The engine picks the next node by weighted chance, without asking the
+    // player or the judge (e.g. random secondary screening)
+    Random(Vec<(NextNode, f32)>),
+    // A scripted beat without a question (filling in a form, a fingerprint
+    // scan): the first branch whose `when` holds is taken, so the game's
+    // state decides where it leads
+    Interlude(Vec<NextNode>),
+}
+
+impl NodeType {
+    /// Nodes this one can lead to, not counting appeals.
+    pub fn next_nodes(&self) -> impl Iterator<Item = &NextNode> {
+        let (decision, random): (&[NextNode], &[(NextNode, f32)]) = match self {
+            NodeType::Terminal(_) => (&[], &[]),
+            NodeType::Decision(next) | NodeType::Interlude(next) => (next, &[]),
+            NodeType::Random(branches) => (&[], branches),
+        };
+        decision.iter().chain(random.iter().map(|(next, _)| next))
+    }
+}
+
+/// Random and interlude nodes passed through in a row before giving up,
+/// in case a scenario has a cycle of them.
+pub const MAX_RANDOM_HOPS: usize = 16;
+
+/// Pick a branch of a [`NodeType::Random`] node in proportion to its
+/// weight. Weights that are not positive never win; if none is positive
+/// the first branch is taken. `None` only without branches.
+pub fn pick_weighted<'a>(branches: &'a [(NextNode, f32)], rng: &mut Rng) -> Option<&'a NextNode> {
+    let weight = |w: f32| if w > 0.0 { w } else { 0.0 };
+    let total: f32 = branches.iter().map(|(_, w)| weight(*w)).sum();
+    if total <= 0.0 {
+        return branches.first().map(|(next, _)| next);
+    }
+    let mut roll = rng.next_f32() * total;
+    for (next, w) in branches {
+        if roll < weight(*w) {
+            return Some(next);
+        }
+        roll -= weight(*w);
+    }
+    // Rounding left the roll just past the end: take the last one that
+    // could have won.
+    branches
+        .iter()
+        .rev()
+        .find(|(_, w)| weight(*w) > 0.0)
+        .map(|(next, _)| next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_variants() {
+        let line: Transcript = serde_json::from_str(r#""Passport?""#).unwrap();
+        assert_eq!(line, Transcript::from("Passport?"));
+        assert_eq!(serde_json::to_string(&line).unwrap(), r#""Passport?""#);
+
+        let variants: Transcript =
+            serde_json::from_str(r#"["Passport?", "Papers, please."]"#).unwrap();
+        assert_eq!(variants.first(), "Passport?");
+        let mut rng = Rng::new(3);
+        let picked: Vec<&str> = (0..16).map(|_| variants.pick(&mut rng)).collect();
+        assert!(picked.contains(&"Passport?") && picked.contains(&"Papers, please."));
+        let mut again = Rng::new(3);
+        assert_eq!(variants.pick(&mut again), picked[0]);
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let node_type: NodeType = serde_json::from_str(
+            r#"{"Random": [[{"id": "SCREENING", "description": "selected"}, 1.0],
+                           [{"id": "DESK", "description": "waved on"}, 3.0],
+                           [{"id": "NEVER", "description": "disabled"}, 0.0]]}"#,
+        )
+        .unwrap();
+        let NodeType::Random(branches) = &node_type else {
+            panic!("expected a random node");
+        };
+        assert_eq!(node_type.next_nodes().count(), 3);
+
+        let mut rng = Rng::new(7);
+        let mut screened = 0;
+        for _ in 0..1000 {
+            match pick_weighted(branches, &mut rng).unwrap().id.as_str() {
+                "SCREENING" => screened += 1,
+                "DESK" => {}
+                other => panic!("picked {other}"),
+            }
+        }
+        assert!(
+            (180..320).contains(&screened),
+            "screened {screened} of 1000"
+        );
+        assert!(pick_weighted(&[], &mut rng).is_none());
+    }
 }
@@ -0,0 +1,198 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::rng::Rng;
+
+// ---------------------------------------------------------------------------
+// Typing rhythm
+// ---------------------------------------------------------------------------
+
+/// Prints guard lines at a typing pace instead of all at once, and
+/// animates the wait for the judge.
+///
+/// ```toml
+/// [pacing]
+/// cps = 40
+/// spinner = false
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PacingConfig {
+    /// Characters per second. 0 prints lines instantly.
+    pub cps: f32,
+    /// Animate "Thinking..." while the judge runs, on terminals.
+    pub spinner: bool,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            cps: 0.0,
+            spinner: true,
+        }
+    }
+}
+
+impl PacingConfig {
+    pub fn enabled(&self) -> bool {
+        self.cps > 0.0
+    }
+
+    /// How long to wait after typing `c`.
+    pub fn delay(&self, c: char, rng: &mut Rng) -> Duration {
+        // +/-30% jitter so the rhythm does not sound mechanical.
+        let jitter = 0.7 + 0.6 * rng.next_f32();
+        Duration::from_secs_f32(pause_factor(c) * jitter / self.cps)
+    }
+}
+
+/// How many characters' worth of time to pause after `c`, so the guard
+/// breathes at punctuation.
+fn pause_factor(c: char) -> f32 {
+    match c {
+        '.' | '!' | '?' | '…' => 8.0,
+        ',' | ';' | ':' => 3.0,
+        _ => 1.0,
+    }
+}
+
+/// Print `text` and a newline, one character at a time when pacing is on.
+/// Pressing Enter prints the rest of the line at once.
+pub fn type_out(text: &str, pacing: &PacingConfig, rng: &mut Rng) -> io::Result<()> {
+    if !pacing.enabled() {
+        println!("{text}");
+        return Ok(());
+    }
+
+    let mut stdout = io::stdout();
+    // Color codes are printed at once, not typed.
+    let mut escape = false;
+    for (i, c) in text.char_indices() {
+        if skip_requested() {
+            print!("{}", &text[i..]);
+            break;
+        }
+        print!("{c}");
+        if c == '\x1b' || escape {
+            escape = c != 'm';
+            continue;
+        }
+        stdout.flush()?;
+        thread::sleep(pacing.delay(c, rng));
+    }
+    println!();
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Spinner
+// ---------------------------------------------------------------------------
+
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+
+/// The spinner frame to show `elapsed` into a wait.
+pub fn spinner(elapsed: Duration) -> char {
+    let frame = elapsed.as_millis() / SPINNER_FRAME.as_millis();
+    SPINNER[frame as usize % SPINNER.len()]
+}
+
+// ---------------------------------------------------------------------------
+// Shared stdin reader
+// ---------------------------------------------------------------------------
+
+/// Once pacing first checks for a keypress, stdin is read on a background
+/// thread so that check doesn't block. From then on all interactive reads
+/// must go through [`read_line`]. Until then stdin is free, e.g. for the
+/// line editor.
+struct Input {
+    lines: Option<Receiver<String>>,
+    /// A non-empty line typed while a line was still being printed. It skips
+    /// the pacing and is kept as the player's next answer.
+    pending: Option<String>,
+}
+
+fn input() -> MutexGuard<'static, Input> {
+    static INPUT: OnceLock<Mutex<Input>> = OnceLock::new();
+    INPUT
+        .get_or_init(|| {
+            Mutex::new(Input {
+                lines: None,
+                pending: None,
+            })
+        })
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+fn read_in_background() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Whether the player pressed Enter since the last check.
+fn skip_requested() -> bool {
+    let mut input = input();
+    if input.pending.is_some() {
+        return true;
+    }
+    match input
+        .lines
+        .get_or_insert_with(read_in_background)
+        .try_recv()
+    {
+        Ok(line) if line.trim().is_empty() => true,
+        Ok(line) => {
+            input.pending = Some(line);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Read one line from stdin, without the trailing newline. Returns an empty
+/// string once stdin is closed.
+pub fn read_line() -> io::Result<String> {
+    let mut input = input();
+    if let Some(line) = input.pending.take() {
+        return Ok(line);
+    }
+    if let Some(lines) = &input.lines {
+        return Ok(lines.recv().unwrap_or_default());
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pauses_longer_at_sentence_end() {
+        assert!(pause_factor('.') > pause_factor(','));
+        assert!(pause_factor(',') > pause_factor('a'));
+        assert!(!PacingConfig::default().enabled());
+    }
+
+    #[test]
+    fn test_spinner_turns_every_frame() {
+        assert_eq!(spinner(Duration::ZERO), spinner(Duration::from_millis(99)));
+        assert_ne!(spinner(Duration::ZERO), spinner(Duration::from_millis(100)));
+        assert_eq!(spinner(Duration::ZERO), spinner(SPINNER_FRAME * 10));
+    }
+}
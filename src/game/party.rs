@@ -0,0 +1,54 @@
+use anyhow::{bail, Result};
+
+/// Couch co-op: several players, e.g. a couple travelling together, take
+/// turns answering at the same desk. Each answer is tagged with its
+/// speaker's name, so the judge can hold one player's story against the
+/// other's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Party {
+    names: Vec<String>,
+    /// Index of the player answering next.
+    turn: usize,
+}
+
+impl Party {
+    pub fn new(names: Vec<String>) -> Result<Self> {
+        if names.len() < 2 {
+            bail!("a party needs at least two players");
+        }
+        if let Some(name) = names.iter().find(|name| name.trim().is_empty()) {
+            bail!("player name '{name}' is empty");
+        }
+        if let Some((_, name)) = names
+            .iter()
+            .enumerate()
+            .find(|(i, name)| names[..*i].contains(name))
+        {
+            bail!("player name '{name}' is given twice");
+        }
+        Ok(Self { names, turn: 0 })
+    }
+
+    /// The player whose turn it is.
+    pub fn speaker(&self) -> &str {
+        &self.names[self.turn]
+    }
+
+    /// Hand the turn to the next player.
+    pub fn pass(&mut self) {
+        self.turn = (self.turn + 1) % self.names.len();
+    }
+
+    /// The judge's note on who is answering, with `player` the scenario's
+    /// word for them (e.g. "traveller").
+    pub fn describe(&self, player: &str) -> String {
+        format!(
+            "The {player} is {} people answering in turn: {}. {} is answering now; each of \
+             their messages starts with their name. An answer that does not match what another \
+             of them said counts against them.",
+            self.names.len(),
+            self.names.join(" and "),
+            self.speaker()
+        )
+    }
+}
@@ -0,0 +1,218 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::tree::GameTree;
+
+/// Features beyond scripted lines and the judge's picks, which a scenario
+/// has to ask for and the player has to allow with `--allow` before it is
+/// played:
+///
+/// ```json
+/// "permissions": {"allows_tool_use": true}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Permissions {
+    /// The model words guard lines: small talk, follow-up questions,
+    /// callbacks to earlier answers.
+    pub allows_dynamic_dialogue: bool,
+    /// The judge looks things up mid-judgment (`regulations`).
+    pub allows_tool_use: bool,
+    /// Assets fetched from a web catalog. Nothing in the engine fetches
+    /// any yet; the flag is reserved so scenarios can ask for it.
+    pub allows_web_catalog_assets: bool,
+    /// Game variables: `set`, `extract` and `when` conditions.
+    pub allows_scripting: bool,
+}
+
+impl Permissions {
+    pub fn is_empty(&self) -> bool {
+        self.requested().is_empty()
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::DynamicDialogue => self.allows_dynamic_dialogue,
+            Capability::ToolUse => self.allows_tool_use,
+            Capability::WebCatalogAssets => self.allows_web_catalog_assets,
+            Capability::Scripting => self.allows_scripting,
+        }
+    }
+
+    /// The capabilities asked for.
+    pub fn requested(&self) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|&c| self.allows(c))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    DynamicDialogue,
+    ToolUse,
+    WebCatalogAssets,
+    Scripting,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::DynamicDialogue,
+        Capability::ToolUse,
+        Capability::WebCatalogAssets,
+        Capability::Scripting,
+    ];
+
+    /// Name for `--allow`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::DynamicDialogue => "dynamic-dialogue",
+            Capability::ToolUse => "tool-use",
+            Capability::WebCatalogAssets => "web-catalog-assets",
+            Capability::Scripting => "scripting",
+        }
+    }
+
+    /// The scenario's flag for it.
+    fn flag(self) -> &'static str {
+        match self {
+            Capability::DynamicDialogue => "allows_dynamic_dialogue",
+            Capability::ToolUse => "allows_tool_use",
+            Capability::WebCatalogAssets => "allows_web_catalog_assets",
+            Capability::Scripting => "allows_scripting",
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Capability::ALL.into_iter().find(|c| c.name() == s) {
+            Some(capability) => Ok(capability),
+            None => {
+                let names: Vec<&str> = Capability::ALL.iter().map(|c| c.name()).collect();
+                bail!(
+                    "unknown capability '{s}' (expected {} or all)",
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Parse a comma-separated `--allow` list; `all` allows everything.
+pub fn parse_allowed(list: &str) -> Result<Vec<Capability>> {
+    if list.trim() == "all" {
+        return Ok(Capability::ALL.to_vec());
+    }
+    list.split(',').map(|name| name.trim().parse()).collect()
+}
+
+/// Where `tree` uses each capability, e.g. `(ToolUse, "regulations")`.
+pub fn used(tree: &GameTree) -> Vec<(Capability, String)> {
+    let mut used = Vec::new();
+    if !tree.regulations.is_empty() {
+        used.push((Capability::ToolUse, "regulations".to_string()));
+    }
+    let mut personas = tree
+        .persona
+        .iter()
+        .chain(tree.speakers.values().filter_map(|s| s.persona.as_ref()));
+    if personas.any(|persona| persona.callbacks) {
+        used.push((Capability::DynamicDialogue, "persona callbacks".to_string()));
+    }
+    let mut nodes: Vec<_> = tree.nodes.values().collect();
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    for node in nodes {
+        let id = &node.id;
+        if node.min_turns.is_some_and(|n| n > 1) || node.max_turns.is_some_and(|n| n > 1) {
+            used.push((Capability::DynamicDialogue, format!("{id}: small talk")));
+        }
+        if node.clarify.as_ref().is_some_and(|c| c.line.is_none()) {
+            used.push((
+                Capability::DynamicDialogue,
+                format!("{id}: judge's follow-ups"),
+            ));
+        }
+        if !node.set.is_empty() || !node.extract.is_empty() {
+            used.push((Capability::Scripting, format!("{id}: variables")));
+        }
+        if node.node_type.next_nodes().any(|next| next.when.is_some()) {
+            used.push((Capability::Scripting, format!("{id}: conditions")));
+        }
+    }
+    used
+}
+
+/// Fail if `tree` uses a capability that its `permissions` don't ask for.
+pub fn check_declared(tree: &GameTree) -> Result<()> {
+    let undeclared: Vec<String> = used(tree)
+        .into_iter()
+        .filter(|(capability, _)| !tree.permissions.allows(*capability))
+        .map(|(capability, at)| format!("\"{}\" for {at}", capability.flag()))
+        .collect();
+    if !undeclared.is_empty() {
+        bail!(
+            "the scenario uses features it does not ask for in \"permissions\": {}",
+            undeclared.join("; ")
+        );
+    }
+    Ok(())
+}
+
+/// Fail unless the player allowed everything the scenario asks for.
+pub fn check_allowed(tree: &GameTree, allowed: &[Capability], shown: &str) -> Result<()> {
+    let missing: Vec<&str> = tree
+        .permissions
+        .requested()
+        .into_iter()
+        .filter(|c| !allowed.contains(c))
+        .map(Capability::name)
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "{shown} asks for {}; if you trust it, play it with --allow {}",
+            missing.join(", "),
+            missing.join(",")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenarios_ask_and_players_allow() {
+        let mut tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "regulations": {"5.2": "Fruit must be declared."},
+                "nodes": {"A": {"id": "A", "transcript": "Hi", "set": {"met": true},
+                    "node_type": {"Decision": [{"id": "B", "description": "ok"}]}},
+                "B": {"id": "B", "transcript": "Bye", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        let used: Vec<_> = used(&tree).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(used, [Capability::ToolUse, Capability::Scripting]);
+        let err = check_declared(&tree).unwrap_err().to_string();
+        assert!(err.contains("\"allows_tool_use\" for regulations"), "{err}");
+
+        tree.permissions.allows_tool_use = true;
+        tree.permissions.allows_scripting = true;
+        check_declared(&tree).unwrap();
+        let err = check_allowed(&tree, &[Capability::ToolUse], "x.json")
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("--allow scripting"), "{err}");
+        check_allowed(&tree, &parse_allowed("all").unwrap(), "x.json").unwrap();
+        assert_eq!(
+            parse_allowed("tool-use, scripting").unwrap(),
+            [Capability::ToolUse, Capability::Scripting]
+        );
+        assert!(parse_allowed("root").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::shift::Shift;
+
+/// Scenario-level description of who the guard is and how they talk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Persona {
+    /// The guard's name, e.g. "Officer Brandt".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How the guard comes across, e.g. "bored and grumpy".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperament: Option<String>,
+    /// Habits of speech, e.g. "sighs before every question".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quirks: Vec<String>,
+    /// How the guard phrases things, whatever they say.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<Style>,
+    /// Optional second language the guard code-switches into. Phrases are
+    /// referenced from transcripts with `{{key}}` placeholders.
+    #[serde(default)]
+    pub second_language: Option<SecondLanguage>,
+    /// Moods by local time of day, e.g. a tired night shift. The first
+    /// one on applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shifts: Vec<Shift>,
+    /// When talking in character, the guard calls back to details from the
+    /// player's earlier answers (see [`crate::game::memory::Memory`]).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub callbacks: bool,
+}
+
+impl Persona {
+    /// Who the guard is, for the model's prompts: "You are Officer Brandt,
+    /// bored and grumpy. Your habits of speech: ...". `None` if the persona
+    /// says nothing about it.
+    pub fn describe(&self) -> Option<String> {
+        let mut text = match (&self.name, &self.temperament) {
+            (Some(name), Some(temperament)) => format!("You are {name}, {temperament}."),
+            (Some(who), None) | (None, Some(who)) => format!("You are {who}."),
+            (None, None) => String::new(),
+        };
+        if !self.quirks.is_empty() {
+            text.push_str(&format!(
+                " Your habits of speech: {}.",
+                self.quirks.join("; ")
+            ));
+        }
+        if let Some(style) = self.style {
+            text.push(' ');
+            text.push_str(style.instruction());
+        }
+        let text = text.trim_start();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+/// Another character of the scenario, e.g. a customs officer the guard
+/// hands the player over to. Nodes name it in their `speaker`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Speaker {
+    /// Shown with the speaker's lines, e.g. "Customs Officer".
+    pub name: String,
+    /// Who they are and how they talk; the scenario's persona is used for
+    /// the rest (second language, shifts, callbacks).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<Persona>,
+}
+
+/// Register the guard speaks in, e.g. `"style": "terse"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    Terse,
+    Formal,
+    Chatty,
+}
+
+impl Style {
+    fn instruction(self) -> &'static str {
+        match self {
+            Style::Terse => "You speak tersely: as few words as will do, no pleasantries.",
+            Style::Formal => {
+                "You speak formally: complete sentences, polite and official, no slang."
+            }
+            Style::Chatty => {
+                "You are chatty: you wrap what you have to say in a friendly remark or two."
+            }
+        }
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecondLanguage {
+    /// Human readable language name (e.g. "German").
+    pub language: String,
+    /// Phrase key -> phrase in the second language and its translation.
+    pub phrases: HashMap<String, Phrase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Phrase {
+    /// The phrase as spoken in the second language.
+    pub text: String,
+    /// Translation in the scenario's main language.
+    pub translation: String,
+}
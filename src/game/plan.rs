@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::game::node::{GameNode, NextNode, NodeType, Transcript};
+use crate::game::vars::Vars;
+
+/// Judge reply key listing the other open topics an answer already covers.
+pub const COVERS: &str = "covers";
+
+const DEFAULT_FAILS: &str = "refuses, dodges the question or does not meet the above";
+
+/// A checklist of topics the guard has to get through, instead of a fixed
+/// order of questions:
+///
+/// ```json
+/// "plan": {"success": "CLEARED", "failure": "REFUSED", "topics": [
+///   {"id": "ORIGIN", "question": "Where are you flying in from?",
+///    "criteria": "names the city or country they flew in from"},
+///   {"id": "FUNDS", "question": "How will you pay for your stay?",
+///    "criteria": "names a credible means of support"}]}
+/// ```
+///
+/// Each topic becomes a decision node of the same id when the scenario is
+/// loaded, passing on to the next topic and failing to `failure`. Edges
+/// elsewhere can lead to a topic like to any node. After a topic is passed
+/// the game asks about the first one still open, so a topic the player
+/// already answered along the way (the judge says so in
+/// [`COVERS`]) is never asked; once none is open it goes to `success`.
+/// Plans of included files are not used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub topics: Vec<Topic>,
+    pub success: String,
+    pub failure: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Topic {
+    pub id: String,
+    pub question: Transcript,
+    /// What an answer must do to settle the topic.
+    pub criteria: String,
+    /// What fails it; a refusal or an answer that doesn't meet `criteria`
+    /// by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fails: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_context: Option<String>,
+}
+
+impl Plan {
+    pub fn topic(&self, id: &str) -> Option<&Topic> {
+        self.topics.iter().find(|topic| topic.id == id)
+    }
+
+    /// The decision node of each topic.
+    pub fn nodes(&self) -> impl Iterator<Item = GameNode> + '_ {
+        self.topics.iter().enumerate().map(|(i, topic)| {
+            let passed = self.after(i);
+            let edge = |id: &str, description: &str| NextNode {
+                id: id.to_string(),
+                description: description.to_string(),
+                when: None,
+            };
+            GameNode {
+                id: topic.id.clone(),
+                transcript: topic.question.clone(),
+                node_type: NodeType::Decision(vec![
+                    edge(passed, &topic.criteria),
+                    edge(
+                        &self.failure,
+                        topic.fails.as_deref().unwrap_or(DEFAULT_FAILS),
+                    ),
+                ]),
+                system_context: topic.system_context.clone(),
+                appeal: None,
+                repeats: None,
+                set: Vars::new(),
+                extract: Default::default(),
+                contradiction: None,
+                model: None,
+                clarify: None,
+                min_turns: None,
+                max_turns: None,
+                checkpoint: false,
+                points: None,
+                rank: Vec::new(),
+                difficulty: Default::default(),
+                speaker: None,
+                display_name: None,
+                locales: Default::default(),
+                rules: Vec::new(),
+            }
+        })
+    }
+
+    /// Whether `next` is where the topic `id` goes when it is passed.
+    pub fn passes(&self, id: &str, next: &str) -> bool {
+        self.topics
+            .iter()
+            .position(|topic| topic.id == id)
+            .is_some_and(|i| self.after(i) == next)
+    }
+
+    /// Where the `i`th topic's pass edge leads: the next topic in plan
+    /// order, or `success` after the last.
+    fn after(&self, i: usize) -> &str {
+        self.topics
+            .get(i + 1)
+            .map_or(&self.success, |topic| &topic.id)
+    }
+
+    /// The open topics other than `current`, for the judge to say which the
+    /// last answer already covers. `None` if there are none.
+    pub fn others(&self, current: &str, vars: &Vars) -> Option<String> {
+        let open: Vec<String> = self
+            .topics
+            .iter()
+            .filter(|topic| topic.id != current && !covered(&topic.id, vars))
+            .map(|topic| format!("{} ({})", topic.id, topic.criteria))
+            .collect();
+        (!open.is_empty()).then(|| open.join("; "))
+    }
+
+    /// Mark `current` and the topics the judge listed in `covers` as
+    /// settled.
+    pub fn settle(&self, current: &str, covers: Option<&Value>, vars: &mut Vars) {
+        let listed: Vec<&str> = match covers {
+            Some(Value::Array(ids)) => ids.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(ids)) => ids.split(',').map(str::trim).collect(),
+            _ => Vec::new(),
+        };
+        for id in std::iter::once(current).chain(listed) {
+            if self.topic(id).is_some() {
+                vars.insert(covered_var(id), Value::Bool(true));
+            }
+        }
+    }
+
+    /// The first topic still open, or `success`.
+    pub fn next(&self, vars: &Vars) -> &str {
+        self.topics
+            .iter()
+            .find(|topic| !covered(&topic.id, vars))
+            .map_or(&self.success, |topic| &topic.id)
+    }
+}
+
+/// Game variable set once a topic is settled, e.g. `covered_funds`, so
+/// edge conditions can test it.
+fn covered_var(id: &str) -> String {
+    format!("covered_{}", id.to_lowercase())
+}
+
+fn covered(id: &str, vars: &Vars) -> bool {
+    vars.get(&covered_var(id)) == Some(&Value::Bool(true))
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+use log::warn;
+
+use crate::game::build_judge_messages;
+use crate::game::instruction::JudgeInstruction;
+use crate::game::locale;
+use crate::game::node::NodeType;
+use crate::game::report_card::ReportCard;
+use crate::game::transcript::{LogEntry, TurnRecord};
+use crate::game::tree::{describe_revision, GameTree};
+use crate::llm::{ChatMessage, LLM};
+
+/// Totals printed at the end of a replay.
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    pub turns: usize,
+    /// Turns re-judged (only when a model was given).
+    pub rejudged: usize,
+    /// Re-judged turns whose decision differs from the recording.
+    pub changed: usize,
+    /// The recording was made against different scenario content than the
+    /// one re-judged with, so changes may come from the scenario.
+    pub scenario_changed: bool,
+}
+
+/// Print a recorded session. When `rejudge` is given, the judge is run again
+/// on every recorded answer with the conversation as it stood at that turn,
+/// and turns whose decision changed are flagged.
+pub fn replay(
+    entries: &[LogEntry],
+    rejudge: Option<(&mut LLM, &GameTree)>,
+) -> Result<ReplaySummary> {
+    let mut summary = ReplaySummary::default();
+    let mut conversation: Vec<ChatMessage> = Vec::new();
+    let (mut model, tree) = match rejudge {
+        Some((model, tree)) => (Some(model), Some(tree)),
+        None => (None, None),
+    };
+
+    for entry in entries {
+        match entry {
+            LogEntry::SessionStart {
+                session_id,
+                scenario_path,
+                scenario_hash,
+                scenario_version,
+                seed,
+                ..
+            } => {
+                let recorded = scenario_hash
+                    .as_deref()
+                    .map(|hash| describe_revision(scenario_version.as_deref(), hash));
+                println!("=== Session {session_id} ===");
+                println!(
+                    "  scenario: {} {}  seed: {seed}",
+                    scenario_path.as_deref().unwrap_or("(unknown)"),
+                    recorded.as_deref().unwrap_or("(revision not recorded)")
+                );
+                if let (Some(hash), Some(tree)) = (scenario_hash, tree) {
+                    if *hash != tree.content_hash() {
+                        summary.scenario_changed = true;
+                        println!(
+                            "  !! scenario changed since recording (now {}); decisions may differ because of it",
+                            tree.revision()
+                        );
+                    }
+                }
+            }
+            LogEntry::Turn(turn) => {
+                if turn.turn == 1 {
+                    conversation.clear();
+                    println!("\n--- Round {} ---", turn.round);
+                }
+                summary.turns += 1;
+                print_turn(turn);
+
+                conversation.push(ChatMessage::assistant(&turn.guard_line));
+                conversation.push(ChatMessage::user(&turn.player_input));
+
+                if let (Some(model), Some(tree)) = (model.as_deref_mut(), tree) {
+                    rejudge_turn(model, tree, turn, &conversation, &mut summary)?;
+                }
+            }
+            LogEntry::GameOver {
+                terminal_node_id,
+                success,
+                steps_completed,
+                ..
+            } => match (terminal_node_id, success) {
+                (Some(id), Some(success)) => println!(
+                    "  == Game over at {id} ({}) after {steps_completed} steps",
+                    if *success { "cleared" } else { "failed" }
+                ),
+                _ => println!("  == Player quit after {steps_completed} steps"),
+            },
+            LogEntry::ReportCard { rows, .. } => {
+                let card = ReportCard { rows: rows.clone() };
+                for line in card.lines(&locale::ENGLISH) {
+                    println!("  {line}");
+                }
+            }
+        }
+    }
+
+    println!("\n{} turns replayed", summary.turns);
+    if summary.rejudged > 0 {
+        println!(
+            "{} re-judged, {} changed decision",
+            summary.rejudged, summary.changed
+        );
+    }
+    Ok(summary)
+}
+
+fn print_turn(turn: &TurnRecord) {
+    println!("\n[{}] Guard: {}", turn.node_id, turn.guard_line);
+    println!("[You]: {}", turn.player_input);
+    println!(
+        "  -> {} ({}) [{} ms, {}+{} tokens]",
+        turn.decision, turn.reason, turn.latency_ms, turn.prompt_tokens, turn.completion_tokens
+    );
+}
+
+fn rejudge_turn(
+    model: &mut LLM,
+    tree: &GameTree,
+    turn: &TurnRecord,
+    conversation: &[ChatMessage],
+    summary: &mut ReplaySummary,
+) -> Result<()> {
+    let Some(node) = tree.get(&turn.node_id) else {
+        warn!("Node {} is not in the scenario; skipping", turn.node_id);
+        return Ok(());
+    };
+    let NodeType::Decision(next_nodes) = &node.node_type else {
+        warn!("Node {} is not a decision node; skipping", turn.node_id);
+        return Ok(());
+    };
+
+    let valid_choices: Vec<&str> = next_nodes.iter().map(|n| n.id.as_str()).collect();
+    let messages =
+        build_judge_messages(conversation, node, &tree.setting(), JudgeInstruction::new())?;
+    summary.rejudged += 1;
+
+    let judged = match node.rule_for(&turn.player_input, &valid_choices) {
+        Some(rule) => Ok(rule.decision()),
+        None => model.judge_with(node.model.as_deref(), &messages, &valid_choices, |_| {}),
+    };
+    match judged {
+        Ok(d) if d.decision == turn.decision => println!("  == same: {}", d.decision),
+        Ok(d) => {
+            summary.changed += 1;
+            println!(
+                "  !! CHANGED: recorded {} now {} ({})",
+                turn.decision, d.decision, d.reason
+            );
+        }
+        Err(e) => {
+            summary.changed += 1;
+            println!("  !! CHANGED: recorded {} now error: {e:#}", turn.decision);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(round: usize, turn: usize) -> LogEntry {
+        LogEntry::Turn(TurnRecord {
+            round,
+            turn,
+            node_id: "START".into(),
+            guard_line: "Passport, please.".into(),
+            player_input: "Here you go.".into(),
+            decision: "QUESTION_1".into(),
+            reason: "cooperative".into(),
+            latency_ms: 10,
+            prompt_tokens: 100,
+            completion_tokens: 20,
+        })
+    }
+
+    #[test]
+    fn test_display_only_counts_turns() {
+        let entries = vec![turn(1, 1), turn(1, 2), turn(2, 1)];
+        let summary = replay(&entries, None).unwrap();
+        assert_eq!(summary.turns, 3);
+        assert_eq!(summary.rejudged, 0);
+    }
+
+    #[test]
+    fn test_transcripts_without_scenario_hash_still_load() {
+        let old: LogEntry = serde_json::from_str(
+            r#"{"type": "session_start", "session_id": "1-ab", "started_at": 1,
+                "scenario_path": "airport.json", "seed": 7}"#,
+        )
+        .unwrap();
+        let LogEntry::SessionStart { scenario_hash, .. } = &old else {
+            panic!("expected a session start");
+        };
+        assert_eq!(*scenario_hash, None);
+        let summary = replay(&[old, turn(1, 1)], None).unwrap();
+        assert!(!summary.scenario_changed);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::game::eval::{CaseResult, EvalReport};
+
+/// Report file format, picked by the file's extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Csv,
+}
+
+impl Format {
+    pub fn of(path: &str) -> Result<Self> {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("html" | "htm") => Ok(Format::Html),
+            Some("csv") => Ok(Format::Csv),
+            _ => bail!("cannot tell the report format of {path} (use .html or .csv)"),
+        }
+    }
+}
+
+/// Write `report` to `path`, in the format of its extension.
+pub fn write(report: &EvalReport, path: &str) -> Result<()> {
+    let text = match Format::of(path)? {
+        Format::Html => to_html(report),
+        Format::Csv => to_csv(report),
+    };
+    std::fs::write(path, text).with_context(|| format!("failed to write {path}"))
+}
+
+// ---------------------------------------------------------------------------
+// CSV
+// ---------------------------------------------------------------------------
+
+/// One row per judged case, with the judge's raw output.
+pub fn to_csv(report: &EvalReport) -> String {
+    let mut out = String::from("node,turn,input,expected,judged,passed,reason,raw\n");
+    for r in &report.results {
+        let turn = r.turn.map_or(String::new(), |turn| turn.to_string());
+        let fields = [
+            r.case.node.as_str(),
+            &turn,
+            &r.case.input,
+            &r.case.expected,
+            r.label(),
+            if r.passed() { "true" } else { "false" },
+            &r.reason,
+            &r.raw,
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote a field if it holds a comma, quote or line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTML
+// ---------------------------------------------------------------------------
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#f0f0f0}td.n{text-align:right}\
+td.hit{background:#d9f2d9}td.miss{background:#f8d7d7}\
+pre{margin:0;white-space:pre-wrap;max-width:60em}";
+
+/// A page with the accuracy per node, the confusion matrix and the
+/// failing cases, those at the least accurate nodes first.
+pub fn to_html(report: &EvalReport) -> String {
+    let mut out = String::new();
+    let passed = report.results.iter().filter(|r| r.passed()).count();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Eval report</title>\
+         <style>{STYLE}</style></head><body>\n<h1>Eval report</h1>\n\
+         <p>Scenario: {}<br>Accuracy: {:.1}% ({passed}/{})</p>\n",
+        escape(&report.scenario),
+        100.0 * report.accuracy(),
+        report.results.len()
+    );
+
+    let nodes = report.node_accuracy();
+    out.push_str("<h2>Accuracy per node</h2>\n<table><tr><th>Node</th><th>Passed</th><th>Cases</th><th>Accuracy</th></tr>\n");
+    for (node, (passed, total)) in &nodes {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"n\">{passed}</td><td class=\"n\">{total}</td><td class=\"n\">{:.1}%</td></tr>",
+            escape(node),
+            100.0 * *passed as f32 / *total as f32
+        );
+    }
+    out.push_str("</table>\n");
+
+    let (labels, matrix) = report.confusion_matrix();
+    out.push_str(
+        "<h2>Confusion matrix</h2>\n<p>Rows: expected, columns: judged.</p>\n<table><tr><th></th>",
+    );
+    for label in &labels {
+        let _ = write!(out, "<th>{}</th>", escape(label));
+    }
+    out.push_str("</tr>\n");
+    for (i, (label, row)) in labels.iter().zip(&matrix).enumerate() {
+        let _ = write!(out, "<tr><th>{}</th>", escape(label));
+        for (j, count) in row.iter().enumerate() {
+            let class = match (*count, i == j) {
+                (0, _) => "n",
+                (_, true) => "n hit",
+                (_, false) => "n miss",
+            };
+            let _ = write!(out, "<td class=\"{class}\">{count}</td>");
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+
+    let mut failures: Vec<&CaseResult> = report.results.iter().filter(|r| !r.passed()).collect();
+    let accuracy = |r: &CaseResult| {
+        let (passed, total) = nodes[r.case.node.as_str()];
+        passed as f32 / total as f32
+    };
+    failures.sort_by(|a, b| accuracy(a).total_cmp(&accuracy(b)));
+    if !failures.is_empty() {
+        out.push_str("<h2>Hardest cases</h2>\n<table><tr><th>Node</th><th>Input</th><th>Expected</th><th>Judged</th><th>Reason</th><th>Raw output</th></tr>\n");
+        for r in failures {
+            hardest_case(&mut out, r);
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn hardest_case(out: &mut String, r: &CaseResult) {
+    let node = match r.turn {
+        Some(turn) => format!("{}, turn {turn}", r.case.node),
+        None => r.case.node.clone(),
+    };
+    let _ = writeln!(
+        out,
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><pre>{}</pre></td></tr>",
+        escape(&node),
+        escape(&r.case.input),
+        escape(&r.case.expected),
+        escape(r.label()),
+        escape(&r.reason),
+        escape(&r.raw)
+    );
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::eval::EvalCase;
+
+    fn result(node: &str, expected: &str, decision: &str, raw: &str) -> CaseResult {
+        CaseResult {
+            case: EvalCase {
+                node: node.into(),
+                input: "Hi, \"officer\"".into(),
+                expected: expected.into(),
+            },
+            turn: None,
+            decision: Some(decision.into()),
+            reason: "because".into(),
+            raw: raw.into(),
+        }
+    }
+
+    #[test]
+    fn test_csv_and_html_reports() {
+        let report = EvalReport {
+            scenario: "0123456789abcdef".into(),
+            results: vec![
+                result("START", "PASS", "PASS", "{}"),
+                result("START", "PASS", "FAIL", r#"{"decision": "FAIL"}"#),
+                result("GATE", "PASS", "FAIL", "<b>"),
+            ],
+        };
+        let csv = to_csv(&report);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[2],
+            r#"START,,"Hi, ""officer""",PASS,FAIL,false,because,"{""decision"": ""FAIL""}""#
+        );
+
+        let html = to_html(&report);
+        assert!(html.contains("<td>START</td><td class=\"n\">1</td><td class=\"n\">2</td>"));
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(Format::of("report.txt").is_err());
+        assert_eq!(Format::of("report.csv").unwrap(), Format::Csv);
+
+        // GATE never passes, so its case comes first.
+        assert!(
+            html.find("<td>GATE</td><td>Hi").unwrap() < html.find("<td>START</td><td>Hi").unwrap()
+        );
+    }
+}
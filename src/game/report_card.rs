@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::locale::Messages;
+use crate::game::node::{NodeType, CLARIFY, UNDECIDABLE};
+use crate::game::transcript::TurnRecord;
+use crate::game::tree::GameTree;
+
+/// Widest the step column gets; longer names are cut.
+const STEP_WIDTH: usize = 24;
+
+/// How a judged answer went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mark {
+    Passed,
+    Flagged,
+}
+
+/// One judged answer on the report card.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardRow {
+    /// The step it was given at, as players see it.
+    pub step: String,
+    pub mark: Mark,
+    /// The criterion the judge matched the answer to.
+    pub criterion: String,
+    pub reason: String,
+    /// What the step was looking for instead; on the first answer flagged
+    /// for each criterion only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tip: Option<String>,
+}
+
+/// What went well and what raised flags in a round, from its judged turns.
+/// Shown after game over and kept in the session transcript, so a round
+/// can be gone over like a practice interview.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportCard {
+    pub rows: Vec<CardRow>,
+}
+
+impl ReportCard {
+    pub fn new(tree: &GameTree, turns: &[TurnRecord]) -> Self {
+        let mut tipped = BTreeSet::new();
+        let rows = turns
+            .iter()
+            .filter_map(|turn| {
+                let mut row = row(tree, turn)?;
+                if row.mark == Mark::Flagged
+                    && tipped.insert((turn.node_id.clone(), row.criterion.clone()))
+                {
+                    row.tip = tip(tree, &turn.node_id);
+                }
+                Some(row)
+            })
+            .collect();
+        Self { rows }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The card as a table, one line per row plus its tip.
+    pub fn lines(&self, m: &Messages) -> Vec<String> {
+        if self.rows.is_empty() {
+            return Vec::new();
+        }
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.step.chars().count().min(STEP_WIDTH))
+            .max()
+            .unwrap_or(0);
+        let marks = [m.went_well, m.flagged];
+        let mark_width = marks.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+        let mut lines = vec![m.report_card.to_string()];
+        for row in &self.rows {
+            let step: String = row.step.chars().take(STEP_WIDTH).collect();
+            let mark = match row.mark {
+                Mark::Passed => m.went_well,
+                Mark::Flagged => m.flagged,
+            };
+            lines.push(format!(
+                "  {step:<width$}  {mark:<mark_width$}  {}",
+                row.criterion
+            ));
+            if let Some(tip) = &row.tip {
+                let indent = width + mark_width + 4;
+                lines.push(format!("  {:indent$}{}: {tip}", "", m.tip));
+            }
+        }
+        let flagged = self.rows.iter().filter(|r| r.mark == Mark::Flagged).count();
+        lines.push(format!(
+            "  {}: {}, {}: {flagged}",
+            m.went_well,
+            self.rows.len() - flagged,
+            m.flagged
+        ));
+        lines
+    }
+}
+
+/// The row for a judged turn, or `None` for turns that judged nothing
+/// (small talk, appeals).
+fn row(tree: &GameTree, turn: &TurnRecord) -> Option<CardRow> {
+    let node = tree.get(&turn.node_id)?;
+    let NodeType::Decision(next) = &node.node_type else {
+        return None;
+    };
+    let (mark, criterion) = if turn.decision == CLARIFY || turn.decision == UNDECIDABLE {
+        (Mark::Flagged, "The answer was unclear".to_string())
+    } else if let Some(edge) = next.iter().find(|edge| edge.id == turn.decision) {
+        (mark_of(tree, &edge.id), edge.description.clone())
+    } else if node.contradiction.as_ref() == Some(&turn.decision) {
+        (Mark::Flagged, "Contradicted an earlier answer".to_string())
+    } else {
+        return None;
+    };
+    Some(CardRow {
+        step: tree.display_name(&node.id),
+        mark,
+        criterion,
+        reason: turn.reason.clone(),
+        tip: None,
+    })
+}
+
+fn mark_of(tree: &GameTree, id: &str) -> Mark {
+    match tree.get(id).map(|node| &node.node_type) {
+        Some(NodeType::Terminal(false)) => Mark::Flagged,
+        _ => Mark::Passed,
+    }
+}
+
+/// What the passing edges of `node_id` ask for.
+fn tip(tree: &GameTree, node_id: &str) -> Option<String> {
+    let NodeType::Decision(next) = &tree.get(node_id)?.node_type else {
+        return None;
+    };
+    let passing: Vec<&str> = next
+        .iter()
+        .filter(|edge| mark_of(tree, &edge.id) == Mark::Passed)
+        .map(|edge| edge.description.as_str())
+        .collect();
+    (!passing.is_empty()).then(|| passing.join("; or "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::locale::ENGLISH;
+
+    fn turn(node_id: &str, decision: &str) -> TurnRecord {
+        TurnRecord {
+            round: 1,
+            turn: 1,
+            node_id: node_id.into(),
+            guard_line: String::new(),
+            player_input: String::new(),
+            decision: decision.into(),
+            reason: "because".into(),
+            latency_ms: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        }
+    }
+
+    #[test]
+    fn test_report_card_flags_failed_criteria_with_one_tip_each() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "PASSPORT", "nodes": {
+                "PASSPORT": {"id": "PASSPORT", "transcript": "Passport?",
+                    "node_type": {"Decision": [
+                        {"id": "PURPOSE", "description": "hands over a passport"},
+                        {"id": "DENIED", "description": "refuses to show one"}]}},
+                "PURPOSE": {"id": "PURPOSE", "transcript": "Why are you here?",
+                    "node_type": {"Decision": [
+                        {"id": "CLEARED", "description": "names a clear purpose"},
+                        {"id": "DENIED", "description": "is vague about the trip"}]}},
+                "CLEARED": {"id": "CLEARED", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "DENIED": {"id": "DENIED", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let card = ReportCard::new(
+            &tree,
+            &[
+                turn("PASSPORT", "PURPOSE"),
+                turn("PURPOSE", CLARIFY),
+                turn("PURPOSE", "CONTINUE"),
+                turn("PURPOSE", CLARIFY),
+                turn("PURPOSE", "DENIED"),
+            ],
+        );
+
+        let marks: Vec<_> = card.rows.iter().map(|r| r.mark).collect();
+        assert_eq!(
+            marks,
+            [Mark::Passed, Mark::Flagged, Mark::Flagged, Mark::Flagged]
+        );
+        assert_eq!(card.rows[0].criterion, "hands over a passport");
+        assert_eq!(card.rows[0].tip, None);
+        assert_eq!(card.rows[1].tip.as_deref(), Some("names a clear purpose"));
+        // The same unclear answer again gets no second tip.
+        assert_eq!(card.rows[2].tip, None);
+        assert_eq!(card.rows[3].criterion, "is vague about the trip");
+        assert_eq!(card.rows[3].tip.as_deref(), Some("names a clear purpose"));
+
+        let lines = card.lines(&ENGLISH);
+        assert_eq!(lines[0], "REPORT CARD");
+        assert!(lines[1].starts_with("  Passport  Went well      hands over"));
+        assert_eq!(lines.last().unwrap(), "  Went well: 1, Raised a flag: 3");
+    }
+}
@@ -0,0 +1,347 @@
+//! Property tests: every persisted format must survive serialize ->
+//! deserialize unchanged, so schema changes that break old files show up
+//! here first.
+
+use proptest::prelude::*;
+
+use crate::game::node::{GameNode, NextNode, NodeType, Transcript};
+use crate::game::persona::{Persona, Phrase, SecondLanguage, Speaker, Style};
+use crate::game::report_card::{CardRow, Mark};
+use crate::game::save::{SaveFile, SavedState};
+use crate::game::setting::Setting;
+use crate::game::suspicion::{Suspicion, Threshold};
+use crate::game::transcript::{LogEntry, TurnRecord};
+use crate::game::tree::{GameTree, Strikes};
+use crate::game::vars::Condition;
+use crate::llm::ChatMessage;
+
+// ---------------------------------------------------------------------------
+// Strategies
+// ---------------------------------------------------------------------------
+
+fn id() -> impl Strategy<Value = String> {
+    "[A-Z][A-Z0-9_]{0,11}"
+}
+
+/// Free text, including quotes, backslashes, newlines and non-ASCII.
+fn text() -> impl Strategy<Value = String> {
+    "(\\PC|\n|\"|\\\\){0,40}"
+}
+
+fn condition() -> impl Strategy<Value = Condition> {
+    prop_oneof![
+        Just("nervous"),
+        Just("!nervous"),
+        Just("purpose == \"business\""),
+        Just("bags != 2"),
+    ]
+    .prop_map(|c| Condition::try_from(c.to_string()).unwrap())
+}
+
+fn next_node() -> impl Strategy<Value = NextNode> {
+    (id(), text(), prop::option::of(condition())).prop_map(|(id, description, when)| NextNode {
+        id,
+        description,
+        when,
+    })
+}
+
+fn node_type() -> impl Strategy<Value = NodeType> {
+    prop_oneof![
+        any::<bool>().prop_map(NodeType::Terminal),
+        prop::collection::vec(next_node(), 1..4).prop_map(NodeType::Decision),
+        prop::collection::vec((next_node(), (0u8..10).prop_map(f32::from)), 1..4)
+            .prop_map(NodeType::Random),
+        prop::collection::vec(next_node(), 1..4).prop_map(NodeType::Interlude),
+    ]
+}
+
+fn transcript() -> impl Strategy<Value = Transcript> {
+    prop_oneof![
+        text().prop_map(Transcript::Line),
+        prop::collection::vec(text(), 0..3).prop_map(Transcript::Variants),
+    ]
+}
+
+fn node() -> impl Strategy<Value = GameNode> {
+    (
+        id(),
+        transcript(),
+        node_type(),
+        prop::option::of(text()),
+        prop::option::of(id()),
+        prop::option::of(text()),
+    )
+        .prop_map(
+            |(id, transcript, node_type, system_context, speaker, display_name)| GameNode {
+                id,
+                transcript,
+                node_type,
+                system_context,
+                appeal: None,
+                repeats: None,
+                set: Default::default(),
+                extract: Default::default(),
+                contradiction: None,
+                model: None,
+                clarify: None,
+                min_turns: None,
+                max_turns: None,
+                checkpoint: false,
+                points: None,
+                rank: Vec::new(),
+                difficulty: Default::default(),
+                speaker,
+                display_name,
+                locales: Default::default(),
+                rules: Vec::new(),
+            },
+        )
+}
+
+fn persona() -> impl Strategy<Value = Persona> {
+    let phrase = (text(), text()).prop_map(|(text, translation)| Phrase { text, translation });
+    let language = (text(), prop::collection::hash_map(id(), phrase, 0..4))
+        .prop_map(|(language, phrases)| SecondLanguage { language, phrases });
+    (
+        prop::option::of(text()),
+        prop::collection::vec(text(), 0..3),
+        prop::option::of(language),
+        prop::option::of(prop_oneof![
+            Just(Style::Terse),
+            Just(Style::Formal),
+            Just(Style::Chatty)
+        ]),
+    )
+        .prop_map(|(name, quirks, second_language, style)| Persona {
+            name,
+            temperament: None,
+            quirks,
+            style,
+            second_language,
+            shifts: Vec::new(),
+            callbacks: false,
+        })
+}
+
+fn tree() -> impl Strategy<Value = GameTree> {
+    (
+        prop::collection::vec(node(), 1..8),
+        prop::option::of(persona()),
+        prop::option::of("[0-9]\\.[0-9]\\.[0-9]"),
+        prop::collection::hash_map(id(), id(), 0..3),
+        prop::option::of((text(), text())),
+        prop::option::of(prop::collection::vec(threshold(), 1..3)),
+        prop::option::of(((1u8..5).prop_map(usize::from), id())),
+        prop::collection::hash_map(id(), (text(), prop::option::of(persona())), 0..3),
+    )
+        .prop_map(
+            |(nodes, persona, version, node_aliases, setting, thresholds, strikes, speakers)| {
+                GameTree {
+                    start_node_id: nodes[0].id.clone(),
+                    nodes: nodes.into_iter().map(|n| (n.id.clone(), n)).collect(),
+                    persona,
+                    version,
+                    node_aliases,
+                    setting: setting.map(|(role, player)| Setting {
+                        role,
+                        player,
+                        ..Setting::default()
+                    }),
+                    suspicion: thresholds.map(|thresholds| Suspicion {
+                        thresholds,
+                        lie_detector: None,
+                        decay: None,
+                    }),
+                    strikes: strikes.map(|(max_failures, warning)| Strikes {
+                        max_failures,
+                        warning,
+                    }),
+                    include: Vec::new(),
+                    hint_penalty: 0,
+                    undecidable: None,
+                    plan: None,
+                    speakers: speakers
+                        .into_iter()
+                        .map(|(id, (name, persona))| (id, Speaker { name, persona }))
+                        .collect(),
+                    regulations: Default::default(),
+                    permissions: Default::default(),
+                }
+            },
+        )
+}
+
+fn threshold() -> impl Strategy<Value = Threshold> {
+    ((1u8..10).prop_map(i32::from), id()).prop_map(|(at, node)| Threshold { at, node })
+}
+
+fn message() -> impl Strategy<Value = ChatMessage> {
+    prop_oneof![
+        text().prop_map(ChatMessage::system),
+        text().prop_map(ChatMessage::user),
+        text().prop_map(ChatMessage::assistant),
+    ]
+}
+
+fn saved_state() -> impl Strategy<Value = SavedState> {
+    (
+        id(),
+        prop::collection::vec(message(), 0..6),
+        any::<usize>(),
+        prop::collection::hash_map(id(), any::<bool>(), 0..3),
+        (0u8..10).prop_map(i32::from),
+        prop::collection::hash_map(id(), (1u8..5).prop_map(usize::from), 0..3),
+        (
+            (0u8..20).prop_map(i32::from),
+            (0u8..10).prop_map(|n| i32::from(n) - 5),
+            prop::option::of(any::<u64>()),
+            (0u8..5).prop_map(usize::from),
+        ),
+    )
+        .prop_map(
+            |(
+                current_node_id,
+                conversation,
+                steps_completed,
+                vars,
+                suspicion,
+                visits,
+                (score, streak, rng, calm),
+            )| {
+                SavedState {
+                    current_node_id,
+                    conversation,
+                    steps_completed,
+                    vars: vars.into_iter().map(|(k, v)| (k, v.into())).collect(),
+                    suspicion,
+                    visits: visits.into_iter().collect(),
+                    score,
+                    streak,
+                    calm,
+                    rng,
+                }
+            },
+        )
+}
+
+fn turn_record() -> impl Strategy<Value = TurnRecord> {
+    (
+        (any::<usize>(), any::<usize>(), id()),
+        (text(), text()),
+        (id(), text()),
+        (any::<u64>(), any::<usize>(), any::<usize>()),
+    )
+        .prop_map(
+            |(
+                (round, turn, node_id),
+                (guard_line, player_input),
+                (decision, reason),
+                (latency_ms, prompt_tokens, completion_tokens),
+            )| TurnRecord {
+                round,
+                turn,
+                node_id,
+                guard_line,
+                player_input,
+                decision,
+                reason,
+                latency_ms,
+                prompt_tokens,
+                completion_tokens,
+            },
+        )
+}
+
+fn log_entry() -> impl Strategy<Value = LogEntry> {
+    prop_oneof![
+        (
+            text(),
+            any::<u64>(),
+            prop::option::of(text()),
+            prop::option::of(id()),
+            prop::option::of(text()),
+            any::<u64>()
+        )
+            .prop_map(
+                |(session_id, started_at, scenario_path, scenario_hash, scenario_version, seed)| {
+                    LogEntry::SessionStart {
+                        session_id,
+                        started_at,
+                        scenario_path,
+                        scenario_hash,
+                        scenario_version,
+                        seed,
+                    }
+                }
+            ),
+        turn_record().prop_map(LogEntry::Turn),
+        (
+            any::<usize>(),
+            prop::option::of(id()),
+            prop::option::of(any::<bool>()),
+            any::<usize>()
+        )
+            .prop_map(|(round, terminal_node_id, success, steps_completed)| {
+                LogEntry::GameOver {
+                    round,
+                    terminal_node_id,
+                    success,
+                    steps_completed,
+                }
+            }),
+        (
+            any::<usize>(),
+            prop::collection::vec(
+                (text(), any::<bool>(), text(), prop::option::of(text())),
+                0..4
+            )
+        )
+            .prop_map(|(round, rows)| LogEntry::ReportCard {
+                round,
+                rows: rows
+                    .into_iter()
+                    .map(|(step, flagged, criterion, tip)| CardRow {
+                        step,
+                        mark: if flagged { Mark::Flagged } else { Mark::Passed },
+                        criterion: criterion.clone(),
+                        reason: criterion,
+                        tip,
+                    })
+                    .collect(),
+            }),
+    ]
+}
+
+// ---------------------------------------------------------------------------
+// Round trips
+// ---------------------------------------------------------------------------
+
+proptest! {
+    #[test]
+    fn test_scenario_round_trips(tree in tree()) {
+        let json = serde_json::to_string_pretty(&tree).unwrap();
+        let back: GameTree = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(back.content_hash(), tree.content_hash());
+        prop_assert_eq!(back, tree);
+    }
+
+    #[test]
+    fn test_save_round_trips(tree in tree(), state in saved_state()) {
+        let save = SaveFile::new(&tree, &state);
+        let json = serde_json::to_string_pretty(&save).unwrap();
+        let back: SaveFile = serde_json::from_str(&json).unwrap();
+        let restored: SavedState = serde_json::from_value(back.state.clone()).unwrap();
+        prop_assert_eq!(back, save);
+        prop_assert_eq!(restored, state);
+    }
+
+    #[test]
+    fn test_transcript_entry_round_trips(entry in log_entry()) {
+        let line = serde_json::to_string(&entry).unwrap();
+        // JSONL: one entry per line, whatever the content.
+        prop_assert!(!line.contains('\n'));
+        let back: LogEntry = serde_json::from_str(&line).unwrap();
+        prop_assert_eq!(back, entry);
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::game::tree::{describe_revision, GameTree};
+use crate::game::vars::Vars;
+use crate::llm::ChatMessage;
+
+/// Version of the engine writing the save.
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Keys of [`SavedState`] understood by this engine version. Anything else in
+/// a save is dropped on load.
+const KNOWN_STATE_KEYS: &[&str] = &[
+    "current_node_id",
+    "conversation",
+    "steps_completed",
+    "vars",
+    "suspicion",
+    "visits",
+    "score",
+    "streak",
+    "calm",
+    "rng",
+];
+
+// ---------------------------------------------------------------------------
+// Save file format
+// ---------------------------------------------------------------------------
+
+/// On-disk save: version/hash header plus the raw game state. The state is
+/// kept as raw JSON so that fields written by other engine versions can be
+/// inspected and dropped instead of failing the whole load.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub engine_version: String,
+    pub scenario_version: Option<String>,
+    pub scenario_hash: String,
+    /// Scenario file the game was played from, if known.
+    #[serde(default)]
+    pub scenario_path: Option<String>,
+    pub state: Value,
+}
+
+/// The resumable part of a game round.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub current_node_id: String,
+    pub conversation: Vec<ChatMessage>,
+    pub steps_completed: usize,
+    /// Omitted when empty, so saves of scenarios without variables look
+    /// the same as before.
+    #[serde(default, skip_serializing_if = "Vars::is_empty")]
+    pub vars: Vars,
+    /// Suspicion meter reading; omitted at 0 for the same reason.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub suspicion: i32,
+    /// Times each node was entered; omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub visits: BTreeMap<String, usize>,
+    /// Points collected so far; omitted at 0.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub score: i32,
+    /// Passes (positive) or failures (negative) in a row, for adaptive
+    /// difficulty; omitted at 0.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub streak: i32,
+    /// Solid answers in a row toward the suspicion meter's decay; omitted
+    /// at 0.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub calm: usize,
+    /// [`crate::rng::Rng`] state as the current node's line was picked, so
+    /// a resumed game says the same line and draws the same after it.
+    /// Older saves without it draw from the run's seed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rng: Option<u64>,
+}
+
+fn is_zero<T: Default + PartialEq>(n: &T) -> bool {
+    *n == T::default()
+}
+
+/// How a save relates to the scenario it is being resumed against.
+#[derive(Debug, PartialEq)]
+pub enum Compatibility {
+    /// Same engine line and identical scenario content.
+    Compatible,
+    /// The scenario changed since the save was written; migration may work.
+    ScenarioChanged { saved: String, current: String },
+    /// The save cannot be loaded by this engine.
+    Incompatible(String),
+}
+
+impl SaveFile {
+    pub fn new(tree: &GameTree, state: &SavedState) -> Self {
+        Self {
+            engine_version: ENGINE_VERSION.to_string(),
+            scenario_version: tree.version.clone(),
+            scenario_hash: tree.content_hash(),
+            scenario_path: None,
+            state: serde_json::to_value(state).expect("SavedState always serializes"),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read save {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid save {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write save {}", path.display()))
+    }
+
+    pub fn compatibility(&self, tree: &GameTree) -> Compatibility {
+        if !semver_compatible(&self.engine_version, ENGINE_VERSION) {
+            return Compatibility::Incompatible(format!(
+                "save was written by engine {} but this is {ENGINE_VERSION}",
+                self.engine_version
+            ));
+        }
+        let current = tree.content_hash();
+        if self.scenario_hash != current {
+            return Compatibility::ScenarioChanged {
+                saved: describe_revision(self.scenario_version.as_deref(), &self.scenario_hash),
+                current: tree.revision(),
+            };
+        }
+        Compatibility::Compatible
+    }
+
+    /// Restore the game state. When the scenario changed, `migrate` must be
+    /// set to attempt a best-effort migration: the current node is mapped
+    /// through the scenario's `node_aliases` and unknown state keys are
+    /// dropped.
+    pub fn restore(self, tree: &GameTree, migrate: bool) -> Result<SavedState> {
+        match self.compatibility(tree) {
+            Compatibility::Compatible => {}
+            Compatibility::Incompatible(why) => bail!("cannot resume: {why}"),
+            Compatibility::ScenarioChanged { saved, current } if !migrate => {
+                bail!("cannot resume: scenario changed ({saved} -> {current})")
+            }
+            Compatibility::ScenarioChanged { saved, current } => {
+                info!("Migrating save from scenario {saved} to {current}");
+            }
+        }
+
+        let mut raw = match self.state {
+            Value::Object(map) => map,
+            other => bail!("save state must be an object, got {other}"),
+        };
+        raw.retain(|key, _| {
+            let known = KNOWN_STATE_KEYS.contains(&key.as_str());
+            if !known {
+                warn!("Dropping unknown save state key '{key}'");
+            }
+            known
+        });
+
+        let mut state: SavedState =
+            serde_json::from_value(Value::Object(raw)).context("save state is malformed")?;
+
+        let node_id = tree
+            .resolve_node_id(&state.current_node_id)
+            .with_context(|| {
+                format!(
+                    "saved node '{}' no longer exists in the scenario",
+                    state.current_node_id
+                )
+            })?
+            .to_string();
+        if node_id != state.current_node_id {
+            info!("Mapped saved node {} -> {node_id}", state.current_node_id);
+            state.current_node_id = node_id;
+        }
+
+        Ok(state)
+    }
+}
+
+/// Semver compatibility: same major version, and same minor while on 0.x.
+fn semver_compatible(saved: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Option<(u64, u64)> {
+        let mut parts = v.split('.');
+        Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+    };
+    match (parse(saved), parse(current)) {
+        (Some((0, a)), Some((0, b))) => a == b,
+        (Some((a, _)), Some((b, _))) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tree::airport_security_scenario;
+
+    fn state(node: &str) -> SavedState {
+        SavedState {
+            current_node_id: node.into(),
+            conversation: vec![ChatMessage::assistant("Hello. Passport please.")],
+            steps_completed: 1,
+            vars: Vars::new(),
+            suspicion: 0,
+            visits: BTreeMap::new(),
+            score: 0,
+            streak: 0,
+            calm: 0,
+            rng: None,
+        }
+    }
+
+    #[test]
+    fn test_semver_compatible() {
+        assert!(semver_compatible("0.1.0", "0.1.7"));
+        assert!(!semver_compatible("0.1.0", "0.2.0"));
+        assert!(semver_compatible("1.2.0", "1.9.3"));
+        assert!(!semver_compatible("garbage", "0.1.0"));
+    }
+
+    #[test]
+    fn test_restore_round_trip() {
+        let tree = airport_security_scenario();
+        let save = SaveFile::new(&tree, &state("PASSPORT_CHECK"));
+        assert_eq!(save.compatibility(&tree), Compatibility::Compatible);
+        assert_eq!(save.restore(&tree, false).unwrap(), state("PASSPORT_CHECK"));
+    }
+
+    #[test]
+    fn test_migration_maps_ids_and_drops_unknown_keys() {
+        let old = airport_security_scenario();
+        let mut save = SaveFile::new(&old, &state("PASSPORT_CHECK"));
+        save.state["suspicion"] = Value::from(3);
+
+        let mut new = old.clone();
+        let node = new.nodes.remove("PASSPORT_CHECK").unwrap();
+        new.nodes.insert("DOCUMENTS".into(), node);
+        new.node_aliases
+            .insert("PASSPORT_CHECK".into(), "DOCUMENTS".into());
+
+        assert!(matches!(
+            save.compatibility(&new),
+            Compatibility::ScenarioChanged { .. }
+        ));
+        let unmigrated = SaveFile::new(&old, &state("PASSPORT_CHECK")).restore(&new, false);
+        assert!(unmigrated.is_err());
+        assert_eq!(
+            save.restore(&new, true).unwrap().current_node_id,
+            "DOCUMENTS"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::game::node::RESERVED;
+use crate::game::permissions;
+use crate::game::tree::GameTree;
+
+/// Prefix that selects a built-in scenario instead of a file, e.g.
+/// `builtin:interview`.
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+/// Scenarios compiled into the binary, by name. The same files can be
+/// played from `scenarios/` by path.
+const BUILTIN: &[(&str, &str)] = &[
+    ("airport", include_str!("../../scenarios/airport.json")),
+    ("interview", include_str!("../../scenarios/interview.json")),
+    (
+        "traffic_stop",
+        include_str!("../../scenarios/traffic_stop.json"),
+    ),
+    ("locked_out", include_str!("../../scenarios/stuck.json")),
+];
+
+/// Names of the built-in scenarios.
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    BUILTIN.iter().map(|(name, _)| *name)
+}
+
+/// Includes nested deeper than this are taken for a cycle.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Load a scenario: `builtin:<name>` or the path of a JSON file. A file's
+/// `include`s are merged in and its `plan` is turned into nodes.
+pub fn load(spec: &str) -> Result<GameTree> {
+    if let Some(name) = spec.strip_prefix(BUILTIN_PREFIX) {
+        let Some((_, json)) = BUILTIN.iter().find(|(n, _)| *n == name) else {
+            let names: Vec<&str> = builtin_names().collect();
+            anyhow::bail!(
+                "no built-in scenario '{name}' (available: {})",
+                names.join(", ")
+            );
+        };
+        let mut tree: GameTree = serde_json::from_str(json)
+            .with_context(|| format!("failed to parse built-in scenario {name}"))?;
+        compile_rules(&tree).with_context(|| format!("built-in scenario {name}"))?;
+        tree.expand_plan()?;
+        return Ok(tree);
+    }
+    let mut tree = load_file(Path::new(spec), 0)?;
+    tree.expand_plan()
+        .with_context(|| format!("failed to load the plan of {spec}"))?;
+    permissions::check_declared(&tree).with_context(|| format!("failed to load {spec}"))?;
+    Ok(tree)
+}
+
+fn load_file(path: &Path, depth: usize) -> Result<GameTree> {
+    let shown = path.display();
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read scenario {shown}"))?;
+    let mut tree: GameTree =
+        serde_json::from_str(&text).with_context(|| format!("failed to parse scenario {shown}"))?;
+    if let Some(id) = RESERVED.iter().find(|id| tree.nodes.contains_key(**id)) {
+        bail!("{shown}: node id {id} is reserved for the judge's decisions");
+    }
+    compile_rules(&tree).with_context(|| shown.to_string())?;
+    for include in std::mem::take(&mut tree.include) {
+        if depth == MAX_INCLUDE_DEPTH {
+            bail!("includes of {shown} are nested too deep (do they include each other?)");
+        }
+        let included = path.parent().unwrap_or(Path::new("")).join(&include.path);
+        let subtree = load_file(&included, depth + 1)?;
+        tree.merge(&include.prefix, subtree)
+            .with_context(|| format!("failed to include {} in {shown}", include.path))?;
+    }
+    Ok(tree)
+}
+
+/// Build the regular expressions of every node's rules once, so a bad
+/// pattern fails the load instead of never matching.
+fn compile_rules(tree: &GameTree) -> Result<()> {
+    for node in tree.nodes.values() {
+        for rule in &node.rules {
+            rule.compile()
+                .with_context(|| format!("node {}", node.id))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::game::node::NodeType;
+
+    #[test]
+    fn test_builtin_scenarios_are_playable() {
+        for name in builtin_names() {
+            let tree = load(&format!("{BUILTIN_PREFIX}{name}")).unwrap();
+            assert!(tree.get(&tree.start_node_id).is_some(), "{name}: no start");
+
+            let mut targets: HashSet<&str> = HashSet::new();
+            for node in tree.nodes.values() {
+                targets.extend(node.node_type.next_nodes().map(|n| n.id.as_str()));
+                if let Some(appeal) = &node.appeal {
+                    targets.insert(appeal.granted.as_str());
+                }
+            }
+            for id in targets {
+                assert!(tree.get(id).is_some(), "{name}: missing node {id}");
+            }
+            assert!(
+                tree.nodes
+                    .values()
+                    .any(|n| matches!(n.node_type, NodeType::Terminal(true))),
+                "{name}: no way to win"
+            );
+            // Only the airport relies on the default setting.
+            assert_eq!(tree.setting.is_none(), name == "airport", "{name}: setting");
+        }
+        assert!(load("builtin:nope")
+            .unwrap_err()
+            .to_string()
+            .contains("interview"));
+    }
+
+    #[test]
+    fn test_includes_are_namespaced() {
+        let dir = std::env::temp_dir().join(format!("elsa-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(
+            dir.join("main.json"),
+            r#"{"start_node_id": "START",
+                "include": [{"path": "shared/screening.json", "prefix": "SCREENING"}],
+                "nodes": {
+                    "START": {"id": "START", "transcript": "Passport?",
+                              "node_type": {"Decision": [{"id": "SCREENING/START", "description": "odd"}]}},
+                    "CLEARED": {"id": "CLEARED", "transcript": "Welcome.", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("shared/screening.json"),
+            r#"{"start_node_id": "START", "nodes": {
+                "START": {"id": "START", "transcript": "Open the bag.",
+                          "node_type": {"Decision": [{"id": "CLEARED", "description": "nothing"},
+                                                     {"id": "FAILED", "description": "contraband"}]}},
+                "FAILED": {"id": "FAILED", "transcript": "Come with me.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+
+        let tree = load(dir.join("main.json").to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(tree.include.is_empty());
+        let mut ids: Vec<&str> = tree.nodes.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        assert_eq!(
+            ids,
+            ["CLEARED", "SCREENING/FAILED", "SCREENING/START", "START"]
+        );
+        let screening = tree.get("SCREENING/START").unwrap();
+        let targets: Vec<&str> = screening
+            .node_type
+            .next_nodes()
+            .map(|n| n.id.as_str())
+            .collect();
+        // Its own nodes are namespaced; the rest lead back out.
+        assert_eq!(targets, ["CLEARED", "SCREENING/FAILED"]);
+    }
+
+    #[test]
+    fn test_bad_rule_pattern_fails_the_load() {
+        let path = std::env::temp_dir().join(format!("elsa-rule-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"start_node_id": "START", "nodes": {
+                "START": {"id": "START", "transcript": "Passport?",
+                          "node_type": {"Decision": [{"id": "END", "description": "refuses"}]},
+                          "rules": [{"pattern": "(unclosed", "next": "END"}]},
+                "END": {"id": "END", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+
+        let err = load(path.to_str().unwrap()).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        let err = format!("{err:#}");
+        assert!(err.contains("node START"), "{err}");
+        assert!(err.contains("bad pattern in the rule to END"), "{err}");
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::game::session::{Judge, JudgeRequest, Reply, Session};
+use crate::game::stream::{self, Event, Streams};
+use crate::game::tree::GameTree;
+use crate::game::worker::{self, JudgeQueue};
+use crate::game::{prime_judge, GameOutcome};
+use crate::llm::{LlmDecision, Usage, LLM};
+use crate::rng::Rng;
+
+/// Open sessions are kept in memory; creating more than this fails until
+/// some are deleted.
+const MAX_SESSIONS: usize = 64;
+/// Threads accepting HTTP requests. Requests waiting for the judge each
+/// hold one, so this also bounds how many players are served at once.
+const HTTP_THREADS: usize = 8;
+/// Judge calls that may wait for the model; more are refused with 503.
+const QUEUE_DEPTH: usize = 16;
+
+pub struct ServeOptions {
+    pub port: u16,
+    /// Seed for session ids.
+    pub seed: u64,
+    pub strict: bool,
+}
+
+/// Serve the scenario over HTTP until the process is stopped. Requests are
+/// handled on a pool of threads; judge calls from all sessions queue for
+/// the model, which runs them one at a time on this thread.
+///
+/// - `POST /sessions` starts a game and returns the guard's first line.
+/// - `GET /sessions/{id}` returns the game state.
+/// - `POST /sessions/{id}/messages` with `{"message": "..."}` judges the
+///   answer and returns the decision and the guard's next line. At a
+///   failure with an `appeal_line`, the message is a plea to the supervisor.
+/// - `DELETE /sessions/{id}` ends a game.
+/// - `GET /sessions/{id}/stream` upgrades to a WebSocket that receives the
+///   session's [`Event`]s live: judge status and tokens, then the guard's
+///   next line piece by piece.
+pub fn serve(model: &mut LLM, tree: &GameTree, options: ServeOptions) -> Result<()> {
+    let server = tiny_http::Server::http(("127.0.0.1", options.port))
+        .map_err(|e| anyhow!("cannot listen on port {}: {e}", options.port))?;
+    eprintln!("Listening on http://127.0.0.1:{}", options.port);
+
+    prime_judge(model, tree);
+    let (queue, jobs) = JudgeQueue::new(QUEUE_DEPTH);
+    let app = App::new(tree.clone(), options.seed, options.strict, queue);
+    thread::scope(|scope| {
+        for _ in 0..HTTP_THREADS {
+            scope.spawn(|| accept(&server, &app));
+        }
+        worker::run(jobs, |job, on_piece| job.run(model, on_piece));
+    });
+    Ok(())
+}
+
+/// The judge queue, adding up how long the calls for one answer waited in
+/// it.
+struct Timed {
+    queue: JudgeQueue,
+    queued: Duration,
+}
+
+impl Judge for Timed {
+    fn decide(
+        &mut self,
+        request: &JudgeRequest,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        let judged = self.queue.decide(request, on_piece);
+        if let Some((_, usage)) = &judged {
+            self.queued += usage.queued;
+        }
+        judged
+    }
+
+    fn classify(&mut self, request: &JudgeRequest) -> Result<LlmDecision> {
+        self.queue.classify(request)
+    }
+}
+
+/// Handle requests until the server shuts down.
+fn accept(server: &tiny_http::Server, app: &App) {
+    for mut request in server.incoming_requests() {
+        if let Some(Route::Stream(id)) = route(request.method(), request.url()) {
+            let id = id.to_string();
+            app.subscribe(&id, request);
+            continue;
+        }
+        let mut body = String::new();
+        let (status, value) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => app.handle(request.method(), request.url(), &body),
+            Err(e) => error(400, format!("cannot read request body: {e}")),
+        };
+        info!("{} {} -> {status}", request.method(), request.url());
+        respond(request, status, &value);
+    }
+}
+
+fn respond(request: Request, status: u16, value: &Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to send response: {e}");
+    }
+}
+
+fn error(status: u16, message: impl Into<String>) -> (u16, Value) {
+    (status, json!({ "error": message.into() }))
+}
+
+// ---------------------------------------------------------------------------
+// Routes
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, PartialEq)]
+enum Route<'a> {
+    Create,
+    Get(&'a str),
+    Message(&'a str),
+    Delete(&'a str),
+    Stream(&'a str),
+}
+
+fn route<'a>(method: &Method, url: &'a str) -> Option<Route<'a>> {
+    let path = url.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (Method::Post, ["sessions"]) => Some(Route::Create),
+        (Method::Get, ["sessions", id]) => Some(Route::Get(id)),
+        (Method::Delete, ["sessions", id]) => Some(Route::Delete(id)),
+        (Method::Post, ["sessions", id, "messages"]) => Some(Route::Message(id)),
+        (Method::Get, ["sessions", id, "stream"]) => Some(Route::Stream(id)),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+struct MessageBody {
+    message: String,
+}
+
+/// Game state as returned by every endpoint.
+#[derive(Serialize)]
+struct SessionView<'a> {
+    session_id: &'a str,
+    node_id: &'a str,
+    guard_line: &'a str,
+    /// Who says `guard_line`, in scenarios with several speakers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    npc_name: Option<String>,
+    steps_completed: usize,
+    total_steps: usize,
+    finished: bool,
+    /// Set once the game is finished.
+    success: Option<bool>,
+    /// A finished game can still be appealed: the next message is the
+    /// player's plea to the supervisor, whose opening line this is.
+    appeal_line: Option<String>,
+}
+
+fn view<'a>(id: &'a str, session: &'a Session) -> SessionView<'a> {
+    let success = match session.outcome() {
+        Some(GameOutcome::Finished { success, .. }) => Some(success),
+        _ => None,
+    };
+    SessionView {
+        session_id: id,
+        node_id: &session.node().id,
+        guard_line: &session.guard_line().display,
+        npc_name: session.npc_name(),
+        steps_completed: session.steps_completed(),
+        total_steps: session.total_steps(),
+        finished: success.is_some(),
+        success,
+        appeal_line: session.appeal_line().map(|line| line.display),
+    }
+}
+
+/// Shared by the HTTP threads. Each session has its own lock, so a
+/// player's messages are judged in order while other sessions proceed.
+struct App {
+    tree: GameTree,
+    sessions: Mutex<HashMap<String, Arc<Mutex<Session>>>>,
+    streams: Mutex<Streams>,
+    ids: Mutex<Rng>,
+    strict: bool,
+    queue: JudgeQueue,
+}
+
+impl App {
+    fn new(tree: GameTree, seed: u64, strict: bool, queue: JudgeQueue) -> Self {
+        Self {
+            tree,
+            sessions: Mutex::new(HashMap::new()),
+            streams: Mutex::new(Streams::default()),
+            ids: Mutex::new(Rng::new(seed)),
+            strict,
+            queue,
+        }
+    }
+
+    fn handle(&self, method: &Method, url: &str, body: &str) -> (u16, Value) {
+        match route(method, url) {
+            Some(Route::Create) => self.create(),
+            Some(Route::Get(id)) => self.get(id),
+            Some(Route::Delete(id)) => self.delete(id),
+            Some(Route::Message(id)) => self.message(id, body),
+            Some(Route::Stream(_)) => error(400, "expected a WebSocket upgrade request"),
+            None => error(404, format!("no route for {method} {url}")),
+        }
+    }
+
+    fn session(&self, id: &str) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    fn emit(&self, id: &str, event: &Event) {
+        self.streams.lock().unwrap().emit(id, event);
+    }
+
+    fn create(&self) -> (u16, Value) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= MAX_SESSIONS {
+            return error(503, "too many open sessions");
+        }
+        let mut ids = self.ids.lock().unwrap();
+        let id = format!("{:016x}", ids.next_u64());
+        let session = Session::new(self.tree.clone(), None, self.strict, ids.next_u64());
+        drop(ids);
+        let value = json!(view(&id, &session));
+        info!("Session {id} created");
+        sessions.insert(id, Arc::new(Mutex::new(session)));
+        (201, value)
+    }
+
+    fn get(&self, id: &str) -> (u16, Value) {
+        match self.session(id) {
+            Some(session) => (200, json!(view(id, &session.lock().unwrap()))),
+            None => error(404, format!("no session '{id}'")),
+        }
+    }
+
+    fn delete(&self, id: &str) -> (u16, Value) {
+        self.streams.lock().unwrap().close(id);
+        match self.sessions.lock().unwrap().remove(id) {
+            Some(_) => (200, json!({ "deleted": id })),
+            None => error(404, format!("no session '{id}'")),
+        }
+    }
+
+    fn message(&self, id: &str, body: &str) -> (u16, Value) {
+        let Some(session) = self.session(id) else {
+            return error(404, format!("no session '{id}'"));
+        };
+        let body: MessageBody = match serde_json::from_str(body) {
+            Ok(body) => body,
+            Err(e) => return error(400, format!("expected {{\"message\": \"...\"}}: {e}")),
+        };
+        let mut session = session.lock().unwrap();
+        if session.outcome().is_some() && session.pending_appeal().is_none() {
+            return error(409, "the game is over");
+        }
+        if body.message.trim().is_empty() {
+            return error(400, "empty message");
+        }
+
+        let request = match session.ask(&body.message) {
+            Ok(request) => request,
+            Err(e) => return error(409, format!("{e:#}")),
+        };
+        self.emit(id, &Event::Thinking);
+        let mut queue = Timed {
+            queue: self.queue.clone(),
+            queued: Duration::ZERO,
+        };
+        let reply = session.settle(request, &mut queue, &mut |text| {
+            self.emit(id, &Event::JudgeToken { text })
+        });
+        let judged = match reply {
+            Ok(None) => {
+                self.emit(id, &Event::Retry);
+                return error(503, "the judge is busy; try again shortly");
+            }
+            Ok(Some(Reply::Retry)) => {
+                self.emit(id, &Event::Retry);
+                Value::Null
+            }
+            Ok(Some(Reply::Judged(record) | Reply::Appealed { record, .. })) => {
+                self.emit(
+                    id,
+                    &Event::Decided {
+                        decision: &record.decision,
+                        reason: &record.reason,
+                    },
+                );
+                json!({
+                    "node_id": record.node_id,
+                    "decision": record.decision,
+                    "reason": record.reason,
+                    "latency_ms": record.latency_ms,
+                    "queue_ms": queue.queued.as_millis(),
+                })
+            }
+            Ok(Some(Reply::Reask(_))) => unreachable!("settled above"),
+            Err(e) => {
+                // Strict mode: the session cannot continue reliably.
+                self.sessions.lock().unwrap().remove(id);
+                self.streams.lock().unwrap().close(id);
+                return error(500, format!("{e:#} (session ended)"));
+            }
+        };
+
+        let state = json!(view(id, &session));
+        let mut streams = self.streams.lock().unwrap();
+        streams.emit_guard_line(id, &session.node().id, &session.guard_line().display);
+        streams.emit(
+            id,
+            &Event::State {
+                session: state.clone(),
+            },
+        );
+        (200, json!({ "judged": judged, "session": state }))
+    }
+
+    /// Upgrade `request` to a WebSocket streaming the session's events,
+    /// starting with its current guard line and state.
+    fn subscribe(&self, id: &str, request: Request) {
+        let Some(session) = self.session(id) else {
+            let (status, value) = error(404, format!("no session '{id}'"));
+            return respond(request, status, &value);
+        };
+        let Some(key) = stream::websocket_key(&request) else {
+            let (status, value) = error(400, "expected a WebSocket upgrade request");
+            return respond(request, status, &value);
+        };
+
+        let session = session.lock().unwrap();
+        let node_id = &session.node().id;
+        let mut greeting: Vec<Event> = stream::guard_deltas(&session.guard_line().display)
+            .map(|text| Event::GuardDelta { node_id, text })
+            .collect();
+        greeting.push(Event::State {
+            session: json!(view(id, &session)),
+        });
+        self.streams
+            .lock()
+            .unwrap()
+            .subscribe(id, request, &key, &greeting);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> GameTree {
+        serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(route(&Method::Post, "/sessions"), Some(Route::Create));
+        assert_eq!(
+            route(&Method::Get, "/sessions/ab12"),
+            Some(Route::Get("ab12"))
+        );
+        assert_eq!(
+            route(&Method::Post, "/sessions/ab12/messages?x=1"),
+            Some(Route::Message("ab12"))
+        );
+        assert_eq!(
+            route(&Method::Delete, "/sessions/ab12/"),
+            Some(Route::Delete("ab12"))
+        );
+        assert_eq!(
+            route(&Method::Get, "/sessions/ab12/stream"),
+            Some(Route::Stream("ab12"))
+        );
+        assert_eq!(route(&Method::Get, "/sessions"), None);
+        assert_eq!(route(&Method::Put, "/sessions/ab12"), None);
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let (queue, _jobs) = JudgeQueue::new(1);
+        let app = App::new(tree(), 7, false, queue);
+        let (status, created) = app.create();
+        assert_eq!(status, 201);
+        assert_eq!(created["guard_line"], "Passport?");
+        assert_eq!(created["finished"], false);
+        assert_eq!(created["total_steps"], 1);
+        assert_eq!(created["appeal_line"], Value::Null);
+
+        let id = created["session_id"].as_str().unwrap().to_string();
+        assert_eq!(app.get(&id), (200, created));
+        assert_eq!(app.delete(&id).0, 200);
+        assert_eq!(app.get(&id).0, 404);
+        assert_eq!(app.delete(&id).0, 404);
+    }
+
+    #[test]
+    fn test_busy_judge_is_refused() {
+        // Nothing takes jobs off a zero-depth queue, so the judge is busy.
+        let (queue, _jobs) = JudgeQueue::new(0);
+        let app = App::new(tree(), 7, false, queue);
+        let (_, created) = app.create();
+        let id = created["session_id"].as_str().unwrap();
+
+        let (status, _) = app.message(id, r#"{"message": "Here"}"#);
+        assert_eq!(status, 503);
+        assert_eq!(app.get(id), (200, created.clone()));
+        assert_eq!(app.message("nope", r#"{"message": "Here"}"#).0, 404);
+        assert_eq!(app.message(id, r#"{"text": "Here"}"#).0, 400);
+    }
+}
@@ -0,0 +1,2520 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+
+use crate::game::classifier;
+use crate::game::difficulty::Difficulty;
+use crate::game::instruction::{JudgeInstruction, Slot};
+use crate::game::language;
+use crate::game::locale::{fill, Messages};
+use crate::game::node::{
+    self, Appeal, GameNode, NodeType, Rule, CLARIFY, CONTINUE, LOOKUP, MAX_RANDOM_HOPS, UNDECIDABLE,
+};
+use crate::game::party::Party;
+use crate::game::plan::COVERS;
+use crate::game::save::{SaveFile, SavedState};
+use crate::game::shift::{self, Shift, SHIFT_VAR};
+use crate::game::suspicion::{self, LieDetector, DELTA, MAX_DELTA};
+use crate::game::template::{self, Rendered};
+use crate::game::transcript::TurnRecord;
+use crate::game::tree::{GameTree, UndecidableFallback};
+use crate::game::vars;
+use crate::game::{
+    build_appeal_messages, build_feedback_messages, build_hint_messages, build_judge_messages,
+    build_narration_messages, routes, script, Anomaly, GameOutcome, GameState, APPEAL_DENIED,
+    APPEAL_GRANTED, CONTRADICTS,
+};
+use crate::llm::{ChatMessage, LlmBackend, LlmDecision, PowerSave, Usage};
+
+/// One game round, advanced one player answer at a time. It does no IO:
+/// the terminal loop and the HTTP server both feed it answers and present
+/// what it returns.
+pub struct Session {
+    state: GameState,
+    total_steps: usize,
+    /// Guard line of the current node.
+    line: Rendered,
+    strict: bool,
+    latency: LatencySummary,
+    /// The supervisor ruled on an appeal this round (only one is allowed).
+    appealed: bool,
+    appeal_granted: bool,
+    /// Node and normalized text of the last judged answer, and how many
+    /// times in a row it was given there.
+    last_answer: Option<(String, String)>,
+    repeats: usize,
+    /// Question to go back to after a strike's warning line.
+    retry_at: Option<String>,
+    /// Follow-up questions asked in a row at the current node.
+    clarified: usize,
+    /// Answers at the current node on which the judge kept talking.
+    chatted: usize,
+    /// Judge calls that failed in a row.
+    judge_failures: usize,
+    /// State on entering the last checkpoint node.
+    checkpoint: Option<SavedState>,
+    /// Couch co-op: the players taking turns answering.
+    party: Option<Party>,
+    /// The guard's shift, from the clock.
+    shift: Option<Shift>,
+    /// Lie detector reading of the answer being judged.
+    stress: Option<u8>,
+    /// Hints taken this round.
+    hints: usize,
+    difficulty: Difficulty,
+    /// Adapt `difficulty` to the streak.
+    adaptive: bool,
+    /// `--lang` code answers are expected in, when the judge is told about
+    /// answers in other languages.
+    language: Option<String>,
+    /// Fast play (`--fast`): no small talk or follow-ups, and a lean,
+    /// routed judge.
+    fast: bool,
+    /// The guard line was entered and not yet offered for narration.
+    unnarrated: bool,
+    /// Narrated lines by node and persona description, with the line as
+    /// written, so a line is narrated once per session.
+    narrated: HashMap<(String, String), (String, String)>,
+}
+
+/// Judge timing over a round, shown at game over.
+#[derive(Debug, Clone, Default)]
+pub struct LatencySummary {
+    pub turns: u32,
+    pub total: Duration,
+    /// Part of `total` spent in power-save pauses.
+    pub throttled: Duration,
+}
+
+impl LatencySummary {
+    fn record(&mut self, latency: Duration, usage: Usage) {
+        self.turns += 1;
+        self.total += latency;
+        self.throttled += usage.throttled;
+    }
+
+    /// Lines for the game over screen, in `m`'s words; empty if nothing
+    /// was judged.
+    pub fn lines(&self, power_save: Option<PowerSave>, m: &Messages) -> Vec<String> {
+        if self.turns == 0 {
+            return Vec::new();
+        }
+        let mut lines = vec![fill(
+            m.judge_latency,
+            &[
+                (
+                    "seconds",
+                    &format!("{:.1}", (self.total / self.turns).as_secs_f32()),
+                ),
+                ("turns", &self.turns.to_string()),
+            ],
+        )];
+        if let Some(power_save) = power_save {
+            lines.push(fill(
+                m.power_save_latency,
+                &[
+                    ("threads", &power_save.n_threads.to_string()),
+                    (
+                        "seconds",
+                        &format!("{:.1}", (self.throttled / self.turns).as_secs_f32()),
+                    ),
+                ],
+            ));
+        }
+        lines
+    }
+}
+
+/// A judge call prepared by [`Session::ask`], to be run by the caller (so
+/// it can show a waiting indicator) and handed back to [`Session::apply`].
+#[derive(Debug, Clone)]
+pub struct JudgeRequest {
+    pub messages: Vec<ChatMessage>,
+    valid_choices: Vec<String>,
+    input: String,
+    started: Instant,
+    /// An appeal, to be judged by the supervisor ([`crate::llm::LLM::supervise`]).
+    appeal: bool,
+    /// Identical answers in a row at this node, this one included.
+    repeats: usize,
+    /// Model alias the node asks to be judged by.
+    model: Option<String>,
+    /// Times the judge was asked again after [`UNDECIDABLE`].
+    reasked: usize,
+    /// The judge picks by number ([`crate::llm::LLM::route`]).
+    routed: bool,
+    /// Id and description of each valid choice that leads to another node.
+    criteria: Vec<(String, String)>,
+    /// The node's rule that decides the answer.
+    ruled: Option<Rule>,
+}
+
+impl JudgeRequest {
+    pub fn valid_choices(&self) -> Vec<&str> {
+        self.valid_choices.iter().map(String::as_str).collect()
+    }
+
+    /// Whether the supervisor should answer rather than the judge.
+    pub fn is_appeal(&self) -> bool {
+        self.appeal
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// The player's answer.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// What the valid choices other than the reserved ones stand for, for
+    /// judging without the judge ([`crate::game::classifier`]).
+    pub fn criteria(&self) -> &[(String, String)] {
+        &self.criteria
+    }
+
+    pub fn routed(&self) -> bool {
+        self.routed
+    }
+
+    /// The decision when one of the node's rules settles the answer, so
+    /// the judge need not be asked.
+    pub fn ruled(&self) -> Option<LlmDecision> {
+        self.ruled.as_ref().map(Rule::decision)
+    }
+
+    /// Put the request to `backend`: to the supervisor for an appeal, as a
+    /// route in fast mode, to the judge otherwise. Backends that stream
+    /// hand the judge's output to `on_piece`.
+    pub fn run(
+        &self,
+        backend: &mut (impl LlmBackend + ?Sized),
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<LlmDecision> {
+        let choices = self.valid_choices();
+        if self.appeal {
+            backend.supervise(&self.messages, &choices)
+        } else if self.routed {
+            backend.route(self.model(), &self.messages, &choices)
+        } else {
+            backend.judge_stream(self.model(), &self.messages, &choices, on_piece)
+        }
+    }
+}
+
+/// Where [`Session::settle`] has answers judged: a backend on the calling
+/// thread, or the [`crate::game::worker::JudgeQueue`] that the sessions of
+/// a server or bot share.
+pub trait Judge {
+    /// Run `request` ([`JudgeRequest::run`]), with the usage of the call.
+    /// `None` when there is no room for it at the moment.
+    fn decide(
+        &mut self,
+        request: &JudgeRequest,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)>;
+
+    /// Classify the answer by embedding instead ([`classifier::classify`]).
+    fn classify(&mut self, request: &JudgeRequest) -> Result<LlmDecision>;
+}
+
+impl<B: LlmBackend + ?Sized> Judge for B {
+    fn decide(
+        &mut self,
+        request: &JudgeRequest,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        let judged = request.run(self, on_piece);
+        Some((judged, self.last_usage()))
+    }
+
+    fn classify(&mut self, request: &JudgeRequest) -> Result<LlmDecision> {
+        classifier::classify(self, request.input(), request.criteria())
+    }
+}
+
+/// What became of a player answer.
+#[derive(Debug)]
+pub enum Reply {
+    /// The judge call failed; the guard asks the same question again.
+    Retry,
+    /// The judge decided and the session moved to the next node.
+    Judged(TurnRecord),
+    /// The supervisor ruled on an appeal. If granted, the session moved to
+    /// the appeal's continuation node.
+    Appealed { granted: bool, record: TurnRecord },
+    /// The judge could not decide; run this request, which asks it again,
+    /// and apply its result instead.
+    Reask(JudgeRequest),
+}
+
+impl Session {
+    /// `seed` picks the transcript variants of the nodes entered.
+    pub fn new(tree: GameTree, resume: Option<SavedState>, strict: bool, seed: u64) -> Self {
+        let state = match resume {
+            Some(saved) => {
+                info!("Resuming at node {}", saved.current_node_id);
+                GameState::resumed(tree, saved, seed)
+            }
+            None => GameState::new(tree, seed),
+        };
+        info!("Game started. Initial node: {}", state.current_node_id);
+        let mut session = Self {
+            total_steps: state.tree.total_steps(),
+            state,
+            line: Rendered {
+                display: String::new(),
+                neutral: String::new(),
+            },
+            strict,
+            latency: LatencySummary::default(),
+            appealed: false,
+            appeal_granted: false,
+            last_answer: None,
+            repeats: 0,
+            retry_at: None,
+            clarified: 0,
+            chatted: 0,
+            judge_failures: 0,
+            checkpoint: None,
+            party: None,
+            shift: None,
+            stress: None,
+            hints: 0,
+            difficulty: Difficulty::default(),
+            adaptive: false,
+            language: None,
+            fast: false,
+            unnarrated: false,
+            narrated: HashMap::new(),
+        };
+        session.enter();
+        session
+    }
+
+    /// Play fast (`--fast`).
+    pub fn set_fast(&mut self) {
+        self.fast = true;
+    }
+
+    /// Tell the judge when an answer is in another language than
+    /// `expected`, a `--lang` code.
+    pub fn set_language_hints(&mut self, expected: &str) {
+        self.language = Some(expected.to_string());
+    }
+
+    /// Judge at `difficulty`, with the nodes' criteria for it.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+    }
+
+    /// Adapt the difficulty to the player's streak, starting from `streak`
+    /// or else the one in the resumed state.
+    pub fn set_adaptive(&mut self, streak: Option<i32>) {
+        self.adaptive = true;
+        if let Some(streak) = streak {
+            self.state.streak = streak;
+        }
+    }
+
+    /// Passes (positive) or failures (negative) in a row.
+    pub fn streak(&self) -> i32 {
+        self.state.streak
+    }
+
+    /// The difficulty the judge is at for this answer.
+    fn difficulty(&self) -> Difficulty {
+        if self.adaptive {
+            self.difficulty.adapted(self.state.streak)
+        } else {
+            self.difficulty
+        }
+    }
+
+    /// Let several players take turns answering (see [`Party`]).
+    pub fn set_party(&mut self, party: Option<Party>) {
+        self.party = party;
+    }
+
+    /// Who says the guard line, in scenarios with several speakers (see
+    /// [`GameTree::speaker_name`]).
+    pub fn npc_name(&self) -> Option<String> {
+        self.state.tree.speaker_name(self.node())
+    }
+
+    /// The player whose turn it is to answer, in couch co-op.
+    pub fn speaker(&self) -> Option<&str> {
+        self.party.as_ref().map(Party::speaker)
+    }
+
+    /// Hand the turn to the next player, in couch co-op.
+    pub fn pass_turn(&mut self) {
+        if let Some(party) = self.party.as_mut() {
+            party.pass();
+        }
+    }
+
+    /// Put the guard on the persona's shift for `minute` past midnight, if
+    /// one is on: its mood goes to the judge and its name to the `shift`
+    /// variable.
+    pub fn clock_in(&mut self, minute: u16) {
+        let persona = self.state.tree.persona.as_ref();
+        let Some(on) = persona.and_then(|p| shift::current(&p.shifts, minute)) else {
+            return;
+        };
+        info!("On the {} shift", on.name);
+        self.state
+            .vars
+            .insert(SHIFT_VAR.to_string(), on.name.clone().into());
+        self.shift = Some(on.clone());
+    }
+
+    /// The scenario's lie detector, if its suspicion meter has one.
+    pub fn lie_detector(&self) -> Option<&LieDetector> {
+        self.state.tree.suspicion.as_ref()?.lie_detector.as_ref()
+    }
+
+    /// Note the lie detector's reading of the answer about to be judged.
+    /// It biases the suspicion meter once the answer is judged.
+    pub fn read_stress(&mut self, reading: u8) {
+        self.stress = Some(reading);
+    }
+
+    pub fn node(&self) -> &GameNode {
+        self.state.current_node()
+    }
+
+    /// The guard's line for the current node.
+    pub fn guard_line(&self) -> &Rendered {
+        &self.line
+    }
+
+    pub fn steps_completed(&self) -> usize {
+        self.state.steps_completed
+    }
+
+    pub fn total_steps(&self) -> usize {
+        self.total_steps
+    }
+
+    pub fn latency(&self) -> &LatencySummary {
+        &self.latency
+    }
+
+    /// Set once a terminal node is reached.
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        let node = self.node();
+        match node.node_type {
+            NodeType::Terminal(success) => Some(GameOutcome::Finished {
+                success,
+                steps_completed: self.state.steps_completed,
+                total_steps: self.total_steps,
+                terminal_node_id: node.id.clone(),
+                ending: self.state.tree.display_name(&node.id),
+                appealed: self.appeal_granted,
+                checkpoint: self
+                    .checkpoint
+                    .as_ref()
+                    .filter(|_| !success)
+                    .map(|saved| saved.current_node_id.clone()),
+                suspicion: self
+                    .state
+                    .tree
+                    .suspicion
+                    .as_ref()
+                    .map(|_| self.state.suspicion),
+                score: self.state.tree.has_points().then_some(self.state.score),
+                grade: node::grade(&node.rank, self.state.score).map(String::from),
+                headline: {
+                    let setting = self.state.tree.setting();
+                    if success {
+                        setting.cleared
+                    } else {
+                        setting.denied
+                    }
+                },
+                hints: self.hints,
+                stats: Vec::new(),
+            }),
+            NodeType::Decision(_) | NodeType::Random(_) | NodeType::Interlude(_) => None,
+        }
+    }
+
+    /// The appeal the player may still make from the current node: set at
+    /// failure terminals that have one, until an appeal has been ruled on.
+    pub fn pending_appeal(&self) -> Option<&Appeal> {
+        match &self.node().node_type {
+            NodeType::Terminal(false) if !self.appealed => self.node().appeal.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The supervisor's opening line for the pending appeal.
+    pub fn appeal_line(&self) -> Option<Rendered> {
+        let appeal = self.pending_appeal()?;
+        Some(template::render(
+            &appeal.transcript,
+            self.state.tree.persona.as_ref(),
+        ))
+    }
+
+    /// What the current step is looking for: the descriptions of the ways
+    /// on that don't end the game in failure. Empty at a terminal.
+    pub fn hint(&self) -> Vec<&str> {
+        let node = self.node();
+        let NodeType::Decision(next) = &node.node_type else {
+            return Vec::new();
+        };
+        let overrides = node.difficulty.get(&self.difficulty());
+        next.iter()
+            .filter(|n| n.is_open(&self.state.condition_vars()))
+            .filter(|n| {
+                !matches!(
+                    self.state.tree.get(&n.id).map(|node| &node.node_type),
+                    Some(NodeType::Terminal(false))
+                )
+            })
+            .map(|n| {
+                overrides
+                    .and_then(|criteria| criteria.edges.get(&n.id))
+                    .unwrap_or(&n.description)
+                    .as_str()
+            })
+            .collect()
+    }
+
+    /// Messages asking the model for a nudge towards what the current step
+    /// is looking for, or `None` if there is nothing to hint at.
+    pub fn hint_messages(&self) -> Option<Vec<ChatMessage>> {
+        let hints = self.hint();
+        if hints.is_empty() {
+            return None;
+        }
+        Some(build_hint_messages(
+            &self.line.neutral,
+            &hints,
+            &self.state.tree.setting(),
+        ))
+    }
+
+    /// Messages asking the model to say the guard line just entered in the
+    /// speaker's persona's voice, or `None` if there is no persona to speak
+    /// in, the line has second-language phrases, or it was offered
+    /// already.
+    pub fn narration_messages(&self) -> Option<Vec<ChatMessage>> {
+        let persona = self.persona()?;
+        if !self.unnarrated || self.line.display != self.line.neutral {
+            return None;
+        }
+        let npc = self
+            .npc_name()
+            .unwrap_or_else(|| self.state.tree.setting().npc);
+        Some(build_narration_messages(&self.line.neutral, &persona, &npc))
+    }
+
+    /// Description of the current speaker's persona, if any.
+    fn persona(&self) -> Option<String> {
+        self.state.tree.persona_at(self.node())?.describe()
+    }
+
+    /// Say the guard line as the model rewrote it from
+    /// [`Session::narration_messages`]; `None` keeps it as written.
+    pub fn narrate(&mut self, line: Option<&str>) {
+        self.unnarrated = false;
+        let Some(line) = line.map(str::trim).filter(|line| !line.is_empty()) else {
+            return;
+        };
+        info!("Narrated: \"{}\" -> \"{line}\"", self.line.neutral);
+        if let Some(persona) = self.persona() {
+            self.narrated.insert(
+                (self.state.current_node_id.clone(), persona),
+                (self.line.neutral.clone(), line.to_string()),
+            );
+        }
+        self.say(line);
+    }
+
+    /// Replace the guard line just entered with `line`.
+    fn say(&mut self, line: &str) {
+        self.line = Rendered {
+            display: line.to_string(),
+            neutral: line.to_string(),
+        };
+        let said = self.said(line);
+        if let Some(last) = self.state.conversation.last_mut() {
+            *last = said;
+        }
+    }
+
+    /// The guard's `line` in the conversation, led by who says it in
+    /// scenarios with several speakers.
+    fn said(&self, line: &str) -> ChatMessage {
+        match self.npc_name() {
+            Some(name) => ChatMessage::assistant(format!("{name}: {line}")),
+            None => ChatMessage::assistant(line),
+        }
+    }
+
+    /// The coach's prompt on a round that ended in failure after a judged
+    /// answer: the conversation and the criteria of the question failed.
+    pub fn feedback_messages(&self) -> Option<Vec<ChatMessage>> {
+        if !matches!(self.node().node_type, NodeType::Terminal(false)) {
+            return None;
+        }
+        let (failed_at, _) = self.last_answer.as_ref()?;
+        let failed_at = self.state.tree.get(failed_at)?;
+        Some(build_feedback_messages(
+            &self.state.conversation,
+            failed_at,
+            &self.state.tree,
+        ))
+    }
+
+    /// The conversation so far for `/history`, one "Speaker: line" each.
+    pub fn history(&self) -> Vec<String> {
+        script(&self.state.conversation, &self.state.tree)
+    }
+
+    /// Count a hint the player was given and take the scenario's penalty
+    /// off the score. Returns the penalty.
+    pub fn charge_hint(&mut self) -> i32 {
+        let penalty = self.state.tree.hint_penalty;
+        self.hints += 1;
+        self.state.score -= penalty;
+        info!("Hint {} taken (-{penalty} points)", self.hints);
+        penalty
+    }
+
+    /// State to retry from after a failure: the game as it was when the
+    /// last checkpoint node was entered.
+    pub fn checkpoint(&self) -> Option<&SavedState> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Save file for the current point of the game.
+    pub fn save_file(&self, scenario_path: Option<String>) -> SaveFile {
+        let mut save = SaveFile::new(&self.state.tree, &self.state.snapshot());
+        save.scenario_path = scenario_path;
+        save
+    }
+
+    /// Whether the judge may talk in character on the next answer, with a
+    /// follow-up question or a reply that keeps the conversation going.
+    pub fn speaks_next(&self) -> bool {
+        if self.pending_appeal().is_some() {
+            return false;
+        }
+        let mut node = self.node().resolve(&self.state.condition_vars());
+        let decides = node.offer_continue(self.chatted + 1);
+        let follows_up = node
+            .clarify
+            .as_ref()
+            .is_some_and(|c| decides && self.clarified < c.limit && c.line.is_none());
+        let keeps_talking = matches!(
+            &node.node_type,
+            NodeType::Decision(next) if next.iter().any(|n| n.id == CONTINUE)
+        );
+        follows_up || keeps_talking
+    }
+
+    /// Record the player's answer and build the judge call for it.
+    pub fn ask(&mut self, input: &str) -> Result<JudgeRequest> {
+        self.ask_with(input, JudgeInstruction::new())
+    }
+
+    /// [`Session::ask`] with extra judge instruction lines, e.g. corrective
+    /// examples for this answer.
+    pub fn ask_with(
+        &mut self,
+        input: &str,
+        mut instruction: JudgeInstruction,
+    ) -> Result<JudgeRequest> {
+        let input = input.trim();
+        if input.is_empty() {
+            bail!("empty answer");
+        }
+        if self.pending_appeal().is_some() {
+            return Ok(self.ask_appeal(input));
+        }
+        let mut node = self.node().resolve(&self.state.condition_vars());
+        node.at_difficulty(self.difficulty());
+        // Fast play skips the small talk and follow-ups.
+        let decides = self.fast || node.offer_continue(self.chatted + 1);
+        if let Some(clarify) = node
+            .clarify
+            .as_ref()
+            .filter(|c| !self.fast && decides && self.clarified < c.limit)
+        {
+            if clarify.line.is_none() {
+                instruction.reply_key(
+                    FOLLOW_UP,
+                    format!(
+                        "if you pick {CLARIFY}, the question you ask to clear it up, else null"
+                    ),
+                );
+            }
+            node.offer_clarify();
+        }
+        if decides && self.state.tree.undecidable.is_some() {
+            node.offer_undecidable();
+        }
+        let regulations = &self.state.tree.regulations;
+        if decides && !self.fast && !regulations.is_empty() {
+            node.offer_lookup();
+            let sections: Vec<&str> = regulations.keys().map(String::as_str).collect();
+            instruction.reply_key(
+                SECTION,
+                format!(
+                    "if you pick {LOOKUP}, the regulation section to read, one of: {}; else null",
+                    sections.join(", ")
+                ),
+            );
+        }
+        let NodeType::Decision(next_nodes) = &node.node_type else {
+            bail!("the game is over (ended at {})", node.id);
+        };
+        if next_nodes.iter().any(|next| next.id == CONTINUE) {
+            instruction.reply_key(
+                REPLY,
+                format!("if you pick {CONTINUE}, what you say back, in character, else null"),
+            );
+        }
+        info!("User input: \"{input}\"");
+        let said = match self.speaker() {
+            Some(speaker) => format!("{speaker}: {input}"),
+            None => input.to_string(),
+        };
+        self.state.conversation.push(ChatMessage::user(said));
+
+        // Valid choices for the grammar-constrained judge
+        let mut valid_choices: Vec<String> = next_nodes.iter().map(|n| n.id.clone()).collect();
+        let setting = self.state.tree.setting();
+        if let Some(on) = &self.shift {
+            instruction.add(
+                Slot::State,
+                "shift",
+                usize::MAX,
+                std::iter::once(on.mood.clone()).chain(on.strictness.instruction(&setting.player)),
+            );
+        }
+        if let Some(name) = self.state.tree.speaker(self.node()).map(|s| &s.name) {
+            instruction.add(
+                Slot::Scene,
+                "speaker",
+                usize::MAX,
+                [format!(
+                    "The one asking now is {name}; anything you say, you say as them."
+                )],
+            );
+        }
+        if let Some(persona) = self.persona() {
+            instruction.add(Slot::Scene, "persona", usize::MAX, [persona]);
+        }
+        if let Some(note) = self.difficulty().instruction(&setting.player) {
+            instruction.add(Slot::State, "difficulty", usize::MAX, [note]);
+        }
+        let translate = self.language.as_deref();
+        if let Some(note) = translate.and_then(|lang| language::hint(input, lang, &setting.player))
+        {
+            instruction.add(Slot::State, "language", usize::MAX, [note]);
+        }
+        if let Some(party) = &self.party {
+            instruction.add(
+                Slot::State,
+                "party",
+                usize::MAX,
+                [party.describe(&setting.player)],
+            );
+        }
+        let repeats = self.repeats_with(&node.id, input);
+        if let Some(penalty) = node.repeats.as_ref().filter(|r| repeats >= r.limit) {
+            info!("Answer repeated {repeats} times at {}", node.id);
+            instruction.add(
+                Slot::State,
+                "repeats",
+                usize::MAX,
+                [format!(
+                    "The {} has given this same answer {repeats} times in a row.",
+                    setting.player
+                )],
+            );
+            if let Some(next) = &penalty.next {
+                valid_choices = vec![next.clone()];
+            }
+        }
+        if node.contradiction.is_some() && !self.state.vars.is_empty() {
+            let said = self
+                .state
+                .vars
+                .iter()
+                .map(|(name, value)| format!("- {name}: {}", vars::display(value)));
+            instruction.add(
+                Slot::Facts,
+                "facts",
+                usize::MAX,
+                std::iter::once(format!(
+                    "Facts from the {}'s earlier answers:",
+                    setting.player
+                ))
+                .chain(said),
+            );
+        }
+        if let Some(meter) = &self.state.tree.suspicion {
+            let level = self.state.suspicion;
+            let limit = meter
+                .next_above(level)
+                .map(|threshold| format!(" of {}", threshold.at))
+                .unwrap_or_default();
+            instruction.add(
+                Slot::State,
+                "suspicion",
+                usize::MAX,
+                [format!(
+                    "Suspicion of the {} so far: {level}{limit}.",
+                    setting.player
+                )],
+            );
+            instruction.reply_key(
+                DELTA,
+                format!(
+                    "-{MAX_DELTA} to {MAX_DELTA}, how much more suspicious the last response makes you"
+                ),
+            );
+        }
+
+        let others = self
+            .state
+            .tree
+            .plan
+            .as_ref()
+            .filter(|plan| plan.topic(&node.id).is_some())
+            .and_then(|plan| plan.others(&node.id, &self.state.vars));
+        if let Some(others) = others {
+            instruction.reply_key(
+                COVERS,
+                format!("which of these other topics the last response already settles too, as a list of ids, else []: {others}"),
+            );
+        }
+
+        if self.fast {
+            instruction.fast();
+        }
+        let routed = routes(&node, &instruction);
+        let criteria = next_nodes
+            .iter()
+            .filter(|next| {
+                valid_choices.contains(&next.id) && self.state.tree.get(&next.id).is_some()
+            })
+            .map(|next| (next.id.clone(), next.description.clone()))
+            .collect();
+        let ruled = node.rule_for(input, &valid_choices).cloned();
+        if let Some(rule) = &ruled {
+            info!("Answer decided by a rule: {}", rule.next);
+        }
+        let messages =
+            build_judge_messages(&self.state.conversation, &node, &setting, instruction)?;
+        debug!(
+            "Judge messages ({} total):\n{}",
+            messages.len(),
+            messages
+                .iter()
+                .enumerate()
+                .map(|(i, m)| format!("  msg[{i}] {m}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+
+        Ok(JudgeRequest {
+            messages,
+            valid_choices,
+            input: input.to_string(),
+            started: Instant::now(),
+            appeal: false,
+            repeats,
+            model: node.model.clone(),
+            reasked: 0,
+            routed,
+            criteria,
+            ruled,
+        })
+    }
+
+    /// How many times in a row `input` will have been given at `node_id`.
+    /// Answers match regardless of case, spacing and final punctuation.
+    fn repeats_with(&self, node_id: &str, input: &str) -> usize {
+        match &self.last_answer {
+            Some((id, last)) if id == node_id && *last == normalize_answer(input) => {
+                self.repeats + 1
+            }
+            _ => 1,
+        }
+    }
+
+    /// The plea goes to the supervisor after their opening line.
+    fn ask_appeal(&mut self, input: &str) -> JudgeRequest {
+        let appeal = self.pending_appeal().expect("appeal is pending").clone();
+        let line = self.appeal_line().expect("appeal is pending");
+        info!("Appeal: \"{input}\"");
+        self.state
+            .conversation
+            .push(ChatMessage::assistant(&line.neutral));
+        self.state.conversation.push(ChatMessage::user(input));
+
+        JudgeRequest {
+            messages: build_appeal_messages(
+                &self.state.conversation,
+                &appeal,
+                &self.state.tree.setting(),
+            ),
+            valid_choices: vec![APPEAL_GRANTED.to_string(), APPEAL_DENIED.to_string()],
+            input: input.to_string(),
+            started: Instant::now(),
+            appeal: true,
+            repeats: 0,
+            model: None,
+            reasked: 0,
+            routed: false,
+            criteria: Vec::new(),
+            ruled: None,
+        }
+    }
+
+    /// Judge calls that failed in a row, each leaving the player to answer
+    /// again.
+    pub fn judge_failures(&self) -> usize {
+        self.judge_failures
+    }
+
+    /// Have `judge` decide `request` and apply the result, asking it again
+    /// for as long as the session wants to, so the reply is never a
+    /// [`Reply::Reask`]. Every front end goes through here. A rule of
+    /// the node decides without the judge, and once the judge has failed
+    /// [`classifier::FALLBACK_AFTER`] times in a row the answer is
+    /// classified by embedding instead. `None` when the judge had no room
+    /// for the request; the answer is withdrawn.
+    pub fn settle(
+        &mut self,
+        mut request: JudgeRequest,
+        judge: &mut impl Judge,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<Option<Reply>> {
+        loop {
+            let (judged, usage) = match request.ruled() {
+                Some(decision) => (Ok(decision), Usage::default()),
+                None => match judge.decide(&request, on_piece) {
+                    Some(judged) => judged,
+                    None => {
+                        self.withdraw(request);
+                        return Ok(None);
+                    }
+                },
+            };
+            let judged = match judged {
+                Err(e)
+                    if !request.appeal && self.judge_failures + 1 >= classifier::FALLBACK_AFTER =>
+                {
+                    warn!("Judge failed again ({e:#}); classifying the answer by embedding");
+                    judge
+                        .classify(&request)
+                        .inspect_err(|why| warn!("Classifying failed too: {why:#}"))
+                        .or(Err(e))
+                }
+                judged => judged,
+            };
+            match self.apply(request, judged, usage)? {
+                Reply::Reask(again) => request = again,
+                reply => return Ok(Some(reply)),
+            }
+        }
+    }
+
+    /// Apply the judge's result to a request from [`Session::ask`].
+    pub fn apply(
+        &mut self,
+        request: JudgeRequest,
+        judged: Result<LlmDecision>,
+        usage: Usage,
+    ) -> Result<Reply> {
+        let node = self.node().clone();
+        let valid_choices = request.valid_choices();
+        let latency = request.started.elapsed();
+
+        let mut decision = match judged {
+            Ok(decision) => {
+                self.judge_failures = 0;
+                decision
+            }
+            Err(e) => {
+                self.judge_failures += 1;
+                Anomaly::new(
+                    "judge failure",
+                    &node,
+                    &request.input,
+                    &valid_choices,
+                    format!("{e:#}"),
+                )
+                .report(self.strict)?;
+                // Drop the answer so the guard's question stands again. An
+                // appeal also drops the supervisor's line; it is offered again.
+                self.withdraw(request);
+                return Ok(Reply::Retry);
+            }
+        };
+        if request.appeal {
+            return Ok(self.rule_on_appeal(&node, request, decision, latency, usage));
+        }
+        if decision.decision == LOOKUP && valid_choices.contains(&LOOKUP) {
+            let section = judge_line(&decision, SECTION);
+            info!(
+                "Looking up {} at {} (reason: {})",
+                section.as_deref().unwrap_or("nothing"),
+                node.id,
+                decision.reason
+            );
+            let text = section
+                .as_deref()
+                .and_then(|s| self.state.tree.regulations.get(s));
+            return Ok(Reply::Reask(look_up(request, section.as_deref(), text)));
+        }
+        let undecidable = (decision.decision == UNDECIDABLE
+            && valid_choices.contains(&UNDECIDABLE))
+        .then(|| self.state.tree.undecidable.clone())
+        .flatten();
+        if let Some(policy) = undecidable {
+            info!("Undecidable at {} (reason: {})", node.id, decision.reason);
+            if request.reasked < policy.reasks {
+                return Ok(Reply::Reask(reask(request, &decision.reason)));
+            }
+            match policy.fallback {
+                UndecidableFallback::Ask => {
+                    self.withdraw(request);
+                    return Ok(Reply::Retry);
+                }
+                UndecidableFallback::First => decision.decision = valid_choices[0].to_string(),
+            }
+        }
+        if decision.decision == CLARIFY && valid_choices.contains(&CLARIFY) {
+            return Ok(Reply::Judged(
+                self.clarify(&node, request, decision, latency, usage),
+            ));
+        }
+        if decision.decision == CONTINUE && valid_choices.contains(&CONTINUE) {
+            let reply = judge_line(&decision, REPLY).unwrap_or_else(|| DEFAULT_REPLY.to_string());
+            info!(
+                "Talking at {} ({} so far): \"{reply}\" (reason: {})",
+                node.id, self.chatted, decision.reason
+            );
+            self.chatted += 1;
+            return Ok(Reply::Judged(
+                self.stay(&node, request, decision, &reply, latency, usage),
+            ));
+        }
+
+        let contradicted = contradiction(&node, &decision).map(String::from);
+        // Grammar ensures decision is valid, but keep a safety check
+        let mut next = if let Some(target) = contradicted {
+            info!(
+                "Contradiction: {} -> {target} instead of {} (reason: {})",
+                node.id, decision.decision, decision.reason
+            );
+            decision.decision = target.clone();
+            target
+        } else if valid_choices.contains(&decision.decision.as_str()) {
+            info!(
+                "Transition: {} -> {} (reason: {})",
+                node.id, decision.decision, decision.reason
+            );
+            decision.decision.clone()
+        } else {
+            Anomaly::new(
+                "fallback transition",
+                &node,
+                &request.input,
+                &valid_choices,
+                format!(
+                    "judge chose '{}'; falling back to the first option",
+                    decision.decision
+                ),
+            )
+            .report(self.strict)?;
+            let fallback = valid_choices[0].to_string();
+            info!("Fallback transition: {} -> {}", node.id, fallback);
+            fallback
+        };
+        info!("(Judge reasoning: {})", decision.reason);
+        debug!(
+            "(Judge confidence: {:?}, tone: {:?}, quote: {:?})",
+            decision.confidence, decision.tone, decision.quote
+        );
+
+        // A settled topic leads on to the first one still open.
+        if let Some(plan) = self
+            .state
+            .tree
+            .plan
+            .as_ref()
+            .filter(|plan| plan.passes(&node.id, &next))
+        {
+            plan.settle(&node.id, decision.facts.get(COVERS), &mut self.state.vars);
+            next = plan.next(&self.state.vars).to_string();
+            info!("Topic {} settled: -> {next}", node.id);
+        }
+
+        // A failure short of the last one is let off with a warning.
+        if let Some(strikes) = &self.state.tree.strikes {
+            let fails = matches!(
+                self.state.tree.get(&next).map(|n| &n.node_type),
+                Some(NodeType::Terminal(false))
+            );
+            let struck = self
+                .state
+                .visits
+                .get(&strikes.warning)
+                .copied()
+                .unwrap_or(0);
+            if fails && struck + 1 < strikes.max_failures {
+                if self.state.tree.get(&strikes.warning).is_some() {
+                    info!(
+                        "Strike {} of {}: {next} -> {}",
+                        struck + 1,
+                        strikes.max_failures,
+                        strikes.warning
+                    );
+                    next = strikes.warning.clone();
+                    self.retry_at = Some(node.id.clone());
+                } else {
+                    warn!("Strikes warning node {} does not exist", strikes.warning);
+                }
+            }
+        }
+
+        let mut raised = false;
+        if let Some(meter) = &self.state.tree.suspicion {
+            let before = self.state.suspicion;
+            let stress = match (&meter.lie_detector, self.stress.take()) {
+                (Some(detector), Some(reading)) => detector.bias(reading),
+                _ => 0,
+            };
+            let change = suspicion::delta(&decision) + stress;
+            raised = change > 0;
+            self.state.suspicion += change;
+            info!("Suspicion: {before} -> {}", self.state.suspicion);
+            if let Some(threshold) = meter.crossed(before, self.state.suspicion) {
+                if self.state.tree.get(&threshold.node).is_some() {
+                    info!("Suspicion reached {}: -> {}", threshold.at, threshold.node);
+                    next = threshold.node.clone();
+                    decision.decision = next.clone();
+                    self.retry_at = None;
+                } else {
+                    warn!("Suspicion threshold node {} does not exist", threshold.node);
+                }
+            }
+        }
+
+        let failed = next == self.state.tree.strikes.as_ref().map_or("", |s| &s.warning)
+            || matches!(
+                self.state.tree.get(&next).map(|n| &n.node_type),
+                Some(NodeType::Terminal(false))
+            );
+        if let Some(decay) = self
+            .state
+            .tree
+            .suspicion
+            .as_ref()
+            .and_then(|m| m.decay.as_ref())
+        {
+            let before = self.state.suspicion;
+            self.state.suspicion = decay.apply(&mut self.state.calm, !failed && !raised, before);
+            if self.state.suspicion < before {
+                info!("Suspicion decayed: {before} -> {}", self.state.suspicion);
+                self.state
+                    .vars
+                    .insert(suspicion::REDEEMED.to_string(), true.into());
+            }
+        }
+        let before = self.difficulty();
+        self.state.streak = if failed {
+            self.state.streak.min(0) - 1
+        } else {
+            self.state.streak.max(0) + 1
+        };
+        if self.adaptive && self.difficulty() != before {
+            info!(
+                "Streak of {}: difficulty {before:?} -> {:?}",
+                self.state.streak,
+                self.difficulty()
+            );
+        }
+
+        for (name, value) in &decision.facts {
+            if !node.extract.contains_key(name) || value.is_null() || value == "" {
+                continue;
+            }
+            info!("Extracted {name} = {value}");
+            self.state.vars.insert(name.clone(), value.clone());
+        }
+        // Only judged answers count, so a retried answer isn't a repeat.
+        self.last_answer = Some((node.id.clone(), normalize_answer(&request.input)));
+        self.repeats = request.repeats;
+        self.clarified = 0;
+        self.chatted = 0;
+        self.state.current_node_id = next;
+        self.state.steps_completed += 1;
+        self.latency.record(latency, usage);
+        let record = TurnRecord {
+            round: 0,
+            turn: 0,
+            node_id: node.id.clone(),
+            guard_line: self.line.neutral.clone(),
+            player_input: request.input,
+            decision: decision.decision,
+            reason: decision.reason,
+            latency_ms: latency.as_millis() as u64,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        };
+        self.enter();
+        Ok(Reply::Judged(record))
+    }
+
+    /// Stay at the node and ask the follow-up: the scripted one, else the
+    /// judge's, else a generic one.
+    fn clarify(
+        &mut self,
+        node: &GameNode,
+        request: JudgeRequest,
+        decision: LlmDecision,
+        latency: Duration,
+        usage: Usage,
+    ) -> TurnRecord {
+        let scripted = node
+            .clarify
+            .as_ref()
+            .and_then(|clarify| clarify.line.as_ref())
+            .map(|line| line.pick(&mut self.state.rng).to_string());
+        let follow_up = scripted
+            .or_else(|| judge_line(&decision, FOLLOW_UP))
+            .unwrap_or_else(|| DEFAULT_FOLLOW_UP.to_string());
+        info!(
+            "Clarify at {}: \"{follow_up}\" (reason: {})",
+            node.id, decision.reason
+        );
+        self.clarified += 1;
+        self.stay(node, request, decision, &follow_up, latency, usage)
+    }
+
+    /// Record an answer that keeps the game at the node, and have the guard
+    /// say `line` next.
+    fn stay(
+        &mut self,
+        node: &GameNode,
+        request: JudgeRequest,
+        decision: LlmDecision,
+        line: &str,
+        latency: Duration,
+        usage: Usage,
+    ) -> TurnRecord {
+        self.latency.record(latency, usage);
+
+        let record = TurnRecord {
+            round: 0,
+            turn: 0,
+            node_id: node.id.clone(),
+            guard_line: self.line.neutral.clone(),
+            player_input: request.input,
+            decision: decision.decision,
+            reason: decision.reason,
+            latency_ms: latency.as_millis() as u64,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        };
+        self.line = template::render(line, self.state.tree.persona.as_ref());
+        self.state
+            .conversation
+            .push(ChatMessage::assistant(&self.line.neutral));
+        record
+    }
+
+    fn rule_on_appeal(
+        &mut self,
+        node: &GameNode,
+        request: JudgeRequest,
+        decision: LlmDecision,
+        latency: Duration,
+        usage: Usage,
+    ) -> Reply {
+        let appeal = node
+            .appeal
+            .clone()
+            .expect("appeal request at a node with an appeal");
+        let granted = decision.decision == APPEAL_GRANTED;
+        info!(
+            "Appeal {} (reason: {})",
+            if granted { "granted" } else { "denied" },
+            decision.reason
+        );
+        self.appealed = true;
+        self.latency.record(latency, usage);
+
+        let record = TurnRecord {
+            round: 0,
+            turn: 0,
+            node_id: node.id.clone(),
+            guard_line: self.appeal_line_neutral(&appeal),
+            player_input: request.input,
+            decision: decision.decision,
+            reason: decision.reason,
+            latency_ms: latency.as_millis() as u64,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        };
+        if granted {
+            self.appeal_granted = true;
+            self.state.current_node_id = appeal.granted;
+            self.state.steps_completed = self.state.steps_completed.saturating_sub(appeal.penalty);
+            self.enter();
+        }
+        Reply::Appealed { granted, record }
+    }
+
+    fn appeal_line_neutral(&self, appeal: &Appeal) -> String {
+        template::render(&appeal.transcript, self.state.tree.persona.as_ref()).neutral
+    }
+
+    /// Take back a request that was never judged, e.g. because the judge
+    /// was too busy to take it. The question (or appeal) stands again.
+    pub fn withdraw(&mut self, request: JudgeRequest) {
+        self.state.conversation.pop();
+        if request.appeal {
+            self.state.conversation.pop();
+        }
+    }
+
+    /// Render the current node's line and add it to the conversation.
+    /// Random and interlude nodes are passed straight through: their line
+    /// leads into the line of the node picked for them.
+    fn enter(&mut self) {
+        let mut lines = Vec::new();
+        let mut node = self.state.current_node().clone();
+        for hop in 0.. {
+            info!("Current node: {}. node_type {:?}", node.id, node.node_type);
+            let visits = self.state.visits.entry(node.id.clone()).or_default();
+            *visits += 1;
+            if let Some(points) = node.points.filter(|_| *visits == 1) {
+                self.state.score += points;
+                debug!("Points {points:+} at {}: {}", node.id, self.state.score);
+            }
+            for (name, value) in &node.set {
+                debug!("Set {name} = {value}");
+                self.state.vars.insert(name.clone(), value.clone());
+            }
+            let persona = self.state.tree.persona.as_ref();
+            self.state.line_rng = self.state.rng.clone();
+            lines.push(template::render(
+                node.transcript.pick(&mut self.state.rng),
+                persona,
+            ));
+            let next = if let Some(question) = self.retry_at.take() {
+                info!("Back to the question: {} -> {question}", node.id);
+                question
+            } else {
+                let open = node.resolve(&self.state.condition_vars());
+                let (next, kind) = match &open.node_type {
+                    NodeType::Random(branches) => {
+                        (node::pick_weighted(branches, &mut self.state.rng), "Random")
+                    }
+                    NodeType::Interlude(branches) => (branches.first(), "Interlude"),
+                    NodeType::Terminal(_) | NodeType::Decision(_) => break,
+                };
+                if hop == MAX_RANDOM_HOPS {
+                    warn!(
+                        "Stopping at node {} after {hop} nodes passed through",
+                        node.id
+                    );
+                    break;
+                }
+                let Some(next) = next.filter(|next| self.state.tree.get(&next.id).is_some()) else {
+                    warn!("{kind} node {} has no branch to take", node.id);
+                    break;
+                };
+                info!("{kind} transition: {} -> {}", node.id, next.id);
+                next.id.clone()
+            };
+            self.state.current_node_id = next;
+            node = self.state.current_node().clone();
+        }
+        self.line = Rendered {
+            display: join_lines(lines.iter().map(|l| l.display.as_str())),
+            neutral: join_lines(lines.iter().map(|l| l.neutral.as_str())),
+        };
+        let persona = self.state.tree.persona.as_ref();
+
+        // The judge only ever sees the language-neutral line.
+        let leaked = template::foreign_phrases(&self.line.neutral, persona);
+        if !leaked.is_empty() {
+            warn!(
+                "Node {} has untranslated phrases in judged content: {:?}",
+                node.id, leaked
+            );
+        }
+        if let NodeType::Terminal(success) = node.node_type {
+            info!("Game over at node: {}. success {}", node.id, success);
+        }
+
+        if let Some(speaker) = node.speaker.as_deref() {
+            if !self.state.tree.speakers.contains_key(speaker) {
+                warn!("Node {} names unknown speaker '{speaker}'", node.id);
+            }
+        }
+        let said = self.said(&self.line.neutral);
+        self.state.conversation.push(said);
+        self.unnarrated = true;
+        if let Some(persona) = self.persona() {
+            let key = (node.id.clone(), persona);
+            if let Some((_, line)) = self
+                .narrated
+                .get(&key)
+                .filter(|(written, _)| *written == self.line.neutral)
+                .cloned()
+            {
+                debug!("Narrated before: \"{line}\"");
+                self.unnarrated = false;
+                self.say(&line);
+            }
+        }
+        if node.checkpoint && matches!(node.node_type, NodeType::Decision(_)) {
+            info!("Checkpoint at {}", node.id);
+            self.checkpoint = Some(self.state.snapshot());
+        }
+    }
+}
+
+/// Extra key of the judge's reply where it words its own follow-up.
+const FOLLOW_UP: &str = "follow_up";
+
+/// Extra key of the judge's reply naming the regulation section it picked
+/// [`LOOKUP`] for.
+const SECTION: &str = "section";
+
+/// Follow-up when neither the scenario nor the judge gave one.
+const DEFAULT_FOLLOW_UP: &str = "Could you be more specific?";
+
+/// Extra key of the judge's reply with what the guard says back while the
+/// conversation at a multi-turn node goes on.
+const REPLY: &str = "reply";
+
+/// Guard line when the judge keeps talking without saying anything.
+const DEFAULT_REPLY: &str = "Go on.";
+
+/// A line the judge wrote under `key`, if it wrote one.
+fn judge_line(decision: &LlmDecision, key: &str) -> Option<String> {
+    decision
+        .facts
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+}
+
+/// The node's `contradiction` target, if the judge found that the answer
+/// contradicts what the player said earlier.
+pub fn contradiction<'a>(node: &'a GameNode, decision: &LlmDecision) -> Option<&'a str> {
+    let flagged = matches!(decision.facts.get(CONTRADICTS), Some(v) if v == true || v == "true");
+    node.contradiction.as_deref().filter(|_| flagged)
+}
+
+/// `request` again, with the judge told why it could not decide and to
+/// look at the answer once more.
+fn reask(mut request: JudgeRequest, reason: &str) -> JudgeRequest {
+    if let Some(system) = request.messages.first_mut() {
+        system.content.push_str(&format!(
+            "\n\nYou already answered {UNDECIDABLE} to this response, because: {reason}\n\
+             Read it once more and pick the option it meets best. \
+             Answer {UNDECIDABLE} again only if it truly meets none of them."
+        ));
+    }
+    request.reasked += 1;
+    request.ruled = None;
+    request
+}
+
+/// Ask the judge again with the regulation `section` it looked up. One
+/// lookup per answer: [`LOOKUP`] is no longer an option.
+fn look_up(
+    mut request: JudgeRequest,
+    section: Option<&str>,
+    text: Option<&String>,
+) -> JudgeRequest {
+    let found = match (section, text) {
+        (Some(section), Some(text)) => format!("Regulation \"{section}\":\n{text}"),
+        (Some(section), None) => format!("There is no regulation section \"{section}\"."),
+        (None, _) => "You named no regulation section.".to_string(),
+    };
+    if let Some(system) = request.messages.first_mut() {
+        system.content.push_str(&format!(
+            "\n\n{found}\nNow decide with what you know; {LOOKUP} is no longer an option."
+        ));
+    }
+    request.valid_choices.retain(|choice| choice != LOOKUP);
+    request.ruled = None;
+    request
+}
+
+fn normalize_answer(input: &str) -> String {
+    input
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', '!', '?'])
+        .to_lowercase()
+}
+
+fn join_lines<'a>(lines: impl Iterator<Item = &'a str>) -> String {
+    lines
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::locale;
+    use crate::game::persona::Style;
+    use crate::game::tree::Strikes;
+
+    fn tree() -> GameTree {
+        serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"},
+                                                 {"id": "C", "description": "refuses"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap()
+    }
+
+    fn decision(id: &str) -> Result<LlmDecision> {
+        Ok(LlmDecision {
+            decision: id.to_string(),
+            reason: "because".to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_session_advances_on_judged_answer() {
+        let mut session = Session::new(tree(), None, false, 0);
+        assert_eq!(session.guard_line().display, "Passport?");
+        assert!(session.outcome().is_none());
+        assert_eq!(session.hint(), ["shows it"]);
+        assert!(session.ask("  ").is_err());
+
+        let request = session.ask("Here it is").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C"]);
+        let Reply::Judged(record) = session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap()
+        else {
+            panic!("expected a judged reply");
+        };
+        assert_eq!(record.player_input, "Here it is");
+        assert_eq!(record.guard_line, "Passport?");
+        assert_eq!(session.guard_line().display, "Welcome.");
+        assert!(matches!(
+            session.outcome(),
+            Some(GameOutcome::Finished {
+                success: true,
+                steps_completed: 1,
+                ..
+            })
+        ));
+        assert!(session.ask("bye").is_err());
+        assert_eq!(session.latency().turns, 1);
+    }
+
+    #[test]
+    fn test_node_routes_to_its_model() {
+        let mut tree = tree();
+        tree.nodes.get_mut("A").unwrap().model = Some("big".to_string());
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("Here it is").unwrap();
+        assert_eq!(request.model(), Some("big"));
+    }
+
+    #[test]
+    fn test_random_node_is_passed_through() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "R", "nodes": {
+                "R": {"id": "R", "transcript": "Hold on.",
+                      "node_type": {"Random": [[{"id": "A", "description": "spot check"}, 1.0],
+                                               [{"id": "B", "description": "never"}, 0.0]]}},
+                "A": {"id": "A", "transcript": "Open the bag.", "node_type": {"Terminal": true}},
+                "B": {"id": "B", "transcript": "Go.", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        let session = Session::new(tree, None, false, 7);
+        assert_eq!(session.guard_line().display, "Hold on. Open the bag.");
+        assert!(session.outcome().is_some());
+    }
+
+    #[test]
+    fn test_interlude_follows_suspicion() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "suspicion": {"thresholds": [{"at": 9, "node": "C"}]},
+                "nodes": {
+                "A": {"id": "A", "transcript": "Purpose of your visit?",
+                      "node_type": {"Decision": [{"id": "FORM", "description": "answers"}]}},
+                "FORM": {"id": "FORM", "transcript": "*You fill in the arrival card.*",
+                      "node_type": {"Interlude": [
+                          {"id": "C", "description": "too suspicious", "when": "suspicion >= 2"},
+                          {"id": "B", "description": "in order"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let play = |delta: f32| {
+            let mut session = Session::new(tree.clone(), None, false, 0);
+            let request = session.ask("Business.").unwrap();
+            let mut decision = decision("FORM").unwrap();
+            decision.suspicion_delta = Some(delta);
+            session
+                .apply(request, Ok(decision), Usage::default())
+                .unwrap();
+            session
+        };
+
+        let calm = play(1.0);
+        assert_eq!(
+            calm.guard_line().display,
+            "*You fill in the arrival card.* Welcome."
+        );
+        assert_eq!(calm.node().id, "B");
+        assert_eq!(play(2.0).node().id, "C");
+        // Not a step: the player answers nothing there.
+        assert_eq!(calm.total_steps(), 1);
+    }
+
+    #[test]
+    fn test_rules_decide_obvious_answers() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Anything to declare?",
+                      "node_type": {"Decision": [{"id": "B", "description": "declares"},
+                                                 {"id": "C", "description": "refuses"}]},
+                      "rules": [{"keywords": ["no comment", "lawyer"], "next": "C"},
+                                {"pattern": "^\\s*nothing\\W*$", "next": "B",
+                                 "reason": "Nothing to declare."},
+                                {"keywords": ["gate"], "next": "GONE"}]},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let ruled = |input: &str| {
+            let request = Session::new(tree.clone(), None, false, 0)
+                .ask(input)
+                .unwrap();
+            request.ruled().map(|d| (d.decision, d.reason))
+        };
+        assert_eq!(
+            ruled("NO COMMENT."),
+            Some(("C".into(), "The answer matches a rule leading to C.".into()))
+        );
+        assert_eq!(
+            ruled(" Nothing! "),
+            Some(("B".into(), "Nothing to declare.".into()))
+        );
+        // Keywords are whole words, and rules to edges the node lacks are skipped.
+        assert_eq!(ruled("My lawyers said nothing, so here it is."), None);
+        assert_eq!(ruled("Which gate?"), None);
+
+        let mut session = Session::new(tree.clone(), None, false, 0);
+        let request = session.ask("I want a lawyer").unwrap();
+        let decision = request.ruled().unwrap();
+        session
+            .apply(request, Ok(decision), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "C");
+
+        let mut rule = tree.nodes["A"].rules[1].clone();
+        rule.pattern = Some("(unclosed".into());
+        assert!(rule.regex().is_err());
+    }
+
+    #[test]
+    fn test_repeated_answer_is_routed() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Purpose of your visit?",
+                      "node_type": {"Decision": [{"id": "A", "description": "unclear"},
+                                                 {"id": "B", "description": "clear"}]},
+                      "repeats": {"limit": 2, "next": "C"}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+
+        let request = session.ask("Just visiting.").unwrap();
+        session.withdraw(request);
+        let request = session.ask("Just visiting.").unwrap();
+        session
+            .apply(request, decision("A"), Usage::default())
+            .unwrap();
+
+        let request = session.ask("just  visiting").unwrap();
+        assert_eq!(request.valid_choices(), ["C"]);
+        assert!(request.messages[0].to_string().contains("2 times in a row"));
+        session.withdraw(request);
+        let request = session.ask("Tourism.").unwrap();
+        assert_eq!(request.valid_choices(), ["A", "B"]);
+    }
+
+    #[test]
+    fn test_suspicion_threshold_forces_failure() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "suspicion": {"thresholds": [{"at": 3, "node": "C"}]},
+                "nodes": {
+                "A": {"id": "A", "transcript": "Purpose of your visit?",
+                      "node_type": {"Decision": [{"id": "A", "description": "vague"},
+                                                 {"id": "B", "description": "clear"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+        let suspicious = |delta: i32| {
+            let mut decision = decision("A").unwrap();
+            decision.suspicion_delta = Some(delta as f32);
+            Ok(decision)
+        };
+
+        let request = session.ask("Business, I guess.").unwrap();
+        assert!(request.messages[0].content.contains("so far: 0 of 3."));
+        assert!(request.messages[0].content.contains("\"delta\""));
+        session
+            .apply(request, suspicious(2), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "A");
+
+        let request = session.ask("Why do you ask?").unwrap();
+        assert!(request.messages[0].content.contains("so far: 2 of 3."));
+        session
+            .apply(request, suspicious(5), Usage::default())
+            .unwrap();
+        assert!(matches!(
+            session.outcome(),
+            Some(GameOutcome::Finished {
+                success: false,
+                suspicion: Some(4),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_calm_answers_open_redemption() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A",
+                "suspicion": {"thresholds": [{"at": 9, "node": "C"}], "decay": {"after": 2}},
+                "nodes": {
+                "A": {"id": "A", "transcript": "Purpose?", "node_type": {"Decision": [{"id": "B", "description": "any"}]}},
+                "B": {"id": "B", "transcript": "Length?", "node_type": {"Decision": [{"id": "D", "description": "any"}]}},
+                "D": {"id": "D", "transcript": "Address?", "node_type": {"Decision": [{"id": "E", "description": "any"}]}},
+                "E": {"id": "E", "transcript": "Anything else?",
+                      "node_type": {"Decision": [{"id": "R", "description": "believes them", "when": "redeemed"},
+                                                 {"id": "C", "description": "refuses"}]}},
+                "R": {"id": "R", "transcript": "Alright, I believe you.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+        let judged = |next: &str, delta: f32| {
+            let mut decision = decision(next).unwrap();
+            decision.suspicion_delta = Some(delta);
+            Ok(decision)
+        };
+
+        for (next, delta) in [("B", 2.0), ("D", 0.0), ("E", 0.0)] {
+            let request = session.ask("Well...").unwrap();
+            session
+                .apply(request, judged(next, delta), Usage::default())
+                .unwrap();
+        }
+        assert_eq!(session.state.suspicion, 1);
+        let request = session.ask("No.").unwrap();
+        assert_eq!(request.valid_choices(), ["R", "C"]);
+    }
+
+    #[test]
+    fn test_strikes_warn_before_failing() {
+        let mut tree = tree();
+        tree.strikes = Some(Strikes {
+            max_failures: 2,
+            warning: "W".to_string(),
+        });
+        tree.nodes.insert(
+            "W".to_string(),
+            serde_json::from_str(
+                r#"{"id": "W", "transcript": "Last warning.", "node_type": {"Terminal": false}}"#,
+            )
+            .unwrap(),
+        );
+        let mut session = Session::new(tree, None, false, 0);
+
+        let request = session.ask("No.").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "A");
+        assert_eq!(session.guard_line().display, "Last warning. Passport?");
+        assert!(session.outcome().is_none());
+
+        let request = session.ask("Still no.").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "C");
+    }
+
+    #[test]
+    fn test_unclear_answer_gets_a_follow_up() {
+        let mut tree = tree();
+        tree.nodes.get_mut("A").unwrap().clarify = Some(node::Clarify {
+            line: None,
+            limit: 1,
+        });
+        let mut session = Session::new(tree, None, false, 0);
+        let clarify = |question: &str| {
+            let mut decision = decision(CLARIFY).unwrap();
+            decision
+                .facts
+                .insert(FOLLOW_UP.to_string(), question.into());
+            Ok(decision)
+        };
+
+        let request = session.ask("Maybe.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C", CLARIFY]);
+        assert!(request.messages[0].content.contains("\"follow_up\""));
+        let Reply::Judged(record) = session
+            .apply(request, clarify("Is that a yes?"), Usage::default())
+            .unwrap()
+        else {
+            panic!("expected a judged reply");
+        };
+        assert_eq!(record.decision, CLARIFY);
+        assert_eq!(session.node().id, "A");
+        assert_eq!(session.guard_line().display, "Is that a yes?");
+        assert_eq!(session.steps_completed(), 0);
+
+        // Out of follow-ups: the judge has to decide.
+        let request = session.ask("Maybe.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C"]);
+    }
+
+    #[test]
+    fn test_undecidable_is_asked_again_then_falls_back() {
+        let mut tree = tree();
+        tree.undecidable = Some(crate::game::tree::Undecidable {
+            reasks: 1,
+            fallback: UndecidableFallback::Ask,
+        });
+        let mut session = Session::new(tree.clone(), None, false, 0);
+
+        let request = session.ask("It's somewhere.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C", UNDECIDABLE]);
+        let Reply::Reask(request) = session
+            .apply(request, decision(UNDECIDABLE), Usage::default())
+            .unwrap()
+        else {
+            panic!("expected the judge to be asked again");
+        };
+        let reasked = format!("\n\nYou already answered {UNDECIDABLE} to this response, because: because\nRead it once more and pick the option it meets best. Answer {UNDECIDABLE} again only if it truly meets none of them.");
+        assert!(
+            request.messages[0].content.ends_with(&reasked),
+            "{}",
+            request.messages[0].content
+        );
+        // Still undecided: the guard asks the player again.
+        assert!(matches!(
+            session.apply(request, decision(UNDECIDABLE), Usage::default()),
+            Ok(Reply::Retry)
+        ));
+        assert_eq!(session.node().id, "A");
+        assert_eq!(session.history().len(), 1);
+
+        tree.undecidable = Some(crate::game::tree::Undecidable {
+            reasks: 0,
+            fallback: UndecidableFallback::First,
+        });
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("It's somewhere.").unwrap();
+        session
+            .apply(request, decision(UNDECIDABLE), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "B");
+    }
+
+    #[test]
+    fn test_judge_looks_up_a_regulation_once() {
+        let mut tree = tree();
+        tree.regulations.insert(
+            "4.2".to_string(),
+            "Passports must be valid for three months after departure.".to_string(),
+        );
+        let mut session = Session::new(tree, None, false, 0);
+
+        let request = session.ask("It expires next month.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C", LOOKUP]);
+        let mut lookup = decision(LOOKUP).unwrap();
+        lookup.facts.insert(SECTION.to_string(), "4.2".into());
+        let Reply::Reask(request) = session
+            .apply(request, Ok(lookup), Usage::default())
+            .unwrap()
+        else {
+            panic!("expected the judge to be asked again");
+        };
+        assert!(request.messages[0]
+            .content
+            .contains("three months after departure"));
+        assert_eq!(request.valid_choices(), ["B", "C"]);
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "C");
+    }
+
+    #[test]
+    fn test_difficulty_changes_criteria() {
+        let mut tree = tree();
+        tree.nodes.get_mut("A").unwrap().difficulty = serde_json::from_str(
+            r#"{"strict": {"suffix": "Expired passports do not count.",
+                           "edges": {"B": "shows a valid passport"}}}"#,
+        )
+        .unwrap();
+
+        let mut session = Session::new(tree.clone(), None, false, 0);
+        let request = session.ask("Here it is").unwrap();
+        assert!(request.messages[0]
+            .content
+            .contains("description: shows it"));
+        assert!(!request.messages[0].content.contains("Be strict"));
+
+        let mut session = Session::new(tree, None, false, 0);
+        session.set_difficulty(Difficulty::Strict);
+        assert_eq!(session.hint(), ["shows a valid passport"]);
+        let request = session.ask("Here it is").unwrap();
+        let prompt = &request.messages[0].content;
+        assert!(prompt.contains("description: shows a valid passport"));
+        assert!(prompt.contains("Expired passports do not count."));
+        assert!(prompt.contains("Be strict"));
+    }
+
+    #[test]
+    fn test_plan_skips_topics_already_covered() {
+        let mut tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "ORIGIN", "nodes": {
+                "CLEARED": {"id": "CLEARED", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "REFUSED": {"id": "REFUSED", "transcript": "Step aside.", "node_type": {"Terminal": false}}},
+                "plan": {"success": "CLEARED", "failure": "REFUSED", "topics": [
+                    {"id": "ORIGIN", "question": "Where from?", "criteria": "names a city"},
+                    {"id": "PURPOSE", "question": "Why are you here?", "criteria": "gives a reason"},
+                    {"id": "FUNDS", "question": "How will you pay?", "criteria": "names a means"}]}}"#,
+        )
+        .unwrap();
+        tree.expand_plan().unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+
+        let request = session.ask("Lyon, here for a conference.").unwrap();
+        assert_eq!(request.valid_choices(), ["PURPOSE", "REFUSED"]);
+        assert!(request.messages[0]
+            .content
+            .contains("PURPOSE (gives a reason); FUNDS (names a means)"));
+        let mut judged = decision("PURPOSE").unwrap();
+        judged
+            .facts
+            .insert(COVERS.into(), serde_json::json!(["PURPOSE"]));
+        session
+            .apply(request, Ok(judged), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "FUNDS");
+
+        let request = session.ask("My employer pays.").unwrap();
+        assert_eq!(request.valid_choices(), ["CLEARED", "REFUSED"]);
+        // Nothing else is open, so the judge isn't asked about it.
+        assert!(!request.messages[0].content.contains(COVERS));
+        session
+            .apply(request, decision("CLEARED"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "CLEARED");
+    }
+
+    #[test]
+    fn test_resume_replays_the_same_draws() {
+        let mut tree = tree();
+        let variants =
+            node::Transcript::Variants((0..20).map(|i| format!("Passport {i}?")).collect());
+        tree.nodes.get_mut("A").unwrap().transcript = variants;
+        let session = Session::new(tree.clone(), None, false, 7);
+        let saved = session.state.snapshot();
+        // Resumed with another run seed: the same line, and the same draws
+        // after it.
+        let mut resumed = Session::new(tree, Some(saved), false, 99);
+        assert_eq!(resumed.guard_line(), session.guard_line());
+        assert_eq!(
+            resumed.state.rng.next_u64(),
+            session.state.rng.clone().next_u64()
+        );
+    }
+
+    #[test]
+    fn test_points_and_grade() {
+        let mut tree = tree();
+        tree.nodes.get_mut("A").unwrap().points = Some(2);
+        let b = tree.nodes.get_mut("B").unwrap();
+        b.points = Some(3);
+        b.rank = serde_json::from_str(
+            r#"[{"at": 5, "title": "Model Citizen"}, {"at": 0, "title": "Barely Cleared"}]"#,
+        )
+        .unwrap();
+        let session = Session::new(tree.clone(), None, false, 0);
+        // Resuming at the start counts its points once.
+        let saved = session.state.snapshot();
+        assert_eq!(saved.score, 0);
+        let mut session = Session::new(tree, Some(saved), false, 0);
+        assert_eq!(session.state.score, 2);
+
+        let request = session.ask("Here.").unwrap();
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        let Some(GameOutcome::Finished { score, grade, .. }) = session.outcome() else {
+            panic!("expected the game to be over");
+        };
+        assert_eq!(score, Some(5));
+        assert_eq!(grade.as_deref(), Some("Model Citizen"));
+        assert_eq!(node::grade(&session.node().rank, 4), Some("Barely Cleared"));
+    }
+
+    #[test]
+    fn test_scenario_sets_the_judge_system_prompt() {
+        let mut tree = tree();
+        tree.setting = Some(
+            serde_json::from_str(
+                r#"{"judge_system_prompt": "You are a librarian. Sort the reader's reply by these rules:"}"#,
+            )
+            .unwrap(),
+        );
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("Here.").unwrap();
+        let system = &request.messages[0].content;
+        assert!(system.starts_with("You are a librarian. Sort the reader's reply by these rules:"));
+        assert!(!system.contains("border"));
+    }
+
+    #[test]
+    fn test_endings_show_display_names() {
+        let mut tree = tree();
+        tree.nodes.get_mut("B").unwrap().display_name = Some("Welcome aboard".into());
+        assert_eq!(tree.display_name("FAILED_CONTRABAND"), "Failed contraband");
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("Here.").unwrap();
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        let outcome = session.outcome().unwrap();
+        assert!(outcome
+            .summary(&locale::ENGLISH)
+            .contains(&"Ended at: Welcome aboard".to_string()));
+        let GameOutcome::Finished {
+            terminal_node_id, ..
+        } = outcome
+        else {
+            panic!("expected a finished game");
+        };
+        assert_eq!(terminal_node_id, "B");
+    }
+
+    #[test]
+    fn test_speakers_take_over() {
+        let mut tree = tree();
+        tree.speakers = serde_json::from_str(
+            r#"{"customs": {"name": "Customs Officer", "persona": {"temperament": "chatty"}}}"#,
+        )
+        .unwrap();
+        let b = tree.nodes.get_mut("B").unwrap();
+        b.speaker = Some("customs".into());
+        b.node_type = NodeType::Decision(vec![node::NextNode {
+            id: "C".into(),
+            description: "declares nothing".into(),
+            when: None,
+        }]);
+        let mut session = Session::new(tree, None, false, 0);
+        assert_eq!(session.npc_name().as_deref(), Some("Guard"));
+
+        let request = session.ask("Here.").unwrap();
+        assert!(!request.messages[0].content.contains("The one asking now"));
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        assert_eq!(session.npc_name().as_deref(), Some("Customs Officer"));
+        assert_eq!(session.guard_line().display, "Welcome.");
+        assert_eq!(
+            session.history(),
+            [
+                "Guard: Passport?",
+                "Traveller: Here.",
+                "Customs Officer: Welcome."
+            ]
+        );
+        let request = session.ask("Nothing.").unwrap();
+        let system = &request.messages[0].content;
+        assert!(system.contains("The one asking now is Customs Officer"));
+        assert!(system.contains("You are chatty."));
+    }
+
+    #[test]
+    fn test_persona_speaks_in_character() {
+        let mut tree = tree();
+        tree.persona = Some(
+            serde_json::from_str(
+                r#"{"name": "Officer Brandt", "temperament": "bored and grumpy",
+                    "quirks": ["sighs a lot", "calls everyone pal"]}"#,
+            )
+            .unwrap(),
+        );
+        let mut session = Session::new(tree, None, false, 0);
+        let messages = session.narration_messages().unwrap();
+        assert!(messages[0].content.contains(
+            "You are Officer Brandt, bored and grumpy. Your habits of speech: sighs a lot; calls everyone pal."
+        ));
+        assert_eq!(messages[1].content, "Passport?");
+
+        session.narrate(Some("*sigh* Passport, pal."));
+        assert!(session.narration_messages().is_none());
+        assert_eq!(session.guard_line().display, "*sigh* Passport, pal.");
+        let request = session.ask("Here.").unwrap();
+        let system = &request.messages[0].content;
+        assert!(system.contains("You are Officer Brandt, bored and grumpy."));
+        assert!(request
+            .messages
+            .iter()
+            .any(|m| m.content == "*sigh* Passport, pal."));
+    }
+
+    #[test]
+    fn test_narration_is_kept_per_node_and_persona() {
+        let mut tree = tree();
+        tree.persona = Some(serde_json::from_str(r#"{"style": "terse"}"#).unwrap());
+        let mut session = Session::new(tree, None, false, 0);
+        let messages = session.narration_messages().unwrap();
+        assert!(messages[0].content.contains("You speak tersely"));
+        session.narrate(Some("Passport."));
+
+        // Back at the node: the narrated line, without asking again.
+        session.enter();
+        assert!(session.narration_messages().is_none());
+        assert_eq!(session.guard_line().display, "Passport.");
+        assert_eq!(
+            session.state.conversation.last().unwrap().content,
+            "Passport."
+        );
+
+        // Another persona speaks it in its own voice.
+        session.state.tree.persona.as_mut().unwrap().style = Some(Style::Chatty);
+        session.enter();
+        assert_eq!(session.guard_line().display, "Passport?");
+        assert!(session.narration_messages().is_some());
+    }
+
+    #[test]
+    fn test_night_shift_mood() {
+        let mut tree = tree();
+        tree.persona = Some(
+            serde_json::from_str(
+                r#"{"shifts": [{"name": "night", "from": "22:00", "to": "06:00",
+                                "mood": "It is the night shift.", "strictness": "strict"}]}"#,
+            )
+            .unwrap(),
+        );
+        let mut session = Session::new(tree.clone(), None, false, 0);
+        session.clock_in(14 * 60);
+        assert!(!session.state.vars.contains_key(SHIFT_VAR));
+
+        let mut session = Session::new(tree, None, false, 0);
+        session.clock_in(23 * 60);
+        assert_eq!(session.state.vars[SHIFT_VAR], "night");
+        let request = session.ask("Here.").unwrap();
+        let system = &request.messages[0].content;
+        assert!(system.contains("It is the night shift."));
+        assert!(system.contains("decide against the Traveller"));
+    }
+
+    #[test]
+    fn test_hint_costs_points() {
+        let mut tree = tree();
+        tree.hint_penalty = 2;
+        let mut session = Session::new(tree, None, false, 0);
+        let messages = session.hint_messages().unwrap();
+        assert!(messages[0].content.contains("\"Passport?\""));
+        assert!(messages[0].content.contains("shows it"));
+        assert!(!messages[0].content.contains("refuses"));
+        assert_eq!(session.charge_hint(), 2);
+
+        let request = session.ask("Here.").unwrap();
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        assert!(session.hint_messages().is_none());
+        let Some(GameOutcome::Finished { score, hints, .. }) = session.outcome() else {
+            panic!("expected the game to be over");
+        };
+        assert_eq!((score, hints), (Some(-2), 1));
+    }
+
+    #[test]
+    fn test_feedback_after_failure() {
+        let mut session = Session::new(tree(), None, false, 0);
+        assert!(session.feedback_messages().is_none());
+        let request = session.ask("No.").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+
+        let messages = session.feedback_messages().unwrap();
+        assert!(messages[0]
+            .content
+            .contains("- passes: shows it\n- fails: refuses"));
+        assert_eq!(
+            messages[1].content,
+            "Guard: Passport?\nTraveller: No.\nGuard: Step aside."
+        );
+    }
+
+    #[test]
+    fn test_party_takes_turns() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        assert!(Party::new(names(&["Ann"])).is_err());
+        assert!(Party::new(names(&["Ann", "Ann"])).is_err());
+        let mut session = Session::new(tree(), None, false, 0);
+        session.set_party(Some(Party::new(names(&["Ann", "Ben"])).unwrap()));
+
+        let request = session.ask("Here are both passports.").unwrap();
+        assert_eq!(
+            request.messages.last().unwrap().content,
+            "Ann: Here are both passports."
+        );
+        assert!(request.messages[0].content.contains("Ann is answering now"));
+        session.pass_turn();
+        assert_eq!(session.speaker(), Some("Ben"));
+        session.pass_turn();
+        assert_eq!(session.speaker(), Some("Ann"));
+    }
+
+    #[test]
+    fn test_retry_from_checkpoint() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"}]}},
+                "B": {"id": "B", "transcript": "Anything to declare?", "checkpoint": true,
+                      "node_type": {"Decision": [{"id": "C", "description": "declares it"},
+                                                 {"id": "D", "description": "smuggles"}]}},
+                "C": {"id": "C", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "D": {"id": "D", "transcript": "Step aside.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree.clone(), None, false, 0);
+        let request = session.ask("Here.").unwrap();
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        let request = session.ask("Just cash in the lining.").unwrap();
+        session
+            .apply(request, decision("D"), Usage::default())
+            .unwrap();
+        assert!(matches!(
+            session.outcome(),
+            Some(GameOutcome::Finished { checkpoint: Some(id), .. }) if id == "B"
+        ));
+
+        let retry = Session::new(tree, session.checkpoint().cloned(), false, 1);
+        assert_eq!(retry.node().id, "B");
+        assert_eq!(retry.steps_completed(), 1);
+        assert_eq!(retry.guard_line().display, "Anything to declare?");
+        assert_eq!(retry.state.conversation.len(), 3);
+    }
+
+    #[test]
+    fn test_small_talk_before_deciding() {
+        let mut tree = tree();
+        let a = tree.nodes.get_mut("A").unwrap();
+        a.min_turns = Some(2);
+        a.max_turns = Some(3);
+        let mut session = Session::new(tree, None, false, 0);
+        let talk = |reply: &str| {
+            let mut decision = decision(CONTINUE).unwrap();
+            decision.facts.insert(REPLY.to_string(), reply.into());
+            Ok(decision)
+        };
+
+        let request = session.ask("Long flight, huh?").unwrap();
+        assert_eq!(request.valid_choices(), [CONTINUE]);
+        assert!(request.messages[0].content.contains("\"reply\""));
+        session
+            .apply(request, talk("Long enough."), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "A");
+        assert_eq!(session.guard_line().display, "Long enough.");
+
+        // Ready from the second answer, and made to decide on the third.
+        let request = session.ask("Anyway.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C", CONTINUE]);
+        session
+            .apply(request, talk("Passport, then."), Usage::default())
+            .unwrap();
+        let request = session.ask("Here.").unwrap();
+        assert_eq!(request.valid_choices(), ["B", "C"]);
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "B");
+    }
+
+    #[test]
+    fn test_fast_play_skips_small_talk_and_routes() {
+        let mut tree = tree();
+        tree.nodes.get_mut("A").unwrap().min_turns = Some(2);
+        let mut session = Session::new(tree, None, false, 0);
+        session.set_fast();
+
+        let request = session.ask("Here.").unwrap();
+        assert!(request.routed());
+        assert_eq!(request.valid_choices(), ["B", "C"]);
+        assert!(request.messages[0]
+            .content
+            .contains("Reply with the number of your pick only."));
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "B");
+    }
+
+    #[test]
+    fn test_variables_gate_edges() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Purpose?",
+                      "node_type": {"Decision": [{"id": "B", "description": "business"}]},
+                      "set": {"purpose": "business"}},
+                "B": {"id": "B", "transcript": "Anything else?",
+                      "node_type": {"Decision": [
+                          {"id": "C", "description": "asks about the meeting", "when": "purpose == business"},
+                          {"id": "D", "description": "asks about the hotel", "when": "purpose != business"}]}},
+                "C": {"id": "C", "transcript": "Enjoy the meeting.", "node_type": {"Terminal": true}},
+                "D": {"id": "D", "transcript": "Enjoy your stay.", "node_type": {"Terminal": true}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("A meeting.").unwrap();
+        session
+            .apply(request, decision("B"), Usage::default())
+            .unwrap();
+
+        assert_eq!(session.hint(), ["asks about the meeting"]);
+        let request = session.ask("No.").unwrap();
+        assert_eq!(request.valid_choices(), ["C"]);
+        assert_eq!(session.save_file(None).state["vars"]["purpose"], "business");
+    }
+
+    #[test]
+    fn test_extracted_facts_fill_later_context_and_catch_contradictions() {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Where from?",
+                      "node_type": {"Decision": [{"id": "B", "description": "answers"}]},
+                      "extract": {"origin_city": "the city they came from"}},
+                "B": {"id": "B", "transcript": "And before that?",
+                      "node_type": {"Decision": [{"id": "C", "description": "consistent"}]},
+                      "system_context": "They said they came from {{vars.origin_city}}.",
+                      "contradiction": "D"},
+                "C": {"id": "C", "transcript": "Go.", "node_type": {"Terminal": true}},
+                "D": {"id": "D", "transcript": "That's not what you said.", "node_type": {"Terminal": false}}}}"#,
+        )
+        .unwrap();
+        let mut session = Session::new(tree, None, false, 0);
+        let request = session.ask("Frankfurt.").unwrap();
+        assert!(request.messages[0]
+            .content
+            .contains(r#""origin_city": "<the city they came from, or null>""#));
+        let mut judged = decision("B").unwrap();
+        judged
+            .facts
+            .insert("origin_city".into(), "Frankfurt".into());
+        judged.facts.insert("mood".into(), "calm".into());
+        session
+            .apply(request, Ok(judged), Usage::default())
+            .unwrap();
+
+        let request = session.ask("Nowhere.").unwrap();
+        let prompt = &request.messages[0].content;
+        assert!(prompt.contains("They said they came from Frankfurt."));
+        assert!(prompt.contains("- origin_city: Frankfurt"));
+        assert!(prompt.contains(r#""contradicts": <true"#));
+        let vars = &session.save_file(None).state["vars"];
+        assert!(vars.get("mood").is_none());
+
+        let mut judged = decision("C").unwrap();
+        judged.facts.insert("contradicts".into(), true.into());
+        let Reply::Judged(record) = session
+            .apply(request, Ok(judged), Usage::default())
+            .unwrap()
+        else {
+            panic!("expected a judged reply");
+        };
+        assert_eq!(record.decision, "D");
+        assert!(matches!(
+            session.outcome(),
+            Some(GameOutcome::Finished { success: false, .. })
+        ));
+    }
+
+    #[test]
+    fn test_latency_summary_reports_power_save() {
+        let mut latency = LatencySummary::default();
+        assert!(latency.lines(None, &locale::ENGLISH).is_empty());
+        let usage = Usage {
+            throttled: Duration::from_millis(500),
+            ..Usage::default()
+        };
+        latency.record(Duration::from_secs(2), usage);
+        latency.record(Duration::from_secs(4), usage);
+        assert_eq!(
+            latency.lines(Some(PowerSave::default()), &locale::ENGLISH),
+            [
+                "Judge latency: 3.0s average over 2 turns",
+                "Power save (2 threads): 0.5s of each turn spent in pauses",
+            ]
+        );
+        assert_eq!(latency.lines(None, &locale::ENGLISH).len(), 1);
+        assert_eq!(
+            latency.lines(None, &locale::GERMAN),
+            ["Bedenkzeit: im Schnitt 3.0 s über 2 Runden"]
+        );
+    }
+
+    #[test]
+    fn test_session_retries_and_falls_back() {
+        let mut session = Session::new(tree(), None, false, 0);
+        let request = session.ask("Hm").unwrap();
+        let reply = session
+            .apply(request, Err(anyhow::anyhow!("garbled")), Usage::default())
+            .unwrap();
+        assert!(matches!(reply, Reply::Retry));
+        assert_eq!(session.node().id, "A");
+        assert_eq!(session.state.conversation.len(), 1);
+
+        // Unknown decisions fall back to the first option...
+        let request = session.ask("Hm").unwrap();
+        session
+            .apply(request, decision("Z"), Usage::default())
+            .unwrap();
+        assert_eq!(session.node().id, "B");
+
+        // ...unless strict mode makes them fatal.
+        let mut session = Session::new(tree(), None, true, 0);
+        let request = session.ask("Hm").unwrap();
+        assert!(session
+            .apply(request, decision("Z"), Usage::default())
+            .is_err());
+    }
+
+    fn appeal_tree() -> GameTree {
+        serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {
+                "A": {"id": "A", "transcript": "Passport?",
+                      "node_type": {"Decision": [{"id": "B", "description": "shows it"},
+                                                 {"id": "C", "description": "refuses"}]}},
+                "B": {"id": "B", "transcript": "Welcome.", "node_type": {"Terminal": true}},
+                "C": {"id": "C", "transcript": "Step aside.", "node_type": {"Terminal": false},
+                      "appeal": {"granted": "A", "transcript": "I'm the supervisor. Explain.",
+                                 "criteria": "a credible excuse"}}}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_appeal_granted_returns_to_the_desk() {
+        let mut session = Session::new(appeal_tree(), None, false, 0);
+        let request = session.ask("No").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        assert!(session.outcome().is_some());
+        assert_eq!(
+            session.appeal_line().unwrap().display,
+            "I'm the supervisor. Explain."
+        );
+
+        let request = session.ask("I panicked, here it is").unwrap();
+        assert!(request.is_appeal());
+        assert_eq!(request.valid_choices(), [APPEAL_GRANTED, APPEAL_DENIED]);
+        assert!(request.messages[0].content.contains("a credible excuse"));
+        let reply = session
+            .apply(request, decision(APPEAL_GRANTED), Usage::default())
+            .unwrap();
+        assert!(matches!(reply, Reply::Appealed { granted: true, .. }));
+        assert_eq!(session.node().id, "A");
+        // The penalty takes back the step that led to the refusal.
+        assert_eq!(session.steps_completed(), 0);
+        assert!(session.appeal_line().is_none());
+
+        // Only one appeal per round.
+        let request = session.ask("No").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        assert!(session.pending_appeal().is_none());
+        assert!(matches!(
+            session.outcome(),
+            Some(GameOutcome::Finished {
+                success: false,
+                appealed: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_appeal_denied_or_failed() {
+        let mut session = Session::new(appeal_tree(), None, false, 0);
+        let request = session.ask("No").unwrap();
+        session
+            .apply(request, decision("C"), Usage::default())
+            .unwrap();
+        let before = session.state.conversation.len();
+
+        // A failed ruling drops the plea and offers the appeal again.
+        let request = session.ask("Please").unwrap();
+        let reply = session
+            .apply(request, Err(anyhow::anyhow!("garbled")), Usage::default())
+            .unwrap();
+        assert!(matches!(reply, Reply::Retry));
+        assert_eq!(session.state.conversation.len(), before);
+        assert!(session.appeal_line().is_some());
+
+        let request = session.ask("Please").unwrap();
+        let reply = session
+            .apply(request, decision(APPEAL_DENIED), Usage::default())
+            .unwrap();
+        assert!(matches!(reply, Reply::Appealed { granted: false, .. }));
+        assert_eq!(session.node().id, "C");
+        assert!(session.appeal_line().is_none());
+        assert!(session.ask("Please").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+/// Scenario-specific framing: who the judge plays, who the player is, and
+/// the text around the game. Scenarios without a setting get the airport
+/// border control one; a partial setting fills the rest from it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Setting {
+    /// Opening of the judge prompt: the role and the conversation.
+    pub role: String,
+    /// The whole opening of the judge prompt, in place of `role` and the
+    /// built-in "Your job is to categorize the player's last response..."
+    /// that follows it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge_system_prompt: Option<String>,
+    /// What the judge calls the player, e.g. "Traveller".
+    pub player: String,
+    /// What the player calls the other side, e.g. "guard".
+    pub npc: String,
+    /// Opening of the appeal prompt.
+    pub supervisor: String,
+    /// Who the model plays in `simulate`.
+    pub player_brief: String,
+    /// Prompt for the ambient lines shown while the judge thinks.
+    pub ambient: String,
+    /// In-fiction lines shown in turn while a decision takes long. Empty
+    /// for none.
+    pub beats: Vec<String>,
+    /// Game over headline on success.
+    pub cleared: String,
+    /// Game over headline on failure.
+    pub denied: String,
+    /// Game over line when the player quits.
+    pub walked_away: String,
+}
+
+impl Default for Setting {
+    fn default() -> Self {
+        Self {
+            role: "You are a border security guard at an airport. You are having a conversation with a traveller.".into(),
+            judge_system_prompt: None,
+            player: "Traveller".into(),
+            npc: "guard".into(),
+            supervisor: "You are the shift supervisor at an airport border control. A guard refused a traveller, and the traveller is appealing.".into(),
+            player_brief: "You are a traveller going through airport border control.".into(),
+            ambient: "You describe the queue at an airport border control. In one short sentence, describe a different traveller being processed at a neighbouring booth. Plain text only, no quotes.".into(),
+            beats: vec![
+                "The guard types something into the terminal...".into(),
+                "The guard frowns at the screen and scrolls.".into(),
+                "The guard picks up the phone, listens, and puts it down again.".into(),
+                "The guard is still typing...".into(),
+            ],
+            cleared: "You passed border control!".into(),
+            denied: "You were stopped at the border.".into(),
+            walked_away: "You walked away from the border control booth.".into(),
+        }
+    }
+}
+
+impl Setting {
+    /// [`Setting::npc`] with a capital first letter, for labels.
+    pub fn npc_title(&self) -> String {
+        let mut chars = self.npc.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Game variable holding the name of the guard's current shift.
+pub const SHIFT_VAR: &str = "shift";
+
+/// A stretch of the guard's working day that colours their mood, e.g. the
+/// night shift or rush hour. Played games pick it from the host's local
+/// time unless `--no-clock` is given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shift {
+    /// Set as the game variable [`SHIFT_VAR`], e.g. "night".
+    pub name: String,
+    /// Local start and end time, "HH:MM". A shift may run past midnight.
+    pub from: String,
+    pub to: String,
+    /// Told to the judge, e.g. "It is the night shift and you are tired."
+    pub mood: String,
+    #[serde(default)]
+    pub strictness: Strictness,
+}
+
+/// How the guard rules on borderline answers during a shift.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    Lenient,
+    #[default]
+    Normal,
+    Strict,
+}
+
+impl Strictness {
+    /// The judge's note on borderline answers, if any.
+    pub fn instruction(self, player: &str) -> Option<String> {
+        match self {
+            Strictness::Lenient => Some(format!(
+                "On borderline answers, give the {player} the benefit of the doubt."
+            )),
+            Strictness::Normal => None,
+            Strictness::Strict => Some(format!(
+                "On borderline answers, decide against the {player}."
+            )),
+        }
+    }
+}
+
+impl Shift {
+    /// Whether the shift is on at `minute` past midnight.
+    pub fn covers(&self, minute: u16) -> Result<bool> {
+        let (from, to) = (minute_of(&self.from)?, minute_of(&self.to)?);
+        Ok(if from <= to {
+            from <= minute && minute < to
+        } else {
+            minute >= from || minute < to
+        })
+    }
+}
+
+/// The first of `shifts` that is on at `minute` past midnight. Shifts with
+/// unreadable times are skipped.
+pub fn current(shifts: &[Shift], minute: u16) -> Option<&Shift> {
+    shifts.iter().find(|shift| match shift.covers(minute) {
+        Ok(on) => on,
+        Err(e) => {
+            warn!("Skipping shift {}: {e:#}", shift.name);
+            false
+        }
+    })
+}
+
+/// Minutes past midnight on the host's clock, in its local time zone.
+pub fn local_minute() -> u16 {
+    let now = jiff::Zoned::now();
+    now.hour() as u16 * 60 + now.minute() as u16
+}
+
+fn minute_of(time: &str) -> Result<u16> {
+    let parsed = time.split_once(':').and_then(|(hours, minutes)| {
+        Some((
+            hours.trim().parse::<u16>().ok()?,
+            minutes.trim().parse::<u16>().ok()?,
+        ))
+    });
+    let (hours, minutes) = parsed.with_context(|| format!("'{time}' is not HH:MM"))?;
+    if hours > 24 || minutes > 59 || hours * 60 + minutes > 24 * 60 {
+        bail!("'{time}' is not a time of day");
+    }
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_shift() {
+        let shifts: Vec<Shift> = serde_json::from_str(
+            r#"[{"name": "night", "from": "22:00", "to": "06:00", "mood": "Tired.", "strictness": "lenient"},
+                {"name": "rush_hour", "from": "7:30", "to": "9:00", "mood": "Impatient."},
+                {"name": "broken", "from": "25:00", "to": "26:00", "mood": "?"}]"#,
+        )
+        .unwrap();
+        let at = |time: &str| current(&shifts, minute_of(time).unwrap()).map(|s| s.name.as_str());
+        assert_eq!(at("23:15"), Some("night"));
+        assert_eq!(at("05:59"), Some("night"));
+        assert_eq!(at("06:00"), None);
+        assert_eq!(at("08:00"), Some("rush_hour"));
+        assert_eq!(shifts[0].strictness, Strictness::Lenient);
+        assert_eq!(shifts[1].strictness, Strictness::Normal);
+        assert!(minute_of("noon").is_err());
+    }
+}
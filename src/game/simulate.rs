@@ -0,0 +1,412 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::game::classifier;
+use crate::game::eval::{EvalEntry, EvalSuite, EvalTurn};
+use crate::game::instruction::JudgeInstruction;
+use crate::game::node::{pick_weighted, NodeType, MAX_RANDOM_HOPS};
+use crate::game::setting::Setting;
+use crate::game::template;
+use crate::game::tree::GameTree;
+use crate::game::vars::Vars;
+use crate::game::{build_judge_messages, Anomaly};
+use crate::llm::{strip_think, ChatMessage, LLM};
+use crate::rng::Rng;
+
+// ---------------------------------------------------------------------------
+// Traveller personas
+// ---------------------------------------------------------------------------
+
+/// How the simulated traveller behaves at the booth.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Traveller {
+    #[default]
+    Cooperative,
+    Evasive,
+    Hostile,
+}
+
+impl Traveller {
+    fn system_prompt(self, setting: &Setting) -> String {
+        let npc = &setting.npc;
+        let attitude = match self {
+            Self::Cooperative => {
+                "You are polite and honest, and answer every question directly.".to_string()
+            }
+            Self::Evasive => {
+                "You are nervous and vague. You dodge questions, give partial answers \
+                 and change the subject when pressed."
+                    .to_string()
+            }
+            Self::Hostile => format!(
+                "You are irritated and rude. You resent being questioned and push back \
+                 on the {npc}."
+            ),
+        };
+        format!(
+            "{} {attitude} \
+             Reply to the {npc} in one or two short sentences, in character. \
+             Do not narrate or describe actions.",
+            setting.player_brief
+        )
+    }
+}
+
+impl FromStr for Traveller {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cooperative" => Ok(Self::Cooperative),
+            "evasive" => Ok(Self::Evasive),
+            "hostile" => Ok(Self::Hostile),
+            _ => bail!("unknown traveller '{s}' (expected cooperative, evasive or hostile)"),
+        }
+    }
+}
+
+impl fmt::Display for Traveller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cooperative => "cooperative",
+            Self::Evasive => "evasive",
+            Self::Hostile => "hostile",
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Self-play
+// ---------------------------------------------------------------------------
+
+pub struct SimulateOptions {
+    pub games: usize,
+    pub traveller: Traveller,
+    /// Games that have not ended after this many judged turns are cut off,
+    /// so scenarios with cycles cannot run forever.
+    pub max_turns: usize,
+    /// Seeds the traveller's sampler for each game.
+    pub seed: u64,
+    /// Abort on judge failures and fallback transitions instead of counting
+    /// them as endings.
+    pub strict: bool,
+    /// Write the games here as an eval dataset, labeled by the referee.
+    pub dataset: Option<PathBuf>,
+    /// Scenario recorded in the dataset.
+    pub scenario_path: Option<String>,
+}
+
+/// How a simulated game ended.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Ending {
+    Terminal { node_id: String, success: bool },
+    TurnLimit,
+    JudgeError,
+}
+
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Terminal { node_id, success } => write!(
+                f,
+                "{node_id} ({})",
+                if *success { "cleared" } else { "denied" }
+            ),
+            Self::TurnLimit => f.write_str("(turn limit)"),
+            Self::JudgeError => f.write_str("(judge error)"),
+        }
+    }
+}
+
+/// Play `options.games` games where the model answers as the traveller, and
+/// print the distribution of endings.
+pub fn simulate(model: &mut LLM, tree: &GameTree, options: &SimulateOptions) -> Result<()> {
+    let mut seeds = Rng::new(options.seed);
+    // Transcript variants, kept apart from `seeds` so chat seeds stay put.
+    let mut lines = Rng::new(options.seed.rotate_left(32));
+    let mut endings: BTreeMap<Ending, usize> = BTreeMap::new();
+    let mut total_turns = 0;
+    let mut labeled = Vec::new();
+
+    for game in 1..=options.games {
+        model.set_chat_seed(seeds.next_u32());
+        let mut game_labels = Vec::new();
+        let (ending, turns) = play_game(model, tree, options, &mut lines, &mut game_labels)?;
+        labeled.push(game_labels);
+        println!(
+            "Game {game}/{}: {ending} after {turns} turns",
+            options.games
+        );
+        total_turns += turns;
+        *endings.entry(ending).or_default() += 1;
+    }
+
+    let games = options.games.max(1) as f32;
+    println!(
+        "\n=== {} games, {} traveller ===",
+        options.games, options.traveller
+    );
+    for (ending, count) in &endings {
+        println!(
+            "  {:<32} {count:>4}  {:5.1}%",
+            ending.to_string(),
+            100.0 * *count as f32 / games
+        );
+    }
+    let cleared: usize = endings
+        .iter()
+        .filter(|(e, _)| matches!(e, Ending::Terminal { success: true, .. }))
+        .map(|(_, n)| n)
+        .sum();
+    println!("  Cleared: {:.1}%", 100.0 * cleared as f32 / games);
+    println!("  Average turns: {:.1}", total_turns as f32 / games);
+
+    if let Some(path) = &options.dataset {
+        let disagreed = labeled
+            .iter()
+            .flatten()
+            .filter(|turn| turn.label.as_ref() != Some(&turn.judged))
+            .count();
+        let cases: Vec<EvalEntry> = labeled.iter().filter_map(|game| script(game)).collect();
+        let turns: usize = cases
+            .iter()
+            .map(|case| match case {
+                EvalEntry::Conversation { turns } => turns.len(),
+                EvalEntry::Case(_) => 1,
+            })
+            .sum();
+        let suite = EvalSuite {
+            scenario: options.scenario_path.clone(),
+            cases,
+        };
+        suite.write(path, DATASET_HEADER)?;
+        println!(
+            "  Dataset: {} conversations, {turns} turns, written to {} (the referee disagreed with the judge {disagreed} times)",
+            suite.cases.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Eval dataset
+// ---------------------------------------------------------------------------
+
+const DATASET_HEADER: &str = "\
+Eval cases from `simulate`, labeled by the referee model (`--supervisor-model`).
+Review them before relying on them: the labels are a model's calls.";
+
+/// A judged answer of a simulated game with the referee's call on it.
+struct Labeled {
+    node: String,
+    input: String,
+    /// What the judge decided, which the game followed.
+    judged: String,
+    /// What the referee decided, `None` if it failed.
+    label: Option<String>,
+}
+
+/// A simulated game as an eval conversation, expecting the referee's
+/// labels. The game followed the judge, so the script stops after the
+/// first turn the referee disagrees on, before one it could not label, and
+/// where a random node took the game somewhere the label did not lead.
+fn script(game: &[Labeled]) -> Option<EvalEntry> {
+    let mut turns: Vec<EvalTurn> = Vec::new();
+    for turn in game {
+        let Some(label) = &turn.label else {
+            break;
+        };
+        let at = turns.last().map(|t| t.expected.as_str());
+        if at.is_some_and(|at| at != turn.node) {
+            break;
+        }
+        turns.push(EvalTurn {
+            node: at.is_none().then(|| turn.node.clone()),
+            input: turn.input.clone(),
+            expected: label.clone(),
+        });
+        if *label != turn.judged {
+            break;
+        }
+    }
+    (!turns.is_empty()).then_some(EvalEntry::Conversation { turns })
+}
+
+fn play_game(
+    model: &mut LLM,
+    tree: &GameTree,
+    options: &SimulateOptions,
+    lines: &mut Rng,
+    labeled: &mut Vec<Labeled>,
+) -> Result<(Ending, usize)> {
+    // The judge sees the guard as assistant and the traveller as user; the
+    // traveller sees the same exchange with the roles swapped.
+    let mut conversation: Vec<ChatMessage> = Vec::new();
+    let setting = tree.setting();
+    let mut traveller = vec![ChatMessage::system(
+        options.traveller.system_prompt(&setting),
+    )];
+    let mut node_id = tree.start_node_id.clone();
+    let mut vars = Vars::new();
+
+    for turn in 0..options.max_turns {
+        let Some(mut node) = tree.get(&node_id) else {
+            bail!("scenario references missing node '{node_id}'");
+        };
+        // Random and interlude nodes are passed through; their lines lead
+        // into the line of the node picked for them.
+        let mut said = Vec::new();
+        for hop in 0.. {
+            let line = template::render(node.transcript.pick(lines), tree.persona.as_ref()).neutral;
+            let npc = tree
+                .speaker_name(node)
+                .unwrap_or_else(|| setting.npc_title());
+            println!("  [{}] {npc}: {line}", node.id);
+            said.push(line);
+            vars.extend(node.set.clone());
+            let open = node.resolve(&vars);
+            let next = match &open.node_type {
+                NodeType::Random(branches) => pick_weighted(branches, lines),
+                NodeType::Interlude(branches) => branches.first(),
+                NodeType::Terminal(_) | NodeType::Decision(_) => break,
+            };
+            if hop == MAX_RANDOM_HOPS {
+                bail!("scenario loops through scripted nodes at '{}'", node.id);
+            }
+            let next = next.with_context(|| format!("node '{}' has no branches", node.id))?;
+            node = tree
+                .get(&next.id)
+                .with_context(|| format!("scenario references missing node '{}'", next.id))?;
+        }
+        said.retain(|line| !line.is_empty());
+        let guard_line = match tree.speaker_name(node) {
+            Some(name) => format!("{name}: {}", said.join(" ")),
+            None => said.join(" "),
+        };
+        let node = &node.resolve(&vars);
+
+        let next_nodes = match &node.node_type {
+            NodeType::Terminal(success) => {
+                let ending = Ending::Terminal {
+                    node_id: node.id.clone(),
+                    success: *success,
+                };
+                return Ok((ending, turn));
+            }
+            NodeType::Decision(next_nodes) => next_nodes,
+            NodeType::Random(_) | NodeType::Interlude(_) => unreachable!("passed through above"),
+        };
+
+        conversation.push(ChatMessage::assistant(&guard_line));
+        traveller.push(ChatMessage::user(&guard_line));
+
+        let answer = strip_think(&model.chat(&traveller)?).trim().to_string();
+        println!("  [{}]: {answer}", setting.player);
+        conversation.push(ChatMessage::user(&answer));
+        traveller.push(ChatMessage::assistant(&answer));
+
+        let valid_choices: Vec<&str> = next_nodes.iter().map(|n| n.id.as_str()).collect();
+        let messages =
+            build_judge_messages(&conversation, node, &setting, JudgeInstruction::new())?;
+        let criteria: Vec<_> = next_nodes
+            .iter()
+            .map(|n| (n.id.clone(), n.description.clone()))
+            .collect();
+        let judged = match node.rule_for(&answer, &valid_choices) {
+            Some(rule) => Ok(rule.decision()),
+            None => classifier::judge_or_classify(model, &answer, &criteria, |model| {
+                model.judge_with(node.model.as_deref(), &messages, &valid_choices, |_| {})
+            }),
+        };
+        let decision = match judged {
+            Ok(d) => d,
+            Err(e) => {
+                Anomaly::new(
+                    "judge failure",
+                    node,
+                    &answer,
+                    &valid_choices,
+                    format!("{e:#}"),
+                )
+                .report(options.strict)?;
+                return Ok((Ending::JudgeError, turn + 1));
+            }
+        };
+        info!(
+            "Transition: {} -> {} (reason: {})",
+            node.id, decision.decision, decision.reason
+        );
+        if options.dataset.is_some() {
+            let label = model
+                .supervise(&messages, &valid_choices)
+                .inspect_err(|e| warn!("The referee could not label the answer: {e:#}"))
+                .ok()
+                .map(|referee| referee.decision);
+            labeled.push(Labeled {
+                node: node.id.clone(),
+                input: answer.clone(),
+                judged: decision.decision.clone(),
+                label,
+            });
+        }
+        node_id = if valid_choices.contains(&decision.decision.as_str()) {
+            decision.decision
+        } else {
+            Anomaly::new(
+                "fallback transition",
+                node,
+                &answer,
+                &valid_choices,
+                format!("judge chose '{}'", decision.decision),
+            )
+            .report(options.strict)?;
+            next_nodes[0].id.clone()
+        };
+    }
+
+    Ok((Ending::TurnLimit, options.max_turns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_traveller() {
+        assert_eq!("evasive".parse::<Traveller>().unwrap(), Traveller::Evasive);
+        assert!("grumpy".parse::<Traveller>().is_err());
+        assert_eq!(Traveller::Hostile.to_string(), "hostile");
+    }
+
+    #[test]
+    fn test_script_stops_where_the_referee_disagrees() {
+        let turn = |node: &str, judged: &str, label: Option<&str>| Labeled {
+            node: node.into(),
+            input: format!("at {node}"),
+            judged: judged.into(),
+            label: label.map(String::from),
+        };
+        let expect = |node: Option<&str>, expected: &str| EvalTurn {
+            node: node.map(String::from),
+            input: format!("at {}", node.unwrap_or("B")),
+            expected: expected.into(),
+        };
+        let game = [
+            turn("A", "B", Some("B")),
+            turn("B", "C", Some("FAILED")),
+            turn("C", "D", Some("D")),
+        ];
+        let Some(EvalEntry::Conversation { turns }) = script(&game) else {
+            panic!("expected a conversation");
+        };
+        assert_eq!(turns, [expect(Some("A"), "B"), expect(None, "FAILED")]);
+
+        assert!(script(&[turn("A", "B", None)]).is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::node::NodeType;
+use crate::game::tree::GameTree;
+use crate::game::GameOutcome;
+
+/// Default file the player's stats are kept in.
+pub const DEFAULT_STATS_PATH: &str = "elsa-stats.json";
+
+/// The player's record over all sessions, per scenario.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Keyed by the scenario path or name it was played from.
+    #[serde(default)]
+    pub scenarios: BTreeMap<String, ScenarioStats>,
+}
+
+/// Aggregates for one scenario.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioStats {
+    /// Rounds played to an ending or quit.
+    pub plays: u32,
+    /// Rounds that reached a success ending.
+    pub wins: u32,
+    /// Highest score of a finished round, in scenarios that keep score.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_score: Option<i32>,
+    /// Terminal node ids reached so far.
+    pub endings: BTreeSet<String>,
+}
+
+impl Stats {
+    /// Read the stats file; a missing file is an empty record.
+    pub fn read(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("invalid stats file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(format!("failed to read stats {}", path.display())),
+        }
+    }
+
+    /// Write the stats file through a temporary file, so an interrupted
+    /// write never loses the earlier record.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let partial = path.with_extension("json.part");
+        std::fs::write(&partial, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write stats {}", partial.display()))?;
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("failed to write stats {}", path.display()))
+    }
+}
+
+impl ScenarioStats {
+    /// Count a round.
+    pub fn record(&mut self, outcome: &GameOutcome) {
+        self.plays += 1;
+        if let GameOutcome::Finished {
+            success,
+            terminal_node_id,
+            score,
+            ..
+        } = outcome
+        {
+            if *success {
+                self.wins += 1;
+            }
+            if let Some(score) = *score {
+                self.best_score = Some(self.best_score.map_or(score, |best| best.max(score)));
+            }
+            self.endings.insert(terminal_node_id.clone());
+        }
+    }
+
+    /// Lines for the game over screen. Endings that are no longer in the
+    /// scenario are not counted.
+    pub fn summary(&self, tree: &GameTree) -> Vec<String> {
+        let endings: BTreeSet<&str> = tree
+            .nodes
+            .values()
+            .filter(|node| matches!(node.node_type, NodeType::Terminal(_)))
+            .map(|node| node.id.as_str())
+            .collect();
+        let found = self
+            .endings
+            .iter()
+            .filter(|id| endings.contains(id.as_str()))
+            .count();
+        let mut lines = vec![
+            format!("Endings: {found}/{} discovered", endings.len()),
+            format!("Record: {} cleared in {} plays", self.wins, self.plays),
+        ];
+        if let Some(best) = self.best_score {
+            lines.push(format!("Best:   {best} points"));
+        }
+        lines
+    }
+}
+
+/// Add a round of `scenario` to the stats file at `path` and return the
+/// scenario's updated record. The file is re-read first, so games played
+/// side by side don't overwrite each other's rounds.
+pub fn record(path: &Path, scenario: &str, outcome: &GameOutcome) -> Result<ScenarioStats> {
+    let mut stats = Stats::read(path)?;
+    let entry = stats.scenarios.entry(scenario.to_string()).or_default();
+    entry.record(outcome);
+    let entry = entry.clone();
+    stats.write(path)?;
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(success: bool, ending: &str, score: Option<i32>) -> GameOutcome {
+        GameOutcome::Finished {
+            success,
+            steps_completed: 1,
+            total_steps: 2,
+            terminal_node_id: ending.to_string(),
+            ending: ending.to_string(),
+            appealed: false,
+            checkpoint: None,
+            suspicion: None,
+            score,
+            grade: None,
+            headline: String::new(),
+            hints: 0,
+            stats: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_stats_accumulate_across_sessions() {
+        let path = std::env::temp_dir().join(format!("elsa-stats-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        record(&path, "airport", &finished(false, "FAILED", Some(2))).unwrap();
+        record(&path, "airport", &finished(true, "CLEARED", Some(7))).unwrap();
+        record(&path, "other", &finished(true, "CLEARED", None)).unwrap();
+        // An ending since removed from the scenario.
+        record(&path, "airport", &finished(false, "RETIRED", None)).unwrap();
+        let quit = GameOutcome::Quit {
+            steps_completed: 0,
+            headline: String::new(),
+        };
+        let airport = record(&path, "airport", &quit).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(airport.plays, 4);
+        assert_eq!(airport.wins, 1);
+        assert_eq!(airport.best_score, Some(7));
+        let tree = crate::game::tree::airport_security_scenario();
+        assert_eq!(
+            airport.summary(&tree),
+            [
+                "Endings: 2/2 discovered",
+                "Record: 1 cleared in 4 plays",
+                "Best:   7 points"
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use log::{debug, info};
+use serde::Serialize;
+use tiny_http::{Header, ReadWrite, Request, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+type Socket = WebSocket<Box<dyn ReadWrite + Send>>;
+
+/// Live event pushed to a session's WebSocket subscribers. Serialized as
+/// `{"type": "<snake_case variant>", ...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The judge started on the player's answer.
+    Thinking,
+    /// Raw judge output as it is generated.
+    JudgeToken { text: &'a str },
+    /// The judge picked the next node.
+    Decided { decision: &'a str, reason: &'a str },
+    /// The judge call failed; the guard repeats the question.
+    Retry,
+    /// Next piece of the guard's line, for live typing.
+    GuardDelta { node_id: &'a str, text: &'a str },
+    /// Game state after the event sequence, same shape as the REST API.
+    State { session: serde_json::Value },
+}
+
+/// Split a guard line into word-sized pieces (each keeps its trailing
+/// whitespace) so clients can type it out as it arrives.
+pub fn guard_deltas(line: &str) -> impl Iterator<Item = &str> {
+    line.split_inclusive(char::is_whitespace)
+}
+
+/// The `Sec-WebSocket-Key` of an upgrade request, if it is one.
+pub fn websocket_key(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn encode(event: &Event) -> Message {
+    Message::text(serde_json::to_string(event).expect("events serialize"))
+}
+
+/// Open WebSocket connections, by session id. Sockets are write-only from
+/// the server's side; a failed send drops the connection.
+#[derive(Default)]
+pub struct Streams {
+    sockets: HashMap<String, Vec<Socket>>,
+}
+
+impl Streams {
+    /// Complete the WebSocket handshake on `request` (whose key was read
+    /// with [`websocket_key`]), send it `greeting` and subscribe it to
+    /// `session_id`.
+    pub fn subscribe(&mut self, session_id: &str, request: Request, key: &str, greeting: &[Event]) {
+        let accept = Header::from_bytes(
+            &b"Sec-WebSocket-Accept"[..],
+            derive_accept_key(key.as_bytes()).as_bytes(),
+        )
+        .expect("accept key is a valid header value");
+        let stream = request.upgrade("websocket", Response::empty(101).with_header(accept));
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for event in greeting {
+            if let Err(e) = socket.send(encode(event)) {
+                debug!("Stream of session {session_id} closed during greeting: {e}");
+                return;
+            }
+        }
+
+        info!("Stream subscribed to session {session_id}");
+        self.sockets
+            .entry(session_id.to_string())
+            .or_default()
+            .push(socket);
+    }
+
+    pub fn has_subscribers(&self, session_id: &str) -> bool {
+        self.sockets.get(session_id).is_some_and(|s| !s.is_empty())
+    }
+
+    /// Send `event` to every subscriber of `session_id`.
+    pub fn emit(&mut self, session_id: &str, event: &Event) {
+        let Some(sockets) = self.sockets.get_mut(session_id) else {
+            return;
+        };
+        let message = encode(event);
+        sockets.retain_mut(|socket| match socket.send(message.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Dropping stream of session {session_id}: {e}");
+                false
+            }
+        });
+    }
+
+    /// Stream a guard line piece by piece.
+    pub fn emit_guard_line(&mut self, session_id: &str, node_id: &str, line: &str) {
+        if !self.has_subscribers(session_id) {
+            return;
+        }
+        for text in guard_deltas(line) {
+            self.emit(session_id, &Event::GuardDelta { node_id, text });
+        }
+    }
+
+    /// Close and forget every subscriber of `session_id`.
+    pub fn close(&mut self, session_id: &str) {
+        for mut socket in self.sockets.remove(session_id).unwrap_or_default() {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_shape() {
+        let event = Event::Decided {
+            decision: "B",
+            reason: "ok",
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"decided","decision":"B","reason":"ok"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::Thinking).unwrap(),
+            r#"{"type":"thinking"}"#
+        );
+    }
+
+    #[test]
+    fn test_guard_deltas_rebuild_the_line() {
+        let line = "Passport,  please. Where from?";
+        let deltas: Vec<&str> = guard_deltas(line).collect();
+        assert_eq!(deltas, ["Passport, ", " ", "please. ", "Where ", "from?"]);
+        assert_eq!(deltas.concat(), line);
+    }
+}
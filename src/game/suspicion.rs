@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::llm::{LlmDecision, Surprise};
+
+/// Key of the judge's reply that moves the suspicion meter.
+pub const DELTA: &str = "delta";
+
+/// Most the meter moves in one turn, either way.
+pub const MAX_DELTA: i32 = 2;
+
+/// A scenario's suspicion meter. It starts at 0; after every judged answer
+/// the judge raises or lowers it by up to [`MAX_DELTA`], and reaching a
+/// threshold sends the game to that threshold's node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suspicion {
+    pub thresholds: Vec<Threshold>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lie_detector: Option<LieDetector>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decay: Option<Decay>,
+}
+
+/// Game variable set once decay has lowered the meter, so that edges with
+/// `"when": "redeemed"` lead only players who talked their way back.
+pub const REDEEMED: &str = "redeemed";
+
+/// Calm answers wear the meter down: after `after` judged answers in a row
+/// that pass without raising it, it drops by `by`, never below 0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decay {
+    #[serde(default = "default_after")]
+    pub after: usize,
+    #[serde(default = "default_by")]
+    pub by: i32,
+}
+
+fn default_after() -> usize {
+    3
+}
+
+fn default_by() -> i32 {
+    1
+}
+
+impl Decay {
+    /// Counts one more judged answer into `calm`, the solid answers in a
+    /// row, and returns the meter after any decay.
+    pub fn apply(&self, calm: &mut usize, solid: bool, meter: i32) -> i32 {
+        *calm = if solid { *calm + 1 } else { 0 };
+        if *calm < self.after.max(1) {
+            return meter;
+        }
+        *calm = 0;
+        (meter - self.by).max(meter.min(0))
+    }
+}
+
+/// The guard's "lie detector": a stress reading of each answer, taken from
+/// how much more surprising the model finds its words than it expected to.
+/// Rehearsed, ordinary answers read calm; odd or evasive phrasing reads
+/// tense. A reading at `alarm` or above adds 1 to the meter, on top of the
+/// judge's own change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LieDetector {
+    /// Excess surprise per token, in nats, that reads as 0% stress.
+    #[serde(default)]
+    pub calm: f32,
+    /// Excess surprise per token that reads as 100% stress.
+    #[serde(default = "default_tense")]
+    pub tense: f32,
+    /// Reading, in percent, from which the meter goes up.
+    #[serde(default = "default_alarm")]
+    pub alarm: u8,
+}
+
+fn default_tense() -> f32 {
+    3.0
+}
+
+fn default_alarm() -> u8 {
+    80
+}
+
+impl LieDetector {
+    /// Stress reading of an answer, in percent.
+    pub fn reading(&self, surprise: &Surprise) -> u8 {
+        let excess = surprise.surprise - surprise.entropy;
+        let span = (self.tense - self.calm).max(f32::EPSILON);
+        (((excess - self.calm) / span).clamp(0.0, 1.0) * 100.0).round() as u8
+    }
+
+    /// What a reading adds to the meter.
+    pub fn bias(&self, reading: u8) -> i32 {
+        i32::from(reading >= self.alarm)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+    /// Meter reading that triggers the transition.
+    pub at: i32,
+    /// Node the game goes to, usually a failure ending.
+    pub node: String,
+}
+
+impl Suspicion {
+    /// The highest threshold the meter passed on its way from `before` to
+    /// `after`, if it went up through one.
+    pub fn crossed(&self, before: i32, after: i32) -> Option<&Threshold> {
+        self.thresholds
+            .iter()
+            .filter(|t| before < t.at && t.at <= after)
+            .max_by_key(|t| t.at)
+    }
+
+    /// The lowest threshold above `level`, to show the judge how close the
+    /// player is.
+    pub fn next_above(&self, level: i32) -> Option<&Threshold> {
+        self.thresholds
+            .iter()
+            .filter(|t| t.at > level)
+            .min_by_key(|t| t.at)
+    }
+}
+
+/// The meter change the judge asked for, clamped to [`MAX_DELTA`]. Missing
+/// or unreadable values count as 0.
+pub fn delta(decision: &LlmDecision) -> i32 {
+    let delta = decision.suspicion_delta.unwrap_or(0.0);
+    (delta.round() as i32).clamp(-MAX_DELTA, MAX_DELTA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thresholds_and_delta() {
+        let meter: Suspicion = serde_json::from_str(
+            r#"{"thresholds": [{"at": 3, "node": "SECONDARY"}, {"at": 5, "node": "DENIED"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(meter.crossed(1, 3).unwrap().node, "SECONDARY");
+        assert_eq!(meter.crossed(2, 6).unwrap().node, "DENIED");
+        assert!(meter.crossed(3, 4).is_none());
+        assert!(meter.crossed(4, 2).is_none());
+        assert_eq!(meter.next_above(3).unwrap().at, 5);
+        assert!(meter.lie_detector.is_none());
+
+        let detector: LieDetector = serde_json::from_str(r#"{"tense": 2.0}"#).unwrap();
+        let answer = |surprise, entropy| Surprise {
+            tokens: 5,
+            surprise,
+            entropy,
+        };
+        assert_eq!(detector.reading(&answer(2.0, 2.5)), 0);
+        assert_eq!(detector.reading(&answer(3.0, 2.0)), 50);
+        assert_eq!(detector.reading(&answer(6.0, 1.0)), 100);
+        assert_eq!(detector.bias(79), 0);
+        assert_eq!(detector.bias(80), 1);
+
+        let decision = |json: &str| -> LlmDecision { serde_json::from_str(json).unwrap() };
+        assert_eq!(delta(&decision(r#"{"decision": "A", "reason": "r"}"#)), 0);
+        assert_eq!(
+            delta(&decision(r#"{"decision": "A", "reason": "r", "delta": 1}"#)),
+            1
+        );
+        assert_eq!(
+            delta(&decision(
+                r#"{"decision": "A", "reason": "r", "delta": "-7"}"#
+            )),
+            -MAX_DELTA
+        );
+    }
+}
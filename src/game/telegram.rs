@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use crate::game::bot;
+use crate::game::locale::{fill, Messages};
+use crate::game::session::Session;
+use crate::game::setting::Setting;
+use crate::game::tree::GameTree;
+use crate::game::worker::{self, JudgeQueue};
+use crate::game::{prime_judge, GameOutcome};
+use crate::llm::LLM;
+use crate::rng::Rng;
+
+/// Environment variable holding the bot token.
+pub const TOKEN_VAR: &str = "TELEGRAM_TOKEN";
+
+const API: &str = "https://api.telegram.org";
+/// Seconds a `getUpdates` long poll waits for something to happen.
+const POLL_TIMEOUT: u64 = 30;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Chats with a game; `/start` in a new chat is refused beyond this.
+const MAX_CHATS: usize = 64;
+/// Judge calls that may wait for the model; more are asked to resend.
+const QUEUE_DEPTH: usize = 16;
+/// A typing indicator lasts about five seconds; while the judge keeps
+/// generating it is renewed this often.
+const TYPING_EVERY: Duration = Duration::from_secs(4);
+
+// Inline keyboard callbacks.
+const RESTART: &str = "restart";
+const QUIT: &str = "quit";
+const APPEAL: &str = "appeal";
+const ACCEPT: &str = "accept";
+
+pub struct TelegramOptions {
+    /// Seed for the games' transcript variants.
+    pub seed: u64,
+    pub strict: bool,
+    /// The bot's words, in the `--lang` language.
+    pub messages: &'static Messages,
+}
+
+/// Run the scenario as a Telegram bot until the token is rejected. Every
+/// chat plays its own game: `/start` begins one and each message is the
+/// player's answer. Choices (appeal or accept, restart or quit) come as
+/// inline keyboards. Games share the model through the judge queue, which
+/// runs on this thread; updates are polled on another.
+pub fn run(model: &mut LLM, tree: &GameTree, options: TelegramOptions) -> Result<()> {
+    let token =
+        std::env::var(TOKEN_VAR).with_context(|| format!("set {TOKEN_VAR} to the bot token"))?;
+    prime_judge(model, tree);
+    let (queue, jobs) = JudgeQueue::new(QUEUE_DEPTH);
+    let bot = Arc::new(Bot::new(tree.clone(), Api::new(&token), &options, queue));
+    let poller = thread::spawn(move || poll(&bot));
+    worker::run(jobs, |job, on_piece| job.run(model, on_piece));
+    poller
+        .join()
+        .map_err(|_| anyhow!("the polling thread panicked"))?
+}
+
+/// Long-poll for updates and hand them to `bot`. Network errors are
+/// retried; a rejected token ends the bot.
+fn poll(bot: &Arc<Bot>) -> Result<()> {
+    let me = bot.api.call("getMe", json!({}))?;
+    info!(
+        "Connected to Telegram as @{}",
+        me["username"].as_str().unwrap_or("?")
+    );
+    let mut offset = 0;
+    loop {
+        let updates = match bot.api.call(
+            "getUpdates",
+            json!({ "offset": offset, "timeout": POLL_TIMEOUT, "allowed_updates": ["message", "callback_query"] }),
+        ) {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("Polling failed: {e:#}");
+                thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        for update in updates.as_array().into_iter().flatten() {
+            if let Some(id) = update["update_id"].as_i64() {
+                offset = offset.max(id + 1);
+            }
+            bot.dispatch(update);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Bot API
+// ---------------------------------------------------------------------------
+
+struct Api {
+    /// `https://api.telegram.org/bot<token>`
+    base: String,
+}
+
+impl Api {
+    fn new(token: &str) -> Self {
+        Self {
+            base: format!("{API}/bot{token}"),
+        }
+    }
+
+    /// Call a Bot API method and return its `result`.
+    fn call(&self, method: &str, body: Value) -> Result<Value> {
+        let response: Value = match ureq::post(&format!("{}/{method}", self.base)).send_json(body) {
+            Ok(response) => response.into_json()?,
+            // Errors carry a JSON body with a description too.
+            Err(ureq::Error::Status(_, response)) => response.into_json()?,
+            Err(e) => return Err(e).with_context(|| format!("{method} failed")),
+        };
+        if response["ok"].as_bool() != Some(true) {
+            bail!(
+                "{method} failed: {}",
+                response["description"].as_str().unwrap_or("no description")
+            );
+        }
+        Ok(response["result"].clone())
+    }
+
+    fn say(&self, chat: i64, text: &str) -> Result<()> {
+        self.call("sendMessage", json!({ "chat_id": chat, "text": text }))?;
+        Ok(())
+    }
+
+    /// Send `text` with a row of inline buttons, given as (label, callback).
+    fn ask(&self, chat: i64, text: &str, buttons: &[(&str, &str)]) -> Result<()> {
+        let row: Vec<Value> = buttons
+            .iter()
+            .map(|(label, data)| json!({ "text": label, "callback_data": data }))
+            .collect();
+        self.call(
+            "sendMessage",
+            json!({ "chat_id": chat, "text": text, "reply_markup": { "inline_keyboard": [row] } }),
+        )?;
+        Ok(())
+    }
+
+    fn typing(&self, chat: i64) {
+        if let Err(e) = self.call(
+            "sendChatAction",
+            json!({ "chat_id": chat, "action": "typing" }),
+        ) {
+            debug!("Typing indicator failed: {e:#}");
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Bot
+// ---------------------------------------------------------------------------
+
+/// Shared by the polling thread and the games' threads. Each game judges
+/// its chat's messages in order on its own thread while other chats
+/// proceed.
+struct Bot {
+    tree: GameTree,
+    setting: Setting,
+    messages: &'static Messages,
+    api: Api,
+    strict: bool,
+    seeds: Mutex<Rng>,
+    queue: JudgeQueue,
+    /// Games by chat id. A finished game stays until the chat restarts or
+    /// quits.
+    games: Mutex<HashMap<i64, bot::Game>>,
+}
+
+impl Bot {
+    fn new(tree: GameTree, api: Api, options: &TelegramOptions, queue: JudgeQueue) -> Self {
+        Self {
+            setting: tree.setting(),
+            messages: options.messages,
+            tree,
+            api,
+            strict: options.strict,
+            seeds: Mutex::new(Rng::new(options.seed)),
+            queue,
+            games: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn game(&self, chat: i64) -> Option<Arc<Mutex<Session>>> {
+        self.games
+            .lock()
+            .unwrap()
+            .get(&chat)
+            .map(bot::Game::session)
+    }
+
+    /// Handle an update. Failures are logged; they never stop polling.
+    fn dispatch(self: &Arc<Self>, update: &Value) {
+        let result = if let Some(query) = update.get("callback_query") {
+            self.button(query)
+        } else if let Some(message) = update.get("message") {
+            self.message(message)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            warn!("Failed to handle update: {e:#}");
+        }
+    }
+
+    fn message(self: &Arc<Self>, message: &Value) -> Result<()> {
+        let chat = message["chat"]["id"]
+            .as_i64()
+            .context("message without a chat")?;
+        let Some(text) = message["text"].as_str() else {
+            return Ok(());
+        };
+        // Commands may be addressed as /start@botname in groups.
+        match text.split(['@', ' ']).next().unwrap_or("") {
+            "/start" => self.start(chat),
+            "/quit" => self.quit(chat),
+            _ => {
+                if let Some(game) = self.games.lock().unwrap().get(&chat) {
+                    game.answer(text);
+                    return Ok(());
+                }
+                self.api.say(chat, self.messages.send_start)
+            }
+        }
+    }
+
+    fn button(self: &Arc<Self>, query: &Value) -> Result<()> {
+        let id = query["id"].as_str().context("callback without an id")?;
+        // Stop the client's spinner on the button.
+        self.api
+            .call("answerCallbackQuery", json!({ "callback_query_id": id }))?;
+        let message = &query["message"];
+        let chat = message["chat"]["id"]
+            .as_i64()
+            .context("callback without a chat")?;
+        // Each keyboard is answered once.
+        self.api.call(
+            "editMessageReplyMarkup",
+            json!({ "chat_id": chat, "message_id": message["message_id"], "reply_markup": { "inline_keyboard": [] } }),
+        )?;
+        match query["data"].as_str() {
+            Some(RESTART) => self.start(chat),
+            Some(QUIT | ACCEPT) => self.quit(chat),
+            Some(APPEAL) => {
+                let Some(session) = self.game(chat) else {
+                    return Ok(());
+                };
+                let line = session.try_lock().ok().and_then(|s| s.appeal_line());
+                match line {
+                    Some(line) => self.api.say(
+                        chat,
+                        &format!(
+                            "{}: {}\n\n{}",
+                            self.messages.supervisor, line.display, self.messages.plead
+                        ),
+                    ),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Start (or restart) the chat's game. A game being judged is not
+    /// restarted, so no answer to it comes after the new one begins.
+    fn start(self: &Arc<Self>, chat: i64) -> Result<()> {
+        let mut games = self.games.lock().unwrap();
+        if let Some(game) = games.get(&chat) {
+            let session = game.session();
+            // The polling thread must not wait for the judge.
+            let Ok(_idle) = session.try_lock() else {
+                drop(games);
+                return self.api.say(chat, &self.wait_for_answer());
+            };
+            game.end();
+        } else if games.len() >= MAX_CHATS {
+            drop(games);
+            return self.api.say(chat, self.messages.too_many_games);
+        }
+        let seed = self.seeds.lock().unwrap().next_u64();
+        let session = Session::new(self.tree.clone(), None, self.strict, seed);
+        let line = self.guard_line(&session);
+        let bot = Arc::clone(self);
+        let game = bot::Game::new(session, move |session, text| {
+            if let Err(e) = bot.answer(chat, session, text) {
+                warn!("Failed to answer in chat {chat}: {e:#}");
+            }
+        });
+        games.insert(chat, game);
+        drop(games);
+        info!("Game started in chat {chat}");
+        self.api.say(chat, &line)
+    }
+
+    fn quit(&self, chat: i64) -> Result<()> {
+        let Some(session) = self.game(chat) else {
+            return self.api.say(chat, self.messages.send_start);
+        };
+        // The polling thread must not wait for the judge.
+        let Ok(session) = session.try_lock() else {
+            return self.api.say(chat, &self.wait_for_answer());
+        };
+        // At an appeal, quitting accepts the decision.
+        let outcome = session.outcome().unwrap_or(GameOutcome::Quit {
+            steps_completed: session.steps_completed(),
+            headline: self.setting.walked_away.clone(),
+        });
+        drop(session);
+        self.games.lock().unwrap().remove(&chat);
+        info!("Game in chat {chat} ended");
+        self.api.say(chat, &game_over_text(self.messages, &outcome))
+    }
+
+    fn answer(&self, chat: i64, session: &mut Session, text: &str) -> Result<()> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        let m = self.messages;
+        if session.outcome().is_some() && session.pending_appeal().is_none() {
+            return self.api.ask(
+                chat,
+                m.game_is_over,
+                &[(m.restart, RESTART), (m.quit, QUIT)],
+            );
+        }
+
+        let request = session.ask(text)?;
+        self.api.typing(chat);
+        let mut typed = Instant::now();
+        let reply = session.settle(request, &mut self.queue.clone(), &mut |_| {
+            if typed.elapsed() >= TYPING_EVERY {
+                self.api.typing(chat);
+                typed = Instant::now();
+            }
+        });
+        let reply = match reply {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return self.api.say(chat, m.busy),
+            Err(e) => {
+                // Strict mode: the game cannot continue reliably.
+                self.games.lock().unwrap().remove(&chat);
+                return self
+                    .api
+                    .say(chat, &fill(m.game_broke, &[("error", &format!("{e:#}"))]));
+            }
+        };
+        let guard_line = self.guard_line(session);
+        let lines = bot::reply_lines(&reply, m, &self.setting, &guard_line, str::to_string);
+
+        let text = lines.join("\n\n");
+        if session.pending_appeal().is_some() {
+            self.api
+                .ask(chat, &text, &[(m.appeal, APPEAL), (m.accept, ACCEPT)])
+        } else if let Some(outcome) = session.outcome() {
+            self.api.say(chat, &text)?;
+            self.api.ask(
+                chat,
+                &game_over_text(m, &outcome),
+                &[(m.restart, RESTART), (m.quit, QUIT)],
+            )
+        } else {
+            self.api.say(chat, &text)
+        }
+    }
+
+    fn wait_for_answer(&self) -> String {
+        fill(self.messages.wait_for_answer, &[("npc", &self.setting.npc)])
+    }
+
+    fn guard_line(&self, session: &Session) -> String {
+        format!(
+            "{}: {}",
+            session
+                .npc_name()
+                .unwrap_or_else(|| self.setting.npc_title()),
+            session.guard_line().display
+        )
+    }
+}
+
+fn game_over_text(m: &Messages, outcome: &GameOutcome) -> String {
+    let mut text = m.game_over.to_string();
+    for line in outcome.summary(m) {
+        text.push_str(&format!("\n{line}"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::locale;
+
+    #[test]
+    fn test_game_over_text() {
+        let outcome = GameOutcome::Quit {
+            steps_completed: 0,
+            headline: Setting::default().walked_away,
+        };
+        assert_eq!(
+            game_over_text(&locale::ENGLISH, &outcome),
+            "GAME OVER\nYou walked away from the border control booth."
+        );
+    }
+}
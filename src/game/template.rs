@@ -0,0 +1,167 @@
+use log::warn;
+use regex::{Captures, Regex};
+
+use crate::game::persona::Persona;
+
+/// A transcript rendered for the two audiences that consume it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendered {
+    /// What the player sees: second-language phrases followed by their
+    /// translation in parentheses.
+    pub display: String,
+    /// What the judge sees: translations only, so the judged conversation
+    /// stays in a single language.
+    pub neutral: String,
+}
+
+/// Expand `{{key}}` placeholders in a transcript using the persona's
+/// second-language phrases. Unknown keys are left untouched.
+pub fn render(transcript: &str, persona: Option<&Persona>) -> Rendered {
+    let Some(lang) = persona.and_then(|p| p.second_language.as_ref()) else {
+        return Rendered {
+            display: transcript.to_string(),
+            neutral: transcript.to_string(),
+        };
+    };
+
+    let re = Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap();
+    let expand = |neutral: bool| {
+        re.replace_all(transcript, |caps: &Captures| {
+            let key = &caps[1];
+            match lang.phrases.get(key) {
+                Some(p) if neutral => p.translation.clone(),
+                Some(p) => format!("{} ({})", p.text, p.translation),
+                None => {
+                    warn!("Unknown {} phrase '{key}' in transcript", lang.language);
+                    caps[0].to_string()
+                }
+            }
+        })
+        .into_owned()
+    };
+
+    Rendered {
+        display: expand(false),
+        neutral: expand(true),
+    }
+}
+
+/// A piece of a guard line: words said aloud, or a stage direction
+/// written between asterisks (`*stamps passport*`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Span<'a> {
+    Speech(&'a str),
+    Action(&'a str),
+}
+
+/// Split a line into speech and stage directions, for front ends that show
+/// or voice them differently. As in Markdown, a direction's asterisks hug
+/// its text (`*nods*`, not `* nods *`); any other asterisk is speech.
+pub fn spans(text: &str) -> Vec<Span<'_>> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    let mut from = 0;
+    while let Some(open) = rest[from..].find('*').map(|i| from + i) {
+        from = open + 1;
+        let Some(close) = rest[from..].find('*').map(|i| from + i) else {
+            break;
+        };
+        let action = &rest[from..close];
+        if action.is_empty() || action.trim() != action {
+            continue;
+        }
+        if !rest[..open].trim().is_empty() {
+            spans.push(Span::Speech(rest[..open].trim()));
+        }
+        spans.push(Span::Action(action));
+        rest = &rest[close + 1..];
+        from = 0;
+    }
+    if !rest.trim().is_empty() {
+        spans.push(Span::Speech(rest.trim()));
+    }
+    spans
+}
+
+/// Return the second-language phrases that leaked into content meant to be
+/// judged. Authors should reference phrases through placeholders instead of
+/// writing them inline.
+pub fn foreign_phrases<'a>(content: &str, persona: Option<&'a Persona>) -> Vec<&'a str> {
+    let Some(lang) = persona.and_then(|p| p.second_language.as_ref()) else {
+        return Vec::new();
+    };
+    let lowered = content.to_lowercase();
+    lang.phrases
+        .values()
+        .map(|p| p.text.as_str())
+        .filter(|text| lowered.contains(&text.to_lowercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::game::persona::{Phrase, SecondLanguage};
+
+    fn german() -> Persona {
+        Persona {
+            name: None,
+            temperament: None,
+            quirks: Vec::new(),
+            style: None,
+            second_language: Some(SecondLanguage {
+                language: "German".into(),
+                phrases: HashMap::from([(
+                    "hello".to_string(),
+                    Phrase {
+                        text: "Guten Tag".into(),
+                        translation: "Good day".into(),
+                    },
+                )]),
+            }),
+            shifts: Vec::new(),
+            callbacks: false,
+        }
+    }
+
+    #[test]
+    fn test_render_code_switch() {
+        let r = render("{{hello}}. Passport please.", Some(&german()));
+        assert_eq!(r.display, "Guten Tag (Good day). Passport please.");
+        assert_eq!(r.neutral, "Good day. Passport please.");
+    }
+
+    #[test]
+    fn test_render_without_persona_is_identity() {
+        let r = render("{{hello}}. Passport please.", None);
+        assert_eq!(r.display, r.neutral);
+    }
+
+    #[test]
+    fn test_stage_directions() {
+        assert_eq!(
+            spans("*stamps passport* Welcome. *waves you through*"),
+            [
+                Span::Action("stamps passport"),
+                Span::Speech("Welcome."),
+                Span::Action("waves you through")
+            ]
+        );
+        assert_eq!(
+            spans("Rated 5* out of ** stars"),
+            [Span::Speech("Rated 5* out of ** stars")]
+        );
+    }
+
+    #[test]
+    fn test_foreign_phrases_detected() {
+        let persona = german();
+        assert!(foreign_phrases("Good day.", Some(&persona)).is_empty());
+        assert_eq!(
+            foreign_phrases("guten tag, sir", Some(&persona)),
+            vec!["Guten Tag"]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::report_card::CardRow;
+use crate::game::tree::GameTree;
+use crate::persistence::{Journal, RetentionPolicy};
+
+// ---------------------------------------------------------------------------
+// Log entries
+// ---------------------------------------------------------------------------
+
+/// One line of a session transcript (`session-<id>.jsonl`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogEntry {
+    SessionStart {
+        session_id: String,
+        /// Unix timestamp (seconds).
+        started_at: u64,
+        scenario_path: Option<String>,
+        /// [`GameTree::content_hash`] of the scenario played. Missing in
+        /// transcripts written before it was recorded.
+        #[serde(default)]
+        scenario_hash: Option<String>,
+        #[serde(default)]
+        scenario_version: Option<String>,
+        seed: u64,
+    },
+    Turn(TurnRecord),
+    GameOver {
+        round: usize,
+        /// `None` when the player quit mid-game.
+        terminal_node_id: Option<String>,
+        success: Option<bool>,
+        steps_completed: usize,
+    },
+    /// What went well and what raised flags in the round just over.
+    ReportCard {
+        round: usize,
+        rows: Vec<CardRow>,
+    },
+}
+
+/// A single judged exchange.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurnRecord {
+    pub round: usize,
+    pub turn: usize,
+    pub node_id: String,
+    pub guard_line: String,
+    pub player_input: String,
+    pub decision: String,
+    pub reason: String,
+    pub latency_ms: u64,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+// ---------------------------------------------------------------------------
+// Writer
+// ---------------------------------------------------------------------------
+
+/// Appends [`LogEntry`] lines to a per-session file under the log directory.
+pub struct TranscriptLog {
+    journal: Journal,
+    round: usize,
+    turn: usize,
+}
+
+impl TranscriptLog {
+    pub fn create(
+        log_dir: &Path,
+        retention: RetentionPolicy,
+        scenario_path: Option<&str>,
+        tree: &GameTree,
+        seed: u64,
+    ) -> Result<Self> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let session_id = format!("{started_at}-{:08x}", seed as u32);
+        let path = log_dir.join(format!("session-{session_id}.jsonl"));
+
+        let mut log = Self {
+            journal: Journal::new(path, retention),
+            round: 0,
+            turn: 0,
+        };
+        log.write(&LogEntry::SessionStart {
+            session_id,
+            started_at,
+            scenario_path: scenario_path.map(String::from),
+            scenario_hash: Some(tree.content_hash()),
+            scenario_version: tree.version.clone(),
+            seed,
+        })?;
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.journal.path()
+    }
+
+    /// Mark the start of a new round; turn numbers restart at 1.
+    pub fn start_round(&mut self) {
+        self.round += 1;
+        self.turn = 0;
+    }
+
+    /// Record a judged exchange. `round` and `turn` are filled in here.
+    pub fn turn(&mut self, mut record: TurnRecord) -> Result<()> {
+        self.turn += 1;
+        record.round = self.round;
+        record.turn = self.turn;
+        self.write(&LogEntry::Turn(record))
+    }
+
+    pub fn game_over(
+        &mut self,
+        terminal_node_id: Option<String>,
+        success: Option<bool>,
+        steps_completed: usize,
+    ) -> Result<()> {
+        self.write(&LogEntry::GameOver {
+            round: self.round,
+            terminal_node_id,
+            success,
+            steps_completed,
+        })
+    }
+
+    pub fn report_card(&mut self, rows: &[CardRow]) -> Result<()> {
+        self.write(&LogEntry::ReportCard {
+            round: self.round,
+            rows: rows.to_vec(),
+        })
+    }
+
+    fn write(&mut self, entry: &LogEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        self.journal
+            .append_line(&line)
+            .context("failed to write session transcript")
+    }
+}
+
+/// Read every entry of a session transcript.
+pub fn read_log(path: &Path) -> Result<Vec<LogEntry>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: malformed entry", path.display(), i + 1))
+        })
+        .collect()
+}
@@ -1,14 +1,118 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::game::node::{GameNode, NextNode, NodeType};
+use crate::game::node::{GameNode, NextNode, NodeType, RESERVED};
+use crate::game::permissions::Permissions;
+use crate::game::persona::{Persona, Speaker};
+use crate::game::plan::Plan;
+use crate::game::setting::Setting;
+use crate::game::suspicion::Suspicion;
+use crate::game::vars::Vars;
 
 /// The full scenario tree: a map of node-id -> GameNode.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GameTree {
     pub nodes: HashMap<String, GameNode>,
     pub start_node_id: String,
+    /// Optional guard persona shared by every node of the scenario.
+    #[serde(default)]
+    pub persona: Option<Persona>,
+    /// Author-maintained scenario version (e.g. "1.2.0").
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Old node id -> new node id, used to migrate saves made against an
+    /// earlier revision of the scenario.
+    #[serde(default)]
+    pub node_aliases: HashMap<String, String>,
+    /// Who the judge and the player are; defaults to airport border control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setting: Option<Setting>,
+    /// Optional suspicion meter with thresholds that end the game early.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspicion: Option<Suspicion>,
+    /// Optional lives: failures short of the last one are let off with a
+    /// warning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikes: Option<Strikes>,
+    /// Other scenario files merged into this one when it is loaded (see
+    /// [`crate::game::scenarios::load`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Include>,
+    /// Points each `/hint` costs; 0 makes hints free.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub hint_penalty: i32,
+    /// Lets the judge answer [`crate::game::node::UNDECIDABLE`] instead of
+    /// guessing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undecidable: Option<Undecidable>,
+    /// Topics asked in whatever order is left open, instead of a fixed
+    /// order of nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Plan>,
+    /// Characters other than the guard, by the key nodes name them with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub speakers: BTreeMap<String, Speaker>,
+    /// Regulation sections the judge can look up mid-judgment (see
+    /// [`crate::game::node::LOOKUP`]), by section name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub regulations: BTreeMap<String, String>,
+    /// Features the scenario asks the player to allow.
+    #[serde(default, skip_serializing_if = "Permissions::is_empty")]
+    pub permissions: Permissions,
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+/// Another scenario file whose nodes become part of this one, e.g. a
+/// secondary screening shared by several scenarios. Its node ids are
+/// namespaced as `prefix/id`, so `SCREENING/START` is the start node of an
+/// include with prefix `SCREENING`. Edges to ids the file does not define
+/// are left as they are and lead back into the including scenario.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Include {
+    /// Path of the file, relative to the including one.
+    pub path: String,
+    pub prefix: String,
+}
+
+/// Three-strikes mode. An answer that would end the game in failure sends
+/// the player to the `warning` node instead, whose line is said before the
+/// question is asked again. The `max_failures`th failure ends the game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Strikes {
+    pub max_failures: usize,
+    /// Node whose line is the warning. Its edges are not used: the game
+    /// always goes back to the question that was failed.
+    pub warning: String,
+}
+
+/// What happens when the judge cannot decide. It is asked again up to
+/// `reasks` times, told its own reason and to look at the answer once
+/// more; if it still cannot decide, `fallback` applies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Undecidable {
+    #[serde(default = "default_reasks")]
+    pub reasks: usize,
+    #[serde(default)]
+    pub fallback: UndecidableFallback,
+}
+
+fn default_reasks() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UndecidableFallback {
+    /// The guard asks the player to say it again.
+    #[default]
+    Ask,
+    /// Take the node's first option, as on an invalid decision.
+    First,
 }
 
 impl GameTree {
@@ -16,33 +120,204 @@ impl GameTree {
         self.nodes.get(id)
     }
 
+    pub fn setting(&self) -> Setting {
+        self.setting.clone().unwrap_or_default()
+    }
+
+    /// What players are shown for the node `id`: its `display_name`, or
+    /// the id in words ("Failed contraband" for `FAILED_CONTRABAND`).
+    pub fn display_name(&self, id: &str) -> String {
+        if let Some(name) = self.get(id).and_then(|node| node.display_name.clone()) {
+            return name;
+        }
+        let words = id.replace(['_', '/'], " ").to_lowercase();
+        let mut chars = words.trim().chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+
+    /// Switch every node's text to `lang`. Returns how many nodes had it.
+    pub fn localize(&mut self, lang: &str) -> usize {
+        self.nodes
+            .values_mut()
+            .map(|node| node.localize(lang))
+            .filter(|&had| had)
+            .count()
+    }
+
+    /// Languages any node has text in, e.g. "de".
+    pub fn languages(&self) -> BTreeSet<&str> {
+        self.nodes
+            .values()
+            .flat_map(|node| node.locales.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// The character who says `node`'s line, if the node names one of the
+    /// scenario's speakers.
+    pub fn speaker(&self, node: &GameNode) -> Option<&Speaker> {
+        self.speakers.get(node.speaker.as_deref()?)
+    }
+
+    /// Who says `node`'s line, to attribute it: its speaker's name, or the
+    /// npc's title. `None` in scenarios without speakers, where every line
+    /// is the guard's.
+    pub fn speaker_name(&self, node: &GameNode) -> Option<String> {
+        if self.speakers.is_empty() {
+            return None;
+        }
+        Some(match self.speaker(node) {
+            Some(speaker) => speaker.name.clone(),
+            None => self.setting().npc_title(),
+        })
+    }
+
+    /// The persona of whoever says `node`'s line.
+    pub fn persona_at(&self, node: &GameNode) -> Option<&Persona> {
+        match self.speaker(node) {
+            Some(speaker) => speaker.persona.as_ref(),
+            None => self.persona.as_ref(),
+        }
+    }
+
+    /// Whether the scenario keeps score: some node gives points or grades,
+    /// or hints cost points.
+    pub fn has_points(&self) -> bool {
+        self.hint_penalty != 0
+            || self
+                .nodes
+                .values()
+                .any(|node| node.points.is_some() || !node.rank.is_empty())
+    }
+
+    /// Stable hash of the scenario content. Keys are serialized in sorted
+    /// order so the hash does not depend on `HashMap` iteration order.
+    pub fn content_hash(&self) -> String {
+        let canonical = serde_json::to_value(self)
+            .and_then(|v| serde_json::to_string(&v))
+            .expect("GameTree always serializes");
+        // FNV-1a, 64 bit.
+        let hash = canonical.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{hash:016x}")
+    }
+
+    /// Add the nodes of an included scenario under `prefix/` (see
+    /// [`Include`]). Only its nodes are taken: its start node, persona and
+    /// other settings are those of this scenario.
+    pub fn merge(&mut self, prefix: &str, other: GameTree) -> Result<()> {
+        let own: HashSet<String> = other.nodes.keys().cloned().collect();
+        let namespace = |id: &mut String| {
+            if own.contains(id.as_str()) {
+                *id = format!("{prefix}/{id}");
+            }
+        };
+        for mut node in other.nodes.into_values() {
+            namespace(&mut node.id);
+            node.targets_mut().for_each(namespace);
+            if self.nodes.contains_key(&node.id) {
+                bail!("node {} is defined twice", node.id);
+            }
+            self.nodes.insert(node.id.clone(), node);
+        }
+        Ok(())
+    }
+
+    /// Add the node of each topic of the plan, if there is one. A node of
+    /// the same id must be that node already, as in a scenario saved after
+    /// it was loaded.
+    pub fn expand_plan(&mut self) -> Result<()> {
+        let Some(plan) = &self.plan else {
+            return Ok(());
+        };
+        for node in plan.nodes() {
+            if RESERVED.contains(&node.id.as_str()) {
+                bail!("topic id {} is reserved for the judge's decisions", node.id);
+            }
+            match self.nodes.get(&node.id) {
+                Some(existing) if *existing != node => {
+                    bail!("topic {} has the id of a node", node.id)
+                }
+                _ => {
+                    self.nodes.insert(node.id.clone(), node);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Human-readable scenario revision: version (if any) and content hash.
+    pub fn revision(&self) -> String {
+        describe_revision(self.version.as_deref(), &self.content_hash())
+    }
+
+    /// Resolve a node id from an older revision of this scenario.
+    pub fn resolve_node_id<'a>(&'a self, id: &'a str) -> Option<&'a str> {
+        if self.nodes.contains_key(id) {
+            return Some(id);
+        }
+        self.node_aliases
+            .get(id)
+            .map(String::as_str)
+            .filter(|alias| self.nodes.contains_key(*alias))
+    }
+
     /// Count the number of non-terminal nodes on the longest path through
     /// the tree (i.e. the maximum possible steps a player can complete).
     pub fn total_steps(&self) -> usize {
-        self.longest_path(&self.start_node_id)
+        self.longest_path(&self.start_node_id, &mut Vec::new())
     }
 
-    fn longest_path(&self, node_id: &str) -> usize {
+    /// Edges back to a node already on the path (e.g. a node that asks
+    /// again until the player clarifies) add no steps.
+    fn longest_path<'a>(&'a self, node_id: &'a str, path: &mut Vec<&'a str>) -> usize {
         let node = match self.nodes.get(node_id) {
-            Some(n) => n,
-            None => return 0,
+            Some(n) if !path.contains(&node_id) => n,
+            _ => return 0,
         };
-        match node.node_type.clone() {
-            NodeType::Terminal(_) => {
-                return 0;
-            }
+        path.push(node_id);
+        let steps = self.steps_from(node, path);
+        path.pop();
+        steps
+    }
+
+    fn steps_from<'a>(&'a self, node: &'a GameNode, path: &mut Vec<&'a str>) -> usize {
+        match &node.node_type {
+            NodeType::Terminal(_) => 0,
             NodeType::Decision(next_node_ids) => {
                 let max_child = next_node_ids
                     .iter()
-                    .map(|next_node| self.longest_path(next_node.id.as_str()))
+                    .map(|next_node| self.longest_path(next_node.id.as_str(), path))
                     .max()
                     .unwrap_or(0);
                 1 + max_child
             }
+            // Not steps: the player does nothing here.
+            NodeType::Random(branches) => branches
+                .iter()
+                .map(|(next_node, _)| self.longest_path(next_node.id.as_str(), path))
+                .max()
+                .unwrap_or(0),
+            NodeType::Interlude(branches) => branches
+                .iter()
+                .map(|next_node| self.longest_path(next_node.id.as_str(), path))
+                .max()
+                .unwrap_or(0),
         }
     }
 }
 
+/// Describe a recorded scenario revision, e.g. `v1.2.0 (9f0c...)`.
+pub fn describe_revision(version: Option<&str>, hash: &str) -> String {
+    match version {
+        Some(v) => format!("v{v} ({hash})"),
+        None => hash.to_string(),
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Airport security scenario
 // ---------------------------------------------------------------------------
@@ -59,13 +334,32 @@ pub fn airport_security_scenario() -> GameTree {
                 NextNode {
                     id: "PASSPORT_CHECK".into(),
                     description: "User proceeds to get his passport checked".into(),
+                    when: None,
                 },
                 NextNode {
                     id: "FAILED".into(),
                     description: "User failed the border inspection".into(),
+                    when: None,
                 },
             ]),
             system_context: None,
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
         GameNode {
             id: "PASSPORT_CHECK".into(),
@@ -76,10 +370,12 @@ pub fn airport_security_scenario() -> GameTree {
                     id: "QUESTION_PURPOSE".into(),
                     description: "User answered appropriately. Proceeding with the questioning."
                         .into(),
+                    when: None,
                 },
                 NextNode {
                     id: "FAILED".into(),
                     description: "User failed the questioning.".into(),
+                    when: None,
                 },
             ]),
             system_context: Some(
@@ -90,6 +386,23 @@ pub fn airport_security_scenario() -> GameTree {
                 "
                 .into(),
             ),
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
         GameNode {
             id: "QUESTION_PURPOSE".into(),
@@ -98,13 +411,32 @@ pub fn airport_security_scenario() -> GameTree {
                 NextNode {
                     id: "LUGGAGE_CHECK".into(),
                     description: "User answered appropriately. Proceed to luggage check.".into(),
+                    when: None,
                 },
                 NextNode {
                     id: "FAILED".into(),
                     description: "User failed the questioning.".into(),
+                    when: None,
                 },
             ]),
             system_context: None,
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
         GameNode {
             id: "LUGGAGE_CHECK".into(),
@@ -113,14 +445,33 @@ pub fn airport_security_scenario() -> GameTree {
                 NextNode {
                     id: "CLEARED".into(),
                     description: "traveller says nothing to declare or lists normal items.".into(),
+                    when: None,
                 },
                 NextNode {
                     id: "FAILED_CONTRABAND".into(),
                     description:
                         "traveller mentions illegal items, acts nervous, or is suspicious.".into(),
+                    when: None,
                 },
             ]),
             system_context: Some("The guard asked about declarations.\n".into()),
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
         // --- Terminal: success ---
         GameNode {
@@ -128,6 +479,23 @@ pub fn airport_security_scenario() -> GameTree {
             transcript: "Everything checks out. Welcome, and enjoy your stay!".into(),
             node_type: NodeType::Terminal(true),
             system_context: None,
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
         // --- Terminal: failures ---
         GameNode {
@@ -135,6 +503,23 @@ pub fn airport_security_scenario() -> GameTree {
             transcript: "I'm going to have to ask you to step aside. Security!".into(),
             node_type: NodeType::Terminal(false),
             system_context: None,
+            appeal: None,
+            repeats: None,
+            set: Vars::new(),
+            extract: BTreeMap::new(),
+            contradiction: None,
+            model: None,
+            clarify: None,
+            min_turns: None,
+            max_turns: None,
+            checkpoint: false,
+            points: None,
+            rank: Vec::new(),
+            difficulty: BTreeMap::new(),
+            speaker: None,
+            display_name: None,
+            locales: BTreeMap::new(),
+            rules: Vec::new(),
         },
     ];
 
@@ -146,5 +531,18 @@ pub fn airport_security_scenario() -> GameTree {
     GameTree {
         nodes: map,
         start_node_id: "START".into(),
+        persona: None,
+        version: None,
+        node_aliases: HashMap::new(),
+        setting: None,
+        suspicion: None,
+        strikes: None,
+        include: Vec::new(),
+        hint_penalty: 0,
+        undecidable: None,
+        plan: None,
+        speakers: BTreeMap::new(),
+        regulations: BTreeMap::new(),
+        permissions: Permissions::default(),
     }
 }
@@ -0,0 +1,523 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::game::ambient::{Ambient, SHOW_EVERY};
+use crate::game::beats::Beats;
+use crate::game::keymap::{Key, Keymap};
+use crate::game::locale::Messages;
+use crate::game::pacing::{self, PacingConfig};
+use crate::game::template;
+use crate::game::ui::Ui;
+use crate::game::GameOutcome;
+use crate::llm::Usage;
+use crate::rng::Rng;
+#[cfg(feature = "voice")]
+use crate::voice::{Speech, Voice};
+
+const TICK: Duration = Duration::from_millis(100);
+const SIDEBAR_WIDTH: u16 = 28;
+
+#[derive(Debug, Clone, Copy)]
+enum Speaker {
+    Guard,
+    Player,
+    Notice,
+    Ambient,
+    /// The result on the game over screen: cleared or not.
+    Result(bool),
+}
+
+/// Everything that is drawn. Kept apart from the terminal so it can be
+/// rendered from the waiting thread.
+#[derive(Default)]
+struct Screen {
+    lines: Vec<(Option<Speaker>, String)>,
+    input: String,
+    /// Lines scrolled up from the bottom of the conversation.
+    scroll: u16,
+    steps_completed: usize,
+    total_steps: usize,
+    node_id: String,
+    /// Shown in the sidebar when debug logging is on.
+    debug: bool,
+    status: String,
+    /// Spinner frame while the judge runs.
+    thinking: Option<char>,
+    keys: Keymap,
+    messages: &'static Messages,
+    hide_sidebar: bool,
+}
+
+/// A guard line with its stage directions in grey italics.
+fn guard_spans(text: &str) -> Vec<Span<'_>> {
+    let mut spans = Vec::new();
+    for (i, part) in template::spans(text).into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(match part {
+            template::Span::Speech(words) => Span::raw(words),
+            template::Span::Action(action) => Span::styled(
+                action,
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            ),
+        });
+    }
+    spans
+}
+
+impl Screen {
+    fn push(&mut self, speaker: Speaker, text: &str) {
+        self.lines.push((Some(speaker), text.to_string()));
+        self.lines.push((None, String::new()));
+        self.scroll = 0;
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, input, status] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let sidebar_width = if self.hide_sidebar { 0 } else { SIDEBAR_WIDTH };
+        let [conversation, sidebar] =
+            Layout::horizontal([Constraint::Min(20), Constraint::Length(sidebar_width)])
+                .areas(main);
+
+        self.draw_conversation(frame, conversation);
+        if !self.hide_sidebar {
+            self.draw_sidebar(frame, sidebar);
+        }
+
+        let prompt = match self.thinking {
+            Some(frame) => format!("{frame} {}", self.messages.thinking),
+            None => format!("{}▏", self.input),
+        };
+        frame.render_widget(
+            Paragraph::new(prompt).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", self.messages.you)),
+            ),
+            input,
+        );
+        frame.render_widget(
+            Paragraph::new(self.status.as_str()).style(Style::default().fg(Color::DarkGray)),
+            status,
+        );
+    }
+
+    fn draw_conversation(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .lines
+            .iter()
+            .map(|(speaker, text)| match speaker {
+                Some(Speaker::Guard) => {
+                    let mut spans = vec![Span::styled(
+                        "Guard: ",
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                    )];
+                    spans.extend(guard_spans(text));
+                    Line::from(spans)
+                }
+                Some(Speaker::Player) => Line::from(vec![
+                    Span::styled(
+                        format!("{}: ", self.messages.you),
+                        Style::default()
+                            .fg(Color::Cyan)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(text.as_str()),
+                ]),
+                Some(Speaker::Notice) => {
+                    Line::styled(text.as_str(), Style::default().fg(Color::DarkGray))
+                }
+                Some(Speaker::Result(success)) => Line::styled(
+                    text.as_str(),
+                    Style::default()
+                        .fg(if *success { Color::Green } else { Color::Red })
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Some(Speaker::Ambient) => Line::styled(
+                    format!("... {text}"),
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::ITALIC),
+                ),
+                None => Line::raw(""),
+            })
+            .collect();
+
+        // Keep the latest line at the bottom unless the player scrolled up.
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let height: usize = lines.iter().map(|l| l.width().max(1).div_ceil(width)).sum();
+        let visible = area.height.saturating_sub(2) as usize;
+        let bottom = height.saturating_sub(visible) as u16;
+        let offset = bottom.saturating_sub(self.scroll);
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .scroll((offset, 0))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" Border control "),
+                ),
+            area,
+        );
+    }
+
+    fn draw_sidebar(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(" Progress ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [gauge, details] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        let ratio = if self.total_steps == 0 {
+            0.0
+        } else {
+            (self.steps_completed as f64 / self.total_steps as f64).min(1.0)
+        };
+        frame.render_widget(
+            Gauge::default()
+                .ratio(ratio)
+                .label(format!("{}/{}", self.steps_completed, self.total_steps))
+                .gauge_style(Style::default().fg(Color::Green)),
+            gauge,
+        );
+
+        let mut text = vec![Line::raw("")];
+        if self.debug {
+            text.push(Line::raw(format!("Node: {}", self.node_id)));
+        }
+        text.push(Line::raw(""));
+        text.push(Line::styled(
+            "Enter  answer",
+            Style::default().fg(Color::DarkGray),
+        ));
+        text.push(Line::styled(
+            "PgUp/PgDn  scroll",
+            Style::default().fg(Color::DarkGray),
+        ));
+        text.push(Line::styled(
+            format!("{}  hint", self.keys.hint),
+            Style::default().fg(Color::DarkGray),
+        ));
+        text.push(Line::styled(
+            format!("{}  talk", self.keys.talk),
+            Style::default().fg(Color::DarkGray),
+        ));
+        text.push(Line::styled(
+            format!("{}  hide this", self.keys.sidebar),
+            Style::default().fg(Color::DarkGray),
+        ));
+        text.push(Line::styled(
+            "Esc  quit",
+            Style::default().fg(Color::DarkGray),
+        ));
+        frame.render_widget(Paragraph::new(text), details);
+    }
+}
+
+/// Full-screen front end: scrolling conversation, input box, progress
+/// sidebar and a status line with generation speed.
+pub struct TuiUi {
+    terminal: DefaultTerminal,
+    screen: Screen,
+    /// Guard lines read out as they are shown, answers heard.
+    #[cfg(feature = "voice")]
+    voice: Voice,
+    pacing: PacingConfig,
+}
+
+impl TuiUi {
+    pub fn new(keys: Keymap, messages: &'static Messages) -> Result<Self> {
+        Ok(Self {
+            terminal: ratatui::try_init()?,
+            screen: Screen {
+                debug: log::log_enabled!(log::Level::Debug),
+                status: "Type your answer and press Enter.".to_string(),
+                keys,
+                messages,
+                ..Screen::default()
+            },
+            #[cfg(feature = "voice")]
+            voice: Voice::default(),
+            pacing: PacingConfig::default(),
+        })
+    }
+
+    /// Read guard lines out loud and hear answers, as `voice` can.
+    #[cfg(feature = "voice")]
+    pub fn voiced(mut self, voice: Voice) -> Self {
+        self.voice = voice;
+        self
+    }
+
+    /// Type guard lines out at `pacing`'s speed.
+    pub fn paced(mut self, pacing: PacingConfig) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        let screen = &self.screen;
+        self.terminal.draw(|frame| screen.draw(frame))?;
+        Ok(())
+    }
+
+    /// Wait for the next key press, redrawing in between.
+    fn next_key(&mut self) -> Result<event::KeyEvent> {
+        loop {
+            self.redraw()?;
+            if !event::poll(TICK)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    return Ok(key);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TuiUi {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+impl Ui for TuiUi {
+    fn guard_line(&mut self, node_id: &str, text: &str, rng: &mut Rng) -> Result<()> {
+        self.screen.node_id = node_id.to_string();
+        #[cfg(feature = "voice")]
+        if let Some(speech) = &self.voice.speech {
+            speech.say(text);
+        }
+        if !self.pacing.enabled() {
+            self.screen.push(Speaker::Guard, text);
+            return self.redraw();
+        }
+        self.screen.push(Speaker::Guard, "");
+        let at = self.screen.lines.len() - 2;
+        for (i, c) in text.char_indices() {
+            self.screen.lines[at].1 = text[..i + c.len_utf8()].to_string();
+            self.redraw()?;
+            if event::poll(self.pacing.delay(c, rng))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && pressed(&key, self.screen.keys.skip) {
+                        break;
+                    }
+                }
+            }
+        }
+        self.screen.lines[at].1 = text.to_string();
+        self.redraw()
+    }
+
+    fn read_input(&mut self) -> Result<String> {
+        loop {
+            let key = self.next_key()?;
+            if pressed(&key, self.screen.keys.hint) {
+                return Ok("/hint".to_string());
+            }
+            if pressed(&key, self.screen.keys.talk) {
+                return Ok("/talk".to_string());
+            }
+            if pressed(&key, self.screen.keys.sidebar) {
+                self.screen.hide_sidebar = !self.screen.hide_sidebar;
+                continue;
+            }
+            match key.code {
+                KeyCode::Enter if !self.screen.input.trim().is_empty() => {
+                    let input = std::mem::take(&mut self.screen.input);
+                    self.screen.push(Speaker::Player, &input);
+                    return Ok(input);
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok("/quit".to_string());
+                }
+                KeyCode::Esc => return Ok("/quit".to_string()),
+                KeyCode::Char(c) => self.screen.input.push(c),
+                KeyCode::Backspace => {
+                    self.screen.input.pop();
+                }
+                KeyCode::PageUp | KeyCode::Up => {
+                    self.screen.scroll = self.screen.scroll.saturating_add(3)
+                }
+                KeyCode::PageDown | KeyCode::Down => {
+                    self.screen.scroll = self.screen.scroll.saturating_sub(3)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn notice(&mut self, text: &str) -> Result<()> {
+        self.screen.push(Speaker::Notice, text);
+        self.redraw()
+    }
+
+    fn progress(&mut self, steps_completed: usize, total_steps: usize) {
+        self.screen.steps_completed = steps_completed;
+        self.screen.total_steps = total_steps;
+    }
+
+    fn waiting<T>(&mut self, ambient: Option<&Ambient>, beats: &Beats, f: impl FnOnce() -> T) -> T {
+        let done = AtomicBool::new(false);
+        let spin = self.pacing.spinner;
+        let terminal = &mut self.terminal;
+        let screen = &mut self.screen;
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let started = Instant::now();
+                let mut next_ambient = started + SHOW_EVERY / 2;
+                let mut shown = None;
+                while !done.load(Ordering::Relaxed) {
+                    if let Some(line) = beats.next(started.elapsed(), &mut shown) {
+                        screen.push(Speaker::Notice, line);
+                    }
+                    if Instant::now() >= next_ambient {
+                        if let Some(line) = ambient.and_then(Ambient::next_line) {
+                            screen.push(Speaker::Ambient, &line);
+                        }
+                        next_ambient = Instant::now() + SHOW_EVERY;
+                    }
+                    // A still frame when the spinner is turned off.
+                    screen.thinking = Some(if spin {
+                        pacing::spinner(started.elapsed())
+                    } else {
+                        '⠿'
+                    });
+                    let _ = terminal.draw(|frame| screen.draw(frame));
+                    thread::sleep(TICK);
+                }
+                screen.thinking = None;
+            });
+            let result = f();
+            done.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn turn_stats(&mut self, usage: Usage, latency: Duration) {
+        let secs = latency.as_secs_f64().max(f64::EPSILON);
+        self.screen.status = format!(
+            "Last judgement: {:.1}s, {} prompt + {} generated tokens, {:.1} tok/s",
+            secs,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.completion_tokens as f64 / secs
+        );
+        if usage.cached_tokens > 0 {
+            self.screen.status += &format!(" ({} prompt tokens cached)", usage.cached_tokens);
+        }
+        if !usage.throttled.is_zero() {
+            self.screen.status +=
+                &format!(" ({:.1}s power-save pauses)", usage.throttled.as_secs_f64());
+        }
+    }
+
+    fn game_over(&mut self, outcome: &GameOutcome) -> Result<()> {
+        let messages = self.screen.messages;
+        self.screen
+            .push(Speaker::Notice, &format!("=== {} ===", messages.game_over));
+        let result = match outcome {
+            GameOutcome::Finished { success, .. } => Speaker::Result(*success),
+            GameOutcome::Quit { .. } => Speaker::Notice,
+        };
+        for (i, line) in outcome.summary(messages).iter().enumerate() {
+            // The first line is the result.
+            self.screen
+                .push(if i == 0 { result } else { Speaker::Notice }, line);
+        }
+        self.screen.push(
+            Speaker::Notice,
+            &outcome.options(&self.screen.keys, messages),
+        );
+        self.redraw()
+    }
+
+    fn pick(&mut self, keys: &[char], _hint: &str) -> Result<char> {
+        let out = *keys.last().expect("a choice has keys");
+        loop {
+            match self.next_key()?.code {
+                KeyCode::Char(c) if keys.contains(&c.to_ascii_lowercase()) => {
+                    let c = c.to_ascii_lowercase();
+                    // Anything but the way out starts over on a clean screen.
+                    if c != out {
+                        self.screen.lines.clear();
+                    }
+                    return Ok(c);
+                }
+                KeyCode::Esc => return Ok(out),
+                _ => {}
+            }
+        }
+    }
+
+    fn keys(&self) -> &Keymap {
+        &self.screen.keys
+    }
+
+    fn messages(&self) -> &Messages {
+        self.screen.messages
+    }
+
+    #[cfg(feature = "voice")]
+    fn speech(&self) -> Option<&Speech> {
+        self.voice.speech.as_ref()
+    }
+
+    /// What was heard goes into the answer box, to be fixed up if need be
+    /// and sent with Enter.
+    #[cfg(feature = "voice")]
+    fn listen(&mut self) -> Result<Option<String>> {
+        let Some(listener) = self.voice.listener.take() else {
+            self.notice("(No microphone: start the game with --listen to answer out loud.)")?;
+            return Ok(None);
+        };
+        let status = std::mem::replace(&mut self.screen.status, "Listening...".to_string());
+        self.redraw()?;
+        let heard = listener.listen();
+        self.voice.listener = Some(listener);
+        self.screen.status = status;
+        match heard {
+            Ok(heard) if heard.is_empty() => self.notice("(Didn't catch that.)")?,
+            Ok(heard) => {
+                self.screen.input = heard;
+                self.redraw()?;
+            }
+            Err(e) => self.notice(&format!("(Could not listen: {e:#})"))?,
+        }
+        Ok(None)
+    }
+}
+
+/// Whether `key` is the press `binding` stands for.
+fn pressed(key: &event::KeyEvent, binding: Key) -> bool {
+    match binding {
+        Key::F(n) => key.code == KeyCode::F(n),
+        Key::Ctrl(c) => {
+            key.code == KeyCode::Char(c) && key.modifiers.contains(KeyModifiers::CONTROL)
+        }
+        Key::Tab => key.code == KeyCode::Tab,
+    }
+}
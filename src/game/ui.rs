@@ -0,0 +1,381 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::game::ambient::{Ambient, SHOW_EVERY};
+use crate::game::beats::Beats;
+use crate::game::keymap::Keymap;
+#[cfg(feature = "readline")]
+use crate::game::line_editor::LineEditor;
+use crate::game::locale::{self, Messages};
+use crate::game::pacing::{self, PacingConfig};
+use crate::game::template::{self, Span};
+use crate::game::transcript::TurnRecord;
+use crate::game::GameOutcome;
+use crate::llm::Usage;
+use crate::rng::Rng;
+#[cfg(feature = "voice")]
+use crate::voice::{Speech, Voice};
+
+/// Everything the game loop needs from a front end. The plain terminal and
+/// the TUI (and JSONL mode) implement it, so `play_round` never touches stdin/stdout.
+pub trait Ui {
+    /// Show the guard's line for the node being entered.
+    fn guard_line(&mut self, node_id: &str, text: &str, rng: &mut Rng) -> Result<()>;
+    /// Read the player's answer (untrimmed).
+    fn read_input(&mut self) -> Result<String>;
+    /// Out-of-band message such as "(Game saved ...)".
+    fn notice(&mut self, text: &str) -> Result<()>;
+    /// Step progress of the current round.
+    fn progress(&mut self, _steps_completed: usize, _total_steps: usize) {}
+    /// Run a slow call (the judge) with a waiting indicator, the `beats`
+    /// that come due while it runs and, if given, the ambient queue.
+    fn waiting<T>(&mut self, ambient: Option<&Ambient>, beats: &Beats, f: impl FnOnce() -> T) -> T;
+    /// Timing of the judge call that just finished.
+    fn turn_stats(&mut self, _usage: Usage, _latency: Duration) {}
+    /// The judge's (or the supervisor's) ruling on an answer.
+    fn decision(&mut self, _record: &TurnRecord) -> Result<()> {
+        Ok(())
+    }
+    fn game_over(&mut self, outcome: &GameOutcome) -> Result<()>;
+    /// Single-key choice. Returns `true` for `yes`, `false` for `no`.
+    fn choose(&mut self, yes: char, no: char, hint: &str) -> Result<bool> {
+        Ok(self.pick(&[yes, no], hint)? == yes)
+    }
+    /// Single-key choice among `keys`. The last one is the way out, taken
+    /// on Esc or closed input.
+    fn pick(&mut self, keys: &[char], hint: &str) -> Result<char>;
+    /// Keys the player makes choices with.
+    fn keys(&self) -> &Keymap {
+        &Keymap::DEFAULT
+    }
+    /// The engine's words in the player's language.
+    fn messages(&self) -> &Messages {
+        &locale::ENGLISH
+    }
+    /// Push-to-talk (`/talk`): record an answer and have the player check
+    /// what was heard. Returns the answer to send, if any.
+    fn listen(&mut self) -> Result<Option<String>> {
+        self.notice("(No microphone: start the game with --listen to answer out loud.)")?;
+        Ok(None)
+    }
+    /// Text to speech that generated text is streamed to as it comes in
+    /// (`--speak`).
+    #[cfg(feature = "voice")]
+    fn speech(&self) -> Option<&Speech> {
+        None
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Plain terminal
+// ---------------------------------------------------------------------------
+
+/// Back to the start of the line, and erase it.
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+/// Line-oriented stdin/stdout front end, suitable for piping.
+pub struct PlainUi {
+    pacing: PacingConfig,
+    keys: Keymap,
+    messages: &'static Messages,
+    colors: Colors,
+    /// Line editing on an interactive terminal. Pacing reads stdin on a
+    /// thread of its own to notice Enter, so it goes without.
+    #[cfg(feature = "readline")]
+    editor: Option<LineEditor>,
+    /// Guard lines read out as they are typed, answers heard.
+    #[cfg(feature = "voice")]
+    voice: Voice,
+}
+
+impl PlainUi {
+    pub fn new(
+        pacing: PacingConfig,
+        keys: Keymap,
+        messages: &'static Messages,
+        colors: Colors,
+    ) -> Self {
+        #[cfg(feature = "readline")]
+        let editor = (io::stdin().is_terminal() && io::stdout().is_terminal() && !pacing.enabled())
+            .then(LineEditor::new)
+            .and_then(|editor| {
+                editor
+                    .inspect_err(|e| log::warn!("No line editing: {e:#}"))
+                    .ok()
+            });
+        Self {
+            pacing,
+            keys,
+            messages,
+            colors,
+            #[cfg(feature = "readline")]
+            editor,
+            #[cfg(feature = "voice")]
+            voice: Voice::default(),
+        }
+    }
+
+    /// Read guard lines out loud and hear answers, as `voice` can.
+    #[cfg(feature = "voice")]
+    pub fn voiced(mut self, voice: Voice) -> Self {
+        self.voice = voice;
+        self
+    }
+}
+
+impl Ui for PlainUi {
+    fn guard_line(&mut self, _node_id: &str, text: &str, rng: &mut Rng) -> Result<()> {
+        println!();
+        #[cfg(feature = "voice")]
+        if let Some(speech) = &self.voice.speech {
+            speech.say(text);
+        }
+        pacing::type_out(&self.colors.guard_line(text), &self.pacing, rng)?;
+        Ok(())
+    }
+
+    fn read_input(&mut self) -> Result<String> {
+        println!();
+        let prompt = format!("[{}]: ", self.messages.you);
+        // The line editor measures its prompt, so it goes without color.
+        #[cfg(feature = "readline")]
+        if let Some(editor) = &mut self.editor {
+            return editor.answer(&prompt);
+        }
+        print!("{}", self.colors.paint(PLAYER, &prompt));
+        io::stdout().flush()?;
+        Ok(pacing::read_line()?)
+    }
+
+    fn notice(&mut self, text: &str) -> Result<()> {
+        println!("{}", self.colors.paint(NOTICE, text));
+        Ok(())
+    }
+
+    fn waiting<T>(&mut self, ambient: Option<&Ambient>, beats: &Beats, f: impl FnOnce() -> T) -> T {
+        // On a terminal the spinner line is redrawn in place and cleared
+        // once the judge is done; elsewhere a static line is left.
+        let animate = self.pacing.spinner && io::stdout().is_terminal();
+        let thinking = self.messages.thinking;
+        if animate {
+            println!();
+        } else {
+            println!("\n{}", self.colors.paint(NOTICE, &format!("({thinking})")));
+        }
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            // Beats and ambient lines are printed here too, over the
+            // spinner frame, so they never land in the middle of it.
+            scope.spawn(|| {
+                let started = Instant::now();
+                let mut next_ambient = started + SHOW_EVERY / 2;
+                let mut shown = None;
+                let mut stdout = io::stdout();
+                let above = |line: &str| {
+                    if animate {
+                        print!("{CLEAR_LINE}");
+                    }
+                    println!("  {line}");
+                };
+                while !done.load(Ordering::Relaxed) {
+                    if let Some(line) = beats.next(started.elapsed(), &mut shown) {
+                        above(&self.colors.paint(NOTICE, line));
+                    }
+                    if Instant::now() >= next_ambient {
+                        if let Some(line) = ambient.and_then(Ambient::next_line) {
+                            above(&format!("... {line}"));
+                        }
+                        next_ambient = Instant::now() + SHOW_EVERY;
+                    }
+                    if animate {
+                        let frame = format!("{} {thinking}", pacing::spinner(started.elapsed()));
+                        print!("\r{}", self.colors.paint(NOTICE, &frame));
+                        let _ = stdout.flush();
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+                if animate {
+                    print!("{CLEAR_LINE}");
+                    let _ = stdout.flush();
+                }
+            });
+            let result = f();
+            done.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn decision(&mut self, record: &TurnRecord) -> Result<()> {
+        let ruling = format!("({}: {})", record.decision, record.reason);
+        println!("{}", self.colors.paint(REASON, &ruling));
+        Ok(())
+    }
+
+    fn game_over(&mut self, outcome: &GameOutcome) -> Result<()> {
+        let rule = "========================================";
+        let (result, banner) = match outcome {
+            GameOutcome::Finished { success: true, .. } => (CLEARED, CLEARED),
+            GameOutcome::Finished { success: false, .. } => (DENIED, DENIED),
+            GameOutcome::Quit { .. } => ("", BANNER),
+        };
+        println!("\n{}", self.colors.paint(banner, rule));
+        let title = format!("{:^35}", self.messages.game_over);
+        println!("{}", self.colors.paint(BANNER, &title));
+        println!("{}", self.colors.paint(banner, rule));
+        for (i, line) in outcome.summary(self.messages).iter().enumerate() {
+            // The first line is the result.
+            let style = if i == 0 { result } else { "" };
+            println!("  {}", self.colors.paint(style, line));
+        }
+        println!("{}\n", self.colors.paint(banner, rule));
+        println!("  {}\n", outcome.options(&self.keys, self.messages));
+        Ok(())
+    }
+
+    fn pick(&mut self, keys: &[char], hint: &str) -> Result<char> {
+        #[cfg(feature = "readline")]
+        if let Some(editor) = &mut self.editor {
+            loop {
+                let Some(input) = editor.read("> ")? else {
+                    return Ok(*keys.last().expect("a choice has keys"));
+                };
+                match key_of(&input, keys) {
+                    Some(c) => return Ok(c),
+                    None => println!("{hint}"),
+                }
+            }
+        }
+        prompt_key(keys, hint)
+    }
+
+    fn keys(&self) -> &Keymap {
+        &self.keys
+    }
+
+    fn messages(&self) -> &Messages {
+        self.messages
+    }
+
+    #[cfg(feature = "voice")]
+    fn speech(&self) -> Option<&Speech> {
+        self.voice.speech.as_ref()
+    }
+
+    #[cfg(feature = "voice")]
+    fn listen(&mut self) -> Result<Option<String>> {
+        let Some(listener) = &self.voice.listener else {
+            self.notice("(No microphone: start the game with --listen to answer out loud.)")?;
+            return Ok(None);
+        };
+        println!("\n(Listening...)");
+        let heard = match listener.listen() {
+            Ok(heard) if heard.is_empty() => {
+                println!("(Didn't catch that.)");
+                return Ok(None);
+            }
+            Ok(heard) => heard,
+            Err(e) => {
+                println!("(Could not listen: {e:#})");
+                return Ok(None);
+            }
+        };
+        println!("(Heard: \"{heard}\")");
+        Ok(self
+            .choose('y', 'n', "  Press [y] to answer that or [n] to try again.")?
+            .then_some(heard))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Colors
+// ---------------------------------------------------------------------------
+
+/// SGR codes of the plain terminal's styles.
+const GUARD: &str = "33";
+const DIRECTION: &str = "3;90";
+const PLAYER: &str = "1;36";
+const NOTICE: &str = "90";
+const REASON: &str = "3;35";
+const BANNER: &str = "1";
+const CLEARED: &str = "1;32";
+const DENIED: &str = "1;31";
+
+/// Whether the plain terminal styles its output, with the same palette as
+/// the TUI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors(bool);
+
+impl Colors {
+    pub const OFF: Colors = Colors(false);
+
+    /// Colors on an interactive terminal, unless `NO_COLOR` is set.
+    pub fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Colors(io::stdout().is_terminal() && !no_color)
+    }
+
+    /// `text` in the style of SGR `code`, or as it is without colors.
+    fn paint(self, code: &str, text: &str) -> String {
+        if !self.0 || code.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{code}m{text}\x1b[0m")
+    }
+
+    /// A guard line with its stage directions in grey italics. Without
+    /// colors they keep their `*asterisks*`.
+    fn guard_line(self, text: &str) -> String {
+        if !self.0 {
+            return text.to_string();
+        }
+        template::spans(text)
+            .into_iter()
+            .map(|span| match span {
+                Span::Speech(words) => self.paint(GUARD, words),
+                Span::Action(action) => self.paint(DIRECTION, action),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Read a single-key choice among `keys` from stdin.
+fn prompt_key(keys: &[char], hint: &str) -> Result<char> {
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let input = pacing::read_line()?;
+        match key_of(&input, keys) {
+            Some(c) => return Ok(c),
+            None => println!("{hint}"),
+        }
+    }
+}
+
+/// The key among `keys` that `input` is, ignoring case and spacing.
+fn key_of(input: &str, keys: &[char]) -> Option<char> {
+    match input.trim().to_lowercase().chars().collect::<Vec<_>>()[..] {
+        [c] if keys.contains(&c) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors_style_guard_lines() {
+        let line = "*stamps passport* Next.";
+        assert_eq!(Colors::OFF.guard_line(line), line);
+        assert_eq!(
+            Colors(true).guard_line(line),
+            "\x1b[3;90mstamps passport\x1b[0m \x1b[33mNext.\x1b[0m"
+        );
+        assert_eq!(Colors(true).paint("", "plain"), "plain");
+    }
+}
@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Game variables, set by nodes on entry (`"set": {"nervous": true}`) or
+/// from facts the judge extracts, and read by edge conditions.
+pub type Vars = BTreeMap<String, Value>;
+
+/// Condition on an edge, written as `name`, `!name`, `name == value`,
+/// `name != value`, or a comparison with a number such as `name >= 3`.
+/// Values are JSON (`true`, `2`, `"business"`); a bare word is read as a
+/// string. A variable that was never set is `null`, and only numbers
+/// compare.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    /// The condition as written, kept so scenarios round-trip unchanged.
+    source: String,
+    name: String,
+    test: Test,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Test {
+    Set,
+    Unset,
+    Eq(Value),
+    Ne(Value),
+    /// `<`, `<=`, `>` or `>=` the number.
+    Cmp(Ordering, bool, f64),
+}
+
+impl Condition {
+    pub fn holds(&self, vars: &Vars) -> bool {
+        let value = vars.get(&self.name).unwrap_or(&Value::Null);
+        match &self.test {
+            Test::Set => truthy(value),
+            Test::Unset => !truthy(value),
+            Test::Eq(expected) => value == expected,
+            Test::Ne(expected) => value != expected,
+            Test::Cmp(order, or_equal, than) => value
+                .as_f64()
+                .and_then(|v| v.partial_cmp(than))
+                .is_some_and(|o| o == *order || (*or_equal && o == Ordering::Equal)),
+        }
+    }
+}
+
+/// `null`, `false`, `0` and `""` are false; everything else is true.
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(_) | Value::Object(_) => true,
+    }
+}
+
+/// Fill in `{{vars.name}}` placeholders. Strings go in without quotes;
+/// variables that were never set read as `unknown`.
+pub fn interpolate(text: &str, vars: &Vars) -> String {
+    let re = Regex::new(r"\{\{\s*vars\.([A-Za-z0-9_]+)\s*\}\}").unwrap();
+    re.replace_all(text, |caps: &Captures| match vars.get(&caps[1]) {
+        Some(value) => display(value),
+        None => display(&Value::Null),
+    })
+    .into_owned()
+}
+
+/// A variable as shown to the judge: strings without quotes, `unknown`
+/// for `null`.
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "unknown".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn literal(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let (name, test) = if let Some((name, value)) = source.split_once("==") {
+            (name, Test::Eq(literal(value.trim())))
+        } else if let Some((name, value)) = source.split_once("!=") {
+            (name, Test::Ne(literal(value.trim())))
+        } else if let Some((name, order, or_equal, value)) = comparison(&source) {
+            let Ok(than) = value.trim().parse() else {
+                bail!("invalid condition '{source}' (only numbers compare)");
+            };
+            (name, Test::Cmp(order, or_equal, than))
+        } else if let Some(name) = source.trim().strip_prefix('!') {
+            (name, Test::Unset)
+        } else {
+            (source.as_str(), Test::Set)
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("invalid condition '{source}' (expected e.g. 'nervous == true')");
+        }
+        Ok(Self {
+            name: name.to_string(),
+            test,
+            source,
+        })
+    }
+}
+
+/// Split `name >= value` and the like at the operator.
+fn comparison(source: &str) -> Option<(&str, Ordering, bool, &str)> {
+    let at = source.find(['<', '>'])?;
+    let order = if source[at..].starts_with('<') {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    };
+    Some(match source[at + 1..].strip_prefix('=') {
+        Some(value) => (&source[..at], order, true, value),
+        None => (&source[..at], order, false, &source[at + 1..]),
+    })
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.source
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holds(condition: &str, vars: &Vars) -> bool {
+        Condition::try_from(condition.to_string())
+            .unwrap()
+            .holds(vars)
+    }
+
+    #[test]
+    fn test_conditions() {
+        let vars: Vars = serde_json::from_str(
+            r#"{"nervous": true, "purpose": "business", "bags": 2, "name": ""}"#,
+        )
+        .unwrap();
+        assert!(holds("nervous == true", &vars));
+        assert!(holds("nervous", &vars));
+        assert!(holds("purpose == business", &vars));
+        assert!(holds("purpose==\"business\"", &vars));
+        assert!(holds("bags != 1", &vars));
+        assert!(holds("!name", &vars));
+        assert!(holds("!unknown", &vars));
+        assert!(holds("unknown == null", &vars));
+        assert!(!holds("purpose == tourism", &vars));
+        assert!(holds("bags >= 2", &vars) && holds("bags<3", &vars));
+        assert!(!holds("bags > 2", &vars) && !holds("unknown <= 2", &vars));
+        assert!(Condition::try_from("bags > two".to_string()).is_err());
+        assert!(Condition::try_from("== true".to_string()).is_err());
+        assert!(Condition::try_from("two words".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars: Vars =
+            serde_json::from_str(r#"{"origin_city": "Frankfurt", "bags": 2}"#).unwrap();
+        assert_eq!(
+            interpolate(
+                "Claimed {{vars.origin_city}} with {{ vars.bags }} bags, for {{vars.purpose}}. {{hello}}",
+                &vars
+            ),
+            "Claimed Frankfurt with 2 bags, for unknown. {{hello}}"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+
+use crate::game::classifier;
+use crate::game::session::{Judge, JudgeRequest};
+use crate::llm::{LlmBackend, LlmDecision, Usage};
+
+/// A judge call waiting for the model.
+pub struct JudgeJob {
+    pub request: JudgeRequest,
+    /// Classify the answer by embedding instead of asking the judge.
+    pub classify: bool,
+    events: Sender<JudgeEvent>,
+}
+
+impl JudgeJob {
+    /// Carry the job out on `backend`, the way [`Judge`] does on the
+    /// calling thread.
+    pub fn run(
+        &self,
+        backend: &mut (impl LlmBackend + ?Sized),
+        on_piece: &mut dyn FnMut(&str),
+    ) -> (Result<LlmDecision>, Usage) {
+        if self.classify {
+            let request = &self.request;
+            let classified = classifier::classify(backend, request.input(), request.criteria());
+            return (classified, Usage::default());
+        }
+        (self.request.run(backend, on_piece), backend.last_usage())
+    }
+}
+
+/// What the worker reports back on a job.
+enum JudgeEvent {
+    /// The worker took the job off the queue.
+    Started,
+    Piece(String),
+    Done(Result<LlmDecision>, Usage),
+}
+
+/// Handle for queueing judge calls on the model worker. Cheap to clone;
+/// every clone feeds the same bounded queue.
+#[derive(Clone)]
+pub struct JudgeQueue {
+    jobs: SyncSender<JudgeJob>,
+}
+
+impl JudgeQueue {
+    /// A queue holding up to `depth` waiting jobs, and the receiving end to
+    /// pass to [`run`].
+    pub fn new(depth: usize) -> (Self, Receiver<JudgeJob>) {
+        let (jobs, queued) = mpsc::sync_channel(depth);
+        (Self { jobs }, queued)
+    }
+
+    /// Queue `request` and block until the worker has judged it, handing
+    /// generated pieces to `on_piece` as they arrive. Returns `None` without
+    /// queueing anything when the queue is full. Answers decided by a rule
+    /// of the node skip the queue.
+    pub fn judge(
+        &self,
+        request: &JudgeRequest,
+        on_piece: impl FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        if let Some(decision) = request.ruled() {
+            return Some((Ok(decision), Usage::default()));
+        }
+        self.queue(request, false, on_piece)
+    }
+
+    fn queue(
+        &self,
+        request: &JudgeRequest,
+        classify: bool,
+        mut on_piece: impl FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        let (events, received) = mpsc::channel();
+        let job = JudgeJob {
+            request: request.clone(),
+            classify,
+            events,
+        };
+        let sent = Instant::now();
+        let mut queued = Default::default();
+        match self.jobs.try_send(job) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => return None,
+            Err(TrySendError::Disconnected(_)) => {
+                return Some((Err(anyhow!("the model worker stopped")), Usage::default()))
+            }
+        }
+
+        for event in received {
+            match event {
+                JudgeEvent::Started => queued = sent.elapsed(),
+                JudgeEvent::Piece(text) => on_piece(&text),
+                JudgeEvent::Done(judged, mut usage) => {
+                    usage.queued = queued;
+                    return Some((judged, usage));
+                }
+            }
+        }
+        Some((
+            Err(anyhow!("the model worker dropped the job")),
+            Usage::default(),
+        ))
+    }
+}
+
+impl Judge for JudgeQueue {
+    fn decide(
+        &mut self,
+        request: &JudgeRequest,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Option<(Result<LlmDecision>, Usage)> {
+        self.judge(request, on_piece)
+    }
+
+    fn classify(&mut self, request: &JudgeRequest) -> Result<LlmDecision> {
+        match self.queue(request, true, |_| {}) {
+            Some((classified, _)) => classified,
+            None => Err(anyhow!("the judge queue is full")),
+        }
+    }
+}
+
+/// Run queued jobs one at a time with `judge` until every [`JudgeQueue`] is
+/// dropped. This owns the model, so it runs on the thread that loaded it.
+pub fn run(
+    jobs: Receiver<JudgeJob>,
+    mut judge: impl FnMut(&JudgeJob, &mut dyn FnMut(&str)) -> (Result<LlmDecision>, Usage),
+) {
+    for job in jobs {
+        let events = job.events.clone();
+        let _ = events.send(JudgeEvent::Started);
+        let (judged, usage) = judge(&job, &mut |text| {
+            let _ = events.send(JudgeEvent::Piece(text.to_string()));
+        });
+        if job.events.send(JudgeEvent::Done(judged, usage)).is_err() {
+            debug!("Judge job finished after its requester went away");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::game::session::Session;
+    use crate::game::tree::GameTree;
+
+    fn request() -> JudgeRequest {
+        let tree: GameTree = serde_json::from_str(
+            r#"{"start_node_id": "A", "nodes": {"A": {"id": "A", "transcript": "Hi",
+                "node_type": {"Decision": [{"id": "B", "description": "ok"}]}}}}"#,
+        )
+        .unwrap();
+        Session::new(tree, None, false, 0).ask("Hello").unwrap()
+    }
+
+    fn echo(job: &JudgeJob, on_piece: &mut dyn FnMut(&str)) -> (Result<LlmDecision>, Usage) {
+        on_piece("{\"decision\": ");
+        let decision = LlmDecision {
+            decision: job.request.valid_choices()[0].to_string(),
+            reason: job.request.messages.last().unwrap().content.clone(),
+            ..Default::default()
+        };
+        (Ok(decision), Usage::default())
+    }
+
+    #[test]
+    fn test_concurrent_jobs_are_judged_one_by_one() {
+        let (queue, jobs) = JudgeQueue::new(8);
+        let clients: Vec<_> = (0..4)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut pieces = String::new();
+                    let (judged, _) = queue
+                        .judge(&request(), |text| pieces.push_str(text))
+                        .unwrap();
+                    (judged.unwrap(), pieces)
+                })
+            })
+            .collect();
+        drop(queue);
+        run(jobs, echo);
+
+        for client in clients {
+            let (decision, pieces) = client.join().unwrap();
+            assert_eq!(decision.decision, "B");
+            assert_eq!(decision.reason, "Hello");
+            assert_eq!(pieces, "{\"decision\": ");
+        }
+    }
+
+    #[test]
+    fn test_full_queue_is_refused() {
+        let (queue, _jobs) = JudgeQueue::new(0);
+        // Nothing is receiving, so a zero-depth queue has no room.
+        assert!(queue.judge(&request(), |_| {}).is_none());
+    }
+}
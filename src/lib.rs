@@ -0,0 +1,21 @@
+//! The judge-driven dialogue engine behind the `elsa` game, for embedding
+//! in other applications.
+//!
+//! A scenario is a [`GameTree`] of guard lines and the criteria that move
+//! the conversation on. An [`Engine`] plays one through: it shows the
+//! guard's line, has an [`LlmBackend`] judge each answer against the
+//! current node's criteria, and reports a [`JudgeResult`] with where the
+//! game went. [`llm::LLM`] is the llama.cpp backend the game uses.
+
+pub mod config;
+pub mod game;
+pub mod llm;
+pub mod persistence;
+pub mod rng;
+#[cfg(feature = "voice")]
+pub mod voice;
+
+pub use game::engine::{Engine, JudgeResult};
+pub use game::tree::GameTree;
+pub use llm::mock::MockBackend;
+pub use llm::LlmBackend;
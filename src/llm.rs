@@ -1,35 +1,81 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::num::NonZeroU32;
 use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 use regex::Regex;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::context::params::{KvCacheType, LlamaContextParams, LlamaPoolingType};
 use llama_cpp_2::context::LlamaContext;
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
 use llama_cpp_2::model::{AddBos, LlamaChatMessage, LlamaModel};
 use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_2::token::LlamaToken;
+
+use sampler::{build_sampler, SamplerConfig};
+
+pub mod mock;
+pub mod sampler;
 
 // ---------------------------------------------------------------------------
 // LLM judge response
 // ---------------------------------------------------------------------------
 
-/// The structured JSON the LLM is expected to produce.
-#[derive(Debug, Deserialize)]
+/// The structured JSON the LLM is expected to produce. Only `decision`
+/// and `reason` are required. The optional fields are filled when the
+/// judge includes them; a value of the wrong type reads as missing instead
+/// of failing the reply, so features can ask for more without breaking
+/// older scenarios.
+#[derive(Debug, Default, Deserialize)]
 pub struct LlmDecision {
     pub decision: String,
     pub reason: String,
+    /// How sure the judge is, from 0 to 1.
+    #[serde(default, deserialize_with = "lenient")]
+    pub confidence: Option<f32>,
+    /// How the answer came across, e.g. "nervous".
+    #[serde(default, deserialize_with = "lenient")]
+    pub tone: Option<String>,
+    /// Change of the suspicion meter ([`crate::game::suspicion::DELTA`]).
+    #[serde(default, rename = "delta", deserialize_with = "lenient")]
+    pub suspicion_delta: Option<f32>,
+    /// The words of the answer the decision hinges on.
+    #[serde(default, deserialize_with = "lenient")]
+    pub quote: Option<String>,
+    /// Any other keys, kept as they are: e.g. facts the node asked the
+    /// judge to extract.
+    #[serde(flatten)]
+    pub facts: BTreeMap<String, serde_json::Value>,
+}
+
+/// Read an optional field, treating a value of the wrong type as missing.
+/// Small models often quote numbers, so a string is also tried as JSON.
+fn lenient<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    if let Ok(parsed) = serde_json::from_value(value.clone()) {
+        return Ok(Some(parsed));
+    }
+    Ok(value
+        .as_str()
+        .and_then(|text| serde_json::from_str(text.trim()).ok()))
 }
 
 // ---------------------------------------------------------------------------
 // Chat message helpers
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
@@ -66,13 +112,30 @@ impl std::fmt::Display for ChatMessage {
 // Model configuration
 // ---------------------------------------------------------------------------
 
+#[derive(Clone)]
 pub struct ModelConfig {
     /// How many layers to offload to GPU (0 = CPU only).
     pub n_gpu_layers: u32,
     /// Context window size in tokens.
     pub n_ctx: u32,
+    /// Threads for generation and prompt decoding; llama.cpp's default if
+    /// unset.
+    pub n_threads: Option<i32>,
+    /// Precision of the KV cache; llama.cpp's default (f16) if unset.
+    pub cache_type: Option<CacheType>,
     /// Maximum tokens to generate per completion.
     pub max_tokens: usize,
+    /// Sampling profile used for judge calls.
+    pub judge_sampler: SamplerConfig,
+    /// Samplings per judge, route or supervisor call, majority-voted.
+    /// 1 samples once.
+    pub ensemble: usize,
+    /// Sampling profile used for unconstrained chat / narration.
+    pub chat_sampler: SamplerConfig,
+    /// Sampling profile used for the supervisor judging appeals.
+    pub supervisor_sampler: SamplerConfig,
+    /// Throttle generation to keep the machine cool (`--power-save`).
+    pub power_save: Option<PowerSave>,
 }
 
 impl Default for ModelConfig {
@@ -80,205 +143,1224 @@ impl Default for ModelConfig {
         Self {
             n_gpu_layers: 0,
             n_ctx: 8092,
+            n_threads: None,
+            cache_type: None,
             max_tokens: 1024,
+            judge_sampler: SamplerConfig::judge(),
+            ensemble: 1,
+            chat_sampler: SamplerConfig::default(),
+            supervisor_sampler: SamplerConfig::supervisor(),
+            power_save: None,
         }
     }
 }
 
-// ---------------------------------------------------------------------------
-// Sampler builders
-// ---------------------------------------------------------------------------
+/// KV cache precision. Smaller types fit a longer context in less memory,
+/// at some cost in accuracy.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheType {
+    F32,
+    F16,
+    Bf16,
+    Q8_0,
+    Q5_1,
+    Q5_0,
+    Q4_1,
+    Q4_0,
+    Iq4_nl,
+}
+
+impl From<CacheType> for KvCacheType {
+    fn from(cache_type: CacheType) -> Self {
+        match cache_type {
+            CacheType::F32 => KvCacheType::F32,
+            CacheType::F16 => KvCacheType::F16,
+            CacheType::Bf16 => KvCacheType::BF16,
+            CacheType::Q8_0 => KvCacheType::Q8_0,
+            CacheType::Q5_1 => KvCacheType::Q5_1,
+            CacheType::Q5_0 => KvCacheType::Q5_0,
+            CacheType::Q4_1 => KvCacheType::Q4_1,
+            CacheType::Q4_0 => KvCacheType::Q4_0,
+            CacheType::Iq4_nl => KvCacheType::IQ4_NL,
+        }
+    }
+}
 
-fn build_free_sampler() -> LlamaSampler {
-    LlamaSampler::chain_simple([
-        LlamaSampler::penalties(64, 1.1, 0.0, 0.0),
-        LlamaSampler::top_k(40),
-        LlamaSampler::top_p(0.95, 1),
-        LlamaSampler::min_p(0.0, 1),
-        LlamaSampler::temp(1.0),
-        LlamaSampler::dist(1234),
-    ])
+/// Power-save throttling: fewer threads, and a short sleep after every
+/// chunk of decoded tokens so the CPU gets to idle. Trades latency for
+/// lower sustained load.
+#[derive(Debug, Clone, Copy)]
+pub struct PowerSave {
+    pub n_threads: i32,
+    /// Prompt tokens decoded per batch.
+    pub prompt_chunk: usize,
+    /// Generated tokens between pauses.
+    pub decode_chunk: usize,
+    pub pause: Duration,
 }
 
-fn build_sampler() -> Result<LlamaSampler> {
-    Ok(LlamaSampler::chain_simple([
-        LlamaSampler::penalties(64, 1.1, 0.0, 0.0),
-        LlamaSampler::top_k(40),
-        LlamaSampler::top_p(0.95, 1),
-        LlamaSampler::min_p(0.0, 1),
-        LlamaSampler::temp(1.0),
-        LlamaSampler::dist(1234),
-    ]))
+impl Default for PowerSave {
+    fn default() -> Self {
+        Self {
+            n_threads: 2,
+            prompt_chunk: 64,
+            decode_chunk: 8,
+            pause: Duration::from_millis(30),
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
 // LLM — loaded model handle
 // ---------------------------------------------------------------------------
 
+/// Token accounting for the most recent generation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    /// Prompt tokens whose KV cache was kept from the previous call and
+    /// not decoded again.
+    pub cached_tokens: usize,
+    /// Time spent in power-save pauses.
+    pub throttled: Duration,
+    /// Time the call waited in the judge queue before the model took it.
+    pub queued: Duration,
+}
+
 pub struct LLM {
-    #[allow(dead_code)]
-    backend: &'static LlamaBackend,
-    model: &'static LlamaModel,
     ctx: LlamaContext<'static>,
+    /// Tokens whose KV entries are in `ctx`, in order.
+    cached: Vec<LlamaToken>,
+    pins: Pins,
     n_ctx: u32,
     max_tokens: usize,
+    judge_sampler: SamplerConfig,
+    ensemble: usize,
+    chat_sampler: SamplerConfig,
+    supervisor_sampler: SamplerConfig,
+    /// Separate model for appeals; `None` uses this one.
+    supervisor: Option<Box<LLM>>,
+    /// Extra models loaded under an alias, for nodes that ask for one.
+    pool: HashMap<String, Box<LLM>>,
+    power_save: Option<PowerSave>,
+    last_usage: Usage,
+    /// Context for [`LLM::embed`], created on first use.
+    embed_ctx: Option<LlamaContext<'static>>,
+    /// Context for [`LLM::score`], created on first use.
+    score_ctx: Option<LlamaContext<'static>>,
+    // Declared last so they are dropped after the contexts above, which
+    // borrow them (see [`new_context`]).
+    model: Arc<LlamaModel>,
+    backend: Arc<LlamaBackend>,
+}
+
+/// Longest text [`LLM::embed`] looks at, in tokens.
+const EMBED_CTX: u32 = 512;
+
+/// Longest context plus text [`LLM::score`] looks at, in tokens.
+const SCORE_CTX: u32 = 512;
+
+/// How expected a text was under the model, averaged over its tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Surprise {
+    pub tokens: usize,
+    /// Mean negative log-probability of each token, in nats. Perplexity is
+    /// its exponential.
+    pub surprise: f32,
+    /// Mean entropy of the model's prediction before each token, in nats:
+    /// how unsure the model was of what would come next.
+    pub entropy: f32,
+}
+
+impl Surprise {
+    pub fn perplexity(&self) -> f32 {
+        self.surprise.exp()
+    }
 }
 
 impl LLM {
     pub fn load_model(model_path: &str, config: ModelConfig) -> Result<Self> {
-        let backend: &'static LlamaBackend = Box::leak(Box::new(
-            LlamaBackend::init().context("failed to init llama backend")?,
-        ));
+        let backend = Arc::new(LlamaBackend::init().context("failed to init llama backend")?);
+        Self::load_with_backend(backend, model_path, config)
+    }
+
+    /// Load a separate model for appeals. It shares this model's backend
+    /// (which can only be initialized once) and judges with the
+    /// supervisor profile.
+    pub fn load_supervisor(&mut self, model_path: &str, config: ModelConfig) -> Result<()> {
+        let config = ModelConfig {
+            judge_sampler: config.supervisor_sampler.clone(),
+            ..config
+        };
+        let supervisor = Self::load_with_backend(self.backend.clone(), model_path, config)
+            .context("failed to load supervisor model")?;
+        self.supervisor = Some(Box::new(supervisor));
+        Ok(())
+    }
+
+    /// Load a model under `alias`, for scenario nodes that name it in
+    /// their `model` field. It shares this model's backend.
+    pub fn load_alias(&mut self, alias: &str, model_path: &str, config: ModelConfig) -> Result<()> {
+        let model = Self::load_with_backend(self.backend.clone(), model_path, config)
+            .with_context(|| format!("failed to load model '{alias}'"))?;
+        self.pool.insert(alias.to_string(), Box::new(model));
+        Ok(())
+    }
 
+    fn load_with_backend(
+        backend: Arc<LlamaBackend>,
+        model_path: &str,
+        config: ModelConfig,
+    ) -> Result<Self> {
         info!("Loading model from: {model_path}");
         info!(
-            "  config: n_gpu_layers={}, n_ctx={}, max_tokens={}",
-            config.n_gpu_layers, config.n_ctx, config.max_tokens
+            "  config: n_gpu_layers={}, n_ctx={}, max_tokens={}, n_threads={:?}, cache_type={:?}",
+            config.n_gpu_layers,
+            config.n_ctx,
+            config.max_tokens,
+            config.n_threads,
+            config.cache_type
         );
+        info!("  judge sampler: {:?}", config.judge_sampler);
+        if config.ensemble > 1 {
+            info!("  judge ensemble: {}", config.ensemble);
+            if config.judge_sampler.near_greedy() {
+                warn!(
+                    "The judge sampler is near-greedy, so the {} ensemble members will mostly agree; raise --temp for the vote to count",
+                    config.ensemble
+                );
+            }
+        }
+        info!("  chat sampler: {:?}", config.chat_sampler);
+        info!("  supervisor sampler: {:?}", config.supervisor_sampler);
 
         let model_params = pin!(LlamaModelParams::default().with_n_gpu_layers(config.n_gpu_layers));
-        let model: &'static LlamaModel = Box::leak(Box::new(
-            LlamaModel::load_from_file(backend, model_path, &model_params)
+        let model = Arc::new(
+            LlamaModel::load_from_file(&backend, model_path, &model_params)
                 .context("failed to load model")?,
-        ));
+        );
 
         info!("Model loaded successfully");
 
-        let ctx_params = LlamaContextParams::default().with_n_ctx(Some(
+        let mut ctx_params = LlamaContextParams::default().with_n_ctx(Some(
             NonZeroU32::new(config.n_ctx).expect("n_ctx must be > 0"),
         ));
-        let ctx = model
-            .new_context(backend, ctx_params)
+        if let Some(n_threads) = config.n_threads {
+            ctx_params = ctx_params
+                .with_n_threads(n_threads)
+                .with_n_threads_batch(n_threads);
+        }
+        if let Some(cache_type) = config.cache_type {
+            ctx_params = ctx_params
+                .with_type_k(cache_type.into())
+                .with_type_v(cache_type.into());
+        }
+        // Power save keeps to its own, lower thread count.
+        if let Some(power_save) = config.power_save {
+            info!("  power save: {power_save:?}");
+            ctx_params = ctx_params
+                .with_n_threads(power_save.n_threads)
+                .with_n_threads_batch(power_save.n_threads);
+        }
+        let ctx = new_context(&model, &backend, ctx_params)
             .context("failed to create inference context")?;
 
         Ok(Self {
-            backend,
-            model,
             ctx,
+            cached: Vec::new(),
+            pins: Pins::default(),
             n_ctx: config.n_ctx,
             max_tokens: config.max_tokens,
+            judge_sampler: config.judge_sampler,
+            ensemble: config.ensemble.max(1),
+            chat_sampler: config.chat_sampler,
+            supervisor_sampler: config.supervisor_sampler,
+            supervisor: None,
+            pool: HashMap::new(),
+            power_save: config.power_save,
+            last_usage: Usage::default(),
+            embed_ctx: None,
+            score_ctx: None,
+            model,
+            backend,
         })
     }
 
-    /// Run an unconstrained chat completion.
-    #[allow(dead_code)]
-    pub fn chat(&mut self, messages: &[ChatMessage]) -> Result<String> {
-        let mut sampler = build_free_sampler();
-        self.generate(messages, &mut sampler)
+    /// Token counts of the last completed `chat` or `judge` call.
+    pub fn last_usage(&self) -> Usage {
+        self.last_usage
     }
 
-    pub fn judge(
-        &mut self,
-        messages: &[ChatMessage],
-        valid_choices: &[&str],
-    ) -> Result<LlmDecision> {
-        info!("Judging messages \n {messages:?}");
-
-        // Initialize Sampler
-        let mut sampler = build_sampler().context("Failed to initialize sampler")?;
+    pub fn power_save(&self) -> Option<PowerSave> {
+        self.power_save
+    }
 
-        // Generate
-        let raw = self.generate(messages, &mut sampler)?;
+    /// Free this model, its contexts, and the supervisor and aliased models
+    /// loaded with it. The backend goes with the last of them, so a later
+    /// [`LLM::load_model`] starts afresh. Open background slots (see
+    /// [`LLM::background`]) keep the weights until they are dropped.
+    pub fn shutdown(self) {
+        let shared = Arc::strong_count(&self.model) - 1;
+        if shared > 0 {
+            warn!("{shared} background handle(s) still use the model; it is freed when they close");
+        }
+        drop(self);
+        info!("Model unloaded");
+    }
 
-        // 4. Parse & Validate
-        let decision = parse_decision(&raw)?;
+    /// Mean-pooled, unit-length embedding of `text`, for similarity search.
+    /// Runs in a separate context, so it does not disturb generation.
+    pub fn embed(&mut self, text: &str) -> Result<Vec<f32>> {
+        let ctx = match &mut self.embed_ctx {
+            Some(ctx) => ctx,
+            None => {
+                let params = LlamaContextParams::default()
+                    .with_n_ctx(NonZeroU32::new(EMBED_CTX))
+                    .with_n_batch(EMBED_CTX)
+                    .with_embeddings(true)
+                    .with_pooling_type(LlamaPoolingType::Mean);
+                let ctx = new_context(&self.model, &self.backend, params)
+                    .context("failed to create embedding context")?;
+                self.embed_ctx.insert(ctx)
+            }
+        };
+        ctx.clear_kv_cache();
 
-        if valid_choices.contains(&decision.decision.as_str()) {
-            info!(
-                "Judge succeeded: {} (reason: {})",
-                decision.decision, decision.reason
-            );
-            return Ok(decision);
+        let mut tokens = self
+            .model
+            .str_to_token(text, AddBos::Always)
+            .context("tokenization failed")?;
+        tokens.truncate(EMBED_CTX as usize);
+        let mut batch = LlamaBatch::new(tokens.len(), 1);
+        for (i, tok) in (0i32..).zip(&tokens) {
+            batch.add(*tok, i, &[0], true)?;
         }
+        ctx.decode(&mut batch).context("embedding decode failed")?;
 
-        anyhow::bail!(
-            "Judge generated invalid decision '{}' (valid: {:?})",
-            decision.decision,
-            valid_choices
-        );
+        let mut embedding = ctx
+            .embeddings_seq_ith(0)
+            .context("model produced no embedding")?
+            .to_vec();
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|x| *x /= norm);
+        }
+        Ok(embedding)
     }
-    /// Core generation: tokenize messages, feed prompt, sample tokens.
-    fn generate(&mut self, messages: &[ChatMessage], sampler: &mut LlamaSampler) -> Result<String> {
-        info!("=== LLM CALL: {} messages ===", messages.len());
-        for (i, msg) in messages.iter().enumerate() {
-            debug!("  msg[{i}] {msg}");
+
+    /// How surprising `text` is to the model when it follows `context`.
+    /// Scores the text itself, not a reply to it: no chat template is
+    /// applied. Runs in a separate context, so it does not disturb
+    /// generation. When the two are too long, the start of `context` is cut.
+    pub fn score(&mut self, context: &str, text: &str) -> Result<Surprise> {
+        let ctx = match &mut self.score_ctx {
+            Some(ctx) => ctx,
+            None => {
+                let params = LlamaContextParams::default()
+                    .with_n_ctx(NonZeroU32::new(SCORE_CTX))
+                    .with_n_batch(SCORE_CTX);
+                let ctx = new_context(&self.model, &self.backend, params)
+                    .context("failed to create scoring context")?;
+                self.score_ctx.insert(ctx)
+            }
+        };
+        ctx.clear_kv_cache();
+
+        let mut tokens = self
+            .model
+            .str_to_token(context, AddBos::Always)
+            .context("tokenization failed")?;
+        let mut scored = self
+            .model
+            .str_to_token(text, AddBos::Never)
+            .context("tokenization failed")?;
+        scored.truncate(SCORE_CTX as usize - 1);
+        if scored.is_empty() {
+            return Ok(Surprise::default());
         }
+        let keep = SCORE_CTX as usize - scored.len();
+        if tokens.len() > keep {
+            tokens.drain(..tokens.len() - keep);
+        }
+        // The text's first token is predicted from the last context token.
+        let first = tokens
+            .len()
+            .checked_sub(1)
+            .context("nothing to score the text against")?;
+        tokens.extend_from_slice(&scored);
 
-        self.ctx.clear_kv_cache();
+        let mut batch = LlamaBatch::new(tokens.len(), 1);
+        for (i, tok) in (0i32..).zip(&tokens) {
+            batch.add(*tok, i, &[0], i as usize >= first)?;
+        }
+        ctx.decode(&mut batch).context("scoring decode failed")?;
 
-        let llama_msgs: Vec<LlamaChatMessage> = messages
-            .iter()
-            .map(|m| LlamaChatMessage::new(m.role.clone(), m.content.clone()))
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .context("failed to create chat messages")?;
+        let mut total = Surprise {
+            tokens: scored.len(),
+            ..Surprise::default()
+        };
+        for (i, next) in scored.iter().enumerate() {
+            let logits = ctx.get_logits_ith((first + i) as i32);
+            let (surprise, entropy) = token_surprise(logits, next.0 as usize);
+            total.surprise += surprise;
+            total.entropy += entropy;
+        }
+        total.surprise /= scored.len() as f32;
+        total.entropy /= scored.len() as f32;
+        debug!(
+            "Scored {} tokens: perplexity {:.1}, entropy {:.2}",
+            total.tokens,
+            total.perplexity(),
+            total.entropy
+        );
+        Ok(total)
+    }
 
+    /// Decode `system_prefix`, the fixed start of every judge system prompt,
+    /// into the KV cache before the first call. Calls whose prompt starts
+    /// the same way then only decode what follows it. Aliased models are
+    /// primed too.
+    pub fn prime(&mut self, system_prefix: &str) -> Result<()> {
+        for model in self.pool.values_mut() {
+            model.prime(system_prefix)?;
+        }
+        let message = LlamaChatMessage::new("system".to_string(), system_prefix.to_string())
+            .context("failed to create chat message")?;
         let tmpl = self
             .model
             .chat_template(None)
             .context("model has no chat template")?;
-        let prompt = self
+        let rendered = self
             .model
-            .apply_chat_template(&tmpl, &llama_msgs, true)
+            .apply_chat_template(&tmpl, &[message], false)
             .context("failed to apply chat template")?;
-
-        trace!("=== RENDERED PROMPT ===\n{prompt}\n=== END PROMPT ===");
-
+        // Cut the rendered prompt right after the prefix, before the
+        // template closes the message.
+        let end = rendered
+            .find(system_prefix)
+            .context("the chat template dropped the system prompt")?
+            + system_prefix.len();
         let tokens = self
             .model
-            .str_to_token(&prompt, AddBos::Always)
+            .str_to_token(&rendered[..end], AddBos::Always)
             .context("tokenization failed")?;
+        if self.cached.starts_with(&tokens) {
+            return Ok(());
+        }
 
-        info!("Prompt tokenized: {} tokens", tokens.len());
-
-        let mut batch = LlamaBatch::new(self.n_ctx as usize, 1);
-        let last_idx = (tokens.len() - 1) as i32;
-        for (i, tok) in (0i32..).zip(tokens.iter()) {
-            batch.add(*tok, i, &[0], i == last_idx)?;
+        self.ctx.clear_kv_cache();
+        self.cached.clear();
+        let mut batch = LlamaBatch::new(tokens.len(), 1);
+        for (i, tok) in (0i32..).zip(&tokens) {
+            batch.add(*tok, i, &[0], false)?;
         }
         self.ctx
             .decode(&mut batch)
-            .context("initial decode failed")?;
+            .context("priming decode failed")?;
+        info!("Primed {} prompt tokens", tokens.len());
+        self.cached = tokens;
+        Ok(())
+    }
+
+    /// Reseed the chat sampler, e.g. so repeated simulated games diverge.
+    pub fn set_chat_seed(&mut self, seed: u32) {
+        self.chat_sampler.seed = seed;
+    }
+
+    /// Run an unconstrained chat completion.
+    pub fn chat(&mut self, messages: &[ChatMessage]) -> Result<String> {
+        self.chat_stream(messages, |_| {})
+    }
 
-        let mut output = String::new();
-        let mut decoder = encoding_rs::UTF_8.new_decoder();
-        let mut n_cur = batch.n_tokens();
+    /// Run an unconstrained chat completion, handing every decoded piece to
+    /// `on_piece` as soon as it is sampled.
+    pub fn chat_stream(
+        &mut self,
+        messages: &[ChatMessage],
+        mut on_piece: impl FnMut(&str),
+    ) -> Result<String> {
+        let mut sampler = build_sampler(&self.chat_sampler, &self.model);
+        self.generate(
+            messages,
+            false,
+            self.max_tokens,
+            &mut sampler,
+            &mut on_piece,
+        )
+    }
 
-        for _ in 0..self.max_tokens {
-            let tok = sampler.sample(&self.ctx, batch.n_tokens() - 1);
-            sampler.accept(tok);
+    pub fn judge(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        self.judge_stream(messages, valid_choices, |_| {})
+    }
 
-            if self.model.is_eog_token(tok) {
-                debug!("Hit EOG token, stopping generation");
-                break;
+    /// [`LLM::judge`], handing the raw output to `on_piece` as it is
+    /// generated. With an ensemble, only the first member's output is
+    /// handed on.
+    pub fn judge_stream(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+        mut on_piece: impl FnMut(&str),
+    ) -> Result<LlmDecision> {
+        info!("Judging messages \n {messages:?}");
+        let config = self.judge_sampler.clone();
+        self.vote(messages, &config, self.max_tokens, &mut on_piece, |raw| {
+            check_decision(raw, valid_choices)
+        })
+    }
+
+    /// Sample a decision with `config` once per ensemble member, read each
+    /// reply with `read`, and take the [`majority`]. Only the first
+    /// member's output goes to `on_piece`.
+    fn vote(
+        &mut self,
+        messages: &[ChatMessage],
+        config: &SamplerConfig,
+        max_tokens: usize,
+        on_piece: &mut dyn FnMut(&str),
+        read: impl Fn(&str) -> Result<LlmDecision>,
+    ) -> Result<LlmDecision> {
+        let mut sampler = build_sampler(config, &self.model);
+        let raw = self.generate(messages, true, max_tokens, &mut sampler, on_piece)?;
+        let first = read(&raw);
+        if self.ensemble == 1 {
+            return first;
+        }
+
+        // The other members sample the same prompt. `generate` finds it in
+        // the KV cache and decodes only its last token again, for the
+        // logits, so the prompt is decoded once for the whole ensemble.
+        let mut usage = self.last_usage;
+        let mut votes = vec![first];
+        for member in 1..self.ensemble {
+            let config = SamplerConfig {
+                seed: config.seed.wrapping_add(member as u32),
+                ..config.clone()
+            };
+            let mut sampler = build_sampler(&config, &self.model);
+            let raw = self.generate(messages, true, max_tokens, &mut sampler, &mut |_| {})?;
+            usage.completion_tokens += self.last_usage.completion_tokens;
+            usage.throttled += self.last_usage.throttled;
+            votes.push(read(&raw));
+        }
+        self.last_usage = usage;
+        majority(votes)
+    }
+
+    /// [`LLM::judge_stream`] on the model loaded as `alias`, or on this one
+    /// if `alias` is `None` or was never loaded.
+    pub fn judge_with(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+        on_piece: impl FnMut(&str),
+    ) -> Result<LlmDecision> {
+        if let Some(alias) = alias {
+            if let Some(model) = self.pool.get_mut(alias) {
+                let judged = model.judge_stream(messages, valid_choices, on_piece);
+                self.last_usage = model.last_usage;
+                return judged;
             }
+            warn!("No model loaded as '{alias}'; judging on the default model");
+        }
+        self.judge_stream(messages, valid_choices, on_piece)
+    }
 
-            let piece = self
-                .model
-                .token_to_piece(tok, &mut decoder, true, None)
-                .context("token_to_piece failed")?;
-            output.push_str(&piece);
+    /// Judge an appeal with the supervisor profile, on the supervisor model
+    /// if one was loaded.
+    pub fn supervise(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        if let Some(supervisor) = self.supervisor.as_deref_mut() {
+            let judged = supervisor.judge(messages, valid_choices);
+            self.last_usage = supervisor.last_usage;
+            return judged;
+        }
+        info!("Supervising messages \n {messages:?}");
+        let config = self.supervisor_sampler.clone();
+        self.vote(messages, &config, self.max_tokens, &mut |_| {}, |raw| {
+            check_decision(raw, valid_choices)
+        })
+    }
 
-            batch.clear();
-            batch.add(tok, n_cur, &[0], true)?;
-            self.ctx.decode(&mut batch).context("decode step failed")?;
-            n_cur += 1;
+    /// Judge on a one-token reply: the number of the pick among
+    /// `valid_choices`, listed in that order in the prompt. Much faster
+    /// than a JSON reply, but the decision comes without a reason.
+    pub fn route(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        if let [only] = valid_choices {
+            self.last_usage = Usage::default();
+            return Ok(routed(only));
         }
+        let config = self.judge_sampler.clone();
+        self.vote(messages, &config, ROUTE_TOKENS, &mut |_| {}, |raw| {
+            parse_route(raw, valid_choices)
+        })
+    }
 
-        info!(
-            "=== LLM RAW OUTPUT ({} chars) ===\n{}\n=== END OUTPUT ===",
-            output.len(),
-            output
-        );
+    /// [`LLM::route`] on the model loaded as `alias`, as with
+    /// [`LLM::judge_with`].
+    pub fn route_with(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        if let Some(model) = alias.and_then(|alias| self.pool.get_mut(alias)) {
+            let routed = model.route(messages, valid_choices);
+            self.last_usage = model.last_usage;
+            return routed;
+        }
+        self.route(messages, valid_choices)
+    }
 
+    /// Core generation on the main context. `pinned` keeps a snapshot of
+    /// the system prompt for later calls (see [`Pins`]).
+    fn generate(
+        &mut self,
+        messages: &[ChatMessage],
+        pinned: bool,
+        max_tokens: usize,
+        sampler: &mut LlamaSampler,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<String> {
+        let (output, usage) = generate(
+            &self.model,
+            &mut self.ctx,
+            &mut self.cached,
+            pinned.then_some(&mut self.pins),
+            self.n_ctx,
+            max_tokens,
+            self.power_save,
+            messages,
+            sampler,
+            on_piece,
+        )?;
+        self.last_usage = usage;
         Ok(output)
     }
 }
 
+// ---------------------------------------------------------------------------
+// Backends
+// ---------------------------------------------------------------------------
+
+/// A model that judges answers. [`LLM`] is the one the game uses; embedding
+/// applications can bring their own to an [`crate::Engine`].
+pub trait LlmBackend {
+    /// Pick one of `valid_choices` for the last answer in `messages`, with
+    /// a reason. `alias` is the model the node asks for (see
+    /// `--model-alias`), if any.
+    fn judge(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision>;
+
+    /// [`LlmBackend::judge`], handing the raw output to `on_piece` as it
+    /// is generated. Backends that don't stream hand nothing on.
+    fn judge_stream(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+        _on_piece: &mut dyn FnMut(&str),
+    ) -> Result<LlmDecision> {
+        self.judge(alias, messages, valid_choices)
+    }
+
+    /// Judge in fast mode, where the decision may come without a reason.
+    /// Judged like any answer unless overridden.
+    fn route(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        self.judge(alias, messages, valid_choices)
+    }
+
+    /// Rule on an appeal. Judged like any answer unless overridden.
+    fn supervise(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        self.judge(None, messages, valid_choices)
+    }
+
+    /// A unit-length embedding of `text`, for the fallback classifier
+    /// ([`crate::game::classifier`]).
+    fn embed(&mut self, _text: &str) -> Result<Vec<f32>> {
+        anyhow::bail!("this backend has no embeddings")
+    }
+
+    /// Token counts and timing of the last call.
+    fn last_usage(&self) -> Usage {
+        Usage::default()
+    }
+}
+
+impl LlmBackend for LLM {
+    fn judge(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        self.judge_with(alias, messages, valid_choices, |_| {})
+    }
+
+    fn judge_stream(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<LlmDecision> {
+        self.judge_with(alias, messages, valid_choices, on_piece)
+    }
+
+    fn route(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        self.route_with(alias, messages, valid_choices)
+    }
+
+    fn supervise(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        LLM::supervise(self, messages, valid_choices)
+    }
+
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>> {
+        LLM::embed(self, text)
+    }
+
+    fn last_usage(&self) -> Usage {
+        self.last_usage
+    }
+}
+
+impl<B: LlmBackend + ?Sized> LlmBackend for &mut B {
+    fn judge(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        (**self).judge(alias, messages, valid_choices)
+    }
+
+    fn judge_stream(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<LlmDecision> {
+        (**self).judge_stream(alias, messages, valid_choices, on_piece)
+    }
+
+    fn route(
+        &mut self,
+        alias: Option<&str>,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        (**self).route(alias, messages, valid_choices)
+    }
+
+    fn supervise(
+        &mut self,
+        messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        (**self).supervise(messages, valid_choices)
+    }
+
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>> {
+        (**self).embed(text)
+    }
+
+    fn last_usage(&self) -> Usage {
+        (**self).last_usage()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Background slot
+// ---------------------------------------------------------------------------
+
+/// Handle for opening extra contexts on an already loaded model. It can be
+/// sent to another thread, where [`BackgroundModel::open_slot`] creates a
+/// context that runs independently of the main one.
+#[derive(Clone)]
+pub struct BackgroundModel {
+    model: Arc<LlamaModel>,
+    backend: Arc<LlamaBackend>,
+    sampler: SamplerConfig,
+}
+
+/// A small, low-priority context for background generation (e.g. ambient
+/// narration while the judge runs).
+pub struct BackgroundSlot {
+    ctx: LlamaContext<'static>,
+    cached: Vec<LlamaToken>,
+    n_ctx: u32,
+    max_tokens: usize,
+    sampler: SamplerConfig,
+    // Dropped after `ctx`, which borrows them.
+    model: Arc<LlamaModel>,
+    _backend: Arc<LlamaBackend>,
+}
+
+impl LLM {
+    /// Handle for opening background slots, using the chat sampler profile.
+    pub fn background(&self) -> BackgroundModel {
+        BackgroundModel {
+            model: self.model.clone(),
+            backend: self.backend.clone(),
+            sampler: self.chat_sampler.clone(),
+        }
+    }
+}
+
+impl BackgroundModel {
+    /// Create a context limited to `n_threads` threads so it does not starve
+    /// the main context.
+    pub fn open_slot(
+        self,
+        n_ctx: u32,
+        n_threads: i32,
+        max_tokens: usize,
+    ) -> Result<BackgroundSlot> {
+        let params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(n_ctx))
+            .with_n_threads(n_threads)
+            .with_n_threads_batch(n_threads);
+        let ctx = new_context(&self.model, &self.backend, params)
+            .context("failed to create background context")?;
+        Ok(BackgroundSlot {
+            ctx,
+            cached: Vec::new(),
+            n_ctx,
+            max_tokens,
+            sampler: self.sampler,
+            model: self.model,
+            _backend: self.backend,
+        })
+    }
+}
+
+impl BackgroundSlot {
+    /// Unconstrained completion with the given sampler seed.
+    pub fn chat(&mut self, messages: &[ChatMessage], seed: u32) -> Result<String> {
+        let config = SamplerConfig {
+            seed,
+            ..self.sampler.clone()
+        };
+        let mut sampler = build_sampler(&config, &self.model);
+        let (output, _) = generate(
+            &self.model,
+            &mut self.ctx,
+            &mut self.cached,
+            None,
+            self.n_ctx,
+            self.max_tokens,
+            None,
+            messages,
+            &mut sampler,
+            &mut |_| {},
+        )?;
+        Ok(output)
+    }
+}
+
+/// Create a context on `model`. The context borrows the model for
+/// `'static`, so its owner must also hold `model` (and the backend) and
+/// drop the context first: declare the context field before them.
+fn new_context(
+    model: &Arc<LlamaModel>,
+    backend: &LlamaBackend,
+    params: LlamaContextParams,
+) -> Result<LlamaContext<'static>> {
+    // SAFETY: the model stays at the same address for as long as an `Arc`
+    // to it is alive, and every owner of the context holds one and drops
+    // the context before it.
+    let model: &'static LlamaModel = unsafe { &*Arc::as_ptr(model) };
+    Ok(model.new_context(backend, params)?)
+}
+
+// ---------------------------------------------------------------------------
+// Generation
+// ---------------------------------------------------------------------------
+
+/// Core generation: tokenize messages, feed prompt, sample tokens.
+/// `cached` lists the tokens already in `ctx`'s KV cache and is kept up to
+/// date. With `pins`, a leading system prompt is restored from a snapshot
+/// if one beats `cached`, and snapshotted after it is decoded otherwise.
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    model: &LlamaModel,
+    ctx: &mut LlamaContext,
+    cached: &mut Vec<LlamaToken>,
+    mut pins: Option<&mut Pins>,
+    n_ctx: u32,
+    max_tokens: usize,
+    power_save: Option<PowerSave>,
+    messages: &[ChatMessage],
+    sampler: &mut LlamaSampler,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<(String, Usage)> {
+    info!("=== LLM CALL: {} messages ===", messages.len());
+    for (i, msg) in messages.iter().enumerate() {
+        debug!("  msg[{i}] {msg}");
+    }
+
+    let llama_msgs: Vec<LlamaChatMessage> = messages
+        .iter()
+        .map(|m| LlamaChatMessage::new(m.role.clone(), m.content.clone()))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("failed to create chat messages")?;
+
+    let tmpl = model
+        .chat_template(None)
+        .context("model has no chat template")?;
+    let prompt = model
+        .apply_chat_template(&tmpl, &llama_msgs, true)
+        .context("failed to apply chat template")?;
+
+    trace!("=== RENDERED PROMPT ===\n{prompt}\n=== END PROMPT ===");
+
+    let tokens = model
+        .str_to_token(&prompt, AddBos::Always)
+        .context("tokenization failed")?;
+
+    info!("Prompt tokenized: {} tokens", tokens.len());
+
+    // Where the system prompt ends, to snapshot the KV cache there.
+    let mut system_end = None;
+    if pins.is_some() && messages.first().is_some_and(|m| m.role == "system") {
+        let system = model
+            .apply_chat_template(&tmpl, &llama_msgs[..1], false)
+            .context("failed to apply chat template")?;
+        let system = model
+            .str_to_token(&system, AddBos::Always)
+            .context("tokenization failed")?;
+        if system.len() < tokens.len() && tokens.starts_with(&system) {
+            system_end = Some(system.len());
+        }
+    }
+
+    let mut reused = reusable_prefix(cached, &tokens);
+    if let Some(pins) = pins.as_deref_mut() {
+        if let Some((i, n)) = pins.best(&tokens, reused) {
+            let pin = pins.touch(i);
+            // SAFETY: `pin.state` was copied out of a context on the same
+            // model with the same parameters.
+            let read = unsafe { ctx.set_state_data(&pin.state) };
+            if read == pin.state.len() {
+                debug!("Restored {n} pinned prompt tokens");
+                cached.clone_from(&pin.tokens);
+                reused = n;
+            } else {
+                warn!("Failed to restore a pinned prompt; decoding it again");
+                pins.0.pop_front();
+                cached.clear();
+                reused = 0;
+            }
+        }
+    }
+    if reused == 0 {
+        ctx.clear_kv_cache();
+    } else {
+        debug!("Reusing {reused} cached prompt tokens");
+        ctx.clear_kv_cache_seq(Some(0), Some(reused as u32), None)
+            .context("failed to trim the KV cache")?;
+    }
+    cached.truncate(reused);
+
+    let mut throttle = Throttle::new(power_save);
+    let mut batch = LlamaBatch::new(n_ctx as usize, 1);
+    let last_idx = (tokens.len() - 1) as i32;
+    // Decoded in two parts if the system prompt is to be snapshotted.
+    let snapshot_at = system_end.filter(|&end| end > reused);
+    let mut from = reused;
+    for to in snapshot_at.into_iter().chain([tokens.len()]) {
+        let pending = &tokens[from..to];
+        // Power save decodes the prompt in chunks so it can pause in between.
+        let chunk_size = power_save.map_or(pending.len(), |p| p.prompt_chunk).max(1);
+        for (start, chunk) in (from as i32..)
+            .step_by(chunk_size)
+            .zip(pending.chunks(chunk_size))
+        {
+            batch.clear();
+            for (i, tok) in (start..).zip(chunk) {
+                batch.add(*tok, i, &[0], i == last_idx)?;
+            }
+            ctx.decode(&mut batch).context("initial decode failed")?;
+            cached.extend_from_slice(chunk);
+            throttle.pause();
+        }
+        if let Some(pins) = pins.as_deref_mut().filter(|_| Some(to) == snapshot_at) {
+            pins.insert(snapshot(ctx, cached));
+        }
+        from = to;
+    }
+
+    let mut output = String::new();
+    let mut decoder = encoding_rs::UTF_8.new_decoder();
+    let mut usage = Usage {
+        prompt_tokens: tokens.len(),
+        cached_tokens: reused,
+        ..Usage::default()
+    };
+
+    for n_cur in (tokens.len() as i32..).take(max_tokens) {
+        let tok = sampler.sample(ctx, batch.n_tokens() - 1);
+        sampler.accept(tok);
+
+        if model.is_eog_token(tok) {
+            debug!("Hit EOG token, stopping generation");
+            break;
+        }
+
+        let piece = model
+            .token_to_piece(tok, &mut decoder, true, None)
+            .context("token_to_piece failed")?;
+        on_piece(&piece);
+        output.push_str(&piece);
+
+        batch.clear();
+        batch.add(tok, n_cur, &[0], true)?;
+        ctx.decode(&mut batch).context("decode step failed")?;
+        cached.push(tok);
+        usage.completion_tokens += 1;
+        throttle.token();
+    }
+    usage.throttled = throttle.slept;
+
+    info!(
+        "=== LLM RAW OUTPUT ({} chars) ===\n{}\n=== END OUTPUT ===",
+        output.len(),
+        output
+    );
+
+    Ok((output, usage))
+}
+
+/// Negative log-probability of token `next` under `logits`, and the
+/// entropy of the whole distribution, both in nats.
+fn token_surprise(logits: &[f32], next: usize) -> (f32, f32) {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let sum: f32 = logits.iter().map(|l| (l - max).exp()).sum();
+    let log_sum = max + sum.ln();
+    let entropy = logits
+        .iter()
+        .map(|l| {
+            let log_p = l - log_sum;
+            -log_p.exp() * log_p
+        })
+        .sum();
+    let surprise = logits.get(next).map_or(f32::INFINITY, |l| log_sum - l);
+    (surprise, entropy)
+}
+
+/// How many leading `tokens` can keep their KV cache entries from `cached`,
+/// the tokens of the previous call (e.g. a primed judge role). The last
+/// prompt token is always decoded again, since sampling needs its logits.
+fn reusable_prefix(cached: &[LlamaToken], tokens: &[LlamaToken]) -> usize {
+    cached
+        .iter()
+        .zip(tokens)
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(tokens.len().saturating_sub(1))
+}
+
+// ---------------------------------------------------------------------------
+// Pinned prompts
+// ---------------------------------------------------------------------------
+
+/// Most system prompts [`Pins`] keeps a snapshot of.
+const MAX_PINS: usize = 8;
+
+/// KV cache snapshots of recent judge system prompts, most recently used
+/// first. A node's instruction and criteria are decoded once; when the
+/// player comes back to the node (a retry, a loop) after other nodes
+/// overwrote the cache, its snapshot is restored instead of decoding them
+/// again. The snapshots live in host memory so every call keeps the whole
+/// context window.
+#[derive(Default)]
+struct Pins(VecDeque<Pin>);
+
+struct Pin {
+    tokens: Vec<LlamaToken>,
+    /// The context state with exactly `tokens` in the KV cache.
+    state: Vec<u8>,
+}
+
+impl Pins {
+    /// The pin sharing the longest reusable prefix with `tokens`, and that
+    /// length, if it is longer than `reused`.
+    fn best(&self, tokens: &[LlamaToken], reused: usize) -> Option<(usize, usize)> {
+        self.0
+            .iter()
+            .map(|pin| reusable_prefix(&pin.tokens, tokens))
+            .enumerate()
+            .filter(|&(_, n)| n > reused)
+            .min_by_key(|&(_, n)| std::cmp::Reverse(n))
+    }
+
+    /// Mark the `i`th pin as just used.
+    fn touch(&mut self, i: usize) -> &Pin {
+        if let Some(pin) = self.0.remove(i) {
+            self.0.push_front(pin);
+        }
+        &self.0[0]
+    }
+
+    /// Add `pin`, replacing one of the same tokens and dropping the least
+    /// recently used past [`MAX_PINS`].
+    fn insert(&mut self, pin: Pin) {
+        self.0.retain(|old| old.tokens != pin.tokens);
+        self.0.push_front(pin);
+        self.0.truncate(MAX_PINS);
+    }
+}
+
+/// Snapshot of `ctx`, whose KV cache holds `tokens`.
+fn snapshot(ctx: &LlamaContext, tokens: &[LlamaToken]) -> Pin {
+    let mut state = vec![0; ctx.get_state_size()];
+    // SAFETY: `state` has room for `get_state_size` bytes, the most
+    // llama.cpp writes.
+    let written = unsafe { ctx.copy_state_data(state.as_mut_ptr()) };
+    state.truncate(written);
+    debug!(
+        "Pinned {} prompt tokens ({} bytes)",
+        tokens.len(),
+        state.len()
+    );
+    Pin {
+        tokens: tokens.to_vec(),
+        state,
+    }
+}
+
+/// Sleeps between decode chunks in power-save mode, keeping count of the
+/// time spent so it can be reported.
+struct Throttle {
+    power_save: Option<PowerSave>,
+    /// Tokens generated since the last pause.
+    pending: usize,
+    slept: Duration,
+}
+
+impl Throttle {
+    fn new(power_save: Option<PowerSave>) -> Self {
+        Self {
+            power_save,
+            pending: 0,
+            slept: Duration::ZERO,
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Some(power_save) = self.power_save {
+            std::thread::sleep(power_save.pause);
+            self.slept += power_save.pause;
+            self.pending = 0;
+        }
+    }
+
+    /// Count a generated token, pausing after every full chunk.
+    fn token(&mut self) {
+        self.pending += 1;
+        if self
+            .power_save
+            .is_some_and(|p| self.pending >= p.decode_chunk)
+        {
+            self.pause();
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // JSON extraction
 // ---------------------------------------------------------------------------
 
-pub fn parse_decision(raw: &str) -> Result<LlmDecision> {
+/// Parse a judge reply and check its decision is one of `valid_choices`.
+fn check_decision(raw: &str, valid_choices: &[&str]) -> Result<LlmDecision> {
+    let decision = parse_decision(raw)?;
+
+    if valid_choices.contains(&decision.decision.as_str()) {
+        info!(
+            "Judge succeeded: {} (reason: {})",
+            decision.decision, decision.reason
+        );
+        return Ok(decision);
+    }
+
+    anyhow::bail!(
+        "Judge generated invalid decision '{}' (valid: {:?})",
+        decision.decision,
+        valid_choices
+    );
+}
+
+/// The decision most ensemble members voted for, with the reason of the
+/// first to give it. Ties go to the earlier member. Members whose reply
+/// failed don't vote; if all failed, the first error is returned.
+fn majority(votes: Vec<Result<LlmDecision>>) -> Result<LlmDecision> {
+    let members = votes.len();
+    let mut tally: Vec<(LlmDecision, usize)> = Vec::new();
+    let mut failed = None;
+    for vote in votes {
+        match vote {
+            Ok(decision) => match tally
+                .iter_mut()
+                .find(|(d, _)| d.decision == decision.decision)
+            {
+                Some((_, n)) => *n += 1,
+                None => tally.push((decision, 1)),
+            },
+            Err(e) => {
+                warn!("Ensemble member failed: {e:#}");
+                failed.get_or_insert(e);
+            }
+        }
+    }
+    match tally.into_iter().min_by_key(|&(_, n)| std::cmp::Reverse(n)) {
+        Some((decision, n)) => {
+            info!("Ensemble: {n} of {members} voted {}", decision.decision);
+            Ok(decision)
+        }
+        None => Err(failed.expect("an ensemble has members")),
+    }
+}
+
+/// Remove `<think>...</think>` reasoning blocks, logging their contents.
+pub fn strip_think(raw: &str) -> String {
     let re_think = Regex::new(r"(?s)<think>(.*?)</think>").unwrap();
     for cap in re_think.captures_iter(raw) {
         let thought = cap.get(1).map_or("", |m| m.as_str()).trim();
@@ -286,8 +1368,43 @@ pub fn parse_decision(raw: &str) -> Result<LlmDecision> {
             debug!("Model thinking:\n{thought}");
         }
     }
+    re_think.replace_all(raw, "").into_owned()
+}
+
+/// Tokens a routed judge may generate: the number, and room for a leading
+/// space some tokenizers give it.
+const ROUTE_TOKENS: usize = 2;
+
+/// Reason given for routed decisions, which come without one.
+const ROUTED_REASON: &str = "(routed)";
+
+fn routed(decision: &str) -> LlmDecision {
+    LlmDecision {
+        decision: decision.to_string(),
+        reason: ROUTED_REASON.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Read a routed reply: the 1-based number of a pick in `valid_choices`.
+fn parse_route(raw: &str, valid_choices: &[&str]) -> Result<LlmDecision> {
+    let cleaned = strip_think(raw);
+    let digits: String = cleaned
+        .trim_start()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    let pick = digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| valid_choices.get(n.checked_sub(1)?))
+        .with_context(|| format!("Routed judge gave no valid pick: {raw:?}"))?;
+    info!("Judge routed: {pick}");
+    Ok(routed(pick))
+}
 
-    let cleaned = re_think.replace_all(raw, "");
+pub fn parse_decision(raw: &str) -> Result<LlmDecision> {
+    let cleaned = strip_think(raw);
     debug!("After stripping <think> blocks:\n{cleaned}");
 
     let re_json = Regex::new(r"(?s)\{[^{}]*\}").unwrap();
@@ -307,6 +1424,45 @@ pub fn parse_decision(raw: &str) -> Result<LlmDecision> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_route() {
+        let choices = ["PASS", "FAIL"];
+        assert_eq!(parse_route(" 2", &choices).unwrap().decision, "FAIL");
+        assert_eq!(parse_route("1.", &choices).unwrap().reason, ROUTED_REASON);
+        assert!(parse_route("3", &choices).is_err());
+        assert!(parse_route("0", &choices).is_err());
+        assert!(parse_route("PASS", &choices).is_err());
+    }
+
+    #[test]
+    fn test_majority_vote() {
+        let vote = |decision: &str, reason: &str| {
+            Ok(LlmDecision {
+                decision: decision.into(),
+                reason: reason.into(),
+                ..LlmDecision::default()
+            })
+        };
+        let d = majority(vec![
+            vote("FAIL", "vague"),
+            vote("PASS", "first pass"),
+            Err(anyhow::anyhow!("no JSON")),
+            vote("PASS", "second pass"),
+        ])
+        .unwrap();
+        assert_eq!(
+            (d.decision.as_str(), d.reason.as_str()),
+            ("PASS", "first pass")
+        );
+        assert_eq!(
+            majority(vec![vote("FAIL", ""), vote("PASS", "")])
+                .unwrap()
+                .decision,
+            "FAIL"
+        );
+        assert!(majority(vec![Err(anyhow::anyhow!("no JSON"))]).is_err());
+    }
+
     #[test]
     fn test_parse_decision_clean() {
         let raw = r#"{"decision": "FAILED_RUDE", "reason": "The user was hostile"}"#;
@@ -331,4 +1487,78 @@ Based on the interaction, {"decision":"PASSPORT_CHECK","reason":"Cooperated nice
         let d = parse_decision(raw).unwrap();
         assert_eq!(d.decision, "PASSPORT_CHECK");
     }
+
+    #[test]
+    fn test_parse_decision_with_facts() {
+        let raw =
+            r#"{"decision": "A", "reason": "ok", "origin_city": "Frankfurt", "purpose": null}"#;
+        let d = parse_decision(raw).unwrap();
+        assert_eq!(d.facts["origin_city"], "Frankfurt");
+        assert!(d.facts["purpose"].is_null());
+        assert!(!d.facts.contains_key("reason"));
+    }
+
+    #[test]
+    fn test_parse_decision_optional_fields() {
+        let raw = r#"{"decision": "A", "reason": "ok", "confidence": "0.8", "tone": "nervous",
+                      "delta": [1], "quote": "just visiting", "mood": 3}"#;
+        let d = parse_decision(raw).unwrap();
+        assert_eq!(d.confidence, Some(0.8));
+        assert_eq!(d.tone.as_deref(), Some("nervous"));
+        assert_eq!(d.quote.as_deref(), Some("just visiting"));
+        // Wrong type: ignored, not an error.
+        assert_eq!(d.suspicion_delta, None);
+        assert_eq!(d.facts["mood"], 3);
+        assert!(!d.facts.contains_key("tone"));
+    }
+
+    #[test]
+    fn test_reusable_prefix() {
+        let tokens = |ids: &[i32]| {
+            ids.iter()
+                .map(|&id| LlamaToken::new(id))
+                .collect::<Vec<_>>()
+        };
+        let primed = tokens(&[1, 2, 3]);
+        assert_eq!(reusable_prefix(&primed, &tokens(&[1, 2, 3, 4, 5])), 3);
+        assert_eq!(reusable_prefix(&primed, &tokens(&[1, 2, 9, 4])), 2);
+        // The last token is decoded again for its logits.
+        assert_eq!(reusable_prefix(&primed, &tokens(&[1, 2, 3])), 2);
+        assert_eq!(reusable_prefix(&[], &tokens(&[1])), 0);
+    }
+
+    #[test]
+    fn test_pins_restore_the_longest_prefix() {
+        let pin = |ids: &[i32]| Pin {
+            tokens: ids.iter().map(|&id| LlamaToken::new(id)).collect(),
+            state: Vec::new(),
+        };
+        let mut pins = Pins::default();
+        for i in 0..=MAX_PINS as i32 {
+            pins.insert(pin(&[1, 2, i]));
+        }
+        pins.insert(pin(&[1, 2, 3, 4]));
+        assert_eq!(pins.0.len(), MAX_PINS);
+        // The first pin was the least recently used.
+        assert!(pins.0.iter().all(|p| p.tokens[2] != LlamaToken::new(0)));
+
+        let prompt = pin(&[1, 2, 3, 4, 5, 6]).tokens;
+        assert_eq!(pins.best(&prompt, 0), Some((0, 4)));
+        assert_eq!(pins.best(&prompt, 4), None);
+        assert_eq!(pins.best(&pin(&[1, 2, 5, 9]).tokens, 2), Some((4, 3)));
+        assert_eq!(pins.touch(4).tokens[2], LlamaToken::new(5));
+        // Of equal pins, the most recently used.
+        assert_eq!(pins.best(&pin(&[1, 2, 9]).tokens, 1), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_token_surprise() {
+        let (surprise, entropy) = token_surprise(&[0.0; 4], 2);
+        assert!((surprise - 4f32.ln()).abs() < 1e-5);
+        assert!((entropy - 4f32.ln()).abs() < 1e-5);
+        let (likely, confident) = token_surprise(&[10.0, 0.0, 0.0, 0.0], 0);
+        assert!(likely < 0.01 && confident < 0.01);
+        let (unlikely, _) = token_surprise(&[10.0, 0.0, 0.0, 0.0], 1);
+        assert!(unlikely > 9.0);
+    }
 }
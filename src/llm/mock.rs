@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::{parse_decision, ChatMessage, LlmBackend, LlmDecision};
+
+/// A backend that plays back canned judge replies instead of running a
+/// model, so games can be tested without a GGUF file or a GPU.
+///
+/// A script has one reply per line, in the JSON the judge would produce,
+/// and every call (judge or supervisor) takes the next one. A line that is
+/// a JSON string instead is the judge's raw output, parsed when it is
+/// played like a model's would be, so a script can garble a reply:
+///
+/// ```json
+/// {"decision": "PASSPORT_CHECK", "reason": "Greeted the officer."}
+/// {"decision": "CLARIFY", "reason": "Too vague.", "confidence": 0.4}
+/// "I think they should pass"
+/// ```
+///
+/// Replies are handed over as they are, without checking them against the
+/// valid choices, so scripts can also exercise how the game copes with a
+/// judge that picks nonsense. Embeddings are a hashed bag of words, enough
+/// for the embedding fallback to pick the criterion sharing most words.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    replies: VecDeque<Scripted>,
+    /// Replies handed out so far.
+    played: usize,
+}
+
+/// One line of a script.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Scripted {
+    Raw(String),
+    Reply(LlmDecision),
+}
+
+/// Dimensions of the mock's embeddings.
+const EMBEDDING_DIMS: usize = 256;
+
+impl MockBackend {
+    pub fn new(replies: impl IntoIterator<Item = LlmDecision>) -> Self {
+        Self {
+            replies: replies.into_iter().map(Scripted::Reply).collect(),
+            played: 0,
+        }
+    }
+
+    /// Load the script at `path`. Blank lines are skipped.
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let replies = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("{}:{}: malformed judge reply", path.display(), i + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { replies, played: 0 })
+    }
+
+    /// Replies not handed out yet.
+    pub fn remaining(&self) -> usize {
+        self.replies.len()
+    }
+}
+
+impl LlmBackend for MockBackend {
+    fn judge(
+        &mut self,
+        _alias: Option<&str>,
+        _messages: &[ChatMessage],
+        valid_choices: &[&str],
+    ) -> Result<LlmDecision> {
+        let Some(reply) = self.replies.pop_front() else {
+            bail!(
+                "the mock script ran out after {} replies (asked to pick from {})",
+                self.played,
+                valid_choices.join(", ")
+            );
+        };
+        self.played += 1;
+        match reply {
+            Scripted::Reply(reply) => Ok(reply),
+            Scripted::Raw(raw) => parse_decision(&raw),
+        }
+    }
+
+    fn embed(&mut self, text: &str) -> Result<Vec<f32>> {
+        let mut embedding = vec![0.0f32; EMBEDDING_DIMS];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            // FNV-1a, so the same word lands in the same place every run.
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |h, b| {
+                    (h ^ u64::from(b)).wrapping_mul(0x100000001b3)
+                });
+            embedding[(hash % EMBEDDING_DIMS as u64) as usize] += 1.0;
+        }
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|x| *x /= norm);
+        }
+        Ok(embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_plays_back_its_script() {
+        let path = std::env::temp_dir().join(format!("elsa-mock-{}.jsonl", std::process::id()));
+        fs::write(
+            &path,
+            "{\"decision\": \"B\", \"reason\": \"ok\"}\n\n{\"decision\": \"C\", \"reason\": \"no\", \"confidence\": 0.5}\n",
+        )
+        .unwrap();
+        let mut mock = MockBackend::open(&path).unwrap();
+        assert_eq!(mock.remaining(), 2);
+        assert_eq!(mock.judge(None, &[], &["B"]).unwrap().decision, "B");
+        let second = mock.supervise(&[], &["B"]).unwrap();
+        assert_eq!(
+            (second.decision.as_str(), second.confidence),
+            ("C", Some(0.5))
+        );
+        let err = mock.judge(None, &[], &["B", "C"]).unwrap_err().to_string();
+        assert!(err.contains("ran out after 2 replies"), "{err}");
+
+        fs::write(&path, "{\"decision\": \"B\"}\n").unwrap();
+        let err = format!("{:#}", MockBackend::open(&path).unwrap_err());
+        assert!(err.contains(":1: malformed judge reply"), "{err}");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mock_garbles_and_embeds() {
+        let mut mock = MockBackend {
+            replies: [
+                "Pass, I suppose.",
+                "{\"decision\": \"B\", \"reason\": \"ok\"}",
+            ]
+            .map(|raw| Scripted::Raw(raw.to_string()))
+            .into(),
+            played: 0,
+        };
+        assert!(mock.judge(None, &[], &["B"]).is_err());
+        assert_eq!(mock.judge(None, &[], &["B"]).unwrap().decision, "B");
+
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let answer = mock.embed("Here is my PASSPORT.").unwrap();
+        assert!((dot(&answer, &answer) - 1.0).abs() < 1e-5);
+        let near = mock.embed("shows the passport").unwrap();
+        let far = mock.embed("refuses to answer").unwrap();
+        assert!(dot(&answer, &near) > dot(&answer, &far));
+    }
+}
@@ -0,0 +1,408 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use llama_cpp_2::model::LlamaModel;
+use llama_cpp_2::sampling::LlamaSampler;
+
+// ---------------------------------------------------------------------------
+// Sampler profile
+// ---------------------------------------------------------------------------
+
+/// Temperature at or below which a profile counts as near-greedy.
+pub const NEAR_GREEDY_TEMP: f32 = 0.3;
+
+/// Parameters of the sampler chain. The judge and free chat each get their
+/// own profile so the judge can run near-greedy while narration stays
+/// creative.
+#[derive(Debug, Clone)]
+pub struct SamplerConfig {
+    pub temperature: f32,
+    pub top_k: i32,
+    pub top_p: f32,
+    pub min_p: f32,
+    /// 1.0 disables the repetition penalty.
+    pub repeat_penalty: f32,
+    /// How many recent tokens the repetition penalty looks at.
+    pub repeat_last_n: i32,
+    pub seed: u32,
+    /// Dynamic temperature range: the effective temperature moves within
+    /// `temperature +/- dynatemp_range` based on entropy. 0.0 disables it.
+    pub dynatemp_range: f32,
+    pub dynatemp_exponent: f32,
+    /// Mirostat v2 replaces top-k/top-p/min-p and the final dist stage,
+    /// targeting a constant surprise instead of a fixed cutoff.
+    pub mirostat: Option<MirostatConfig>,
+    /// Explicit chain from `elsa.toml`. Replaces the parameters above when set.
+    pub chain: Option<Vec<SamplerStage>>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 1.0,
+            top_k: 40,
+            top_p: 0.95,
+            min_p: 0.0,
+            repeat_penalty: 1.1,
+            repeat_last_n: 64,
+            seed: 1234,
+            dynatemp_range: 0.0,
+            dynatemp_exponent: 1.0,
+            mirostat: None,
+            chain: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MirostatConfig {
+    /// Target surprise; lower is more focused.
+    pub tau: f32,
+    /// Learning rate.
+    pub eta: f32,
+}
+
+impl Default for MirostatConfig {
+    fn default() -> Self {
+        Self { tau: 5.0, eta: 0.1 }
+    }
+}
+
+impl SamplerConfig {
+    /// Near-greedy profile for judge calls, so the same answer gets the same
+    /// verdict from one run to the next.
+    pub fn judge() -> Self {
+        Self {
+            temperature: 0.2,
+            top_k: 20,
+            ..Self::default()
+        }
+    }
+
+    /// Stricter, near-greedy profile for the supervisor judging appeals.
+    pub fn supervisor() -> Self {
+        Self {
+            temperature: 0.1,
+            top_k: 10,
+            ..Self::default()
+        }
+    }
+
+    /// Whether samples from this profile hardly differ: it picks greedily,
+    /// keeps only the top token, or runs no hotter than
+    /// [`NEAR_GREEDY_TEMP`].
+    pub fn near_greedy(&self) -> bool {
+        self.stages().iter().any(|stage| match *stage {
+            SamplerStage::Greedy => true,
+            SamplerStage::TopK { k } => k == 1,
+            SamplerStage::Temp { t } => t <= NEAR_GREEDY_TEMP,
+            SamplerStage::TempExt { t, delta, .. } => t + delta <= NEAR_GREEDY_TEMP,
+            _ => false,
+        })
+    }
+
+    /// The chain described by this profile: the configured one if any,
+    /// otherwise the classic penalties -> top-k -> top-p -> min-p -> temp -> dist,
+    /// or penalties -> temp -> mirostat v2 when mirostat is enabled.
+    pub fn stages(&self) -> Vec<SamplerStage> {
+        if let Some(chain) = &self.chain {
+            return chain.clone();
+        }
+
+        let penalties = SamplerStage::Penalties {
+            last_n: self.repeat_last_n,
+            repeat: self.repeat_penalty,
+            freq: 0.0,
+            present: 0.0,
+        };
+        let temp = if self.dynatemp_range > 0.0 {
+            SamplerStage::TempExt {
+                t: self.temperature,
+                delta: self.dynatemp_range,
+                exponent: self.dynatemp_exponent,
+            }
+        } else {
+            SamplerStage::Temp {
+                t: self.temperature,
+            }
+        };
+
+        match self.mirostat {
+            Some(MirostatConfig { tau, eta }) => {
+                vec![penalties, temp, SamplerStage::MirostatV2 { tau, eta }]
+            }
+            None => vec![
+                penalties,
+                SamplerStage::TopK { k: self.top_k },
+                SamplerStage::TopP { p: self.top_p },
+                SamplerStage::MinP { p: self.min_p },
+                temp,
+                SamplerStage::Dist,
+            ],
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Configurable chain
+// ---------------------------------------------------------------------------
+
+/// One stage of a sampler chain as written in `elsa.toml`:
+///
+/// ```toml
+/// [sampler]
+/// judge = [{ type = "top_k", k = 40 }, { type = "temp", t = 0.6 }, { type = "dist" }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SamplerStage {
+    Penalties {
+        last_n: i32,
+        repeat: f32,
+        #[serde(default)]
+        freq: f32,
+        #[serde(default)]
+        present: f32,
+    },
+    TopK {
+        k: i32,
+    },
+    TopP {
+        p: f32,
+    },
+    MinP {
+        p: f32,
+    },
+    Typical {
+        p: f32,
+    },
+    Temp {
+        t: f32,
+    },
+    /// Dynamic temperature: `t` +/- `delta`, scaled by entropy.
+    TempExt {
+        t: f32,
+        delta: f32,
+        #[serde(default = "default_exponent")]
+        exponent: f32,
+    },
+    Xtc {
+        p: f32,
+        t: f32,
+    },
+    Mirostat {
+        tau: f32,
+        eta: f32,
+        #[serde(default = "default_mirostat_m")]
+        m: i32,
+    },
+    MirostatV2 {
+        tau: f32,
+        eta: f32,
+    },
+    Dist,
+    Greedy,
+}
+
+fn default_exponent() -> f32 {
+    1.0
+}
+
+fn default_mirostat_m() -> i32 {
+    100
+}
+
+impl SamplerStage {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Penalties { .. } => "penalties",
+            Self::TopK { .. } => "top_k",
+            Self::TopP { .. } => "top_p",
+            Self::MinP { .. } => "min_p",
+            Self::Typical { .. } => "typical",
+            Self::Temp { .. } => "temp",
+            Self::TempExt { .. } => "temp_ext",
+            Self::Xtc { .. } => "xtc",
+            Self::Mirostat { .. } => "mirostat",
+            Self::MirostatV2 { .. } => "mirostat_v2",
+            Self::Dist => "dist",
+            Self::Greedy => "greedy",
+        }
+    }
+
+    /// Stages that pick the final token. Exactly one must end the chain.
+    fn selects_token(&self) -> bool {
+        matches!(
+            self,
+            Self::Mirostat { .. } | Self::MirostatV2 { .. } | Self::Dist | Self::Greedy
+        )
+    }
+
+    fn build(&self, model: &LlamaModel, seed: u32) -> LlamaSampler {
+        match *self {
+            Self::Penalties {
+                last_n,
+                repeat,
+                freq,
+                present,
+            } => LlamaSampler::penalties(last_n, repeat, freq, present),
+            Self::TopK { k } => LlamaSampler::top_k(k),
+            Self::TopP { p } => LlamaSampler::top_p(p, 1),
+            Self::MinP { p } => LlamaSampler::min_p(p, 1),
+            Self::Typical { p } => LlamaSampler::typical(p, 1),
+            Self::Temp { t } => LlamaSampler::temp(t),
+            Self::TempExt { t, delta, exponent } => LlamaSampler::temp_ext(t, delta, exponent),
+            Self::Xtc { p, t } => LlamaSampler::xtc(p, t, 1, seed),
+            Self::Mirostat { tau, eta, m } => {
+                LlamaSampler::mirostat(model.n_vocab(), seed, tau, eta, m)
+            }
+            Self::MirostatV2 { tau, eta } => LlamaSampler::mirostat_v2(seed, tau, eta),
+            Self::Dist => LlamaSampler::dist(seed),
+            Self::Greedy => LlamaSampler::greedy(),
+        }
+    }
+}
+
+/// Check a configured chain for mistakes llama.cpp would not report.
+pub fn validate_chain(chain: &[SamplerStage]) -> Result<()> {
+    let Some(last) = chain.last() else {
+        bail!("sampler chain is empty");
+    };
+
+    for (i, stage) in chain.iter().enumerate() {
+        let name = stage.name();
+        let pos = i + 1;
+        if stage.selects_token() && pos != chain.len() {
+            bail!(
+                "stage {pos} ({name}) picks the final token and must be the last stage; \
+                 move it to the end of the chain"
+            );
+        }
+        let probability = match *stage {
+            SamplerStage::TopP { p }
+            | SamplerStage::MinP { p }
+            | SamplerStage::Typical { p }
+            | SamplerStage::Xtc { p, .. } => Some(p),
+            _ => None,
+        };
+        if let Some(p) = probability {
+            if !(0.0..=1.0).contains(&p) {
+                bail!("stage {pos} ({name}): p must be between 0 and 1, got {p}");
+            }
+        }
+        match *stage {
+            SamplerStage::TopK { k } if k < 1 => {
+                bail!("stage {pos} (top_k): k must be at least 1, got {k}")
+            }
+            SamplerStage::Temp { t } | SamplerStage::TempExt { t, .. } if t < 0.0 => {
+                bail!("stage {pos} ({name}): t must not be negative, got {t}")
+            }
+            _ => {}
+        }
+    }
+
+    if !last.selects_token() {
+        bail!(
+            "sampler chain ends with '{}' but must end with a token-selecting stage \
+             (dist, greedy, mirostat or mirostat_v2)",
+            last.name()
+        );
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Builder
+// ---------------------------------------------------------------------------
+
+pub fn build_sampler(config: &SamplerConfig, model: &LlamaModel) -> LlamaSampler {
+    LlamaSampler::chain_simple(
+        config
+            .stages()
+            .iter()
+            .map(|stage| stage.build(model, config.seed)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Vec<SamplerStage> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_default_chain_is_valid() {
+        validate_chain(&SamplerConfig::default().stages()).unwrap();
+    }
+
+    #[test]
+    fn test_judge_profile_is_near_greedy() {
+        let judge = SamplerConfig::judge();
+        let chat = SamplerConfig::default();
+        assert!(judge.temperature <= 0.2);
+        assert!(judge.temperature < chat.temperature);
+        assert_ne!(judge.stages(), chat.stages());
+        validate_chain(&judge.stages()).unwrap();
+    }
+
+    #[test]
+    fn test_near_greedy() {
+        assert!(SamplerConfig::judge().near_greedy());
+        assert!(!SamplerConfig::default().near_greedy());
+        let hot_judge = SamplerConfig {
+            temperature: 0.8,
+            ..SamplerConfig::judge()
+        };
+        assert!(!hot_judge.near_greedy());
+        let greedy = SamplerConfig {
+            chain: Some(parse(r#"[{"type": "greedy"}]"#)),
+            ..SamplerConfig::default()
+        };
+        assert!(greedy.near_greedy());
+    }
+
+    #[test]
+    fn test_mirostat_and_dynatemp_profile() {
+        let config = SamplerConfig {
+            dynatemp_range: 0.3,
+            mirostat: Some(MirostatConfig::default()),
+            ..SamplerConfig::default()
+        };
+        let stages = config.stages();
+        validate_chain(&stages).unwrap();
+        assert!(matches!(stages[1], SamplerStage::TempExt { delta, .. } if delta == 0.3));
+        assert!(matches!(
+            stages.last(),
+            Some(SamplerStage::MirostatV2 { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_chain() {
+        let chain = parse(
+            r#"[{"type": "top_k", "k": 40}, {"type": "temp", "t": 0.6},
+                {"type": "mirostat_v2", "tau": 5.0, "eta": 0.1}]"#,
+        );
+        assert_eq!(chain[1], SamplerStage::Temp { t: 0.6 });
+        validate_chain(&chain).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_stage_is_rejected() {
+        let err = serde_json::from_str::<Vec<SamplerStage>>(r#"[{"type": "top_q", "q": 1}]"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("top_q"), "{err}");
+    }
+
+    #[test]
+    fn test_selector_must_be_last() {
+        let err = validate_chain(&parse(r#"[{"type": "dist"}, {"type": "top_k", "k": 1}]"#))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("must be the last stage"), "{err}");
+        assert!(validate_chain(&parse(r#"[{"type": "temp", "t": 0.5}]"#)).is_err());
+    }
+}
@@ -1,53 +1,569 @@
-mod game;
-mod llm;
+mod cli;
+
+use std::io::IsTerminal;
 
 use anyhow::{Context, Result};
-use llm::{ModelConfig, LLM};
+use cli::{Cli, Command};
+use elsa::config::Config;
+use elsa::game;
+use elsa::llm::{ModelConfig, PowerSave, LLM};
+use elsa::rng::Rng;
+#[cfg(feature = "voice")]
+use elsa::voice;
 
-use crate::game::tree::GameTree;
+use elsa::game::corrections::Corrections;
+use elsa::game::eval::EvalSuite;
+use elsa::game::jsonl::{Io, JsonlUi};
+use elsa::game::locale;
+use elsa::game::pacing::PacingConfig;
+use elsa::game::party::Party;
+use elsa::game::permissions;
+use elsa::game::save::SaveFile;
+use elsa::game::scenarios;
+use elsa::game::simulate::SimulateOptions;
+use elsa::game::stats;
+use elsa::game::transcript::{read_log, LogEntry};
+use elsa::game::tree::GameTree;
+use elsa::game::ui::{Colors, PlainUi};
+use elsa::game::RunOptions;
 
 fn main() -> Result<()> {
     // Initialize logging. Control verbosity with RUST_LOG env var:
     //   RUST_LOG=info   cargo run -- model.gguf   # messages + transitions
     //   RUST_LOG=debug  cargo run -- model.gguf   # + judge instructions + parsed JSON
     //   RUST_LOG=trace  cargo run -- model.gguf   # + full rendered prompt template
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .target(env_logger::Target::Stdout)
-        .format_timestamp_millis()
-        .init();
-
-    let args: Vec<String> = std::env::args().collect();
-
-    let model_path = args.get(1).context(
-        "
-        Usage: cargo run <path-to-model.gguf> <path-to-scenario.json>
-        \n\
-        Example:\n  cargo run ./SmolLM3-Q4_K_M ./scenarios/airport.json \n\
-    ",
-    )?;
+    //
+    // The TUI owns the terminal, so its log goes to `elsa.log` instead. In
+    // JSONL mode stdout carries the protocol, so the log goes to stderr.
+    let cli = Cli::parse(std::env::args().skip(1))?;
+    let target = if use_tui(&cli) {
+        env_logger::Target::Pipe(Box::new(
+            std::fs::File::create(TUI_LOG_PATH).context("creating TUI log file")?,
+        ))
+    } else if cli.io == Io::Jsonl {
+        env_logger::Target::Stderr
+    } else {
+        env_logger::Target::Stdout
+    };
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if cli.no_color {
+        logger.write_style(env_logger::WriteStyle::Never);
+    }
+    logger.target(target).format_timestamp_millis().init();
 
-    let scenario = args.get(2).context(
-        "
-        Usage: cargo run <path-to-model.gguf> <path-to-scenario.json>
-        \n\
-        Example:\n  cargo run ./SmolLM3-Q4_K_M ./scenarios/airport.json \n\
-    ",
-    )?;
+    let app_config = Config::load(cli.config_path.as_deref())?;
 
-    let config = ModelConfig {
-        n_gpu_layers: 0,
-        n_ctx: 8092,
-        max_tokens: 1024,
-    };
+    // Every RNG in the run derives from this seed.
+    let seed = cli.seed.unwrap_or_else(Rng::seed_from_clock);
+    let mut seeds = Rng::new(seed);
+    let config = model_config(&cli, &app_config, &mut seeds)?;
+
+    match &cli.command {
+        Command::Play => play(&cli, app_config, config, seed, seeds),
+        Command::Replay { log_path } => replay(&cli, config, log_path),
+        Command::Simulate => simulate(&cli, config, seeds),
+        Command::Eval { suite_path } => eval(&cli, config, suite_path),
+        Command::Graph => graph(&cli),
+        Command::Lint => lint(&cli),
+        Command::Serve => serve(&cli, config, seeds),
+        Command::Discord => discord(&cli, config, seeds),
+        Command::Telegram => telegram(&cli, config, seeds),
+        Command::Loadtest { url } => loadtest(&cli, url.as_deref()),
+    }
+}
+
+fn model_config(cli: &Cli, app_config: &Config, seeds: &mut Rng) -> Result<ModelConfig> {
+    let mut config = ModelConfig::default();
+    if let Some((name, profile)) = app_config.hardware(cli.hw.as_deref())? {
+        log::info!("Hardware profile: {name}");
+        profile.apply(&mut config);
+    }
+    config.judge_sampler.chain = app_config.sampler.judge.clone();
+    config.chat_sampler.chain = app_config.sampler.chat.clone();
+    config.supervisor_sampler.chain = app_config.sampler.supervisor.clone();
+    config.judge_sampler.temperature = cli
+        .difficulty
+        .judge_temperature(config.judge_sampler.temperature);
+    config.judge_sampler.seed = seeds.next_u32();
+    config.chat_sampler.seed = seeds.next_u32();
+    // Derived rather than drawn, so existing seeds replay the same games.
+    config.supervisor_sampler.seed = config.judge_sampler.seed.rotate_left(16);
+    cli.judge_sampler.apply(&mut config.judge_sampler);
+    cli.chat_sampler.apply(&mut config.chat_sampler);
+    if let Some(ensemble) = cli.ensemble {
+        config.ensemble = ensemble;
+    }
+    config.power_save = cli.power_save.then(PowerSave::default);
+    Ok(config)
+}
 
+fn load_model(model_path: &str, config: ModelConfig) -> Result<LLM> {
     eprintln!("Loading model: {model_path}");
     eprintln!("GPU layers : {}", config.n_gpu_layers);
     eprintln!("Context    : {}", config.n_ctx);
+    if let Some(n_threads) = config.n_threads {
+        eprintln!("Threads    : {n_threads}");
+    }
+    if let Some(cache_type) = config.cache_type {
+        eprintln!("KV cache   : {cache_type:?}");
+    }
     eprintln!("Max tokens : {}", config.max_tokens);
+    eprintln!("Judge temp : {}", config.judge_sampler.temperature);
+    if config.ensemble > 1 {
+        eprintln!("Ensemble   : {} judges", config.ensemble);
+    }
+    if let Some(power_save) = config.power_save {
+        eprintln!(
+            "Power save : {} threads, {}ms pause every {} tokens",
+            power_save.n_threads,
+            power_save.pause.as_millis(),
+            power_save.decode_chunk
+        );
+    }
+    LLM::load_model(model_path, config).context("failed to load model")
+}
+
+/// Load the judge model, plus the supervisor model and aliased models
+/// if any were given.
+fn load_models(cli: &Cli, model_path: &str, config: ModelConfig) -> Result<LLM> {
+    let mut model = load_model(model_path, config.clone())?;
+    load_aliases(cli, &mut model, &config)?;
+    if let Some(path) = &cli.supervisor_model {
+        eprintln!("Supervisor : {path}");
+        model.load_supervisor(path, config)?;
+    }
+    Ok(model)
+}
+
+/// Load the models given with `--model-alias`, for nodes that route to one.
+fn load_aliases(cli: &Cli, model: &mut LLM, config: &ModelConfig) -> Result<()> {
+    for (alias, path) in &cli.model_aliases {
+        eprintln!("Model alias: {alias} = {path}");
+        model.load_alias(alias, path, config.clone())?;
+    }
+    Ok(())
+}
 
-    let mut model = LLM::load_model(model_path, config).context("failed to load model")?;
+fn play(
+    cli: &Cli,
+    app_config: Config,
+    config: ModelConfig,
+    seed: u64,
+    mut seeds: Rng,
+) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
 
-    let game_tree: GameTree = serde_json::from_str(&std::fs::read_to_string(scenario)?)?;
+    let save = cli
+        .resume_path
+        .as_deref()
+        .map(|p| SaveFile::read(std::path::Path::new(p)))
+        .transpose()?;
+    let scenario = cli
+        .scenario_path
+        .clone()
+        .or_else(|| save.as_ref().and_then(|s| s.scenario_path.clone()))
+        .context("the save does not record its scenario; pass the scenario path explicitly")?;
+    let game_tree = load_scenario(cli, &scenario)?;
+
+    let mut pacing = app_config.pacing.clone();
+    if let Some(cps) = cli.cps {
+        pacing.cps = cps;
+    }
+    if cli.fast {
+        pacing.cps = 0.0;
+    }
+    #[cfg(not(feature = "voice"))]
+    if cli.speak || cli.listen {
+        anyhow::bail!("this build has no text to speech; rebuild with --features voice");
+    }
+
+    let mut model = load_models(cli, model_path, config)?;
+    eprintln!("Seed       : {seed} (pass --seed {seed} to reproduce this run)");
+    if let Some(dir) = &cli.log_dir {
+        eprintln!(
+            "Transcripts: {dir} (rotate at {} bytes, keep {}, compress: {})",
+            app_config.persistence.max_bytes,
+            app_config.persistence.max_files,
+            app_config.persistence.compress
+        );
+    }
+
+    let options = RunOptions {
+        seed: seeds.next_u64(),
+        scenario_path: Some(scenario),
+        resume: save,
+        // Nobody is at the keyboard to ask over JSONL.
+        migrate: if cli.migrate {
+            Some(true)
+        } else if cli.io == Io::Jsonl {
+            Some(false)
+        } else {
+            None
+        },
+        log_dir: cli.log_dir.clone().map(Into::into),
+        retention: app_config.persistence.clone(),
+        strict: cli.strict,
+        ambient: cli.ambient && !cli.fast,
+        corrections: open_corrections(cli)?,
+        party: (!cli.players.is_empty())
+            .then(|| Party::new(cli.players.clone()))
+            .transpose()?,
+        clock: !cli.no_clock,
+        difficulty: cli.difficulty,
+        adaptive: cli.adaptive,
+        narrate: cli.narrate && !cli.fast,
+        fast: cli.fast,
+        language_hints: cli
+            .language_hints
+            .then(|| cli.lang.clone().unwrap_or_else(|| "en".to_string())),
+        stats: (!cli.no_stats).then(|| {
+            cli.stats_path
+                .as_deref()
+                .unwrap_or(stats::DEFAULT_STATS_PATH)
+                .into()
+        }),
+    };
+
+    let played = play_on(cli, &mut model, game_tree, options, pacing, app_config);
+    model.shutdown();
+    played
+}
+
+/// Run the game loop on the front end the command line asks for.
+fn play_on(
+    cli: &Cli,
+    model: &mut LLM,
+    game_tree: GameTree,
+    options: RunOptions,
+    pacing: PacingConfig,
+    app_config: Config,
+) -> Result<()> {
+    if cli.io == Io::Jsonl {
+        return game::run(&mut JsonlUi::stdio(), model, game_tree, options);
+    }
+    let keys = app_config.keys;
+    #[cfg(feature = "voice")]
+    let voice = voice::Voice {
+        listener: cli
+            .listen
+            .then(|| voice::Listener::new(&app_config.voice))
+            .transpose()?,
+        speech: cli
+            .speak
+            .then(|| voice::Speech::start(app_config.voice))
+            .transpose()?,
+    };
+    #[cfg(feature = "tui")]
+    if use_tui(cli) {
+        let mut ui =
+            game::tui::TuiUi::new(keys, locale::messages(cli.lang.as_deref()))?.paced(pacing);
+        #[cfg(feature = "voice")]
+        {
+            ui = ui.voiced(voice);
+        }
+        return game::run(&mut ui, model, game_tree, options);
+    }
+    let messages = locale::messages(cli.lang.as_deref());
+    let colors = if cli.no_color {
+        Colors::OFF
+    } else {
+        Colors::detect()
+    };
+    let mut ui = PlainUi::new(pacing, keys, messages, colors);
+    #[cfg(feature = "voice")]
+    {
+        ui = ui.voiced(voice);
+    }
+    game::run(&mut ui, model, game_tree, options)
+}
+
+/// Load a scenario, switched to the `--lang` language if one was given.
+fn load_scenario(cli: &Cli, path: &str) -> Result<GameTree> {
+    let mut tree = scenarios::load(path)?;
+    // Looking at a scenario runs none of it.
+    if !matches!(cli.command, Command::Graph | Command::Lint) {
+        permissions::check_allowed(&tree, &cli.allow, path)?;
+    }
+    if let Some(lang) = &cli.lang {
+        if tree.localize(lang) == 0 {
+            log::warn!("The scenario has no text in {lang}; playing it as written");
+        }
+    }
+    Ok(tree)
+}
+
+/// Log file used while the TUI owns the terminal.
+const TUI_LOG_PATH: &str = "elsa.log";
+
+/// The TUI is used for interactive play unless `--plain` is given or stdout
+/// is not a terminal (e.g. piped into a file).
+fn use_tui(cli: &Cli) -> bool {
+    cfg!(feature = "tui")
+        && matches!(cli.command, Command::Play)
+        && !cli.plain
+        && cli.io == Io::Terminal
+        && std::io::stdout().is_terminal()
+}
+
+/// Print a recorded session, re-judging every turn when a model is given.
+fn replay(cli: &Cli, config: ModelConfig, log_path: &str) -> Result<()> {
+    let entries = read_log(std::path::Path::new(log_path))?;
+
+    let Some(model_path) = cli.model_path.as_deref() else {
+        game::replay::replay(&entries, None)?;
+        return Ok(());
+    };
+
+    // The scenario supplies each node's valid choices and judge context.
+    let recorded = entries.iter().find_map(|e| match e {
+        LogEntry::SessionStart { scenario_path, .. } => scenario_path.clone(),
+        _ => None,
+    });
+    let scenario = cli
+        .scenario_path
+        .clone()
+        .or(recorded)
+        .context("the transcript does not record its scenario; pass --scenario")?;
+    let game_tree = load_scenario(cli, &scenario)?;
+
+    let mut model = load_model(model_path, config.clone())?;
+    load_aliases(cli, &mut model, &config)?;
+    game::replay::replay(&entries, Some((&mut model, &game_tree)))?;
+    Ok(())
+}
+
+/// Let the model play the traveller and report how the games ended.
+fn simulate(cli: &Cli, config: ModelConfig, mut seeds: Rng) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let game_tree = load_scenario(cli, scenario)?;
+
+    let mut model = load_models(cli, model_path, config)?;
+    game::simulate::simulate(
+        &mut model,
+        &game_tree,
+        &SimulateOptions {
+            games: cli.games.unwrap_or(20),
+            traveller: cli.traveller.unwrap_or_default(),
+            max_turns: cli.max_turns.unwrap_or(30),
+            seed: seeds.next_u64(),
+            strict: cli.strict,
+            dataset: cli.output_path.clone().map(Into::into),
+            scenario_path: Some(scenario.to_string()),
+        },
+    )
+}
+
+/// Run the scenario as a Discord bot until interrupted.
+#[cfg(feature = "discord")]
+fn discord(cli: &Cli, config: ModelConfig, mut seeds: Rng) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let game_tree = load_scenario(cli, scenario)?;
+
+    let mut model = load_models(cli, model_path, config)?;
+    game::discord::run(
+        &mut model,
+        &game_tree,
+        game::discord::DiscordOptions {
+            seed: seeds.next_u64(),
+            strict: cli.strict,
+            messages: locale::messages(cli.lang.as_deref()),
+        },
+    )
+}
+
+#[cfg(not(feature = "discord"))]
+fn discord(_cli: &Cli, _config: ModelConfig, _seeds: Rng) -> Result<()> {
+    anyhow::bail!("this build has no Discord support; rebuild with --features discord")
+}
+
+/// Run the scenario as a Telegram bot until interrupted.
+#[cfg(feature = "telegram")]
+fn telegram(cli: &Cli, config: ModelConfig, mut seeds: Rng) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let game_tree = load_scenario(cli, scenario)?;
+
+    let mut model = load_models(cli, model_path, config)?;
+    game::telegram::run(
+        &mut model,
+        &game_tree,
+        game::telegram::TelegramOptions {
+            seed: seeds.next_u64(),
+            strict: cli.strict,
+            messages: locale::messages(cli.lang.as_deref()),
+        },
+    )
+}
+
+#[cfg(not(feature = "telegram"))]
+fn telegram(_cli: &Cli, _config: ModelConfig, _seeds: Rng) -> Result<()> {
+    anyhow::bail!("this build has no Telegram support; rebuild with --features telegram")
+}
+
+/// Load test a running server and print the report.
+#[cfg(feature = "loadtest")]
+fn loadtest(cli: &Cli, url: Option<&str>) -> Result<()> {
+    let options = game::loadtest::LoadtestOptions {
+        url: url.map_or_else(
+            || format!("http://127.0.0.1:{}", cli.port.unwrap_or(8080)),
+            String::from,
+        ),
+        sessions: cli.sessions.unwrap_or(20),
+        turns: cli.turns.unwrap_or(10),
+    };
+    game::loadtest::run(&options)?.print();
+    Ok(())
+}
+
+#[cfg(not(feature = "loadtest"))]
+fn loadtest(_cli: &Cli, _url: Option<&str>) -> Result<()> {
+    anyhow::bail!("this build has no load tester; rebuild with --features loadtest")
+}
+
+/// Serve the scenario over HTTP until interrupted.
+#[cfg(feature = "server")]
+fn serve(cli: &Cli, config: ModelConfig, mut seeds: Rng) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let game_tree = load_scenario(cli, scenario)?;
+
+    let mut model = load_models(cli, model_path, config)?;
+    game::server::serve(
+        &mut model,
+        &game_tree,
+        game::server::ServeOptions {
+            port: cli.port.unwrap_or(8080),
+            seed: seeds.next_u64(),
+            strict: cli.strict,
+        },
+    )
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_cli: &Cli, _config: ModelConfig, _seeds: Rng) -> Result<()> {
+    anyhow::bail!("this build has no HTTP server; rebuild with --features server")
+}
+
+/// Judge a suite of labeled cases and print the report.
+fn eval(cli: &Cli, config: ModelConfig, suite_path: &str) -> Result<()> {
+    let model_path = cli.model_path.as_deref().context("missing model path")?;
+    let suite = EvalSuite::read(std::path::Path::new(suite_path))?;
+    let scenario = cli
+        .scenario_path
+        .clone()
+        .or_else(|| suite.scenario.clone())
+        .context("the test file does not name its scenario; pass --scenario")?;
+    if cli.lang_matrix {
+        // Every language is compared to the scenario as written.
+        let game_tree = scenarios::load(&scenario)?;
+        permissions::check_allowed(&game_tree, &cli.allow, &scenario)?;
+        let mut corrections = open_corrections(cli)?;
+        let mut model = load_model(model_path, config.clone())?;
+        load_aliases(cli, &mut model, &config)?;
+        let matrix = game::eval::evaluate_languages(
+            &mut model,
+            &game_tree,
+            &suite,
+            cli.strict,
+            corrections.as_mut(),
+        )?;
+        matrix.print();
+        return Ok(());
+    }
+    let game_tree = load_scenario(cli, &scenario)?;
+    // Before the run, so a bad path doesn't cost one.
+    for path in &cli.report_paths {
+        game::report::Format::of(path)?;
+    }
+
+    let mut corrections = open_corrections(cli)?;
+    let mut model = load_model(model_path, config.clone())?;
+    load_aliases(cli, &mut model, &config)?;
+    let report = game::eval::evaluate(
+        &mut model,
+        &game_tree,
+        &suite,
+        cli.strict,
+        corrections.as_mut(),
+    )?;
+    report.print();
+    for path in &cli.report_paths {
+        game::report::write(&report, path)?;
+        eprintln!("Wrote {path}");
+    }
+    if let Some(corrections) = corrections.as_mut() {
+        let added = report.record_failures(corrections)?;
+        println!(
+            "\n{added} new corrections recorded ({} in the library)",
+            corrections.len()
+        );
+    }
+    Ok(())
+}
+
+fn open_corrections(cli: &Cli) -> Result<Option<Corrections>> {
+    cli.corrections_path
+        .as_deref()
+        .map(|p| Corrections::open(std::path::Path::new(p)))
+        .transpose()
+}
+
+/// Print the scenario's wording warnings.
+fn lint(cli: &Cli) -> Result<()> {
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let tree = load_scenario(cli, scenario)?;
+    let warnings = game::lint::lint(&tree);
+    for warning in &warnings {
+        println!("{warning}");
+    }
+    println!("{} warnings in {scenario}", warnings.len());
+    Ok(())
+}
+
+/// Export the scenario as a DOT graph or a Mermaid flowchart.
+fn graph(cli: &Cli) -> Result<()> {
+    let scenario = cli
+        .scenario_path
+        .as_deref()
+        .context("missing scenario path")?;
+    let tree = load_scenario(cli, scenario)?;
+
+    let format = cli.graph_format.as_deref().unwrap_or_else(|| {
+        match cli.output_path.as_deref().and_then(|p| p.rsplit_once('.')) {
+            Some((_, "mmd" | "md")) => "mermaid",
+            _ => "dot",
+        }
+    });
+    let text = match format {
+        "dot" => game::graph::to_dot(&tree),
+        "mermaid" => game::graph::to_mermaid(&tree),
+        other => anyhow::bail!("unknown graph format '{other}' (expected dot or mermaid)"),
+    };
 
-    game::run(&mut model, game_tree)
+    match &cli.output_path {
+        Some(path) => {
+            std::fs::write(path, text).with_context(|| format!("failed to write {path}"))?;
+            eprintln!("Wrote {path}");
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
 }
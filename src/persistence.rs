@@ -0,0 +1,193 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{debug, info};
+use serde::Deserialize;
+
+// ---------------------------------------------------------------------------
+// Retention policy
+// ---------------------------------------------------------------------------
+
+/// How large an append-only file (journal, transcript) may grow before it is
+/// rotated, and how many rotated generations are kept.
+///
+/// ```toml
+/// [persistence]
+/// max_bytes = 5242880
+/// max_files = 5
+/// compress = true
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Rotate once the live file reaches this many bytes. 0 disables rotation.
+    pub max_bytes: u64,
+    /// Number of rotated files kept next to the live one. Older ones are deleted.
+    pub max_files: usize,
+    /// Gzip rotated files.
+    pub compress: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: 5 * 1024 * 1024,
+            max_files: 5,
+            compress: true,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Rotation
+// ---------------------------------------------------------------------------
+
+/// Path of the `n`th rotated generation of `path` (1 = most recent).
+fn rotated_path(path: &Path, n: usize, compressed: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    if compressed {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Find generation `n` on disk regardless of whether it was compressed.
+fn existing_generation(path: &Path, n: usize) -> Option<PathBuf> {
+    [true, false]
+        .into_iter()
+        .map(|gz| rotated_path(path, n, gz))
+        .find(|p| p.exists())
+}
+
+/// Rotate `path` if it has grown past the policy's size limit. Returns
+/// whether a rotation happened.
+///
+/// `file.jsonl` becomes `file.jsonl.1[.gz]`, existing generations shift up by
+/// one and anything beyond `max_files` is deleted.
+pub fn rotate_if_needed(path: &Path, policy: &RetentionPolicy) -> Result<bool> {
+    if policy.max_bytes == 0 {
+        return Ok(false);
+    }
+    let size = match fs::metadata(path) {
+        Ok(m) => m.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).context(format!("failed to stat {}", path.display())),
+    };
+    if size < policy.max_bytes {
+        return Ok(false);
+    }
+
+    info!(
+        "Rotating {} ({size} bytes >= {} limit)",
+        path.display(),
+        policy.max_bytes
+    );
+
+    // Drop generations that fall outside the retention window.
+    let mut n = policy.max_files.max(1);
+    while let Some(old) = existing_generation(path, n) {
+        debug!("Removing expired {}", old.display());
+        fs::remove_file(&old)?;
+        n += 1;
+    }
+
+    // Shift the remaining generations up by one, oldest first.
+    for n in (1..policy.max_files).rev() {
+        if let Some(src) = existing_generation(path, n) {
+            let compressed = src.extension().is_some_and(|e| e == "gz");
+            fs::rename(&src, rotated_path(path, n + 1, compressed))?;
+        }
+    }
+
+    if policy.max_files == 0 {
+        fs::remove_file(path)?;
+    } else if policy.compress {
+        let mut input = File::open(path)?;
+        let output = File::create(rotated_path(path, 1, true))?;
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+        fs::remove_file(path)?;
+    } else {
+        fs::rename(path, rotated_path(path, 1, false))?;
+    }
+
+    Ok(true)
+}
+
+// ---------------------------------------------------------------------------
+// Append-only journal
+// ---------------------------------------------------------------------------
+
+/// Line-oriented append-only file that rotates itself according to a
+/// [`RetentionPolicy`] before each write.
+pub struct Journal {
+    path: PathBuf,
+    policy: RetentionPolicy,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>, policy: RetentionPolicy) -> Self {
+        Self {
+            path: path.into(),
+            policy,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a single line (a trailing newline is added).
+    pub fn append_line(&mut self, line: &str) -> Result<()> {
+        rotate_if_needed(&self.path, &self.policy)?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("elsa-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rotation_keeps_max_files() {
+        let dir = temp_dir("rotation");
+        let policy = RetentionPolicy {
+            max_bytes: 8,
+            max_files: 2,
+            compress: false,
+        };
+        let mut journal = Journal::new(dir.join("log.jsonl"), policy);
+        for i in 0..5 {
+            journal.append_line(&format!("line number {i}")).unwrap();
+        }
+
+        let live = fs::read_to_string(dir.join("log.jsonl")).unwrap();
+        assert_eq!(live.trim(), "line number 4");
+        assert!(dir.join("log.jsonl.1").exists());
+        assert!(dir.join("log.jsonl.2").exists());
+        assert!(!dir.join("log.jsonl.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
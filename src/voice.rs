@@ -0,0 +1,475 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use anyhow::{bail, Result};
+use log::warn;
+use serde::Deserialize;
+
+use crate::game::template::{self, Span};
+
+/// Something that can speak a complete sentence. Fed sentence by sentence
+/// from the token stream so speech starts before generation finishes.
+pub trait SpeechSink {
+    fn speak(&mut self, sentence: &str);
+    /// A stage direction in a scripted line (`*stamps passport*`). Not
+    /// voiced unless the sink has a way to play it, e.g. a sound effect.
+    fn act(&mut self, _action: &str) {}
+}
+
+/// Words that end with a period without ending the sentence.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "st", "sr", "jr", "vs", "etc", "e.g", "i.e", "approx",
+];
+
+/// Words that are abbreviations only before a number: "No. 5", but "I
+/// said no. Next."
+const NUMBER_ABBREVIATIONS: &[&str] = &["no"];
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Incremental sentence boundary detector over streamed text. Reasoning
+/// blocks (`<think>...</think>`) are dropped so they are never voiced.
+#[derive(Debug, Default)]
+pub struct SentenceChunker {
+    /// Spoken text not yet emitted as a sentence.
+    buf: String,
+    /// Raw text that may still turn out to be part of a think tag.
+    pending: String,
+    in_think: bool,
+}
+
+impl SentenceChunker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a streamed piece; returns the sentences it completed.
+    pub fn push(&mut self, piece: &str) -> Vec<String> {
+        self.pending.push_str(piece);
+        self.filter_think();
+        self.drain_sentences()
+    }
+
+    /// Flush whatever is left once the stream ends.
+    pub fn finish(&mut self) -> Option<String> {
+        if !self.in_think {
+            self.buf.push_str(&std::mem::take(&mut self.pending));
+        }
+        self.pending.clear();
+        let rest = std::mem::take(&mut self.buf);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+
+    /// Move text from `pending` to `buf`, skipping think blocks and holding
+    /// back anything that could be the start of a tag.
+    fn filter_think(&mut self) {
+        loop {
+            let tag = if self.in_think {
+                THINK_CLOSE
+            } else {
+                THINK_OPEN
+            };
+            if let Some(pos) = self.pending.find(tag) {
+                if !self.in_think {
+                    self.buf.push_str(&self.pending[..pos]);
+                }
+                self.pending.drain(..pos + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+
+            // Keep a possible partial tag at the end of the pending text.
+            let keep = (1..tag.len())
+                .rev()
+                .find(|&n| self.pending.ends_with(&tag[..n]))
+                .unwrap_or(0);
+            let cut = self.pending.len() - keep;
+            if !self.in_think {
+                self.buf.push_str(&self.pending[..cut]);
+            }
+            self.pending.drain(..cut);
+            return;
+        }
+    }
+
+    fn drain_sentences(&mut self) -> Vec<String> {
+        let mut sentences = Vec::new();
+        while let Some(end) = self.sentence_end() {
+            let sentence: String = self.buf.drain(..end).collect();
+            let sentence = sentence.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+        }
+        sentences
+    }
+
+    /// Byte offset just past the first complete sentence in `buf`, if any.
+    /// A sentence is complete once its terminator is followed by whitespace.
+    fn sentence_end(&self) -> Option<usize> {
+        let chars: Vec<(usize, char)> = self.buf.char_indices().collect();
+        for (i, &(pos, c)) in chars.iter().enumerate() {
+            if !matches!(c, '.' | '!' | '?' | '…') {
+                continue;
+            }
+            // Swallow runs like "?!" or "..." and closing quotes/brackets.
+            let mut j = i + 1;
+            while j < chars.len() && matches!(chars[j].1, '.' | '!' | '?' | '"' | '\'' | ')') {
+                j += 1;
+            }
+            // Terminator at the very end: wait for more text.
+            let &(next_pos, next) = chars.get(j)?;
+            if !next.is_whitespace() {
+                continue;
+            }
+            if c == '.' && j == i + 1 {
+                let word = self.word_before(pos);
+                if ABBREVIATIONS.contains(&word.as_str()) {
+                    continue;
+                }
+                if NUMBER_ABBREVIATIONS.contains(&word.as_str()) {
+                    // Wait for the next word to tell.
+                    let next = self.buf[next_pos..].trim_start().chars().next()?;
+                    if next.is_ascii_digit() {
+                        continue;
+                    }
+                }
+            }
+            return Some(next_pos);
+        }
+        None
+    }
+
+    /// The word ending at byte offset `end`, lowercased.
+    fn word_before(&self, end: usize) -> String {
+        self.buf[..end]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or("")
+            .to_lowercase()
+    }
+}
+
+/// Wrap a speech sink into a stream callback: every completed sentence is
+/// voiced immediately, stage directions and all. Call
+/// [`SentenceChunker::finish`] on the chunker after the stream ends and
+/// voice the tail with [`speak_line`].
+pub fn tee_to_speech<'a>(
+    chunker: &'a mut SentenceChunker,
+    sink: &'a mut dyn SpeechSink,
+) -> impl FnMut(&str) + 'a {
+    move |piece| {
+        for sentence in chunker.push(piece) {
+            speak_line(&sentence, &mut *sink);
+        }
+    }
+}
+
+/// Voice a scripted guard line: speech sentence by sentence, stage
+/// directions through [`SpeechSink::act`].
+pub fn speak_line(line: &str, sink: &mut dyn SpeechSink) {
+    for span in template::spans(line) {
+        match span {
+            Span::Speech(text) => {
+                let mut chunker = SentenceChunker::new();
+                let sentences = chunker.push(text);
+                for sentence in sentences
+                    .iter()
+                    .map(String::as_str)
+                    .chain(chunker.finish().as_deref())
+                {
+                    sink.speak(sentence);
+                }
+            }
+            Span::Action(action) => sink.act(action),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Text to speech
+// ---------------------------------------------------------------------------
+
+/// Speaks guard lines through an external text-to-speech program, and
+/// hears the player's answers through a speech-to-text one.
+///
+/// ```toml
+/// [voice]
+/// command = "piper --model {voice} --output-raw | aplay -q -r 22050 -f S16_LE -t raw -"
+/// voice = "en_GB-alan-medium.onnx"
+/// speakers = { Supervisor = "en_US-amy-medium.onnx" }
+/// listen = "rec -q -r 16000 -c 1 /tmp/elsa.wav silence 1 0.1 1% 1 1.5 1% && whisper-cli -m ggml-base.en.bin -nt -np -f /tmp/elsa.wav"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VoiceConfig {
+    /// Shell command that reads a sentence on stdin and speaks it.
+    /// `{voice}` is replaced by the speaker's voice.
+    pub command: Option<String>,
+    /// Voice of lines without a speaker of their own.
+    pub voice: String,
+    /// Voices by speaker name, as shown before their lines.
+    pub speakers: BTreeMap<String, String>,
+    /// Shell command that records an answer and prints what was said.
+    pub listen: Option<String>,
+}
+
+impl VoiceConfig {
+    /// The voice of `speaker`, or the default one.
+    fn voice_for(&self, speaker: Option<&str>) -> &str {
+        speaker
+            .and_then(|name| self.speakers.get(name))
+            .unwrap_or(&self.voice)
+    }
+
+    /// The voice for a guard line as the UI shows it, and the words to
+    /// say: a `Name: ` prefix picks that speaker's voice and is not read out.
+    fn voice_of<'a>(&'a self, text: &'a str) -> (&'a str, &'a str) {
+        self.speakers
+            .iter()
+            .find_map(|(name, voice)| {
+                let line = text.strip_prefix(name.as_str())?.strip_prefix(':')?;
+                Some((voice.as_str(), line.trim_start()))
+            })
+            .unwrap_or((&self.voice, text))
+    }
+}
+
+/// Speaks lines on a thread of its own, in order, so the text keeps
+/// printing while the voice catches up.
+pub struct Speech {
+    config: VoiceConfig,
+    sentences: Sender<(String, String)>,
+    /// The next guard line was already read out as it was generated.
+    said_ahead: Cell<bool>,
+}
+
+impl Speech {
+    pub fn start(config: VoiceConfig) -> Result<Self> {
+        let Some(command) = config.command.clone() else {
+            bail!("--speak needs a [voice] command in the config file");
+        };
+        let (sentences, queue) = mpsc::channel::<(String, String)>();
+        thread::spawn(move || {
+            for (voice, sentence) in queue {
+                if let Err(e) = run(&command.replace("{voice}", &voice), &sentence) {
+                    warn!("Text to speech failed: {e:#}");
+                }
+            }
+        });
+        Ok(Self {
+            config,
+            sentences,
+            said_ahead: Cell::new(false),
+        })
+    }
+
+    /// Speak a guard line as the UI shows it, unless it was streamed to
+    /// speech already.
+    pub fn say(&self, text: &str) {
+        if self.said_ahead.take() {
+            return;
+        }
+        let (voice, line) = self.config.voice_of(text);
+        let mut voiced = Voiced {
+            voice: voice.to_string(),
+            speech: self,
+        };
+        speak_line(line, &mut voiced);
+    }
+
+    /// A sink for text in `speaker`'s voice, e.g. to stream generated
+    /// lines through [`tee_to_speech`].
+    pub fn sink(&self, speaker: Option<&str>) -> impl SpeechSink + '_ {
+        Voiced {
+            voice: self.config.voice_for(speaker).to_string(),
+            speech: self,
+        }
+    }
+
+    /// The guard line about to be shown was already spoken as it streamed
+    /// in, so the next [`Speech::say`] stays quiet.
+    pub fn said_ahead(&self) {
+        self.said_ahead.set(true);
+    }
+}
+
+/// Push-to-talk: records one answer and transcribes it.
+pub struct Listener {
+    command: String,
+}
+
+impl Listener {
+    pub fn new(config: &VoiceConfig) -> Result<Self> {
+        let Some(command) = config.listen.clone() else {
+            bail!("--listen needs a [voice] listen command in the config file");
+        };
+        Ok(Self { command })
+    }
+
+    /// Record an answer and return what was said; empty if nothing was.
+    pub fn listen(&self) -> Result<String> {
+        let output = Command::new("sh")
+            .args(["-c", &self.command])
+            .stdin(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            bail!("`{}` exited with {}", self.command, output.status);
+        }
+        Ok(transcript(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// The words of a speech-to-text transcript, on one line. Markers for
+/// what is not speech, like whisper's `[BLANK_AUDIO]` or `(coughs)`, are
+/// dropped.
+fn transcript(text: &str) -> String {
+    let mut words = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => words.push(c),
+            _ => {}
+        }
+    }
+    words.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// What a front end does with voices: reads guard lines out, hears answers.
+#[derive(Default)]
+pub struct Voice {
+    pub speech: Option<Speech>,
+    pub listener: Option<Listener>,
+}
+
+/// Sentences of one line, in one voice.
+struct Voiced<'a> {
+    voice: String,
+    speech: &'a Speech,
+}
+
+impl SpeechSink for Voiced<'_> {
+    fn speak(&mut self, sentence: &str) {
+        // Only fails once the thread is gone, and then there is no one to tell.
+        let _ = self
+            .speech
+            .sentences
+            .send((self.voice.clone(), sentence.to_string()));
+    }
+}
+
+/// Run the speech command on one sentence and wait until it is spoken.
+fn run(command: &str, sentence: &str) -> Result<()> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(sentence.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("`{command}` exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(pieces: &[&str]) -> Vec<String> {
+        let mut chunker = SentenceChunker::new();
+        let mut out: Vec<String> = pieces.iter().flat_map(|p| chunker.push(p)).collect();
+        out.extend(chunker.finish());
+        out
+    }
+
+    #[test]
+    fn test_sentences_split_across_pieces() {
+        assert_eq!(
+            chunk(&["Hello", ". Pass", "port please! Wh", "ere from?"]),
+            vec!["Hello.", "Passport please!", "Where from?"]
+        );
+    }
+
+    #[test]
+    fn test_abbreviations_and_decimals_do_not_split() {
+        assert_eq!(
+            chunk(&["Dr. Smith paid 3.50 at the gate. Next."]),
+            vec!["Dr. Smith paid 3.50 at the gate.", "Next."]
+        );
+    }
+
+    #[test]
+    fn test_no_ends_a_sentence_unless_a_number_follows() {
+        assert_eq!(
+            chunk(&["Gate No. 5 is closed. I said no", ". Next."]),
+            vec!["Gate No. 5 is closed.", "I said no.", "Next."]
+        );
+        let mut chunker = SentenceChunker::new();
+        assert!(chunker.push("Answer: no. ").is_empty());
+        assert_eq!(chunker.push("Go."), ["Answer: no."]);
+    }
+
+    #[test]
+    fn test_stage_directions_are_not_spoken() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+        impl SpeechSink for Recorder {
+            fn speak(&mut self, sentence: &str) {
+                self.0.push(sentence.to_string());
+            }
+            fn act(&mut self, action: &str) {
+                self.0.push(format!("[{action}]"));
+            }
+        }
+        let mut recorder = Recorder::default();
+        speak_line("*stamps passport* Welcome. Next!", &mut recorder);
+        assert_eq!(recorder.0, ["[stamps passport]", "Welcome.", "Next!"]);
+    }
+
+    #[test]
+    fn test_speakers_get_their_own_voice() {
+        let config = VoiceConfig {
+            command: Some("say -v {voice}".into()),
+            voice: "Daniel".into(),
+            speakers: BTreeMap::from([("Supervisor".into(), "Karen".into())]),
+            listen: None,
+        };
+        assert_eq!(
+            config.voice_of("Supervisor: Come with me."),
+            ("Karen", "Come with me.")
+        );
+        assert_eq!(
+            config.voice_of("Next, please."),
+            ("Daniel", "Next, please.")
+        );
+    }
+
+    #[test]
+    fn test_transcript_drops_non_speech() {
+        assert_eq!(
+            transcript("[BLANK_AUDIO]\n I'm here to visit my sister. (coughs)\n For a week.\n"),
+            "I'm here to visit my sister. For a week."
+        );
+        assert_eq!(transcript(" [BLANK_AUDIO]\n"), "");
+    }
+
+    #[test]
+    fn test_think_blocks_are_not_voiced() {
+        assert_eq!(
+            chunk(&["<thi", "nk>The user is calm.</th", "ink>Welcome. Enjoy."]),
+            vec!["Welcome.", "Enjoy."]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! End-to-end games against a real (tiny) model, over the JSONL front end.
+//!
+//! Ignored by default: they need a ~100MB download and a few minutes of
+//! CPU. Run them with
+//!
+//!     cargo test --release --test full_game -- --ignored
+//!
+//! The model is read from `ELSA_TEST_MODEL` if set, otherwise downloaded
+//! once (with `curl`) from `ELSA_TEST_MODEL_URL` or a default SmolLM2 build
+//! into `target/test-models`. A model this small is a poor judge, so the
+//! checks are about the game loop holding together, not about its taste.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+const MODEL_URL: &str = "https://huggingface.co/bartowski/SmolLM2-135M-Instruct-GGUF/resolve/main/SmolLM2-135M-Instruct-Q4_K_M.gguf";
+
+const SCENARIO: &str = "scenarios/airport.json";
+
+/// The test model, downloaded on first use.
+fn model() -> PathBuf {
+    if let Ok(path) = std::env::var("ELSA_TEST_MODEL") {
+        return PathBuf::from(path);
+    }
+    let url = std::env::var("ELSA_TEST_MODEL_URL").unwrap_or_else(|_| MODEL_URL.to_string());
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/test-models");
+    let path = dir.join(url.rsplit('/').next().unwrap());
+    if path.exists() {
+        return path;
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    // Download next to the model and rename, so an interrupted download
+    // isn't mistaken for a model next time.
+    let partial = path.with_extension("part");
+    let status = Command::new("curl")
+        .args([
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "--output",
+        ])
+        .arg(&partial)
+        .arg(&url)
+        .status()
+        .expect("curl is needed to download the test model");
+    assert!(status.success(), "failed to download {url}");
+    std::fs::rename(&partial, &path).unwrap();
+    path
+}
+
+/// Play the scenario with `answers`, one per prompt, and return every event
+/// the game wrote. Once the answers run out the input is closed, which
+/// quits the game.
+fn play(answers: &[&str]) -> Vec<Value> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_elsa"))
+        .arg(model())
+        .arg(SCENARIO)
+        .args(["--io", "jsonl", "--seed", "7", "--no-stats"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start the game");
+
+    let mut stdin = child.stdin.take().unwrap();
+    for answer in answers {
+        writeln!(stdin, "{}", serde_json::json!({ "message": answer })).unwrap();
+    }
+    drop(stdin);
+
+    let events = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).expect("events are JSON"))
+        .collect();
+    assert!(
+        child.wait().unwrap().success(),
+        "the game exited with an error"
+    );
+    events
+}
+
+fn of_type<'a>(events: &'a [Value], kind: &'a str) -> impl Iterator<Item = &'a Value> {
+    events.iter().filter(move |event| event["type"] == kind)
+}
+
+fn node_ids() -> BTreeSet<String> {
+    let tree: Value = serde_json::from_str(&std::fs::read_to_string(SCENARIO).unwrap()).unwrap();
+    tree["nodes"].as_object().unwrap().keys().cloned().collect()
+}
+
+/// Checks that hold whatever the judge made of the answers: it only picked
+/// nodes of the scenario, gave a reason, and the game ended once.
+fn assert_coherent(events: &[Value]) {
+    let nodes = node_ids();
+    assert_eq!(events[0]["type"], "node_entered", "{events:#?}");
+    assert_eq!(events[0]["node_id"], "START");
+    for decision in of_type(events, "decision") {
+        let picked = decision["decision"].as_str().unwrap();
+        assert!(nodes.contains(picked), "judge picked {picked}");
+        assert!(!decision["reason"].as_str().unwrap().trim().is_empty());
+    }
+    assert_eq!(of_type(events, "game_over").count(), 1, "{events:#?}");
+}
+
+#[test]
+#[ignore = "downloads a model and runs it"]
+fn test_cooperative_traveller_reaches_an_ending() {
+    let events = play(&[
+        "Good morning, here is my passport.",
+        "I'm flying in from Lisbon.",
+        "I'm visiting my sister for two weeks.",
+        "No, just clothes and a couple of books.",
+        // Spares, in case the judge asks again.
+        "Here you go, officer.",
+        "Just a holiday.",
+        "Nothing to declare.",
+    ]);
+    assert_coherent(&events);
+    let over = of_type(&events, "game_over").next().unwrap();
+    let ending = over["terminal_node_id"].as_str();
+    assert!(
+        matches!(ending, Some("CLEARED" | "FAILED" | "FAILED_CONTRABAND")),
+        "no ending reached: {over}"
+    );
+}
+
+#[test]
+#[ignore = "downloads a model and runs it"]
+fn test_hostile_traveller_is_not_cleared() {
+    let events = play(&[
+        "Why do you need it? I'm not showing you anything.",
+        "None of your business.",
+        "Let's just say I have some packages to deliver.",
+        "Only a few kilos of undeclared cash in the lining.",
+    ]);
+    assert_coherent(&events);
+    let over = of_type(&events, "game_over").next().unwrap();
+    assert_ne!(over["success"], true, "cleared a hostile traveller: {over}");
+}
@@ -0,0 +1,84 @@
+//! Whole games through the library with a scripted judge, so they run
+//! anywhere: no model, no GPU, no download. The scripts under
+//! `tests/scripts` hold the judge's replies, one per answer.
+
+use std::path::Path;
+
+use elsa::game::GameOutcome;
+use elsa::{Engine, JudgeResult, MockBackend};
+
+const SCENARIO: &str = "scenarios/airport.json";
+
+/// Play the scenario against `script`, one answer per reply, and return the
+/// last result.
+fn play(script: &str) -> JudgeResult {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let tree = elsa::game::scenarios::load(root.join(SCENARIO).to_str().unwrap()).unwrap();
+    let judge = MockBackend::open(&root.join("tests/scripts").join(script)).unwrap();
+    let mut engine = Engine::new(tree, judge, 7);
+    assert!(!engine.guard_line().is_empty());
+
+    let mut turns = 0;
+    loop {
+        let result = engine.respond("Whatever the script says.").unwrap();
+        turns += 1;
+        assert!(turns < 20, "the game never ended");
+        if result.outcome.is_some() && result.appeal_line.is_none() {
+            assert_eq!(engine.backend_mut().remaining(), 0, "unused replies");
+            return result;
+        }
+    }
+}
+
+#[test]
+fn cleared() {
+    let result = play("airport_cleared.jsonl");
+    assert_eq!(result.record.unwrap().decision, "CLEARED");
+    let Some(GameOutcome::Finished {
+        success,
+        steps_completed,
+        total_steps,
+        terminal_node_id,
+        ..
+    }) = result.outcome
+    else {
+        panic!("expected a finished game");
+    };
+    assert!(success);
+    assert_eq!(terminal_node_id, "CLEARED");
+    assert_eq!(steps_completed, total_steps);
+}
+
+#[test]
+fn denied_after_a_confused_judge() {
+    let result = play("airport_contraband.jsonl");
+    let Some(GameOutcome::Finished {
+        success,
+        terminal_node_id,
+        ..
+    }) = result.outcome
+    else {
+        panic!("expected a finished game");
+    };
+    assert!(!success);
+    assert_eq!(terminal_node_id, "FAILED_CONTRABAND");
+}
+
+#[test]
+fn classified_by_embedding_when_the_judge_keeps_garbling() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let tree = elsa::game::scenarios::load(root.join(SCENARIO).to_str().unwrap()).unwrap();
+    let judge = MockBackend::open(&root.join("tests/scripts/airport_garbled.jsonl")).unwrap();
+    let mut engine = Engine::new(tree, judge, 7);
+
+    let answer = "Sure, get my passport checked.";
+    // The first bad reply only has the player asked again.
+    let result = engine.respond(answer).unwrap();
+    assert!(result.record.is_none());
+    // The second is one too many: the answer goes to the nearest criterion.
+    let result = engine.respond(answer).unwrap();
+    let record = result.record.unwrap();
+    assert_eq!(record.decision, "PASSPORT_CHECK");
+    assert!(record.reason.contains("by embedding"), "{}", record.reason);
+    assert_eq!(engine.backend_mut().remaining(), 0);
+}